@@ -0,0 +1,472 @@
+//! Layered configuration files with the default values of the command line options.
+//!
+//! The values are read from (in order of increasing precedence):
+//! - the user configuration, `~/.config/task-maker/config.toml`;
+//! - the contest configuration, the first `task-maker.toml` in the parents of the task directory;
+//! - the task configuration, `task-maker.toml` inside the task directory.
+//!
+//! The options passed on the command line take precedence over all of them.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Error};
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use serde::{Deserialize, Serialize};
+
+use task_maker_format::ioi::IOITask;
+use task_maker_format::terry::TerryTask;
+use task_maker_format::ui::UIType;
+
+use crate::{ExecutionOpt, Opt, StorageOpt, UIOpt, STORE_COMPRESSION_LEVELS};
+
+/// The name of the contest and task configuration files.
+pub const CONFIG_FILE_NAME: &str = "task-maker.toml";
+
+/// The keys whose values are paths. The relative ones are relative to the directory of the
+/// configuration file, not to the current one.
+const PATH_KEYS: &[&str] = &["store-dir", "shared-store-dir"];
+
+/// The values that can be set in a configuration file, named like the command line options.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ConfigValues {
+    /// Which UI to use.
+    pub ui: Option<String>,
+    /// Run the evaluation on a remote server instead of locally.
+    pub evaluate_on: Option<String>,
+    /// The number of CPU cores to use.
+    pub num_cores: Option<usize>,
+    /// List of sanity checks to skip.
+    pub skip_checks: Option<Vec<String>>,
+    /// Where to store the storage files, including the cache.
+    pub store_dir: Option<PathBuf>,
+    /// Maximum size of the storage directory, in MiB.
+    pub max_cache: Option<u64>,
+    /// When the storage is flushed, this is the new maximum size, in MiB.
+    pub min_cache: Option<u64>,
+    /// Maximum total size of the pinned files of the storage, in MiB.
+    pub max_pinned: Option<u64>,
+    /// The zstd level for compressing the files of the storage.
+    pub store_compression: Option<i32>,
+    /// A shared storage directory, used read-only when something is missing from the local one.
+    pub shared_store_dir: Option<PathBuf>,
+}
+
+/// Where a configuration file comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// The configuration of the user.
+    User(PathBuf),
+    /// The configuration shared by all the tasks of a contest.
+    Contest(PathBuf),
+    /// The configuration of a single task.
+    Task(PathBuf),
+}
+
+impl ConfigSource {
+    /// The path of the configuration file.
+    pub fn path(&self) -> &Path {
+        match self {
+            ConfigSource::User(path) | ConfigSource::Contest(path) | ConfigSource::Task(path) => {
+                path
+            }
+        }
+    }
+}
+
+impl Display for ConfigSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            ConfigSource::User(_) => "user",
+            ConfigSource::Contest(_) => "contest",
+            ConfigSource::Task(_) => "task",
+        };
+        write!(f, "{} config {}", kind, self.path().display())
+    }
+}
+
+/// The configuration files that apply to a task, in order of increasing precedence.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// The content of each configuration file.
+    layers: Vec<(ConfigSource, toml::value::Table)>,
+}
+
+impl Config {
+    /// Load the configuration files that apply to the task found searching from `base` (the current
+    /// directory if not specified), going at most `max_depth` times up.
+    pub fn load(base: Option<&Path>, max_depth: u32) -> Result<Config, Error> {
+        let cwd = std::env::current_dir().context("Cannot get the current directory")?;
+        let base = match base {
+            Some(base) => cwd.join(base),
+            None => cwd,
+        };
+        let user_config = directories::ProjectDirs::from("", "", "task-maker")
+            .map(|user_dir| user_dir.config_dir().join("config.toml"));
+        Self::load_from(&base, max_depth, user_config)
+    }
+
+    /// Load the configuration files that apply to the task found searching from `base`, using
+    /// `user_config` as the configuration of the user.
+    fn load_from(
+        base: &Path,
+        max_depth: u32,
+        user_config: Option<PathBuf>,
+    ) -> Result<Config, Error> {
+        let task_dir = base
+            .ancestors()
+            .take(max_depth as usize)
+            .find(|dir| IOITask::is_valid(dir) || TerryTask::is_valid(dir));
+
+        let mut sources = vec![];
+        if let Some(user_config) = user_config {
+            sources.push(ConfigSource::User(user_config));
+        }
+        // without a task, the nearest configuration file is used as the contest one
+        let contest_base = task_dir.and_then(Path::parent).unwrap_or(base);
+        let contest = contest_base
+            .ancestors()
+            .map(|dir| dir.join(CONFIG_FILE_NAME))
+            .find(|path| path.exists());
+        if let Some(contest) = contest {
+            sources.push(ConfigSource::Contest(contest));
+        }
+        if let Some(task_dir) = task_dir {
+            sources.push(ConfigSource::Task(task_dir.join(CONFIG_FILE_NAME)));
+        }
+
+        let mut config = Config::default();
+        for source in sources {
+            if !source.path().exists() {
+                continue;
+            }
+            let table = Self::load_file(source.path()).with_context(|| {
+                format!("Invalid configuration file {}", source.path().display())
+            })?;
+            config.layers.push((source, table));
+        }
+        Ok(config)
+    }
+
+    /// Read and validate a configuration file.
+    fn load_file(path: &Path) -> Result<toml::value::Table, Error> {
+        let content = std::fs::read_to_string(path).context("Failed to read the file")?;
+        let table: toml::value::Table = toml::from_str(&content)?;
+        toml::Value::Table(table.clone()).try_into::<ConfigValues>()?;
+        Ok(table)
+    }
+
+    /// The configuration files that have been loaded, in order of increasing precedence.
+    pub fn sources(&self) -> impl Iterator<Item = &ConfigSource> {
+        self.layers.iter().map(|(source, _)| source)
+    }
+
+    /// The effective value of each key, together with the file it comes from.
+    pub fn entries(&self) -> BTreeMap<&str, (&toml::Value, &ConfigSource)> {
+        let mut entries = BTreeMap::new();
+        for (source, table) in &self.layers {
+            for (key, value) in table {
+                entries.insert(key.as_str(), (value, source));
+            }
+        }
+        entries
+    }
+
+    /// The effective values of the configuration, with the relative paths resolved against the
+    /// directories of their files.
+    pub fn values(&self) -> Result<ConfigValues, Error> {
+        let table = self
+            .entries()
+            .into_iter()
+            .map(|(key, (value, source))| (key.to_string(), resolve_path(key, value, source)))
+            .collect();
+        Ok(toml::Value::Table(table).try_into()?)
+    }
+}
+
+/// Make the value of a path key relative to the directory of the configuration file it comes
+/// from. The other values are returned as they are.
+fn resolve_path(key: &str, value: &toml::Value, source: &ConfigSource) -> toml::Value {
+    match (value, source.path().parent()) {
+        (toml::Value::String(path), Some(dir)) if PATH_KEYS.contains(&key) => {
+            toml::Value::String(dir.join(path).to_string_lossy().into_owned())
+        }
+        _ => value.clone(),
+    }
+}
+
+impl Opt {
+    /// Replace the options not provided on the command line with the ones from the configuration
+    /// files of the task.
+    pub fn load_config(&mut self, matches: &ArgMatches) -> Result<(), Error> {
+        let config = Config::load(self.find_task.task_dir.as_deref(), self.find_task.max_depth)?;
+        self.apply_config(&config.values()?, matches)
+    }
+}
+
+/// Whether the value of the argument has been explicitly provided by the user.
+fn is_explicit(matches: &ArgMatches, id: &str) -> bool {
+    matches!(
+        matches.value_source(id),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    )
+}
+
+/// Options that can take their values from the configuration files.
+pub trait ApplyConfig {
+    /// Replace the values that are not explicitly set on the command line with the ones from the
+    /// configuration.
+    fn apply_config(&mut self, config: &ConfigValues, matches: &ArgMatches) -> Result<(), Error>;
+}
+
+impl ApplyConfig for UIOpt {
+    fn apply_config(&mut self, config: &ConfigValues, matches: &ArgMatches) -> Result<(), Error> {
+        if let Some(ui) = config.ui.as_ref().filter(|_| !is_explicit(matches, "ui")) {
+            self.ui = UIType::from_str(ui).map_err(|e| anyhow!("Invalid config: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+impl ApplyConfig for ExecutionOpt {
+    fn apply_config(&mut self, config: &ConfigValues, matches: &ArgMatches) -> Result<(), Error> {
+        if !is_explicit(matches, "evaluate_on") && config.evaluate_on.is_some() {
+            self.evaluate_on = config.evaluate_on.clone();
+        }
+        if !is_explicit(matches, "num_cores") && config.num_cores.is_some() {
+            self.num_cores = config.num_cores;
+        }
+        Ok(())
+    }
+}
+
+impl ApplyConfig for StorageOpt {
+    fn apply_config(&mut self, config: &ConfigValues, matches: &ArgMatches) -> Result<(), Error> {
+        if !is_explicit(matches, "store_dir") && config.store_dir.is_some() {
+            self.store_dir = config.store_dir.clone();
+        }
+        if let Some(max_cache) = config
+            .max_cache
+            .filter(|_| !is_explicit(matches, "max_cache"))
+        {
+            self.max_cache = max_cache;
+        }
+        if let Some(min_cache) = config
+            .min_cache
+            .filter(|_| !is_explicit(matches, "min_cache"))
+        {
+            self.min_cache = min_cache;
+        }
+        if let Some(max_pinned) = config
+            .max_pinned
+            .filter(|_| !is_explicit(matches, "max_pinned"))
+        {
+            self.max_pinned = max_pinned;
+        }
+        if let Some(level) = config
+            .store_compression
+            .filter(|_| !is_explicit(matches, "store_compression"))
+        {
+            if !STORE_COMPRESSION_LEVELS.contains(&level.into()) {
+                bail!(
+                    "Invalid config: store-compression must be between {} and {}, not {}",
+                    STORE_COMPRESSION_LEVELS.start(),
+                    STORE_COMPRESSION_LEVELS.end(),
+                    level
+                );
+            }
+            self.store_compression = Some(level);
+        }
+        if !is_explicit(matches, "shared_store_dir") && config.shared_store_dir.is_some() {
+            self.shared_store_dir = config.shared_store_dir.clone();
+        }
+        Ok(())
+    }
+}
+
+impl ApplyConfig for Opt {
+    fn apply_config(&mut self, config: &ConfigValues, matches: &ArgMatches) -> Result<(), Error> {
+        let explicit = is_explicit(matches, "skip_sanity_checks");
+        if let Some(skip_checks) = config.skip_checks.as_ref().filter(|_| !explicit) {
+            self.skip_sanity_checks = skip_checks.clone();
+        }
+        self.ui.apply_config(config, matches)?;
+        self.execution.apply_config(config, matches)?;
+        self.storage.apply_config(config, matches)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, FromArgMatches};
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// A task inside a contest directory, with a configuration file at each level.
+    struct Layout {
+        dir: TempDir,
+    }
+
+    impl Layout {
+        fn new(user: &str, contest: &str, task: &str) -> Layout {
+            let dir = TempDir::new().unwrap();
+            let task_dir = dir.path().join("contest/task");
+            std::fs::create_dir_all(&task_dir).unwrap();
+            std::fs::write(task_dir.join("task.yaml"), "").unwrap();
+            std::fs::write(dir.path().join("user.toml"), user).unwrap();
+            std::fs::write(dir.path().join("contest").join(CONFIG_FILE_NAME), contest).unwrap();
+            std::fs::write(task_dir.join(CONFIG_FILE_NAME), task).unwrap();
+            Layout { dir }
+        }
+
+        fn task_dir(&self) -> PathBuf {
+            self.dir.path().join("contest/task")
+        }
+
+        fn config(&self) -> Result<Config, Error> {
+            Config::load_from(&self.task_dir(), 3, Some(self.dir.path().join("user.toml")))
+        }
+
+        /// Parse the command line arguments and apply the configuration files to them.
+        fn opt(&self, args: &[&str]) -> Opt {
+            let task_dir = self.task_dir();
+            let args = ["task-maker", "--task-dir", task_dir.to_str().unwrap()]
+                .into_iter()
+                .chain(args.iter().copied());
+            let matches = Opt::command().try_get_matches_from(args).unwrap();
+            let mut opt = Opt::from_arg_matches(&matches).unwrap();
+            let config = self.config().unwrap();
+            opt.apply_config(&config.values().unwrap(), &matches)
+                .unwrap();
+            opt
+        }
+    }
+
+    #[test]
+    fn test_config_sources() {
+        let layout = Layout::new("", "", "");
+        let config = layout.config().unwrap();
+        let sources: Vec<_> = config.sources().cloned().collect();
+        assert_eq!(
+            sources,
+            vec![
+                ConfigSource::User(layout.dir.path().join("user.toml")),
+                ConfigSource::Contest(layout.dir.path().join("contest").join(CONFIG_FILE_NAME)),
+                ConfigSource::Task(layout.task_dir().join(CONFIG_FILE_NAME)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_precedence() {
+        let layout = Layout::new(
+            "num-cores = 1\nmax-cache = 10\nui = \"print\"",
+            "num-cores = 2\nmax-cache = 20",
+            "num-cores = 3",
+        );
+        let opt = layout.opt(&[]);
+        assert_eq!(opt.execution.num_cores, Some(3));
+        assert_eq!(opt.storage.max_cache, 20);
+        assert!(matches!(opt.ui.ui, UIType::Print));
+        // The defaults are kept for the values not in any file.
+        assert_eq!(opt.storage.min_cache, 2048);
+        assert_eq!(opt.execution.evaluate_on, None);
+
+        let config = layout.config().unwrap();
+        let entries = config.entries();
+        assert!(matches!(entries["num-cores"].1, ConfigSource::Task(_)));
+        assert!(matches!(entries["max-cache"].1, ConfigSource::Contest(_)));
+        assert!(matches!(entries["ui"].1, ConfigSource::User(_)));
+    }
+
+    #[test]
+    fn test_config_command_line_overrides() {
+        let layout = Layout::new(
+            "ui = \"print\"",
+            "max-cache = 20\nskip-checks = [\"Foo\"]",
+            "num-cores = 3",
+        );
+        let opt = layout.opt(&["--num-cores", "8", "--ui", "raw", "-W", "Bar"]);
+        assert_eq!(opt.execution.num_cores, Some(8));
+        assert!(matches!(opt.ui.ui, UIType::Raw));
+        assert_eq!(opt.skip_sanity_checks, vec!["Bar".to_string()]);
+        assert_eq!(opt.storage.max_cache, 20);
+    }
+
+    #[test]
+    fn test_config_command_line_default_value() {
+        let layout = Layout::new("", "max-cache = 20", "");
+        // Passing explicitly the default value still overrides the files.
+        let opt = layout.opt(&["--max-cache", "3072"]);
+        assert_eq!(opt.storage.max_cache, 3072);
+    }
+
+    #[test]
+    fn test_config_without_task() {
+        let layout = Layout::new("", "num-cores = 2", "num-cores = 3");
+        std::fs::remove_file(layout.task_dir().join("task.yaml")).unwrap();
+        let config = Config::load_from(&layout.task_dir(), 3, None).unwrap();
+        let sources: Vec<_> = config.sources().cloned().collect();
+        // The nearest file is used as the contest configuration.
+        assert_eq!(
+            sources,
+            vec![ConfigSource::Contest(
+                layout.task_dir().join(CONFIG_FILE_NAME)
+            )]
+        );
+        assert_eq!(config.values().unwrap().num_cores, Some(3));
+    }
+
+    #[test]
+    fn test_config_relative_paths() {
+        let layout = Layout::new(
+            "shared-store-dir = \"/mnt/shared\"",
+            "store-dir = \"store\"",
+            "",
+        );
+        let opt = layout.opt(&[]);
+        assert_eq!(
+            opt.storage.store_dir,
+            Some(layout.dir.path().join("contest/store"))
+        );
+        assert_eq!(
+            opt.storage.shared_store_dir,
+            Some(PathBuf::from("/mnt/shared"))
+        );
+        // The paths on the command line are relative to the current directory.
+        let opt = layout.opt(&["--store-dir", "other"]);
+        assert_eq!(opt.storage.store_dir, Some(PathBuf::from("other")));
+    }
+
+    #[test]
+    fn test_config_invalid_compression() {
+        let layout = Layout::new("", "store-compression = 42", "");
+        let task_dir = layout.task_dir();
+        let args = ["task-maker", "--task-dir", task_dir.to_str().unwrap()];
+        let matches = Opt::command().try_get_matches_from(args).unwrap();
+        let mut opt = Opt::from_arg_matches(&matches).unwrap();
+        let config = layout.config().unwrap().values().unwrap();
+        let err = opt.apply_config(&config, &matches).unwrap_err();
+        assert!(
+            format!("{:?}", err).contains("store-compression"),
+            "{:?}",
+            err
+        );
+
+        let layout = Layout::new("", "store-compression = 19", "");
+        assert_eq!(layout.opt(&[]).storage.store_compression, Some(19));
+    }
+
+    #[test]
+    fn test_config_invalid_key() {
+        let layout = Layout::new("", "num-corez = 2", "");
+        let err = layout.config().unwrap_err();
+        assert!(format!("{:?}", err).contains("num-corez"), "{:?}", err);
+    }
+}
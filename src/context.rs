@@ -10,19 +10,16 @@ use anyhow::{anyhow, bail, Context, Error};
 use task_maker_cache::Cache;
 use task_maker_dag::CacheMode;
 use task_maker_exec::ductile::{new_local_channel, ChannelReceiver, ChannelSender};
-use task_maker_exec::executors::{LocalExecutor, RemoteEntityMessage, RemoteEntityMessageResponse};
+use task_maker_exec::executors::LocalExecutor;
 use task_maker_exec::proto::{ExecutorClientMessage, ExecutorServerMessage};
 use task_maker_exec::ExecutorClient;
 use task_maker_format::ui::{UIChannelReceiver, UIMessage, UIType, UI};
 use task_maker_format::{EvaluationData, TaskFormat, UISender, VALID_TAGS};
 use task_maker_store::FileStore;
 
-use crate::remote::connect_to_remote_server;
+use crate::remote::{connect_to_remote_server, load_token};
 use crate::{render_dag, ExecutionOpt, StorageOpt, ToolsSandboxRunner};
 
-/// Version of task-maker.
-const VERSION: &str = env!("CARGO_PKG_VERSION");
-
 /// First step of the execution: take a task and build the Execution DAG. This needs setting the
 /// first configurations of the environment.
 pub struct RuntimeContext {
@@ -86,7 +83,9 @@ impl RuntimeContext {
             )
             .copy_exe(opt.copy_exe)
             .copy_logs(opt.copy_logs)
-            .priority(opt.priority);
+            .sanitize(opt.sanitize)
+            .priority(opt.priority)
+            .task_dir(task.path());
         if let Some(extra_time) = opt.extra_time {
             if extra_time < 0.0 {
                 bail!("The extra time ({}) cannot be negative!", extra_time);
@@ -137,28 +136,22 @@ impl RuntimeContext {
             )
             .context(
                 "Cannot create the file store (You can try wiping it with task-maker-tools reset)",
-            )?,
+            )?
+            .with_compression(storage_opt.store_compression)
+            .with_pin_budget(storage_opt.max_pinned * 1024 * 1024)
+            .with_shared(storage_opt.shared_file_store_dir()),
         );
 
         // connect either to the remote executor or spawn a local one
         let (tx, rx, local_executor) = if let Some(evaluate_on) = &opt.evaluate_on {
-            let (tx, rx) = connect_to_remote_server(evaluate_on, 27182)
-                .context("Cannot connect to the remote server")?;
             let name = opt
                 .name
                 .clone()
                 .unwrap_or_else(|| format!("{}@{}", whoami::username(), whoami::hostname()));
-            tx.send(RemoteEntityMessage::Welcome {
-                name,
-                version: VERSION.into(),
-            })
-            .context("Cannot send welcome to the server")?;
-            if let RemoteEntityMessageResponse::Rejected(err) =
-                rx.recv().context("Failed to receive welcome response")?
-            {
-                bail!("The server rejected the client connection: {}", err);
-            }
-            (tx.change_type(), rx.change_type(), None)
+            let token = load_token(opt.token_file.as_deref())?;
+            let (tx, rx) = connect_to_remote_server(evaluate_on, 27182, name, token)
+                .context("Cannot connect to the remote server")?;
+            (tx, rx, None)
         } else {
             // start the server and the client
             let (tx, rx_remote) = new_local_channel();
@@ -166,7 +159,9 @@ impl RuntimeContext {
 
             // setup the local cache
             let cache_path = store_path.join("cache");
-            let cache = Cache::new(cache_path).context("Cannot create the cache")?;
+            let cache = Cache::new(cache_path)
+                .context("Cannot create the cache")?
+                .with_shared(storage_opt.shared_cache_dir());
 
             // setup the local executor
             let num_cores = opt.num_cores.unwrap_or_else(num_cpus::get);
@@ -276,6 +271,8 @@ impl ConnectedExecutorWithUI {
         std::mem::swap(&mut dag, &mut self.eval.dag);
 
         let local_executor = self.local_executor;
+        // the files are worth compressing only when sent over the network
+        let compress_transfers = local_executor.is_none();
         let ui_thread = self.ui_thread;
         let sender = self.eval.sender.clone();
         defer! {
@@ -296,9 +293,14 @@ impl ConnectedExecutorWithUI {
 
         // run the actual computation and block until it ends
         let client_sender = self.client_sender;
-        ExecutorClient::evaluate(dag, self.tx, &self.rx, self.file_store, move |status| {
-            ui_sender.send(UIMessage::ServerStatus { status })
-        })
+        ExecutorClient::evaluate(
+            dag,
+            self.tx,
+            &self.rx,
+            self.file_store,
+            compress_transfers,
+            move |status| ui_sender.send(UIMessage::ServerStatus { status }),
+        )
         .with_context(|| {
             if let Some(tx) = client_sender.lock().unwrap().as_ref() {
                 let _ = tx.send(ExecutorClientMessage::Stop);
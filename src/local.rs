@@ -1,10 +1,17 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
 use anyhow::{bail, Context, Error};
 
+use task_maker_cache::Cache;
+use task_maker_diagnostics::DiagnosticContext;
 use task_maker_format::ui::{UIMessage, UI};
+use task_maker_store::FileStore;
 
 use crate::context::RuntimeContext;
 use crate::error::NiceError;
 use crate::opt::Opt;
+use crate::tools::cache::{default_pin_name, pin_task};
 
 /// The result of an evaluation.
 pub enum Evaluation {
@@ -30,7 +37,7 @@ pub enum Evaluation {
 /// # let opt = task_maker_rust::opt::Opt::parse();
 /// run_evaluation(opt, move |ui, mex| ui.on_message(mex));
 /// ```
-pub fn run_evaluation<F>(opt: Opt, on_message: F) -> Result<Evaluation, Error>
+pub fn run_evaluation<F>(opt: Opt, mut on_message: F) -> Result<Evaluation, Error>
 where
     F: FnMut(&mut dyn UI, UIMessage) + Send + 'static,
 {
@@ -50,20 +57,85 @@ where
     }
 
     // setup the configuration and the evaluation metadata
+    let sanity_check_levels = task.sanity_check_levels();
+    let task_dir = task.path().to_path_buf();
     let context = RuntimeContext::new(task, &opt.execution, |task, eval| {
         // build the DAG for the task
         task.build_dag(eval, &eval_config)
             .context("Cannot build the task DAG")
     })?;
 
+    // collect the diagnostics while forwarding the messages to the UI
+    let diagnostics = Arc::new(Mutex::new(DiagnosticContext::new()));
+    let on_message = {
+        let diagnostics = diagnostics.clone();
+        move |ui: &mut dyn UI, message: UIMessage| {
+            if let UIMessage::Diagnostic { diagnostic } = &message {
+                diagnostics
+                    .lock()
+                    .unwrap()
+                    .add_diagnostic(diagnostic.clone());
+            }
+            on_message(ui, message)
+        }
+    };
+
     // start the execution
     let executor = context.connect_executor(&opt.execution, &opt.storage)?;
     let executor = executor.start_ui(&opt.ui.ui, on_message)?;
-    executor.execute()?;
+    let result = executor.execute();
+    // the files are pinned even if the evaluation failed, the completed executions are in the cache
+    let pinned = match &opt.pin {
+        Some(name) => pin_task_files(&opt, name.as_deref(), &task_dir),
+        None => Ok(()),
+    };
+    let diagnostics = diagnostics.lock().unwrap();
+    opt.diagnostics.write_diagnostics(&diagnostics)?;
+    result?;
+    pinned?;
+
+    let denied = diagnostics
+        .diagnostics()
+        .iter()
+        .filter(|diagnostic| sanity_check_levels.is_denied(diagnostic))
+        .count();
+    if denied > 0 {
+        bail!(
+            "{} diagnostics of denied sanity checks were emitted",
+            denied
+        );
+    }
 
     Ok(Evaluation::Done)
 }
 
+/// Pin the files produced by the evaluation of the task, naming the pin after the task directory if
+/// no name is provided.
+fn pin_task_files(opt: &Opt, name: Option<&str>, task_dir: &Path) -> Result<(), Error> {
+    if opt.execution.evaluate_on.is_some() {
+        warn!("--pin has no effect on remote evaluations");
+        return Ok(());
+    }
+    let store_path = opt.storage.store_dir();
+    let file_store = FileStore::new(
+        store_path.join("store"),
+        opt.storage.max_cache * 1024 * 1024,
+        opt.storage.min_cache * 1024 * 1024,
+    )
+    .context("Cannot create the file store")?
+    .with_compression(opt.storage.store_compression)
+    .with_pin_budget(opt.storage.max_pinned * 1024 * 1024);
+    let mut cache = Cache::new(store_path.join("cache")).context("Cannot create the cache")?;
+
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => default_pin_name(task_dir),
+    };
+    let files = pin_task(&file_store, &mut cache, &name, task_dir)?;
+    info!("Pinned {} files as {}", files, name);
+    Ok(())
+}
+
 /// Entry point of the local execution.
 pub fn main_local(opt: Opt) {
     run_evaluation(opt, |ui, mex| ui.on_message(mex)).nice_unwrap();
@@ -90,6 +90,28 @@
 //! </details>
 //!
 //! <details>
+//! <summary>Configuration files</summary>
+//!
+//! The default value of some options (`--ui`, `--evaluate-on`, `--num-cores`,
+//! `--skip-checks`, `--store-dir`, `--max-cache`, `--min-cache`, `--max-pinned`,
+//! `--store-compression` and `--shared-store-dir`) can be set in TOML files, named like the
+//! options:
+//!
+//! ```toml
+//! ui = "print"
+//! evaluate-on = "tcp://server:27182"
+//! skip-checks = ["StatementGit"]
+//! ```
+//!
+//! The files are `~/.config/task-maker/config.toml` for the user, the first `task-maker.toml`
+//! found in the parents of the task directory for the contest, and `task-maker.toml` inside the
+//! task directory for the task. The latter take precedence, and the options on the command line
+//! override all of them. The relative paths in a file are relative to its directory. Use
+//! `task-maker-tools config` to see the effective configuration.
+//!
+//! </details>
+//!
+//! <details>
 //! <summary>Extracting executable files</summary>
 //!
 //! All the compiled files are kept in an internal folder but if you want to use them, for example
@@ -135,6 +157,55 @@
 //! </details>
 //!
 //! <details>
+//! <summary>Inspect the cache</summary>
+//!
+//! To see how much the cache is used, and how effective it was in the last runs, run:
+//! ```bash
+//! task-maker-tools cache stats
+//! ```
+//!
+//! The entries can be listed with `task-maker-tools cache list`, filtering them with `--tag`,
+//! `--task-dir` or `--command`. The same filters select the entries to remove with
+//! `task-maker-tools cache evict`, for example `task-maker-tools cache evict --task-dir path/to/task`
+//! forgets the results of a single task, keeping the rest of the cache.
+//!
+//! When the storage grows over `--max-cache` the least recently used files are removed, so a big
+//! task may need to be evaluated again from scratch after working on other ones. To keep its files
+//! run the evaluation with `task-maker --pin` (or `--pin=name`), or pin the results already in the
+//! cache with:
+//! ```bash
+//! task-maker-tools cache pin --task-dir path/to/task
+//! ```
+//! With `--pin` the files are pinned after the evaluation, even if it fails: the results of the
+//! executions that completed are kept. The pinned files have their own size budget, `--max-pinned`.
+//! The pins are listed with `task-maker-tools cache pinned` and removed with
+//! `task-maker-tools cache unpin <name>`.
+//!
+//! A team can share the results of the expensive executions through a directory readable by
+//! everyone, for example on a network mount, passing it with `--shared-store-dir` (or setting
+//! `shared-store-dir` in the configuration). The results missing from the local cache are
+//! searched there, and copied locally when found. The results of a task, with their files, are
+//! added to the shared directory with:
+//! ```bash
+//! task-maker-tools cache publish --shared-store-dir /shared --task-dir path/to/task
+//! ```
+//!
+//! </details>
+//!
+//! <details>
+//! <summary>Verify the store</summary>
+//!
+//! If the storage directory may have been damaged, for example after a disk failure, run:
+//! ```bash
+//! task-maker-tools store fsck
+//! ```
+//!
+//! This rehashes every file of the store, removing the corrupted ones and repairing the index, and
+//! purges the cache entries referring to files that are no longer available.
+//!
+//! </details>
+//!
+//! <details>
 //! <summary>Remote evaluation</summary>
 //!
 //! On a server (a machine accessible from clients and workers) run
@@ -160,6 +231,56 @@
 //! task-maker-rust --evaluate-on server_addr
 //! ```
 //!
+//! The connections can be protected with TLS by starting the server with
+//! `--tls-cert cert.pem --tls-key key.pem` (and `--tls-client-ca ca.pem` for requiring the clients
+//! and the workers to present a certificate signed by that CA). They then connect to
+//! `tls://server_addr`, adding `?ca=ca.pem` if the certificate of the server is not trusted by the
+//! system, and `&cert=cert.pem&key=key.pem` for their own certificate. Without `--tokens`, the
+//! clients and the workers are identified by their certificate: by its common name, or by its first
+//! DNS name or email. The clients and the workers have 10 seconds for completing the TLS handshake
+//! and introducing themselves, or they are disconnected.
+//!
+//! With `--tokens tokens.toml` the server accepts only the clients and the workers with a valid
+//! token. They read it from the file passed with `--token-file`, or from the `TM_TOKEN` environment
+//! variable, so that it doesn't show up in the list of the processes:
+//! ```bash
+//! TM_TOKEN=... task-maker-rust --evaluate-on tls://server_addr
+//! ```
+//! The file lists the tokens with their owner, and it's read again at every connection, so a token
+//! is revoked by just removing it:
+//! ```toml
+//! [clients]
+//! alice = "the token of alice"
+//!
+//! [workers]
+//! lab = "the token of the workers of the lab"
+//! ```
+//! The tokens are sent as they are, so they are only allowed on connections protected by TLS or by
+//! a password: the server refuses to start with `--tokens` on a plain `tcp://` address, and the
+//! clients and the workers refuse to send a token over one.
+//!
+//! With `--audit-log audit.log` the server appends to that file a JSON line for each connection,
+//! accepted or rejected, and for each evaluation submitted, with the owner of the token or of the
+//! certificate used.
+//!
+//! The workers are shared fairly between the users: the owners of the tokens or of the
+//! certificates get the same number of workers when they all have something to run, no matter how
+//! big their evaluations are, or which `--priority` they ask for: the priority only orders the
+//! evaluations of the same user. All the clients without a token or a certificate count as a single
+//! user. With `--policy policy.toml`
+//! the server can give more workers to some users, cap the `--priority` the clients can ask for and
+//! limit how many workers each of them can use at the same time:
+//! ```toml
+//! [default]
+//! max_priority = 0
+//! max_workers = 4
+//!
+//! [users.alice]
+//! weight = 2
+//! max_workers = 8
+//! ```
+//! The share of the workers of each client is shown in the server status.
+//!
 //! </details>
 //!
 //! ### Using docker
@@ -186,13 +307,16 @@
 #![allow(clippy::too_many_arguments)]
 #![allow(clippy::type_complexity)]
 
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches};
 
+use task_maker_rust::error::NiceError;
 use task_maker_rust::main_local;
 use task_maker_rust::opt;
 
 fn main() {
-    let mut opt = opt::Opt::parse();
+    let matches = opt::Opt::command().get_matches();
+    let mut opt = opt::Opt::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    opt.load_config(&matches).nice_unwrap();
     opt.enable_log();
     main_local(opt);
 }
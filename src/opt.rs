@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
 
 use anyhow::{Context, Error};
@@ -6,8 +7,11 @@ use clap::{ArgAction, Parser};
 use itertools::Itertools;
 
 use task_maker_dag::DagPriority;
+use task_maker_diagnostics::{DiagnosticContext, SarifLog};
 use task_maker_format::terry::Seed;
-use task_maker_format::{find_task, get_sanity_check_list, TaskFormat};
+use task_maker_format::{
+    check_sanity_check_name, find_task, get_sanity_check_list, SanityCheckLevel, TaskFormat,
+};
 use task_maker_format::{EvaluationConfig, VALID_TAGS};
 
 #[derive(Parser, Debug)]
@@ -45,9 +49,21 @@ pub struct Opt {
     #[clap(short = 'W', long = "skip-checks", long_help = skip_sanity_checks_long_help())]
     pub skip_sanity_checks: Vec<String>,
 
+    #[clap(flatten, next_help_heading = Some("DIAGNOSTICS"))]
+    pub diagnostics: DiagnosticsOpt,
+
     #[clap(flatten, next_help_heading = Some("STORAGE"))]
     pub storage: StorageOpt,
 
+    /// Pin the files produced for the task, so that they are never flushed from the storage
+    ///
+    /// The pin is named after the task directory, unless a name is given. Use `task-maker-tools
+    /// cache unpin` to remove it. Only for local evaluations. If the evaluation fails, the files of
+    /// the executions that completed are pinned anyway.
+    #[clap(long = "pin", require_equals = true)]
+    #[allow(clippy::option_option)]
+    pub pin: Option<Option<String>>,
+
     #[clap(flatten, next_help_heading = Some("EXECUTION"))]
     pub execution: ExecutionOpt,
 
@@ -82,6 +98,50 @@ pub struct UIOpt {
     pub ui: task_maker_format::ui::UIType,
 }
 
+#[derive(Parser, Debug, Clone)]
+pub struct DiagnosticsOpt {
+    /// Format of the file written with --diagnostics-out, available formats are: json, sarif.
+    #[clap(long = "diagnostics-format", default_value = "json")]
+    pub diagnostics_format: DiagnosticsFormat,
+
+    /// Write all the diagnostics emitted during the evaluation to this file
+    #[clap(long = "diagnostics-out")]
+    pub diagnostics_out: Option<PathBuf>,
+
+    /// Treat the warnings of the sanity checks as errors, and fail if any is emitted
+    #[clap(long = "deny-warnings")]
+    pub deny_warnings: bool,
+
+    /// Set the level of a sanity check, overriding the task.yaml (e.g. --check-level Name=allow)
+    ///
+    /// The levels are: allow (silence the check), warn (report its diagnostics), deny (report its
+    /// diagnostics as errors and fail the evaluation). The expensive checks (e.g.
+    /// IOGeneratorDeterminism) run only if they are given a level other than allow.
+    #[clap(long = "check-level", value_parser = parse_check_level)]
+    pub check_levels: Vec<(String, SanityCheckLevel)>,
+}
+
+/// The format in which the diagnostics are written to a file.
+#[derive(Debug, Clone, Copy)]
+pub enum DiagnosticsFormat {
+    /// The serialized `DiagnosticContext`.
+    Json,
+    /// A SARIF 2.1.0 log, where the sanity checks are the rules.
+    Sarif,
+}
+
+impl std::str::FromStr for DiagnosticsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DiagnosticsFormat, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(DiagnosticsFormat::Json),
+            "sarif" => Ok(DiagnosticsFormat::Sarif),
+            _ => Err(format!("Unknown diagnostics format: {}", s)),
+        }
+    }
+}
+
 #[derive(Parser, Debug, Clone)]
 pub struct ExecutionOpt {
     /// Keep all the sandbox directories
@@ -113,6 +173,13 @@ pub struct ExecutionOpt {
     #[clap(long = "copy-logs")]
     pub copy_logs: bool,
 
+    /// Compile the C/C++ solutions, generators and validators with ASan and UBSan
+    ///
+    /// The memory limit is relaxed to account for the overhead of the sanitizers, and their
+    /// reports are shown as diagnostics.
+    #[clap(long = "sanitize")]
+    pub sanitize: bool,
+
     /// Store the DAG in DOT format inside of bin/DAG.dot
     #[clap(long = "copy-dag")]
     pub copy_dag: bool,
@@ -125,16 +192,24 @@ pub struct ExecutionOpt {
     #[clap(long = "evaluate-on")]
     pub evaluate_on: Option<String>,
 
+    /// File with the token to authenticate with the remote server. By default the token is read
+    /// from the TM_TOKEN environment variable
+    #[clap(long = "token-file")]
+    pub token_file: Option<PathBuf>,
+
     /// The name to use for the client in remote executions
     #[clap(long)]
     pub name: Option<String>,
 
-    /// Priority of the evaluations spawned by this invocation of task-maker; no effect if running
-    /// locally.
+    /// Priority of the evaluations spawned by this invocation of task-maker, with respect to the
+    /// other evaluations of the same user of the server; no effect if running locally.
     #[clap(long, default_value = "0")]
     pub priority: DagPriority,
 }
 
+/// The valid zstd levels for compressing the files of the storage.
+pub const STORE_COMPRESSION_LEVELS: RangeInclusive<i64> = 1..=19;
+
 #[derive(Parser, Debug, Clone)]
 pub struct StorageOpt {
     /// Where to store the storage files, including the cache
@@ -148,6 +223,30 @@ pub struct StorageOpt {
     /// When the storage is flushed, this is the new maximum size, in MiB.
     #[clap(long = "min-cache", default_value = "2048")]
     pub min_cache: u64,
+
+    /// Maximum total size of the pinned files of the storage, in MiB
+    ///
+    /// The pinned files are never flushed and don't count toward --max-cache.
+    #[clap(long = "max-pinned", default_value = "2048")]
+    pub max_pinned: u64,
+
+    /// Compress the new files of the storage with zstd at this level (1-19)
+    ///
+    /// The compressed files take less space but need to be decompressed when used. Without this
+    /// option the files are stored uncompressed.
+    #[clap(
+        long = "store-compression",
+        value_parser = clap::value_parser!(i32).range(STORE_COMPRESSION_LEVELS)
+    )]
+    pub store_compression: Option<i32>,
+
+    /// A shared storage directory, used read-only when something is missing from the local one
+    ///
+    /// For example a directory on a network mount, shared with the other members of the team. The
+    /// results found there are copied to the local storage. Use `task-maker-tools cache publish` to
+    /// add the local results to it.
+    #[clap(long = "shared-store-dir")]
+    pub shared_store_dir: Option<PathBuf>,
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -207,6 +306,15 @@ fn skip_sanity_checks_long_help() -> &'static str {
     &DOC
 }
 
+/// Parse a `name=level` pair of the --check-level option.
+fn parse_check_level(s: &str) -> Result<(String, SanityCheckLevel), String> {
+    let (name, level) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected name=level, found: {}", s))?;
+    check_sanity_check_name(name)?;
+    Ok((name.into(), level.parse()?))
+}
+
 /// Returns the long-help for the --no-cache option.
 fn no_cache_long_help() -> &'static str {
     lazy_static! {
@@ -227,6 +335,8 @@ impl Opt {
             no_statement: self.booklet.no_statement,
             solution_paths: self.filter.solution.clone(),
             disabled_sanity_checks: self.skip_sanity_checks.clone(),
+            sanity_check_levels: self.diagnostics.check_levels.iter().cloned().collect(),
+            deny_warnings: self.diagnostics.deny_warnings,
             seed: self.terry.seed,
             dry_run: self.execution.dry_run,
         }
@@ -255,6 +365,28 @@ impl UIOpt {
     }
 }
 
+impl DiagnosticsOpt {
+    /// Write the diagnostics to the --diagnostics-out file, if any, in the requested format.
+    pub fn write_diagnostics(&self, diagnostics: &DiagnosticContext) -> Result<(), Error> {
+        let path = match &self.diagnostics_out {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Cannot create {}", path.display()))?;
+        let writer = std::io::BufWriter::new(file);
+        match self.diagnostics_format {
+            DiagnosticsFormat::Json => serde_json::to_writer_pretty(writer, diagnostics),
+            DiagnosticsFormat::Sarif => {
+                let base_dir =
+                    std::env::current_dir().context("Cannot get the current directory")?;
+                serde_json::to_writer_pretty(writer, &SarifLog::new(diagnostics, base_dir))
+            }
+        }
+        .with_context(|| format!("Cannot write the diagnostics to {}", path.display()))
+    }
+}
+
 impl StorageOpt {
     /// Get the store directory of this configuration. If nothing is specified a cache directory is
     /// used if available, otherwise a temporary directory.
@@ -271,6 +403,16 @@ impl StorageOpt {
             }
         }
     }
+
+    /// The directory of the file store inside the shared storage directory, if any.
+    pub fn shared_file_store_dir(&self) -> Option<PathBuf> {
+        self.shared_store_dir.as_ref().map(|dir| dir.join("store"))
+    }
+
+    /// The directory of the cache inside the shared storage directory, if any.
+    pub fn shared_cache_dir(&self) -> Option<PathBuf> {
+        self.shared_store_dir.as_ref().map(|dir| dir.join("cache"))
+    }
 }
 
 impl LoggerOpt {
@@ -1,18 +1,58 @@
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use anyhow::{bail, Context, Error};
 use task_maker_exec::ductile::{
     connect_channel, connect_channel_with_enc, connect_unix_channel, ChannelReceiver, ChannelSender,
 };
+use task_maker_exec::executors::{RemoteEntityMessage, RemoteEntityMessageResponse};
+use task_maker_exec::tls::{client_config, connect_tls_channel, ClientConfig};
 use url::{ParseError, Url};
 
 use task_maker_exec::derive_key_from_password;
 
-/// Parse the server url address and try to connect to that host.
+/// Version of task-maker
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// The environment variable with the token to send to the server, if `--token-file` is not used.
+pub const TOKEN_ENV_VAR: &str = "TM_TOKEN";
+
+/// The token to send to the server: the content of `token_file` if provided, otherwise the value
+/// of the `TM_TOKEN` environment variable, if set. The tokens are never taken from the command
+/// line, where they would be visible to the other users of the machine.
+pub fn load_token(token_file: Option<&Path>) -> Result<Option<String>, Error> {
+    let token = match token_file {
+        Some(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the token from {}", path.display()))?,
+        None => std::env::var(TOKEN_ENV_VAR).unwrap_or_default(),
+    };
+    let token = token.trim();
+    if token.is_empty() {
+        if let Some(path) = token_file {
+            bail!("The token file {} is empty", path.display());
+        }
+        return Ok(None);
+    }
+    Ok(Some(token.to_string()))
+}
+
+/// Parse the server url address, try to connect to that host and introduce ourselves with the
+/// provided name.
+///
+/// The supported schemes are:
+/// - `tcp://[user:password@]host[:port]`, optionally encrypted with the password;
+/// - `tls://host[:port][?ca=ca.pem][&cert=cert.pem&key=key.pem]`, where `ca` is the
+///   certificate of the CA that signed the one of the server (by default the ones of the system are
+///   used), and `cert` and `key` are the certificate of the client, if the server requires one;
+/// - `unix://path/to/socket`.
+///
+/// The token, if any, is sent to the server for authenticating. Since it would be sent in
+/// cleartext, it's refused on `tcp://` without a password.
 pub fn connect_to_remote_server<S, R, Str: AsRef<str>>(
     server_url: Str,
     default_port: u16,
+    name: String,
+    token: Option<String>,
 ) -> Result<(ChannelSender<S>, ChannelReceiver<R>), Error> {
     let url = match Url::parse(server_url.as_ref()) {
         Ok(u) => u,
@@ -27,8 +67,9 @@ pub fn connect_to_remote_server<S, R, Str: AsRef<str>>(
         Unix(PathBuf),
     }
 
+    let mut tls = None;
     let (schema, password) = match url.scheme() {
-        "tcp" => {
+        "tcp" | "tls" => {
             let server_addr = url
                 .socket_addrs(|| Some(default_port))
                 .context("Cannot resolve server address")?;
@@ -39,28 +80,54 @@ pub fn connect_to_remote_server<S, R, Str: AsRef<str>>(
                 bail!("No path should be provided to the server address");
             }
             let password = url.password().map(String::from);
+            if url.scheme() == "tls" {
+                if password.is_some() {
+                    bail!("Passwords are not supported with tls://, use a token instead");
+                }
+                if !url.username().is_empty() {
+                    bail!(
+                        "The token cannot be in the server address, use --token-file or ${}",
+                        TOKEN_ENV_VAR
+                    );
+                }
+                let host = url.host_str().context("Missing server host")?.to_string();
+                tls = Some((host, tls_config(&url)?));
+            } else if url.query().is_some() {
+                bail!("No query should be provided to the server address");
+            }
             (Schema::Tcp(server_addr), password)
         }
         "unix" => (Schema::Unix(url.path().into()), None),
         _ => bail!(
-            "Unsupported server address scheme: {}. The supported schemes are: tcp, unix",
+            "Unsupported server address scheme: {}. The supported schemes are: tcp, tls, unix",
             url.scheme()
         ),
     };
+    if token.is_some() && url.scheme() == "tcp" && password.is_none() {
+        bail!("The token would be sent in cleartext, use tls:// or a password");
+    }
+
     let mut err = None;
-    match schema {
+    let (sender, receiver) = match schema {
         Schema::Tcp(server_addrs) => {
+            let mut channel = None;
             for server_addr in server_addrs {
                 info!("Connecting to remote server at {}", server_addr);
-                let res = match &password {
-                    Some(password) => {
+                let res = match (&tls, &password) {
+                    (Some((host, config)), _) => {
+                        connect_tls_channel(server_addr, host, config.clone())
+                    }
+                    (None, Some(password)) => {
                         let key = derive_key_from_password(password);
                         connect_channel_with_enc(server_addr, &key)
                     }
-                    None => connect_channel(server_addr),
+                    (None, None) => connect_channel(server_addr),
                 };
                 match res {
-                    Ok(x) => return Ok(x),
+                    Ok(x) => {
+                        channel = Some(x);
+                        break;
+                    }
                     Err(e) => {
                         if let Some(io_err) = e.downcast_ref::<std::io::Error>() {
                             debug!("Connection to server failed: {:?}", io_err);
@@ -74,17 +141,69 @@ pub fn connect_to_remote_server<S, R, Str: AsRef<str>>(
                     }
                 }
             }
+            match (channel, err) {
+                (Some(channel), _) => channel,
+                (None, Some(err)) => return Err(err.context("Failed to connect to the server")),
+                (None, None) => bail!("Unknown error while connecting to the remote server"),
+            }
         }
-        Schema::Unix(path) => {
-            let res = connect_unix_channel(&path).with_context(|| {
-                format!("Failed to connect to unix channel at {}", path.display())
-            })?;
-            return Ok(res);
+        Schema::Unix(path) => connect_unix_channel(&path)
+            .with_context(|| format!("Failed to connect to unix channel at {}", path.display()))?,
+    };
+
+    sender
+        .send(RemoteEntityMessage::Welcome {
+            name,
+            version: VERSION.into(),
+            token,
+        })
+        .context("Cannot send welcome to the server")?;
+    let response: RemoteEntityMessageResponse = receiver
+        .recv()
+        .context("The server didn't reply to the welcome message")?;
+    if let RemoteEntityMessageResponse::Rejected(err) = response {
+        bail!("The server rejected the connection: {}", err);
+    }
+    Ok((sender.change_type(), receiver.change_type()))
+}
+
+/// Build the TLS configuration from the query of a `tls://` url.
+fn tls_config(url: &Url) -> Result<Arc<ClientConfig>, Error> {
+    let mut ca = None;
+    let mut cert = None;
+    let mut key = None;
+    for (name, value) in url.query_pairs() {
+        let path = PathBuf::from(value.as_ref());
+        match name.as_ref() {
+            "ca" => ca = Some(path),
+            "cert" => cert = Some(path),
+            "key" => key = Some(path),
+            _ => bail!("Unknown option in the server address: {}", name),
         }
     }
+    let identity = match (&cert, &key) {
+        (Some(cert), Some(key)) => Some((cert.as_path(), key.as_path())),
+        (None, None) => None,
+        _ => bail!("Both cert and key should be provided in the server address"),
+    };
+    client_config(ca.as_deref(), identity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if let Some(err) = err {
-        return Err(err.context("Failed to connect to the server"));
+    #[test]
+    fn test_load_token_file() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("token");
+        std::fs::write(&path, "s3cr3t/+%\n").unwrap();
+        assert_eq!(
+            load_token(Some(&path)).unwrap().as_deref(),
+            Some("s3cr3t/+%")
+        );
+        std::fs::write(&path, "\n").unwrap();
+        assert!(load_token(Some(&path)).is_err());
+        assert!(load_token(Some(&tmp.path().join("missing"))).is_err());
     }
-    bail!("Unknown error while connecting to the remote server")
 }
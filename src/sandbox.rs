@@ -10,25 +10,39 @@ use tabox::result::SandboxExecutionResult;
 use tabox::{Sandbox, SandboxImplementation};
 
 use task_maker_exec::find_tools::find_tools_path;
-use task_maker_exec::{RawSandboxResult, SandboxRunner};
+use task_maker_exec::instruction_counter::InstructionCounter;
+use task_maker_exec::{RawSandboxConfiguration, RawSandboxResult, SandboxRunner};
 
 /// Actually parse the input and return the result.
-fn run_sandbox() -> Result<SandboxExecutionResult, Error> {
-    let config =
+fn run_sandbox() -> Result<(SandboxExecutionResult, Option<u64>), Error> {
+    let config: RawSandboxConfiguration =
         serde_json::from_reader(stdin()).context("Cannot read configuration from stdin")?;
-    let sandbox = SandboxImplementation::run(config).context("Failed to create sandbox")?;
+    // The counter must be opened before spawning the sandbox, so that the sandboxed process
+    // inherits it.
+    let counter = if config.instruction_limit.is_some() {
+        let counter = InstructionCounter::new(config.instruction_limit)
+            .context("Failed to setup instruction counting")?;
+        Some(counter)
+    } else {
+        None
+    };
+    let sandbox = SandboxImplementation::run(config.config).context("Failed to create sandbox")?;
     let res = sandbox.wait().context("Failed to wait sandbox")?;
-    Ok(res)
+    let instructions = match counter {
+        Some(counter) => Some(counter.read().context("Failed to count instructions")?),
+        None => None,
+    };
+    Ok((res, instructions))
 }
 
 /// Run the sandbox for an execution.
 ///
-/// It takes a `SandboxConfiguration`, JSON serialized via standard input and prints to standard
+/// It takes a `RawSandboxConfiguration`, JSON serialized via standard input and prints to standard
 /// output a `RawSandboxResult`, JSON serialized.
 pub fn main_sandbox() {
     match run_sandbox() {
-        Ok(res) => {
-            serde_json::to_writer(stdout(), &RawSandboxResult::Success(res))
+        Ok((res, instructions)) => {
+            serde_json::to_writer(stdout(), &RawSandboxResult::Success(res, instructions))
                 .expect("Failed to print result");
         }
         Err(e) => {
@@ -55,8 +69,13 @@ impl Default for ToolsSandboxRunner {
 }
 
 impl SandboxRunner for ToolsSandboxRunner {
-    fn run(&self, config: SandboxConfiguration, pid: Arc<AtomicU32>) -> RawSandboxResult {
-        match tools_sandbox_internal(&self.tools_path, config, pid) {
+    fn run(
+        &self,
+        config: SandboxConfiguration,
+        instruction_limit: Option<u64>,
+        pid: Arc<AtomicU32>,
+    ) -> RawSandboxResult {
+        match tools_sandbox_internal(&self.tools_path, config, instruction_limit, pid) {
             Ok(res) => res,
             Err(e) => RawSandboxResult::Error(e.to_string()),
         }
@@ -67,6 +86,7 @@ impl SandboxRunner for ToolsSandboxRunner {
 fn tools_sandbox_internal(
     tools_path: &Path,
     config: SandboxConfiguration,
+    instruction_limit: Option<u64>,
     pid: Arc<AtomicU32>,
 ) -> Result<RawSandboxResult, Error> {
     let mut cmd = Command::new(tools_path)
@@ -79,7 +99,11 @@ fn tools_sandbox_internal(
     pid.store(cmd.id(), Ordering::SeqCst);
     {
         let stdin = cmd.stdin.as_mut().context("Failed to open stdin")?;
-        serde_json::to_writer(stdin, &config.build()).context("Failed to write config to stdin")?;
+        let config = RawSandboxConfiguration {
+            config: config.build(),
+            instruction_limit,
+        };
+        serde_json::to_writer(stdin, &config).context("Failed to write config to stdin")?;
     }
     let output = cmd
         .wait_with_output()
@@ -61,6 +61,8 @@ pub fn main_add_solution_checks(
         no_statement: true,
         solution_paths: opt.filter.solution,
         disabled_sanity_checks: Default::default(),
+        sanity_check_levels: Default::default(),
+        deny_warnings: false,
         seed: Default::default(),
         dry_run: true,
     };
@@ -49,6 +49,8 @@ pub fn main_booklet(mut opt: BookletOpt, logger_opt: LoggerOpt) -> Result<(), Er
         no_statement: false,
         solution_paths: vec![],
         disabled_sanity_checks: vec![],
+        sanity_check_levels: Default::default(),
+        deny_warnings: false,
         seed: None,
         dry_run: opt.execution.dry_run,
     };
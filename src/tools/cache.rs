@@ -0,0 +1,388 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Error};
+use clap::Parser;
+
+use task_maker_cache::{Cache, CacheEntryInfo};
+use task_maker_store::{FileStore, FileStoreKey, ReadFileIterator};
+
+use crate::{FindTaskOpt, StorageOpt};
+
+#[derive(Parser, Debug, Clone)]
+pub struct CacheOpt {
+    #[clap(flatten, next_help_heading = Some("STORAGE"))]
+    pub storage: StorageOpt,
+
+    /// What to do with the cache
+    #[clap(subcommand)]
+    pub command: CacheCommand,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub enum CacheCommand {
+    /// Print the number of entries, their size and the hit rate of the last runs
+    Stats,
+    /// List the entries of the cache
+    List(CacheFilterOpt),
+    /// Remove the matching entries from the cache, keeping all the other ones
+    ///
+    /// The entries whose files are all pinned are kept as well.
+    Evict(CacheFilterOpt),
+    /// Pin the files produced by the evaluation of a task, so that they are never flushed
+    ///
+    /// The pinned files don't count toward --max-cache, they have their own size budget
+    /// (--max-pinned). Pinning again with the same name adds the new files to the pin.
+    Pin(CachePinOpt),
+    /// Remove a pin, letting its files be flushed normally
+    Unpin(CacheUnpinOpt),
+    /// List the files of the store that are pinned, and never flushed
+    Pinned,
+    /// Copy the matching entries, with their files, to the shared storage directory
+    ///
+    /// The shared storage directory is the one specified with --shared-store-dir, or in the
+    /// configuration files.
+    Publish(CacheFilterOpt),
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct CacheFilterOpt {
+    /// Only the entries produced by the evaluation of the task in this directory
+    #[clap(long = "task-dir")]
+    pub task_dir: Option<PathBuf>,
+
+    /// Only the entries with this tag (e.g. compilation, generation, evaluation, checking)
+    #[clap(long)]
+    pub tag: Option<String>,
+
+    /// Only the entries with a command line containing this string
+    #[clap(long)]
+    pub command: Option<String>,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct CachePinOpt {
+    #[clap(flatten, next_help_heading = Some("TASK SEARCH"))]
+    pub find_task: FindTaskOpt,
+
+    /// The name of the pin, by default the name of the task directory
+    #[clap(long)]
+    pub name: Option<String>,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct CacheUnpinOpt {
+    /// The name of the pin to remove
+    pub name: String,
+}
+
+impl CacheFilterOpt {
+    /// Whether no filter has been specified.
+    fn is_empty(&self) -> bool {
+        self.task_dir.is_none() && self.tag.is_none() && self.command.is_none()
+    }
+
+    /// Whether the entry matches all the specified filters.
+    fn matches(&self, task_dir: Option<&Path>, entry: &CacheEntryInfo) -> bool {
+        if let Some(task_dir) = task_dir {
+            let entry_dir = entry.task_dir.as_deref().map(canonicalize);
+            if entry_dir.as_deref() != Some(task_dir) {
+                return false;
+            }
+        }
+        if let Some(tag) = &self.tag {
+            if entry.tag.as_ref() != Some(tag) {
+                return false;
+            }
+        }
+        if let Some(command) = &self.command {
+            if !entry.commands.iter().any(|c| c.contains(command.as_str())) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Handler of the `cache` tool. This tool opens the store and the cache like a normal evaluation
+/// would do, waiting if another instance of task-maker is using them.
+pub fn main_cache(opt: CacheOpt) -> Result<(), Error> {
+    let store_path = opt.storage.store_dir();
+    let file_store = FileStore::new(
+        store_path.join("store"),
+        opt.storage.max_cache * 1024 * 1024,
+        opt.storage.min_cache * 1024 * 1024,
+    )
+    .context("Cannot create the file store")?
+    .with_compression(opt.storage.store_compression)
+    .with_pin_budget(opt.storage.max_pinned * 1024 * 1024);
+    let mut cache = Cache::new(store_path.join("cache")).context("Cannot create the cache")?;
+
+    match &opt.command {
+        CacheCommand::Stats => print_stats(&mut cache, &file_store)?,
+        CacheCommand::List(filter) => {
+            let task_dir = filter.task_dir.as_deref().map(canonicalize);
+            let mut entries: Vec<_> = cache
+                .entries()
+                .context("Failed to load the cache")?
+                .into_iter()
+                .filter(|entry| filter.matches(task_dir.as_deref(), entry))
+                .collect();
+            entries.sort_by(|a, b| a.created.cmp(&b.created));
+            for entry in &entries {
+                print_entry(entry, &file_store);
+            }
+            println!("{} entries", entries.len());
+        }
+        CacheCommand::Evict(filter) => {
+            if filter.is_empty() {
+                bail!(
+                    "Specify which entries to evict with --task-dir, --tag or --command \
+                     (use task-maker-tools reset to wipe everything)"
+                );
+            }
+            let task_dir = filter.task_dir.as_deref().map(canonicalize);
+            let pinned: HashSet<_> = file_store.pins().into_values().flatten().collect();
+            let mut kept = 0;
+            let removed = cache
+                .evict(|entry| {
+                    if !filter.matches(task_dir.as_deref(), entry) {
+                        return false;
+                    }
+                    if !entry.files.is_empty() && entry.files.iter().all(|f| pinned.contains(f)) {
+                        kept += 1;
+                        return false;
+                    }
+                    true
+                })
+                .context("Failed to evict the cache entries")?;
+            println!("Evicted {} entries", removed);
+            if kept > 0 {
+                println!("Kept {} matching entries whose files are pinned", kept);
+            }
+        }
+        CacheCommand::Pin(pin) => {
+            let task = pin.find_task.find_task(&Default::default())?;
+            let name = match &pin.name {
+                Some(name) => name.clone(),
+                None => default_pin_name(task.path()),
+            };
+            let files = pin_task(&file_store, &mut cache, &name, task.path())?;
+            println!("Pinned {} files as {}", files, name);
+        }
+        CacheCommand::Unpin(unpin) => {
+            if !file_store.unpin(&unpin.name) {
+                bail!("There is no pin named {}", unpin.name);
+            }
+            println!("Removed the pin {}", unpin.name);
+        }
+        CacheCommand::Pinned => {
+            let pins = file_store.pins();
+            if pins.is_empty() {
+                println!("No file is pinned");
+            } else {
+                println!(
+                    "Pinned files: {} of {}",
+                    format_size(file_store.pinned_size()),
+                    format_size(file_store.pin_budget())
+                );
+            }
+            for (name, keys) in pins {
+                let size = files_size(keys.iter(), &file_store);
+                println!("{} ({} files, {})", name, keys.len(), format_size(size));
+                for key in keys {
+                    let size = file_store.file_size(&key);
+                    let size = size.map(format_size).unwrap_or_else(|| "missing".into());
+                    println!("    {} {}", key, size);
+                }
+            }
+        }
+        CacheCommand::Publish(filter) => {
+            let shared_path = match &opt.storage.shared_store_dir {
+                Some(dir) => dir,
+                None => bail!("Specify the shared storage directory with --shared-store-dir"),
+            };
+            // the shared store is never flushed
+            let shared_store = FileStore::new(shared_path.join("store"), u64::MAX, u64::MAX)
+                .context("Cannot create the shared file store")?
+                .with_compression(opt.storage.store_compression);
+            let mut shared_cache =
+                Cache::new(shared_path.join("cache")).context("Cannot create the shared cache")?;
+
+            let task_dir = filter.task_dir.as_deref().map(canonicalize);
+            let mut files = 0;
+            let mut error = None;
+            let published = cache
+                .export(&mut shared_cache, |entry| {
+                    if error.is_some() || !filter.matches(task_dir.as_deref(), entry) {
+                        return false;
+                    }
+                    match publish_files(&entry.files, &file_store, &shared_store) {
+                        Ok(Some(copied)) => {
+                            files += copied;
+                            true
+                        }
+                        // some files are gone, the entry is useless
+                        Ok(None) => false,
+                        Err(e) => {
+                            error = Some(e);
+                            false
+                        }
+                    }
+                })
+                .context("Failed to publish the cache entries")?;
+            if let Some(e) = error {
+                return Err(e.context("Failed to publish the files"));
+            }
+            println!("Published {} entries and {} new files", published, files);
+        }
+    }
+    Ok(())
+}
+
+/// Pin with the provided name the files of the cache entries produced by the evaluation of the
+/// task in `task_dir`, returning how many files have been pinned. The files no longer in the store
+/// are skipped.
+pub fn pin_task(
+    file_store: &FileStore,
+    cache: &mut Cache,
+    name: &str,
+    task_dir: &Path,
+) -> Result<usize, Error> {
+    let task_dir = canonicalize(task_dir);
+    let files: HashSet<_> = cache
+        .entries()
+        .context("Failed to load the cache")?
+        .into_iter()
+        .filter(|entry| entry.task_dir.as_deref().map(canonicalize).as_ref() == Some(&task_dir))
+        .flat_map(|entry| entry.files)
+        .filter(|key| file_store.file_size(key).is_some())
+        .collect();
+    let count = files.len();
+    file_store
+        .pin(name, files)
+        .with_context(|| format!("Cannot pin the files of {}", task_dir.display()))?;
+    Ok(count)
+}
+
+/// The name of the pin of a task when none is specified: the name of its directory.
+pub fn default_pin_name(task_dir: &Path) -> String {
+    let task_dir = canonicalize(task_dir);
+    match task_dir.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => task_dir.to_string_lossy().into_owned(),
+    }
+}
+
+/// Copy the files to the shared store, returning how many of them were not already there, or
+/// `None` if some of them are missing from the local store.
+fn publish_files(
+    files: &[FileStoreKey],
+    file_store: &FileStore,
+    shared_store: &FileStore,
+) -> Result<Option<usize>, Error> {
+    let mut copied = 0;
+    for key in files {
+        if shared_store.file_size(key).is_some() {
+            continue;
+        }
+        let handle = match file_store.get(key) {
+            Some(handle) => handle,
+            None => return Ok(None),
+        };
+        let content = ReadFileIterator::new(handle.path())?;
+        shared_store
+            .store(key, content)
+            .with_context(|| format!("Failed to copy {} to the shared store", key))?;
+        copied += 1;
+    }
+    Ok(Some(copied))
+}
+
+/// Print the statistics about the cache and the store.
+fn print_stats(cache: &mut Cache, file_store: &FileStore) -> Result<(), Error> {
+    let entries = cache.entries().context("Failed to load the cache")?;
+    let files: HashSet<_> = entries.iter().flat_map(|e| e.files.iter()).collect();
+    println!("Cache entries: {}", entries.len());
+    println!(
+        "Files produced by the cached executions: {} ({})",
+        files.len(),
+        format_size(files_size(files.iter().copied(), file_store))
+    );
+
+    let mut by_tag: BTreeMap<&str, usize> = BTreeMap::new();
+    for entry in &entries {
+        *by_tag
+            .entry(entry.tag.as_deref().unwrap_or("(no tag)"))
+            .or_default() += 1;
+    }
+    for (tag, count) in by_tag {
+        println!("    {}: {} entries", tag, count);
+    }
+
+    let pinned: HashSet<_> = file_store.pins().into_values().flatten().collect();
+    println!(
+        "Store: {} files ({}), {} pinned ({})",
+        file_store.num_files(),
+        format_size(file_store.total_size()),
+        pinned.len(),
+        format_size(files_size(pinned.iter(), file_store))
+    );
+
+    let runs = cache.runs();
+    if runs.is_empty() {
+        println!("No run has used the cache yet");
+    } else {
+        println!("Hit rate of the last runs (most recent last):");
+        for run in runs {
+            let rate = run.hit_rate().unwrap_or_default() * 100.0;
+            println!(
+                "    {:5.1}% ({} hits, {} misses)",
+                rate, run.hits, run.misses
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Print the information about a cache entry.
+fn print_entry(entry: &CacheEntryInfo, file_store: &FileStore) {
+    let tag = entry.tag.as_deref().unwrap_or("no tag");
+    println!("[{}] {}", tag, entry.description);
+    if let Some(task_dir) = &entry.task_dir {
+        println!("    task: {}", task_dir.display());
+    }
+    for command in &entry.commands {
+        println!("    command: {}", command);
+    }
+    println!(
+        "    files: {} ({})",
+        entry.files.len(),
+        format_size(files_size(entry.files.iter(), file_store))
+    );
+}
+
+/// The total size of the files that are still in the store.
+fn files_size<'a, I>(files: I, file_store: &FileStore) -> u64
+where
+    I: IntoIterator<Item = &'a FileStoreKey>,
+{
+    files
+        .into_iter()
+        .filter_map(|key| file_store.file_size(key))
+        .sum()
+}
+
+/// Format a size in bytes in a human readable way.
+fn format_size(size: u64) -> String {
+    if size >= 1024 * 1024 {
+        format!("{:.1} MiB", size as f64 / 1024.0 / 1024.0)
+    } else {
+        format!("{:.1} KiB", size as f64 / 1024.0)
+    }
+}
+
+/// The canonical form of the path, if it exists.
+fn canonicalize(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
@@ -0,0 +1,26 @@
+use anyhow::Error;
+use clap::Parser;
+
+use crate::config::Config;
+use crate::FindTaskOpt;
+
+#[derive(Parser, Debug, Clone)]
+pub struct ConfigOpt {
+    #[clap(flatten, next_help_heading = Some("TASK SEARCH"))]
+    pub find_task: FindTaskOpt,
+}
+
+pub fn main_config(opt: ConfigOpt) -> Result<(), Error> {
+    let config = Config::load(opt.find_task.task_dir.as_deref(), opt.find_task.max_depth)?;
+    let mut sources = config.sources().peekable();
+    if sources.peek().is_none() {
+        println!("# No configuration file found");
+    }
+    for source in sources {
+        println!("# Loaded the {}", source);
+    }
+    for (key, (value, source)) in config.entries() {
+        println!("{} = {}  # from the {}", key, value, source);
+    }
+    Ok(())
+}
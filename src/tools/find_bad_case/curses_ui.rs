@@ -75,7 +75,10 @@ impl CursesUI {
             ]),
             Spans(vec![
                 Span::styled("Batch index:     ", *BOLD),
-                Span::raw(shared.batch_index.to_string()),
+                Span::raw(match shared.minimization {
+                    Some(size) => format!("{} (minimizing, {} bytes)", shared.batch_index, size),
+                    None => shared.batch_index.to_string(),
+                }),
             ]),
             Spans(vec![Span::styled("Progress:", *BLUE)]),
             Spans(vec![
@@ -3,8 +3,12 @@ use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, bail, Context, Error};
 
+use itertools::Itertools;
+
+use task_maker_format::ioi::italian_yaml::{parse_cases_gen_subtasks, CasesGenSubtask};
 use task_maker_format::ioi::{
-    InputGenerator, SubtaskInfo, TestcaseId, TestcaseInfo, GENERATION_PRIORITY,
+    IOITask, InputGenerator, SubtaskId, SubtaskInfo, TaskType, TestcaseId, TestcaseInfo,
+    GENERATION_PRIORITY,
 };
 use task_maker_format::{EvaluationData, TaskFormat};
 
@@ -15,6 +19,9 @@ pub struct TestcaseData {
     pub generator_args: Vec<String>,
     /// The seed used.
     pub seed: i32,
+    /// If set, the input file is not generated but copied from this path. In this case
+    /// `generator_args` and `seed` refer to the testcase this input file has been derived from.
+    pub static_input: Option<PathBuf>,
     /// The path of where to put this input file temporarily.
     pub input_path: PathBuf,
     /// The path of where to put the output file to check.
@@ -23,82 +30,137 @@ pub struct TestcaseData {
     pub correct_output_path: PathBuf,
 }
 
+/// Which generator and validator to use for the testcases.
+#[derive(Debug, Clone, Default)]
+pub struct GeneratorSelection {
+    /// The name of the generator of `gen/cases.gen` to use. If not specified, the generator of the
+    /// subtask is used.
+    pub generator: Option<String>,
+    /// The subtask whose generator, validator and constraints are used. If not specified, the first
+    /// subtask with a generated testcase is used.
+    pub subtask: Option<SubtaskId>,
+}
+
 /// A set of testcases that will be put in a single DAG.
 #[derive(Debug, Clone, Default)]
 pub struct Batch {
     pub testcases: HashMap<TestcaseId, TestcaseData>,
 }
 
+impl TestcaseData {
+    /// Make a new testcase whose files are stored inside `directory`.
+    pub fn new(generator_args: Vec<String>, seed: i32, directory: &Path) -> Self {
+        Self {
+            generator_args,
+            seed,
+            static_input: None,
+            input_path: directory.join("input.txt"),
+            output_path: directory.join("output.txt"),
+            correct_output_path: directory.join("correct_output.txt"),
+        }
+    }
+}
+
 /// Modify the task changing the subtasks and testcases in order to produce a DAG that runs the test
 /// testcases instead of the normal ones.
 pub fn patch_task_for_batch(
     task: &mut TaskFormat,
+    selection: &GeneratorSelection,
     generator_args: &[String],
     batch_size: usize,
     batch_index: usize,
     working_directory: &Path,
+) -> Result<Batch, Error> {
+    let testcases = (0..batch_size)
+        .map(|_| {
+            // [0, i32::MAX] is a safe range for the seeds, since it is compatible with `stoi` in c++.
+            let seed = fastrand::i32(0..i32::MAX);
+            TestcaseData::new(
+                generator_args_for_testcase(generator_args, seed),
+                seed,
+                &working_directory.join(format!("testcase-{}", seed)),
+            )
+        })
+        .collect();
+    patch_task_with_testcases(
+        task,
+        selection,
+        testcases,
+        (batch_index * batch_size) as TestcaseId,
+        format!("batch-{}", batch_index),
+    )
+}
+
+/// Modify the task replacing all the subtasks with a single one, named `name`, containing the
+/// provided testcases. The testcases are numbered starting from `first_testcase_id`.
+pub fn patch_task_with_testcases(
+    task: &mut TaskFormat,
+    selection: &GeneratorSelection,
+    testcases: Vec<TestcaseData>,
+    first_testcase_id: TestcaseId,
+    name: String,
 ) -> Result<Batch, Error> {
     let mut batch = Batch::default();
 
     match task {
         TaskFormat::IOI(task) => {
-            // A template testcase for selecting the generator and validator.
-            let testcase_template = task
-                .subtasks
-                .values()
-                .flat_map(|st| st.testcases.values())
-                .find(|tc| matches!(tc.input_generator, InputGenerator::Custom(_, _)))
-                .cloned()
-                // FIXME: in theory we can find the generator and the solution even without a testcase
-                .ok_or_else(|| anyhow!("Failed to find a base testcase"))?;
+            // A template testcase for selecting the generator, the validator and the official
+            // solution.
+            let (subtask_id, testcase_template) = template_testcase(task, selection)?;
+            let cases_gen_subtask = cases_gen_subtask(task, selection, subtask_id)?;
             // Remove all the original testcases.
             task.subtasks.clear();
             // Create a single subtask with all the testcases of this batch.
-            let mut testcases = HashMap::new();
-            for testcase_index in 0..batch_size {
-                let testcase_id = (batch_index * batch_size + testcase_index) as TestcaseId;
-
-                // [0, i32::MAX] is a safe range for the seeds, since it is compatible with `stoi` in c++.
-                let seed = fastrand::i32(0..i32::MAX);
-
-                let generator_args = generator_args_for_testcase(generator_args, seed);
-                let mut input_generator = testcase_template.input_generator.clone();
-                match &mut input_generator {
-                    InputGenerator::StaticFile(_) => {
-                        unreachable!("The generator cannot be StaticFile")
-                    }
-                    InputGenerator::Custom(_, args) => {
-                        *args = generator_args.clone();
+            let mut testcase_infos = HashMap::new();
+            for (testcase_index, data) in testcases.into_iter().enumerate() {
+                let testcase_id = first_testcase_id + testcase_index as TestcaseId;
+
+                let (input_generator, input_validator) = match &cases_gen_subtask {
+                    Some(cases_gen_subtask) => cases_gen_subtask.testcase(
+                        selection.generator.as_deref(),
+                        data.generator_args.clone(),
+                        testcase_id,
+                    )?,
+                    None => {
+                        let mut input_generator = testcase_template.input_generator.clone();
+                        match &mut input_generator {
+                            InputGenerator::StaticFile(_) | InputGenerator::MultiCustom(_, _) => {
+                                bail!("Subtask {} has no generated testcase", subtask_id)
+                            }
+                            InputGenerator::Custom(_, args) => {
+                                *args = data.generator_args.clone();
+                            }
+                        }
+                        let input_validator =
+                            task.input_validator_generator.generate(Some(subtask_id));
+                        (input_generator, input_validator)
                     }
-                }
+                };
+                let input_generator = match &data.static_input {
+                    Some(path) => InputGenerator::StaticFile(path.clone()),
+                    None => input_generator,
+                };
 
                 let testcase = TestcaseInfo::new(
                     testcase_id,
                     input_generator,
-                    task.input_validator_generator.generate(Some(0)),
+                    input_validator,
                     testcase_template.output_generator.clone(),
                 );
 
-                let data = TestcaseData {
-                    generator_args,
-                    seed,
-                    input_path: working_directory.join(format!("testcase-{}/input.txt", seed)),
-                    output_path: working_directory.join(format!("testcase-{}/output.txt", seed)),
-                    correct_output_path: working_directory
-                        .join(format!("testcase-{}/correct_output.txt", seed)),
-                };
-
-                testcases.insert(testcase_id, testcase);
+                testcase_infos.insert(testcase_id, testcase);
                 batch.testcases.insert(testcase_id, data);
             }
             let subtask = SubtaskInfo {
                 id: 0,
-                name: Some(format!("batch-{}", batch_index)),
+                name: Some(name),
                 description: None,
                 max_score: 100.0,
-                testcases,
+                testcases: testcase_infos,
                 span: None,
                 is_default: false,
+                constraint_coverage: vec![],
+                included_testcases: vec![],
             };
             task.subtasks.insert(0, subtask);
         }
@@ -109,6 +171,77 @@ pub fn patch_task_for_batch(
     Ok(batch)
 }
 
+/// Find the testcase to use as a template for the new ones, and the subtask it belongs to. A
+/// generated testcase is preferred, since it also tells which generator to use.
+fn template_testcase(
+    task: &IOITask,
+    selection: &GeneratorSelection,
+) -> Result<(SubtaskId, TestcaseInfo), Error> {
+    if let Some(subtask_id) = selection.subtask {
+        if !task.subtasks.contains_key(&subtask_id) {
+            bail!("Subtask {} does not exist", subtask_id);
+        }
+    }
+    let testcases = task
+        .subtasks
+        .values()
+        .filter(|st| selection.subtask.map_or(true, |id| st.id == id))
+        .sorted_by_key(|st| st.id)
+        .flat_map(|st| {
+            st.testcases
+                .values()
+                .sorted_by_key(|tc| tc.id)
+                .map(move |tc| (st.id, tc))
+        })
+        .collect_vec();
+    testcases
+        .iter()
+        .find(|(_, tc)| matches!(tc.input_generator, InputGenerator::Custom(_, _)))
+        .or_else(|| testcases.first())
+        .map(|(subtask_id, tc)| (*subtask_id, (*tc).clone()))
+        // FIXME: in theory we can find the generator and the solution even without a testcase
+        .ok_or_else(|| anyhow!("Failed to find a base testcase"))
+}
+
+/// The generators, the validator and the constraints of the subtask, if the task uses
+/// `gen/cases.gen`.
+fn cases_gen_subtask(
+    task: &IOITask,
+    selection: &GeneratorSelection,
+    subtask_id: SubtaskId,
+) -> Result<Option<CasesGenSubtask>, Error> {
+    let subtasks = parse_cases_gen_subtasks(&task.path).context("Failed to parse gen/cases.gen")?;
+    let subtasks = match subtasks {
+        Some(subtasks) => subtasks,
+        None if selection.generator.is_some() => {
+            bail!("Named generators can be used only in tasks with gen/cases.gen")
+        }
+        None => return Ok(None),
+    };
+    let subtask = subtasks
+        .into_iter()
+        .find(|st| st.id == subtask_id)
+        .ok_or_else(|| anyhow!("Subtask {} not found in gen/cases.gen", subtask_id))?;
+    Ok(Some(subtask))
+}
+
+/// The `gen/cases.gen` definition of the subtask whose generator, validator and constraints are
+/// used, if the task uses `gen/cases.gen`.
+pub fn selected_cases_gen_subtask(
+    task: &TaskFormat,
+    selection: &GeneratorSelection,
+) -> Result<Option<CasesGenSubtask>, Error> {
+    match task {
+        TaskFormat::IOI(task) => {
+            let (subtask_id, _) = template_testcase(task, selection)?;
+            cases_gen_subtask(task, selection, subtask_id)
+        }
+        TaskFormat::Terry(_) => {
+            bail!("Terry tasks are not currently supported")
+        }
+    }
+}
+
 /// Produce the set of arguments of the generator replacing '{}' with the seed.
 fn generator_args_for_testcase(args: &[String], seed: i32) -> Vec<String> {
     args.iter()
@@ -124,7 +257,19 @@ fn generator_args_for_testcase(args: &[String], seed: i32) -> Vec<String> {
 /// we want to save also the output produced by the solution to test. Additionally, we want to
 /// change the priorities of the executions, making generations as important as executions (so that
 /// we don't have to wait for all the generations before starting evaluating).
-pub fn patch_dag(eval: &mut EvaluationData, batch_size: usize, batch: &Batch) -> Result<(), Error> {
+pub fn patch_dag(task: &TaskFormat, eval: &mut EvaluationData, batch: &Batch) -> Result<(), Error> {
+    // The number of executions of each solution on each testcase, and whether the solutions
+    // produce an output file.
+    let (executions_per_testcase, has_output) = match task {
+        TaskFormat::IOI(task) => match &task.task_type {
+            TaskType::Batch(_) => (2, true),
+            // In communication tasks the manager runs together with the solution processes.
+            TaskType::Communication(data) => (data.num_processes as usize + 1, false),
+            TaskType::None => bail!("Invalid task type"),
+        },
+        TaskFormat::Terry(_) => bail!("Terry tasks are not currently supported"),
+    };
+    let expected = batch.testcases.len() * eval.solutions.len() * executions_per_testcase;
     let mut processed = 0;
     let get_testcase_id = |path: &Path| -> Option<TestcaseId> {
         let file_name = path.file_name().expect("Path without a file name");
@@ -188,6 +333,9 @@ pub fn patch_dag(eval: &mut EvaluationData, batch_size: usize, batch: &Batch) ->
                     // The priority of generation is GENERATION_PRIORITY - testcase id.
                     exec.priority = GENERATION_PRIORITY + 1;
                     processed += 1;
+                    if !has_output {
+                        continue;
+                    }
                     let stdout = exec.stdout.as_ref();
                     if let Some(stdout) = stdout {
                         let testcase_id = get_testcase_id(&exec.description).ok_or_else(|| {
@@ -217,10 +365,10 @@ pub fn patch_dag(eval: &mut EvaluationData, batch_size: usize, batch: &Batch) ->
         eval.dag.write_file_to_allow_fail(file_id, path, false);
     }
 
-    if processed != batch_size * 2 {
+    if processed != expected {
         bail!(
             "Failed to find the {} executions: {} found",
-            batch_size * 2,
+            expected,
             processed
         );
     }
@@ -0,0 +1,534 @@
+use std::path::Path;
+use std::sync::RwLock;
+
+use anyhow::{Context, Error};
+
+use crate::tools::find_bad_case::dag::TestcaseData;
+use crate::tools::find_bad_case::state::SharedUIState;
+
+/// Number of seeds to try for each smaller value of a generator argument.
+const SEEDS_PER_VALUE: usize = 4;
+/// Maximum number of passes over the generator arguments.
+const MAX_ARGS_ROUNDS: usize = 100;
+
+/// The result of the evaluation of a set of candidate testcases.
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    /// The solution failed on the candidate with this index, with this message.
+    Failing(usize, String),
+    /// The solution solved correctly all the valid candidates.
+    Passing,
+    /// The evaluation has been stopped by the user.
+    Interrupted,
+}
+
+/// A piece of an input file, with whether it can be removed.
+type Piece = (String, bool);
+
+/// The state of the minimization of a failing testcase.
+struct Minimizer<'a, Evaluate> {
+    /// The maximum number of candidates to evaluate at the same time.
+    batch_size: usize,
+    /// Where to store the files of the candidates.
+    working_directory: &'a Path,
+    /// The state shared with the UI.
+    shared_state: &'a RwLock<SharedUIState>,
+    /// The function that evaluates a batch of candidates.
+    evaluate_batch: Evaluate,
+    /// The function that checks whether the arguments of the generator respect the constraints.
+    valid_args: &'a dyn Fn(&[String]) -> bool,
+    /// Counter used for giving a unique directory to each candidate.
+    next_candidate: usize,
+    /// The smallest failing testcase found so far, with its failure message.
+    best: (TestcaseData, String),
+    /// The content of the input file of the best testcase.
+    best_input: String,
+}
+
+/// Shrink a failing testcase while the solution keeps failing on it.
+///
+/// First the numeric arguments of the generator are reduced, then the input file is shrunk using
+/// delta debugging, first removing lines and then removing tokens. The candidates are always
+/// checked by the validator, and a candidate is considered failing only if the solution doesn't
+/// solve it correctly. `evaluate_batch` evaluates a batch of at most `batch_size` candidates, the
+/// generator arguments rejected by `valid_args` are not tried.
+///
+/// Returns the smallest failing testcase found, or `None` if the testcase cannot be shrunk.
+pub fn minimize<Evaluate>(
+    generator_args: &[String],
+    failing: (TestcaseData, String),
+    batch_size: usize,
+    working_directory: &Path,
+    shared_state: &RwLock<SharedUIState>,
+    valid_args: &dyn Fn(&[String]) -> bool,
+    evaluate_batch: Evaluate,
+) -> Result<Option<(TestcaseData, String)>, Error>
+where
+    Evaluate: FnMut(Vec<TestcaseData>) -> Result<Outcome, Error>,
+{
+    let original_input = failing.0.input_path.clone();
+    let best_input = std::fs::read_to_string(&original_input)
+        .with_context(|| format!("Failed to read {}", original_input.display()))?;
+    let mut minimizer = Minimizer {
+        batch_size: batch_size.max(1),
+        working_directory,
+        shared_state,
+        evaluate_batch,
+        valid_args,
+        next_candidate: 0,
+        best: failing,
+        best_input,
+    };
+    minimizer.update_shared_state();
+
+    let completed = minimizer.shrink_generator_args(generator_args)?
+        && minimizer.delta_debugging(split_lines)?
+        && minimizer.delta_debugging(split_tokens)?;
+    if !completed {
+        warn!("Minimization interrupted, using the smallest failing input found so far");
+    }
+
+    if minimizer.best.0.input_path == original_input {
+        Ok(None)
+    } else {
+        Ok(Some(minimizer.best))
+    }
+}
+
+impl<'a, Evaluate> Minimizer<'a, Evaluate>
+where
+    Evaluate: FnMut(Vec<TestcaseData>) -> Result<Outcome, Error>,
+{
+    /// Try to reduce the numeric arguments of the generator that are not the seed. Returns `false`
+    /// if the minimization has been interrupted.
+    fn shrink_generator_args(&mut self, template: &[String]) -> Result<bool, Error> {
+        for _ in 0..MAX_ARGS_ROUNDS {
+            let mut improved = false;
+            for (position, template_arg) in template.iter().enumerate() {
+                if template_arg == "{}" {
+                    continue;
+                }
+                let value = match self.best.0.generator_args.get(position) {
+                    Some(arg) => match arg.parse::<i64>() {
+                        Ok(value) => value,
+                        Err(_) => continue,
+                    },
+                    None => continue,
+                };
+                let mut values = vec![value / 2, value - value / 4, value - value.signum()];
+                values.retain(|&v| v != value);
+                values.dedup();
+
+                let mut candidates = vec![];
+                for &new_value in &values {
+                    for attempt in 0..SEEDS_PER_VALUE {
+                        // Try first with the same seed, since it is the most likely to fail again.
+                        let seed = if attempt == 0 {
+                            self.best.0.seed
+                        } else {
+                            fastrand::i32(0..i32::MAX)
+                        };
+                        let mut args: Vec<_> = template
+                            .iter()
+                            .zip(self.best.0.generator_args.iter())
+                            .map(|(template_arg, arg)| match template_arg.as_str() {
+                                "{}" => seed.to_string(),
+                                _ => arg.clone(),
+                            })
+                            .collect();
+                        args[position] = new_value.to_string();
+                        if !(self.valid_args)(&args) {
+                            continue;
+                        }
+                        candidates.push(self.candidate(args, seed));
+                    }
+                }
+
+                match self.evaluate(&candidates)? {
+                    Outcome::Failing(index, message) => {
+                        let candidate = candidates[index].clone();
+                        let input =
+                            std::fs::read_to_string(&candidate.input_path).with_context(|| {
+                                format!("Failed to read {}", candidate.input_path.display())
+                            })?;
+                        // A smaller argument is an improvement only if it doesn't make the input
+                        // file bigger.
+                        if input.len() <= self.best_input.len() {
+                            self.accept(candidate, message, input);
+                            improved = true;
+                        }
+                    }
+                    Outcome::Passing => {}
+                    Outcome::Interrupted => return Ok(false),
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Shrink the input file using delta debugging, removing the pieces produced by `split`.
+    /// Returns `false` if the minimization has been interrupted.
+    fn delta_debugging(&mut self, split: fn(&str) -> Vec<Piece>) -> Result<bool, Error> {
+        let mut pieces = split(&self.best_input);
+        let mut granularity = 2;
+        loop {
+            let removable: Vec<_> = pieces
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, removable))| *removable)
+                .map(|(index, _)| index)
+                .collect();
+            if removable.is_empty() {
+                break;
+            }
+            granularity = granularity.min(removable.len());
+            // usize::div_ceil requires Rust 1.73
+            #[allow(clippy::manual_div_ceil)]
+            let chunk_size = (removable.len() + granularity - 1) / granularity;
+            let chunks: Vec<_> = removable.chunks(chunk_size).collect();
+
+            // Try removing each chunk, evaluating the complements a batch at a time since there may
+            // be a lot of them.
+            let mut failing = None;
+            for start in (0..chunks.len()).step_by(self.batch_size) {
+                let end = (start + self.batch_size).min(chunks.len());
+                let candidates = chunks[start..end]
+                    .iter()
+                    .map(|chunk| self.static_candidate(&complement(&pieces, chunk)))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                match self.evaluate(&candidates)? {
+                    Outcome::Failing(index, message) => {
+                        failing = Some((start + index, candidates[index].clone(), message));
+                        break;
+                    }
+                    Outcome::Passing => {}
+                    Outcome::Interrupted => return Ok(false),
+                }
+            }
+
+            if let Some((index, candidate, message)) = failing {
+                let chunk = chunks[index];
+                pieces = pieces
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(index, _)| !chunk.contains(index))
+                    .map(|(_, piece)| piece)
+                    .collect();
+                let input = pieces.iter().map(|(piece, _)| piece.as_str()).collect();
+                self.accept(candidate, message, input);
+                granularity = (granularity - 1).max(2);
+            } else {
+                if granularity >= removable.len() {
+                    break;
+                }
+                granularity = (granularity * 2).min(removable.len());
+            }
+        }
+        Ok(true)
+    }
+
+    /// Evaluate the candidates, in batches of at most `batch_size`, stopping at the first failing
+    /// one. The files of the other candidates are removed.
+    fn evaluate(&mut self, candidates: &[TestcaseData]) -> Result<Outcome, Error> {
+        let mut outcome = Outcome::Passing;
+        for (chunk_index, chunk) in candidates.chunks(self.batch_size).enumerate() {
+            outcome = match (self.evaluate_batch)(chunk.to_vec())? {
+                Outcome::Failing(index, message) => {
+                    Outcome::Failing(chunk_index * self.batch_size + index, message)
+                }
+                outcome => outcome,
+            };
+            if !matches!(outcome, Outcome::Passing) {
+                break;
+            }
+        }
+        for (index, candidate) in candidates.iter().enumerate() {
+            if matches!(outcome, Outcome::Failing(failing, _) if failing == index) {
+                continue;
+            }
+            if let Some(directory) = candidate.input_path.parent() {
+                let _ = std::fs::remove_dir_all(directory);
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// Make a new candidate generated with the provided arguments.
+    fn candidate(&mut self, generator_args: Vec<String>, seed: i32) -> TestcaseData {
+        let directory = self
+            .working_directory
+            .join(format!("candidate-{}", self.next_candidate));
+        self.next_candidate += 1;
+        TestcaseData::new(generator_args, seed, &directory)
+    }
+
+    /// Make a new candidate whose input file has the provided content.
+    fn static_candidate(&mut self, content: &str) -> Result<TestcaseData, Error> {
+        let mut candidate = self.candidate(self.best.0.generator_args.clone(), self.best.0.seed);
+        let directory = candidate
+            .input_path
+            .parent()
+            .expect("Invalid candidate path");
+        std::fs::create_dir_all(directory)
+            .with_context(|| format!("Failed to create {}", directory.display()))?;
+        let path = directory.join("candidate.txt");
+        std::fs::write(&path, content)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        candidate.static_input = Some(path);
+        Ok(candidate)
+    }
+
+    /// Use the candidate as the new smallest failing testcase.
+    fn accept(&mut self, testcase: TestcaseData, message: String, input: String) {
+        self.best = (testcase, message);
+        self.best_input = input;
+        self.update_shared_state();
+    }
+
+    /// Tell the UI the size of the smallest failing input found so far.
+    fn update_shared_state(&self) {
+        self.shared_state.write().unwrap().minimization = Some(self.best_input.len());
+    }
+}
+
+/// The content of the input file made by the pieces, excluding the ones with the provided indexes.
+fn complement(pieces: &[Piece], excluded: &[usize]) -> String {
+    pieces
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !excluded.contains(index))
+        .map(|(_, (piece, _))| piece.as_str())
+        .collect()
+}
+
+/// Split the input file in lines, all of them can be removed.
+fn split_lines(input: &str) -> Vec<Piece> {
+    input
+        .split_inclusive('\n')
+        .map(|line| (line.to_string(), true))
+        .collect()
+}
+
+/// Split the input file in tokens, each followed by the spaces after it. The line breaks cannot be
+/// removed, so that the structure of the lines is preserved.
+fn split_tokens(input: &str) -> Vec<Piece> {
+    let mut pieces: Vec<Piece> = vec![];
+    for c in input.chars() {
+        let line_break = c == '\n' || c == '\r';
+        match pieces.last_mut() {
+            // Extend the last piece if it's of the same kind, starting a new token after spaces.
+            Some((piece, removable))
+                if *removable != line_break
+                    && (line_break
+                        || c.is_whitespace()
+                        || !piece.ends_with(char::is_whitespace)) =>
+            {
+                piece.push(c)
+            }
+            _ => pieces.push((c.to_string(), !line_break)),
+        }
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// The content of the input file of a candidate, generated by `generate` if not static.
+    fn read_input(candidate: &TestcaseData) -> String {
+        let path = candidate
+            .static_input
+            .as_ref()
+            .unwrap_or(&candidate.input_path);
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    /// Write the input file of a candidate, as the generator would.
+    fn generate(candidate: &TestcaseData, content: &str) {
+        std::fs::create_dir_all(candidate.input_path.parent().unwrap()).unwrap();
+        std::fs::write(&candidate.input_path, content).unwrap();
+    }
+
+    /// Minimize a static input file that fails when `is_failing` returns true, returning the
+    /// minimized input and the number of evaluated batches.
+    fn minimize_input(
+        input: &str,
+        batch_size: usize,
+        is_failing: impl Fn(&str) -> bool,
+    ) -> (Option<String>, usize) {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let failing = TestcaseData::new(vec!["42".into()], 42, &tmpdir.path().join("failing"));
+        generate(&failing, input);
+        let shared_state = RwLock::new(SharedUIState::default());
+        let mut batches = 0;
+        let result = minimize(
+            &["{}".to_string()],
+            (failing, "failed".into()),
+            batch_size,
+            tmpdir.path(),
+            &shared_state,
+            &|_| true,
+            |candidates| {
+                batches += 1;
+                assert!(candidates.len() <= batch_size.max(1));
+                Ok(candidates
+                    .iter()
+                    .position(|candidate| is_failing(&read_input(candidate)))
+                    .map(|index| Outcome::Failing(index, "failed".into()))
+                    .unwrap_or(Outcome::Passing))
+            },
+        )
+        .unwrap();
+        let minimized = result.map(|(testcase, message)| {
+            assert_eq!(message, "failed");
+            read_input(&testcase)
+        });
+        if let Some(minimized) = &minimized {
+            let size = shared_state.read().unwrap().minimization;
+            assert_eq!(size, Some(minimized.len()));
+        }
+        (minimized, batches)
+    }
+
+    #[test]
+    fn test_split_lines() {
+        let pieces = split_lines("1 2\n3\n\n4");
+        let expected = [("1 2\n", true), ("3\n", true), ("\n", true), ("4", true)];
+        let expected: Vec<_> = expected.iter().map(|(s, r)| (s.to_string(), *r)).collect();
+        assert_eq!(pieces, expected);
+    }
+
+    #[test]
+    fn test_split_tokens() {
+        let pieces = split_tokens("1  2\r\n 3\n");
+        let expected = [
+            ("1  ", true),
+            ("2", true),
+            ("\r\n", false),
+            (" ", true),
+            ("3", true),
+            ("\n", false),
+        ];
+        let expected: Vec<_> = expected.iter().map(|(s, r)| (s.to_string(), *r)).collect();
+        assert_eq!(pieces, expected);
+        let joined: String = pieces.iter().map(|(piece, _)| piece.as_str()).collect();
+        assert_eq!(joined, "1  2\r\n 3\n");
+    }
+
+    #[test]
+    fn test_complement() {
+        let pieces = split_lines("a\nb\nc\n");
+        assert_eq!(complement(&pieces, &[]), "a\nb\nc\n");
+        assert_eq!(complement(&pieces, &[0, 2]), "b\n");
+        assert_eq!(complement(&pieces, &[0, 1, 2]), "");
+    }
+
+    #[test]
+    fn test_minimize_single_line() {
+        let input = "1\n2\n3 bad 4\n5\n";
+        for batch_size in [1, 2, 100] {
+            let (minimized, _) = minimize_input(input, batch_size, |s| s.contains("bad"));
+            assert_eq!(minimized.as_deref(), Some("bad \n"), "batch {}", batch_size);
+        }
+    }
+
+    #[test]
+    fn test_minimize_needs_two_lines() {
+        let input = "x\na\nx\nx\nb\nx\n";
+        let is_failing = |s: &str| s.contains("a\n") && s.contains("b\n");
+        let (minimized, _) = minimize_input(input, 4, is_failing);
+        assert_eq!(minimized.as_deref(), Some("a\nb\n"));
+    }
+
+    #[test]
+    fn test_minimize_keeps_line_structure() {
+        // the line breaks cannot be removed, even if the solution fails without them
+        let (minimized, _) = minimize_input("1 2\n3 4\n", 4, |s| s.contains('2'));
+        assert_eq!(minimized.as_deref(), Some("2\n"));
+    }
+
+    #[test]
+    fn test_minimize_already_minimal() {
+        let (minimized, batches) = minimize_input("bad", 4, |s| s.contains("bad"));
+        assert_eq!(minimized, None);
+        assert_eq!(batches, 2);
+    }
+
+    #[test]
+    fn test_minimize_interrupted() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let failing = TestcaseData::new(vec!["42".into()], 42, &tmpdir.path().join("failing"));
+        generate(&failing, "1\n2\n3\n4\n");
+        let shared_state = RwLock::new(SharedUIState::default());
+        let mut calls = 0;
+        let result = minimize(
+            &["{}".to_string()],
+            (failing, "failed".into()),
+            1,
+            tmpdir.path(),
+            &shared_state,
+            &|_| true,
+            |candidates| {
+                calls += 1;
+                if calls == 1 {
+                    Ok(Outcome::Failing(0, "smaller".into()))
+                } else {
+                    assert_eq!(candidates.len(), 1);
+                    Ok(Outcome::Interrupted)
+                }
+            },
+        )
+        .unwrap();
+        let (testcase, message) = result.unwrap();
+        assert_eq!(message, "smaller");
+        assert_eq!(read_input(&testcase), "3\n4\n");
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn test_minimize_generator_args() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let failing =
+            TestcaseData::new(vec!["100".into(), "7".into()], 7, &tmpdir.path().join("f"));
+        generate(&failing, &"x".repeat(100));
+        let shared_state = RwLock::new(SharedUIState::default());
+        let valid_args = |args: &[String]| args[0].parse::<i64>().unwrap() >= 1;
+        let result = minimize(
+            &["100".to_string(), "{}".to_string()],
+            (failing, "failed".into()),
+            3,
+            tmpdir.path(),
+            &shared_state,
+            &valid_args,
+            |candidates| {
+                let mut outcome = Outcome::Passing;
+                for (index, candidate) in candidates.iter().enumerate() {
+                    let size = match &candidate.static_input {
+                        Some(path) => std::fs::read_to_string(path).unwrap().len(),
+                        None => {
+                            let size: usize = candidate.generator_args[0].parse().unwrap();
+                            assert_eq!(candidate.generator_args[1], candidate.seed.to_string());
+                            generate(candidate, &"x".repeat(size));
+                            size
+                        }
+                    };
+                    if size >= 3 && matches!(outcome, Outcome::Passing) {
+                        outcome = Outcome::Failing(index, "failed".into());
+                    }
+                }
+                Ok(outcome)
+            },
+        )
+        .unwrap();
+        let (testcase, _) = result.unwrap();
+        assert_eq!(testcase.generator_args[0], "3");
+        assert_eq!(read_input(&testcase), "xxx");
+        assert_eq!(testcase.static_input, None::<PathBuf>);
+    }
+}
@@ -1,5 +1,6 @@
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, RwLock};
 
 use anyhow::{anyhow, bail, Context, Error};
 use clap::{Parser, ValueHint};
@@ -7,17 +8,24 @@ use clap::{Parser, ValueHint};
 use task_maker_exec::ductile::ChannelSender;
 use task_maker_exec::proto::ExecutorClientMessage;
 use task_maker_exec::ExecutorClient;
+use task_maker_format::ioi::italian_yaml::find_official_solution;
+use task_maker_format::ioi::{SubtaskId, TaskType, TestcaseId};
 use task_maker_format::ui::{CursesUI, StdoutPrinter, UIMessage, BLUE, BOLD, RED, UI, YELLOW};
-use task_maker_format::{cwrite, cwriteln, get_sanity_check_list, EvaluationConfig};
+use task_maker_format::{cwrite, cwriteln, get_sanity_check_list, EvaluationConfig, TaskFormat};
 
 use crate::context::RuntimeContext;
-use crate::tools::find_bad_case::dag::{patch_dag, patch_task_for_batch, TestcaseData};
+use crate::tools::find_bad_case::dag::{
+    patch_dag, patch_task_for_batch, patch_task_with_testcases, selected_cases_gen_subtask, Batch,
+    GeneratorSelection, TestcaseData,
+};
+use crate::tools::find_bad_case::minimize::{minimize, Outcome};
 use crate::tools::find_bad_case::state::{SharedUIState, UIState};
 use crate::{ExecutionOpt, FindTaskOpt, StorageOpt};
 
 mod curses_ui;
 mod dag;
 mod finish_ui;
+mod minimize;
 mod state;
 
 #[derive(Parser, Debug, Clone)]
@@ -38,6 +46,25 @@ pub struct FindBadCaseOpt {
     #[clap(long, short, default_value = "100")]
     pub batch_size: usize,
 
+    /// After finding a bad case, try to shrink it while the solution still fails.
+    ///
+    /// The numeric arguments of the generator are reduced first, then lines and tokens are removed
+    /// from the input file. All the candidates are checked with the validator of the task.
+    #[clap(long)]
+    pub minimize: bool,
+
+    /// Name of the generator of gen/cases.gen to use.
+    ///
+    /// Defaults to the generator of the selected subtask.
+    #[clap(long)]
+    pub generator: Option<String>,
+
+    /// The subtask whose generator, validator and constraints are used.
+    ///
+    /// Defaults to the first subtask with a generated testcase.
+    #[clap(long)]
+    pub subtask: Option<SubtaskId>,
+
     /// Path to the solution to check against the official solution of the task.
     #[clap(value_hint = ValueHint::FilePath)]
     pub solution: PathBuf,
@@ -52,7 +79,7 @@ pub fn main_find_bad_case(opt: FindBadCaseOpt) -> Result<(), Error> {
         bail!("Cannot find solution at {}", opt.solution.display());
     }
 
-    let eval_config = EvaluationConfig {
+    let mut eval_config = EvaluationConfig {
         solution_filter: vec![],
         booklet_solutions: false,
         no_statement: true,
@@ -61,6 +88,8 @@ pub fn main_find_bad_case(opt: FindBadCaseOpt) -> Result<(), Error> {
             .into_iter()
             .map(|check| check.0.into())
             .collect(),
+        sanity_check_levels: Default::default(),
+        deny_warnings: false,
         seed: None,
         dry_run: false,
     };
@@ -82,8 +111,37 @@ pub fn main_find_bad_case(opt: FindBadCaseOpt) -> Result<(), Error> {
     let task = opt.find_task.find_task(&eval_config)?;
     let task_path = task.path().to_path_buf();
 
+    // Check the generator and its arguments before starting the UI.
+    let selection = GeneratorSelection {
+        generator: opt.generator.clone(),
+        subtask: opt.subtask,
+    };
+    let cases_gen_subtask = selected_cases_gen_subtask(&task, &selection)?;
+    patch_task_for_batch(
+        &mut task.clone(),
+        &selection,
+        &opt.generator_args,
+        1,
+        0,
+        working_directory.path(),
+    )
+    .context("Invalid generator arguments")?;
+
+    // Communication tasks don't have official output files, so the official solution is evaluated
+    // too, and its score is compared with the one of the solution.
+    let official_solution = match &task {
+        TaskFormat::IOI(task) if matches!(task.task_type, TaskType::Communication(_)) => {
+            let official_solution = find_official_solution(&task_path).ok_or_else(|| {
+                anyhow!("The official solution is required for communication tasks")
+            })?;
+            eval_config.solution_paths.push(official_solution.clone());
+            Some(official_solution)
+        }
+        _ => None,
+    };
+
     // Create a single UI for all the batches.
-    let ui_state = UIState::new(&opt, stop_evaluation);
+    let ui_state = UIState::new(&opt, official_solution, stop_evaluation);
     let shared_state = ui_state.shared.clone();
     let mut ui = CursesUI::<UIState, curses_ui::CursesUI, finish_ui::FinishUI>::new(ui_state)
         .context("Failed to start Curses UI")?;
@@ -105,7 +163,11 @@ pub fn main_find_bad_case(opt: FindBadCaseOpt) -> Result<(), Error> {
         let shared_state = shared_state.clone();
         let current_executor_sender = current_executor_sender.clone();
         move || {
-            shared_state.write().unwrap().should_stop = true;
+            {
+                let mut shared_state = shared_state.write().unwrap();
+                shared_state.should_stop = true;
+                shared_state.interrupted = true;
+            }
             let current_executor_sender = current_executor_sender.lock().unwrap();
             if let Some(sender) = current_executor_sender.as_ref() {
                 if sender.send(ExecutorClientMessage::Stop).is_err() {
@@ -120,6 +182,7 @@ pub fn main_find_bad_case(opt: FindBadCaseOpt) -> Result<(), Error> {
         let mut task = opt.find_task.find_task(&eval_config)?;
         let batch = patch_task_for_batch(
             &mut task,
+            &selection,
             &opt.generator_args,
             opt.batch_size,
             batch_index,
@@ -132,77 +195,87 @@ pub fn main_find_bad_case(opt: FindBadCaseOpt) -> Result<(), Error> {
             shared_state.batch_index = batch_index;
         }
 
-        // Setup the configuration and the evaluation metadata.
-        let context = RuntimeContext::new(task, &opt.execution, |task, eval| {
-            task.build_dag(eval, &eval_config)
-                .context("Cannot build the task DAG")?;
-            patch_dag(eval, opt.batch_size, &batch).context("Cannot patch the DAG")
-        })?;
-
-        let mut executor = context.connect_executor(&opt.execution, &opt.storage)?;
-
-        let ui_receiver = executor.ui_receiver;
-        let ui_thread = std::thread::Builder::new()
-            .name("UI".to_owned())
-            .spawn({
-                let sender = sender.clone();
-                move || {
-                    while let Ok(message) = ui_receiver.recv() {
-                        if let UIMessage::StopUI = message {
-                            break;
-                        }
-                        let _ = sender.send(Some(message));
-                    }
-                }
-            })
-            .context("Failed to spawn UI thread")?;
-
-        let mut dag = executor.eval.dag.clone();
-        std::mem::swap(&mut dag, &mut executor.eval.dag);
-
-        // Run the actual computation and block until it ends.
-        let sender = sender.clone();
-        *current_executor_sender.lock().unwrap() = Some(executor.tx.clone());
-        ExecutorClient::evaluate(
-            dag,
-            executor.tx,
-            &executor.rx,
-            executor.file_store,
-            move |status| {
-                sender
-                    .send(Some(UIMessage::ServerStatus { status }))
-                    .map_err(|e| anyhow!("{:?}", e))
-            },
-        )
-        .with_context(|| {
-            shared_state.write().unwrap().should_stop = true;
-            "Client failed"
-        })?;
-
-        // Disable the ctrl-c handler dropping the owned clone of the sender, letting the client exit.
-        current_executor_sender.lock().unwrap().take();
-
-        drop(executor.eval);
-        drop(executor.task);
-        drop(executor.rx);
-
-        if let Some(local_executor) = executor.local_executor {
-            local_executor
-                .join()
-                .map_err(|e| anyhow!("Executor panicked: {:?}", e))
-                .unwrap()
-                .expect("Local executor failed");
-        }
-        ui_thread
-            .join()
-            .map_err(|e| anyhow!("UI panicked: {:?}", e))
-            .unwrap();
+        evaluate_batch(
+            &opt,
+            &eval_config,
+            task,
+            &batch,
+            &current_executor_sender,
+            &sender,
+            &shared_state,
+        )?;
 
         if shared_state.read().unwrap().should_stop {
             break;
         }
     }
 
+    // Shrink the bad case, using the same UI for all the candidates.
+    let found = shared_state.read().unwrap().failing_testcase.clone();
+    let mut minimized = None;
+    if opt.minimize && !shared_state.read().unwrap().interrupted {
+        if let Some(failing) = found.clone() {
+            let mut next_testcase_id = {
+                let shared_state = shared_state.read().unwrap();
+                ((shared_state.batch_index + 1) * opt.batch_size) as TestcaseId
+            };
+            minimized = minimize(
+                &opt.generator_args,
+                failing,
+                opt.batch_size,
+                working_directory.path(),
+                &shared_state,
+                &|args| {
+                    cases_gen_subtask.as_ref().map_or(true, |subtask| {
+                        subtask
+                            .check_constraints(selection.generator.as_deref(), args)
+                            .is_ok()
+                    })
+                },
+                |candidates| {
+                    let mut task = opt.find_task.find_task(&eval_config)?;
+                    let first_testcase_id = next_testcase_id;
+                    next_testcase_id += candidates.len() as TestcaseId;
+                    let batch = patch_task_with_testcases(
+                        &mut task,
+                        &selection,
+                        candidates.clone(),
+                        first_testcase_id,
+                        format!("candidates-{}", first_testcase_id),
+                    )?;
+                    {
+                        let mut shared_state = shared_state.write().unwrap();
+                        shared_state.last_batch = Some(batch.clone());
+                        shared_state.batch_index += 1;
+                        shared_state.failing_testcase = None;
+                        shared_state.should_stop = false;
+                    }
+                    evaluate_batch(
+                        &opt,
+                        &eval_config,
+                        task,
+                        &batch,
+                        &current_executor_sender,
+                        &sender,
+                        &shared_state,
+                    )?;
+                    let shared_state = shared_state.read().unwrap();
+                    if shared_state.interrupted {
+                        return Ok(Outcome::Interrupted);
+                    }
+                    Ok(match &shared_state.failing_testcase {
+                        Some((testcase, message)) => candidates
+                            .iter()
+                            .position(|tc| tc.input_path == testcase.input_path)
+                            .map(|index| Outcome::Failing(index, message.clone()))
+                            .unwrap_or(Outcome::Passing),
+                        None => Outcome::Passing,
+                    })
+                },
+            )?;
+        }
+    }
+
     let _ = sender.send(None);
     global_ui_join_handle
         .join()
@@ -212,7 +285,7 @@ pub fn main_find_bad_case(opt: FindBadCaseOpt) -> Result<(), Error> {
     let mut printer = StdoutPrinter::default();
 
     let shared_state = shared_state.read().unwrap();
-    let (testcase, message) = match shared_state.failing_testcase.clone() {
+    let (testcase, message) = match found {
         Some(testcase) => testcase,
         None => {
             cwriteln!(printer, YELLOW, "No bad case found");
@@ -220,8 +293,9 @@ pub fn main_find_bad_case(opt: FindBadCaseOpt) -> Result<(), Error> {
             return Ok(());
         }
     };
+    let target_dir = task_path.join(format!("fuzz/bad-cases/seed-{}", testcase.seed));
     let (input_path, correct_output_path, failing_output_path) =
-        copy_testcase(&testcase, &task_path)?;
+        copy_testcase(&testcase, &target_dir, "")?;
 
     cwrite!(printer, BOLD, "Solution:           ");
     println!("{}", opt.solution.display());
@@ -254,21 +328,139 @@ pub fn main_find_bad_case(opt: FindBadCaseOpt) -> Result<(), Error> {
         )?;
     }
 
+    if let Some((minimized, message)) = minimized {
+        let (input_path, correct_output_path, failing_output_path) =
+            copy_testcase(&minimized, &target_dir, "minimized-")?;
+        cwriteln!(printer, BOLD, "Minimized testcase:");
+        if minimized.static_input.is_none() {
+            cwrite!(printer, BOLD, "    Generator args: ");
+            println!("{}", minimized.generator_args.join(" "));
+        }
+        cwrite!(printer, BOLD, "    Message:        ");
+        println!("{}", message);
+        println!();
+        print_file(
+            "Minimized input file",
+            &task_path,
+            &input_path,
+            &mut printer,
+        )?;
+        if let Some(correct_output_path) = correct_output_path {
+            print_file(
+                "Minimized correct output file",
+                &task_path,
+                &correct_output_path,
+                &mut printer,
+            )?;
+        }
+        if let Some(failing_output_path) = failing_output_path {
+            print_file(
+                "Minimized failing output file",
+                &task_path,
+                &failing_output_path,
+                &mut printer,
+            )?;
+        }
+    } else if opt.minimize {
+        cwriteln!(printer, YELLOW, "The bad case cannot be minimized");
+        println!();
+    }
+
     print_failures(&shared_state, &mut printer);
     Ok(())
 }
 
+/// Evaluate a batch of testcases, forwarding the UI messages to the global UI. This blocks until
+/// the evaluation ends or it is stopped.
+fn evaluate_batch(
+    opt: &FindBadCaseOpt,
+    eval_config: &EvaluationConfig,
+    task: TaskFormat,
+    batch: &Batch,
+    current_executor_sender: &Mutex<Option<ChannelSender<ExecutorClientMessage>>>,
+    sender: &Sender<Option<UIMessage>>,
+    shared_state: &RwLock<SharedUIState>,
+) -> Result<(), Error> {
+    // Setup the configuration and the evaluation metadata.
+    let context = RuntimeContext::new(task, &opt.execution, |task, eval| {
+        task.build_dag(eval, eval_config)
+            .context("Cannot build the task DAG")?;
+        patch_dag(task, eval, batch).context("Cannot patch the DAG")
+    })?;
+
+    let mut executor = context.connect_executor(&opt.execution, &opt.storage)?;
+
+    let ui_receiver = executor.ui_receiver;
+    let ui_thread = std::thread::Builder::new()
+        .name("UI".to_owned())
+        .spawn({
+            let sender = sender.clone();
+            move || {
+                while let Ok(message) = ui_receiver.recv() {
+                    if let UIMessage::StopUI = message {
+                        break;
+                    }
+                    let _ = sender.send(Some(message));
+                }
+            }
+        })
+        .context("Failed to spawn UI thread")?;
+
+    let mut dag = executor.eval.dag.clone();
+    std::mem::swap(&mut dag, &mut executor.eval.dag);
+
+    // Run the actual computation and block until it ends.
+    let sender = sender.clone();
+    *current_executor_sender.lock().unwrap() = Some(executor.tx.clone());
+    ExecutorClient::evaluate(
+        dag,
+        executor.tx,
+        &executor.rx,
+        executor.file_store,
+        executor.local_executor.is_none(),
+        move |status| {
+            sender
+                .send(Some(UIMessage::ServerStatus { status }))
+                .map_err(|e| anyhow!("{:?}", e))
+        },
+    )
+    .with_context(|| {
+        shared_state.write().unwrap().should_stop = true;
+        "Client failed"
+    })?;
+
+    // Disable the ctrl-c handler dropping the owned clone of the sender, letting the client exit.
+    current_executor_sender.lock().unwrap().take();
+
+    drop(executor.eval);
+    drop(executor.task);
+    drop(executor.rx);
+
+    if let Some(local_executor) = executor.local_executor {
+        local_executor
+            .join()
+            .map_err(|e| anyhow!("Executor panicked: {:?}", e))
+            .unwrap()
+            .expect("Local executor failed");
+    }
+    ui_thread
+        .join()
+        .map_err(|e| anyhow!("UI panicked: {:?}", e))
+        .unwrap();
+    Ok(())
+}
+
 fn copy_testcase(
     testcase: &TestcaseData,
-    task_path: &Path,
+    target_dir: &Path,
+    prefix: &str,
 ) -> Result<(PathBuf, Option<PathBuf>, Option<PathBuf>), Error> {
-    let target_dir = task_path.join(format!("fuzz/bad-cases/seed-{}", testcase.seed));
-    std::fs::create_dir_all(&target_dir)
+    std::fs::create_dir_all(target_dir)
         .with_context(|| format!("Failed to create {}", target_dir.display()))?;
 
-    let input_target = target_dir.join("input.txt");
-    let correct_output_target = target_dir.join("correct-output.txt");
-    let failing_output_target = target_dir.join("failing-output.txt");
+    let input_target = target_dir.join(format!("{}input.txt", prefix));
+    let correct_output_target = target_dir.join(format!("{}correct-output.txt", prefix));
+    let failing_output_target = target_dir.join(format!("{}failing-output.txt", prefix));
 
     std::fs::copy(&testcase.input_path, &input_target).with_context(|| {
         format!(
@@ -304,7 +496,10 @@ fn copy_testcase(
         })?;
         Some(failing_output_target)
     } else {
-        warn!("Output file not found, maybe the solution was killed");
+        // Communication tasks have neither the correct output nor the output of the solution.
+        if correct_output_target.is_some() {
+            warn!("Output file not found, maybe the solution was killed");
+        }
         None
     };
 
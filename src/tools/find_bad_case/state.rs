@@ -1,5 +1,6 @@
+use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 use std::time::SystemTime;
 use task_maker_dag::ExecutionResult;
@@ -21,6 +22,15 @@ pub struct UIState {
 
     /// The path to the solution to evaluate.
     pub solution: PathBuf,
+    /// The path to the official solution, if it's evaluated together with the solution. This
+    /// happens in communication tasks, where there are no official output files and the scores of
+    /// the two solutions are compared.
+    pub official_solution: Option<PathBuf>,
+    /// The scores of the official solution on the testcases of the current batch.
+    pub official_scores: HashMap<TestcaseId, f64>,
+    /// The scores and messages of the solution on the testcases of the current batch that are
+    /// waiting for the score of the official solution.
+    pub pending_scores: HashMap<TestcaseId, (f64, String)>,
     /// The template arguments passed to the generator.
     pub generator_args: Vec<String>,
     /// The size of the batch.
@@ -105,6 +115,10 @@ pub struct SharedUIState {
     pub batch_index: usize,
     /// Whether the UI and the execution should stop and no further batch should be tried.
     pub should_stop: bool,
+    /// Whether the user asked to stop the execution.
+    pub interrupted: bool,
+    /// While minimizing a failing testcase, the size in bytes of the smallest failing input found.
+    pub minimization: Option<usize>,
     /// The last batch being evaluated.
     pub last_batch: Option<Batch>,
     /// A testcase that made the solution fail, together with a failing message.
@@ -114,10 +128,17 @@ pub struct SharedUIState {
 }
 
 impl UIState {
-    pub fn new(opt: &FindBadCaseOpt, stop_evaluation: impl Fn() + Send + Sync + 'static) -> Self {
+    pub fn new(
+        opt: &FindBadCaseOpt,
+        official_solution: Option<PathBuf>,
+        stop_evaluation: impl Fn() + Send + Sync + 'static,
+    ) -> Self {
         Self {
             stop_evaluation: StopEvaluation::new(stop_evaluation),
             solution: opt.solution.clone(),
+            official_solution,
+            official_scores: HashMap::new(),
+            pending_scores: HashMap::new(),
             generator_args: opt.generator_args.clone(),
             batch_size: opt.batch_size,
             executor_status: None,
@@ -126,32 +147,66 @@ impl UIState {
             shared: Arc::new(RwLock::new(SharedUIState::default())),
         }
     }
+
+    /// Set the status of a testcase of the current batch.
+    fn set(&mut self, testcase: TestcaseId, state: TestcaseStatus) {
+        let testcase = &mut self.batches.last_mut().unwrap().testcase_status
+            [testcase as usize % self.batch_size];
+        *testcase = state;
+    }
+
+    /// Whether the message is about the official solution, and not about the one to check.
+    fn is_official(&self, solution: &Path) -> bool {
+        self.official_solution.as_deref() == Some(solution)
+    }
+
+    /// Process the score of the solution on a testcase, stopping the evaluation if it fails.
+    fn testcase_scored(&mut self, testcase: TestcaseId, score: f64, message: String) {
+        let (target, message) = match self.official_scores.get(&testcase) {
+            Some(&official) => (
+                official,
+                format!("{} (the official solution scored {})", message, official),
+            ),
+            None => (1.0, message),
+        };
+        if score >= target {
+            self.set(testcase, TestcaseStatus::Success);
+        } else {
+            self.set(testcase, TestcaseStatus::Failed(message.clone()));
+            let mut shared = self.shared.write().unwrap();
+            let testcase = shared.last_batch.as_ref().unwrap().testcases.get(&testcase);
+            shared.failing_testcase = testcase.map(|tc| (tc.clone(), message));
+            shared.should_stop = true;
+            self.stop_evaluation.stop();
+        }
+    }
 }
 
 impl UIStateT for UIState {
     fn apply(&mut self, message: UIMessage) {
-        let mut set = |testcase: TestcaseId, state: TestcaseStatus| {
-            let testcase = &mut self.batches.last_mut().unwrap().testcase_status
-                [testcase as usize % self.batch_size];
-            *testcase = state;
-        };
         match message {
             UIMessage::IOITask { .. } => {
                 self.batches.push(CurrentBatch::new(self.batch_size));
+                self.official_scores.clear();
+                self.pending_scores.clear();
             }
             UIMessage::ServerStatus { status } => self.executor_status = Some(status),
             UIMessage::IOIGeneration {
                 testcase, status, ..
             } => match status {
-                UIExecutionStatus::Started { .. } => set(testcase, TestcaseStatus::Generating),
+                UIExecutionStatus::Started { .. } => self.set(testcase, TestcaseStatus::Generating),
                 UIExecutionStatus::Done { result } => {
                     self.progress.inputs_generated += 1;
                     self.progress.generator_time_sum += result.resources.cpu_time;
                     if result.status.is_success() {
-                        set(testcase, TestcaseStatus::Generated);
+                        self.set(testcase, TestcaseStatus::Generated);
                     } else {
-                        set(testcase, TestcaseStatus::Error);
+                        self.set(testcase, TestcaseStatus::Error);
                         let mut shared = self.shared.write().unwrap();
+                        // Broken candidates are expected while minimizing.
+                        if shared.minimization.is_some() {
+                            return;
+                        }
                         let testcase = shared
                             .last_batch
                             .as_ref()
@@ -168,13 +223,17 @@ impl UIStateT for UIState {
             UIMessage::IOIValidation {
                 testcase, status, ..
             } => match status {
-                UIExecutionStatus::Started { .. } => set(testcase, TestcaseStatus::Validating),
+                UIExecutionStatus::Started { .. } => self.set(testcase, TestcaseStatus::Validating),
                 UIExecutionStatus::Done { result } => {
                     if result.status.is_success() {
-                        set(testcase, TestcaseStatus::Validated);
+                        self.set(testcase, TestcaseStatus::Validated);
                     } else {
-                        set(testcase, TestcaseStatus::Error);
+                        self.set(testcase, TestcaseStatus::Error);
                         let mut shared = self.shared.write().unwrap();
+                        // Broken candidates are expected while minimizing.
+                        if shared.minimization.is_some() {
+                            return;
+                        }
                         let testcase = shared
                             .last_batch
                             .as_ref()
@@ -189,12 +248,15 @@ impl UIStateT for UIState {
                 _ => {}
             },
             UIMessage::IOIEvaluation {
-                testcase, status, ..
-            } => match status {
-                UIExecutionStatus::Started { .. } => set(testcase, TestcaseStatus::Solving),
+                testcase,
+                solution,
+                status,
+                ..
+            } if !self.is_official(&solution) => match status {
+                UIExecutionStatus::Started { .. } => self.set(testcase, TestcaseStatus::Solving),
                 UIExecutionStatus::Done { result } => {
                     if result.status.is_success() {
-                        set(testcase, TestcaseStatus::Solved);
+                        self.set(testcase, TestcaseStatus::Solved);
                     }
                     self.progress.inputs_solved += 1;
                     self.progress.solution_time_sum += result.resources.cpu_time;
@@ -203,26 +265,30 @@ impl UIStateT for UIState {
             },
             UIMessage::IOIChecker {
                 testcase,
+                solution,
                 status: UIExecutionStatus::Started { .. },
                 ..
-            } => {
-                set(testcase, TestcaseStatus::Checking);
+            } if !self.is_official(&solution) => {
+                self.set(testcase, TestcaseStatus::Checking);
             }
             UIMessage::IOITestcaseScore {
                 testcase,
+                solution,
                 score,
                 message,
                 ..
             } => {
-                if score == 1.0 {
-                    set(testcase, TestcaseStatus::Success);
+                if self.is_official(&solution) {
+                    self.official_scores.insert(testcase, score);
+                    if let Some((score, message)) = self.pending_scores.remove(&testcase) {
+                        self.testcase_scored(testcase, score, message);
+                    }
+                } else if self.official_solution.is_some()
+                    && !self.official_scores.contains_key(&testcase)
+                {
+                    self.pending_scores.insert(testcase, (score, message));
                 } else {
-                    set(testcase, TestcaseStatus::Failed(message.clone()));
-                    let mut shared = self.shared.write().unwrap();
-                    let testcase = shared.last_batch.as_ref().unwrap().testcases.get(&testcase);
-                    shared.failing_testcase = testcase.map(|tc| (tc.clone(), message));
-                    shared.should_stop = true;
-                    self.stop_evaluation.stop();
+                    self.testcase_scored(testcase, score, message);
                 }
             }
             _ => {}
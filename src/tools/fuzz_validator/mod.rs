@@ -0,0 +1,294 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Error};
+use clap::Parser;
+use itertools::Itertools;
+
+use task_maker_dag::File;
+use task_maker_format::ioi::{InputValidator, SubtaskId, TestcaseId};
+use task_maker_format::ui::{StdoutPrinter, UIType, BOLD, GREEN, RED, YELLOW};
+use task_maker_format::{cwrite, cwriteln, EvaluationConfig, TaskFormat};
+
+use crate::context::RuntimeContext;
+use crate::tools::fuzz_validator::mutation::{Mutant, ALL_MUTATIONS};
+use crate::{ExecutionOpt, FindTaskOpt, StorageOpt};
+
+mod mutation;
+
+/// How many times to try applying a kind of mutation to an input file.
+const MUTATION_ATTEMPTS: usize = 10;
+
+#[derive(Parser, Debug, Clone)]
+pub struct FuzzValidatorOpt {
+    #[clap(flatten, next_help_heading = Some("TASK SEARCH"))]
+    pub find_task: FindTaskOpt,
+
+    /// Where to store the mutants accepted by the validator.
+    ///
+    /// The path is relative to the task directory.
+    #[clap(long, default_value = "fuzz")]
+    pub fuzz_dir: PathBuf,
+
+    /// Number of mutants to generate for each input file.
+    #[clap(long, short, default_value = "10")]
+    pub mutants: usize,
+
+    /// Seed for the random mutations.
+    ///
+    /// Defaults to a random seed.
+    #[clap(long)]
+    pub seed: Option<u64>,
+
+    /// Don't run the evaluation for building the input files.
+    #[clap(long)]
+    pub no_build: bool,
+
+    #[clap(flatten, next_help_heading = Some("EXECUTION"))]
+    pub execution: ExecutionOpt,
+
+    #[clap(flatten, next_help_heading = Some("STORAGE"))]
+    pub storage: StorageOpt,
+}
+
+/// A mutant of an input file, with the information about the testcase it comes from.
+#[derive(Debug)]
+struct TestcaseMutant {
+    /// The subtask of the original testcase.
+    subtask_id: SubtaskId,
+    /// The original testcase.
+    testcase_id: TestcaseId,
+    /// The content of the original input file.
+    original: Arc<String>,
+    /// The mutated input file.
+    mutant: Mutant,
+}
+
+pub fn main_fuzz_validator(opt: FuzzValidatorOpt) -> Result<(), Error> {
+    let task_format = opt
+        .find_task
+        .find_task(&Default::default())
+        .context("Failed to locate the task")?;
+    if !matches!(task_format, TaskFormat::IOI(_)) {
+        bail!("The fuzz-validator tool only supports IOI-tasks for now");
+    }
+
+    if !opt.no_build {
+        info!("Running task-maker for building the input files");
+
+        let eval_config = EvaluationConfig {
+            solution_filter: vec!["do not evaluate the solutions!!".into()],
+            no_statement: true,
+            ..Default::default()
+        };
+        let context = RuntimeContext::new(task_format, &opt.execution, |task, eval| {
+            task.build_dag(eval, &eval_config)
+                .context("Cannot build the task DAG")
+        })?;
+        let executor = context.connect_executor(&opt.execution, &opt.storage)?;
+        let executor = executor.start_ui(&UIType::Silent, |_, _| {})?;
+        executor.execute()?;
+    }
+
+    // The source files of the task cannot be reused in a new DAG, so parse the task again.
+    let task_format = opt
+        .find_task
+        .find_task(&Default::default())
+        .context("Failed to locate the task")?;
+    let task = if let TaskFormat::IOI(task) = &task_format {
+        task.clone()
+    } else {
+        unreachable!("The task format changed");
+    };
+    let task_dir = task.path.clone();
+
+    let seed = opt.seed.unwrap_or_else(|| fastrand::u64(..));
+    info!("Using seed {}", seed);
+    let mut rng = fastrand::Rng::with_seed(seed);
+
+    // Generate the mutants of all the input files that have a custom validator.
+    let mut mutants = vec![];
+    let mut validators = vec![];
+    for subtask_id in task.subtasks.keys().copied().sorted() {
+        let subtask = &task.subtasks[&subtask_id];
+        for testcase_id in subtask.testcases.keys().copied().sorted() {
+            let testcase = &subtask.testcases[&testcase_id];
+            if !matches!(testcase.input_validator, InputValidator::Custom(_, _)) {
+                continue;
+            }
+            let path = task_dir.join(format!("input/input{}.txt", testcase_id));
+            if !path.exists() {
+                bail!(
+                    "The input files haven't been generated, please run task-maker (missing {})",
+                    path.display()
+                );
+            }
+            let original = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let original = Arc::new(original);
+            for index in 0..opt.mutants {
+                let kind = ALL_MUTATIONS[index % ALL_MUTATIONS.len()];
+                // some mutations cannot always be applied, try a few times before giving up
+                let mutant = (0..MUTATION_ATTEMPTS).find_map(|_| kind.mutate(&original, &mut rng));
+                if let Some(mutant) = mutant {
+                    mutants.push(TestcaseMutant {
+                        subtask_id,
+                        testcase_id,
+                        original: original.clone(),
+                        mutant,
+                    });
+                    validators.push((testcase.input_validator.clone(), subtask.name.clone()));
+                }
+            }
+        }
+    }
+    if mutants.is_empty() {
+        bail!("No input file validated by a custom validator has been found");
+    }
+
+    // Validate all the mutants, keeping track of the ones accepted.
+    let accepted = Arc::new(Mutex::new(vec![]));
+    let validated = Arc::new(Mutex::new(0usize));
+    let context = RuntimeContext::new(task_format, &opt.execution, |_task, eval| {
+        for (index, (mutant, (validator, subtask_name))) in
+            mutants.iter().zip(validators.iter()).enumerate()
+        {
+            let file = File::new(format!(
+                "Mutant {} of input {} ({})",
+                index,
+                mutant.testcase_id,
+                mutant.mutant.kind.name()
+            ));
+            let input = file.uuid;
+            eval.dag
+                .provide_content(file, mutant.mutant.content.as_bytes().to_vec());
+            let (_, exec) = validator
+                .validate(
+                    eval,
+                    format!(
+                        "Validation of mutant {} of testcase {}, subtask {}",
+                        index, mutant.testcase_id, mutant.subtask_id
+                    ),
+                    mutant.subtask_id,
+                    subtask_name.as_deref(),
+                    mutant.testcase_id,
+                    input,
+                )
+                .context("Failed to build the validation of a mutant")?;
+            if let Some(exec) = exec {
+                let accepted = accepted.clone();
+                let validated = validated.clone();
+                eval.dag.on_execution_done(&exec.uuid, move |result| {
+                    *validated.lock().unwrap() += 1;
+                    if result.status.is_success() {
+                        accepted.lock().unwrap().push(index);
+                    }
+                    Ok(())
+                });
+                eval.dag.add_execution(exec);
+            }
+        }
+        Ok(())
+    })?;
+    let executor = context.connect_executor(&opt.execution, &opt.storage)?;
+    let executor = executor.start_ui(&UIType::Silent, |_, _| {})?;
+    executor.execute()?;
+
+    let validated = *validated.lock().unwrap();
+    if validated == 0 {
+        bail!("No mutant has been validated, maybe the validator failed to compile?");
+    }
+    let mut accepted = accepted.lock().unwrap().clone();
+    accepted.sort_unstable();
+
+    let mut printer = StdoutPrinter::default();
+    let target_dir = task_dir.join(&opt.fuzz_dir).join("validator");
+    if !accepted.is_empty() {
+        std::fs::create_dir_all(&target_dir)
+            .with_context(|| format!("Failed to create {}", target_dir.display()))?;
+    }
+    for &index in &accepted {
+        let mutant = &mutants[index];
+        let path = target_dir.join(format!("mutant-{}-{}.txt", mutant.testcase_id, index));
+        std::fs::write(&path, &mutant.mutant.content)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        cwrite!(printer, RED, "Accepted mutant");
+        print!(" of testcase {}", mutant.testcase_id);
+        cwrite!(printer, BOLD, " [{}] ", mutant.mutant.kind.name());
+        println!("{}", mutant.mutant.description);
+        println!(
+            "(at {})",
+            path.strip_prefix(&task_dir).unwrap_or(&path).display()
+        );
+        print_diff(&mutant.original, &mutant.mutant.content, &mut printer);
+        println!();
+    }
+
+    cwrite!(printer, BOLD, "Seed:     ");
+    println!("{}", seed);
+    cwrite!(printer, BOLD, "Rejected: ");
+    println!("{}/{}", validated - accepted.len(), validated);
+    if accepted.is_empty() {
+        cwriteln!(printer, GREEN, "The validator rejected all the mutants");
+    } else {
+        cwriteln!(
+            printer,
+            YELLOW,
+            "The validator accepted {} mutants, some of them may still be valid inputs",
+            accepted.len()
+        );
+    }
+    Ok(())
+}
+
+/// Print the lines that differ between the original input file and the mutant, with some context.
+fn print_diff(original: &str, mutant: &str, printer: &mut StdoutPrinter) {
+    const CONTEXT: usize = 2;
+    const MAX_LINES: usize = 10;
+    const MAX_LINE_LEN: usize = 80;
+
+    let original: Vec<_> = original.split_inclusive('\n').collect();
+    let mutant: Vec<_> = mutant.split_inclusive('\n').collect();
+    let prefix = original
+        .iter()
+        .zip(mutant.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = original[prefix..]
+        .iter()
+        .rev()
+        .zip(mutant[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let show = |line: &str| {
+        let line = format!("{:?}", line);
+        if line.len() > MAX_LINE_LEN {
+            let end = (0..=MAX_LINE_LEN)
+                .rev()
+                .find(|&i| line.is_char_boundary(i))
+                .unwrap_or(0);
+            #[allow(clippy::string_slice)] // end is a char boundary.
+            let line = &line[..end];
+            format!("{}...", line)
+        } else {
+            line
+        }
+    };
+    for line in &original[prefix.saturating_sub(CONTEXT)..prefix] {
+        println!("  {}", show(line));
+    }
+    for line in original[prefix..original.len() - suffix]
+        .iter()
+        .take(MAX_LINES)
+    {
+        cwriteln!(printer, RED, "- {}", show(line));
+    }
+    for line in mutant[prefix..mutant.len() - suffix].iter().take(MAX_LINES) {
+        cwriteln!(printer, GREEN, "+ {}", show(line));
+    }
+    for line in original[original.len() - suffix..].iter().take(CONTEXT) {
+        println!("  {}", show(line));
+    }
+}
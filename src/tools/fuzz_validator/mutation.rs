@@ -0,0 +1,446 @@
+use std::ops::Range;
+
+use fastrand::Rng;
+
+/// The kinds of mutations that can be applied to an input file. A valid input mutated by any of
+/// these should be rejected by a strict validator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MutationKind {
+    /// Move one of the extreme numbers of the file just past its value.
+    OffByOne,
+    /// Replace a number with a value that is very likely outside the constraints.
+    OutOfRange,
+    /// Add, remove or change the whitespace between the tokens.
+    Whitespace,
+    /// Add, remove or duplicate a line.
+    Lines,
+    /// Add or remove some of the values, breaking the counts declared in the file.
+    Counts,
+}
+
+/// All the kinds of mutations, in the order they are tried.
+pub const ALL_MUTATIONS: [MutationKind; 5] = [
+    MutationKind::OffByOne,
+    MutationKind::OutOfRange,
+    MutationKind::Whitespace,
+    MutationKind::Lines,
+    MutationKind::Counts,
+];
+
+/// An input file obtained by mutating a valid one.
+#[derive(Debug, Clone)]
+pub struct Mutant {
+    /// The kind of the applied mutation.
+    pub kind: MutationKind,
+    /// Human readable description of the applied mutation.
+    pub description: String,
+    /// The content of the mutated file.
+    pub content: String,
+}
+
+/// A token of the input file, i.e. a maximal sequence of non-whitespace characters.
+#[derive(Debug, Clone)]
+struct Token {
+    /// The range of bytes of the token inside the file.
+    range: Range<usize>,
+    /// The 0-based index of the line of the token.
+    line: usize,
+}
+
+impl MutationKind {
+    /// A short name of this kind of mutation.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MutationKind::OffByOne => "off-by-one",
+            MutationKind::OutOfRange => "out-of-range",
+            MutationKind::Whitespace => "whitespace",
+            MutationKind::Lines => "lines",
+            MutationKind::Counts => "counts",
+        }
+    }
+
+    /// Apply a random mutation of this kind to the input file. Returns `None` if this kind of
+    /// mutation cannot be applied to the file, or if the mutant is equal to the original file.
+    pub fn mutate(&self, input: &str, rng: &mut Rng) -> Option<Mutant> {
+        let tokens = tokenize(input);
+        let (description, content) = match self {
+            MutationKind::OffByOne => off_by_one(input, &tokens, rng),
+            MutationKind::OutOfRange => out_of_range(input, &tokens, rng),
+            MutationKind::Whitespace => whitespace(input, &tokens, rng),
+            MutationKind::Lines => lines(input, rng),
+            MutationKind::Counts => counts(input, &tokens, rng),
+        }?;
+        if content == input {
+            return None;
+        }
+        Some(Mutant {
+            kind: *self,
+            description,
+            content,
+        })
+    }
+}
+
+/// Split the input file in tokens.
+fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut line = 0;
+    let mut start = None;
+    for (index, c) in input.char_indices() {
+        if c.is_whitespace() {
+            if let Some(start) = start.take() {
+                tokens.push(Token {
+                    range: start..index,
+                    line,
+                });
+            }
+            if c == '\n' {
+                line += 1;
+            }
+        } else if start.is_none() {
+            start = Some(index);
+        }
+    }
+    if let Some(start) = start {
+        tokens.push(Token {
+            range: start..input.len(),
+            line,
+        });
+    }
+    tokens
+}
+
+/// Replace the bytes in `range` of `input` with `with`.
+#[allow(clippy::string_slice)] // The ranges come from char-aligned functions.
+fn replace(input: &str, range: Range<usize>, with: &str) -> String {
+    let mut result = String::with_capacity(input.len() + with.len());
+    result.push_str(&input[..range.start]);
+    result.push_str(with);
+    result.push_str(&input[range.end..]);
+    result
+}
+
+/// The tokens of the file that are integers, with their value.
+#[allow(clippy::string_slice)] // The ranges come from char-aligned functions.
+fn integers<'a>(input: &str, tokens: &'a [Token]) -> Vec<(&'a Token, i128)> {
+    tokens
+        .iter()
+        .filter_map(|token| Some((token, input[token.range.clone()].parse::<i128>().ok()?)))
+        .collect()
+}
+
+fn off_by_one(input: &str, tokens: &[Token], rng: &mut Rng) -> Option<(String, String)> {
+    let integers = integers(input, tokens);
+    // the extreme values of the file are the ones most likely to be at the bounds of the
+    // constraints, moving them past their value should make the file invalid
+    let (min, max) = if rng.bool() {
+        let max = integers.iter().map(|(_, value)| *value).max()?;
+        (None, Some(max))
+    } else {
+        let min = integers.iter().map(|(_, value)| *value).min()?;
+        (Some(min), None)
+    };
+    let candidates: Vec<_> = integers
+        .iter()
+        .filter(|(_, value)| Some(*value) == min || Some(*value) == max)
+        .collect();
+    let (token, value) = candidates[rng.usize(..candidates.len())];
+    let new_value = if max.is_some() {
+        value.saturating_add(1)
+    } else {
+        value.saturating_sub(1)
+    };
+    Some((
+        format!("{} -> {} at line {}", value, new_value, token.line + 1),
+        replace(input, token.range.clone(), &new_value.to_string()),
+    ))
+}
+
+fn out_of_range(input: &str, tokens: &[Token], rng: &mut Rng) -> Option<(String, String)> {
+    let integers = integers(input, tokens);
+    if integers.is_empty() {
+        return None;
+    }
+    let (token, value) = integers[rng.usize(..integers.len())];
+    let candidates = [
+        "-1".to_string(),
+        "0".to_string(),
+        value.saturating_neg().to_string(),
+        value.saturating_mul(2).saturating_add(1).to_string(),
+        "1000000001".to_string(),
+        "2147483648".to_string(),
+        "9223372036854775808".to_string(),
+    ];
+    let new_value = &candidates[rng.usize(..candidates.len())];
+    Some((
+        format!("{} -> {} at line {}", value, new_value, token.line + 1),
+        replace(input, token.range.clone(), new_value),
+    ))
+}
+
+fn whitespace(input: &str, tokens: &[Token], rng: &mut Rng) -> Option<(String, String)> {
+    if tokens.is_empty() {
+        return None;
+    }
+    let token = &tokens[rng.usize(..tokens.len())];
+    let line = token.line + 1;
+    let next = tokens
+        .iter()
+        .find(|next| next.range.start > token.range.end && next.line == token.line);
+    let result = match rng.usize(..6) {
+        0 => (
+            format!("extra space after the token at line {}", line),
+            replace(input, token.range.end..token.range.end, " "),
+        ),
+        1 => (
+            format!("extra space before the token at line {}", line),
+            replace(input, token.range.start..token.range.start, " "),
+        ),
+        2 => {
+            let next = next?;
+            (
+                format!("tab instead of space at line {}", line),
+                replace(input, token.range.end..next.range.start, "\t"),
+            )
+        }
+        3 => {
+            let next = next?;
+            (
+                format!("missing space between two tokens at line {}", line),
+                replace(input, token.range.end..next.range.start, ""),
+            )
+        }
+        4 => (
+            format!("Windows line endings up to line {}", line),
+            input.replacen('\n', "\r\n", token.line + 1),
+        ),
+        _ => {
+            let trimmed = input.trim_end_matches('\n');
+            if trimmed.len() == input.len() {
+                ("added final new line".to_string(), format!("{}\n", input))
+            } else {
+                ("removed final new line".to_string(), trimmed.to_string())
+            }
+        }
+    };
+    Some(result)
+}
+
+fn lines(input: &str, rng: &mut Rng) -> Option<(String, String)> {
+    let mut lines: Vec<_> = input.split_inclusive('\n').map(String::from).collect();
+    if lines.is_empty() {
+        return None;
+    }
+    if !lines.last().unwrap().ends_with('\n') {
+        lines.last_mut().unwrap().push('\n');
+    }
+    let index = rng.usize(..lines.len());
+    let description = match rng.usize(..3) {
+        0 => {
+            lines.insert(index, lines[index].clone());
+            format!("duplicated line {}", index + 1)
+        }
+        1 => {
+            lines.remove(index);
+            format!("removed line {}", index + 1)
+        }
+        _ => {
+            lines.insert(index, "\n".to_string());
+            format!("empty line before line {}", index + 1)
+        }
+    };
+    Some((description, lines.concat()))
+}
+
+fn counts(input: &str, tokens: &[Token], rng: &mut Rng) -> Option<(String, String)> {
+    if tokens.is_empty() {
+        return None;
+    }
+    // mutate the last token of a random line
+    let line = tokens[rng.usize(..tokens.len())].line;
+    let token = tokens.iter().rev().find(|token| token.line == line)?;
+    #[allow(clippy::string_slice)] // The ranges come from char-aligned functions.
+    let value = &input[token.range.clone()];
+    if rng.bool() {
+        let start = tokens
+            .iter()
+            .rev()
+            .find(|prev| prev.range.end < token.range.start && prev.line == line)
+            .map(|prev| prev.range.end)
+            .unwrap_or(token.range.start);
+        Some((
+            format!("removed the last value of line {}", line + 1),
+            replace(input, start..token.range.end, ""),
+        ))
+    } else {
+        Some((
+            format!("added an extra value at line {}", line + 1),
+            replace(
+                input,
+                token.range.end..token.range.end,
+                &format!(" {}", value),
+            ),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = "3\n1 5 2\n";
+
+    /// The non-whitespace tokens of the file.
+    fn words(input: &str) -> Vec<&str> {
+        input.split_whitespace().collect()
+    }
+
+    /// Apply the mutation with many seeds, returning the mutants.
+    fn mutants(kind: MutationKind, input: &str) -> Vec<Mutant> {
+        (0..200)
+            .filter_map(|seed| kind.mutate(input, &mut Rng::with_seed(seed)))
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let tokens = tokenize("12 3\n\n 45");
+        let ranges: Vec<_> = tokens.iter().map(|t| t.range.clone()).collect();
+        let lines: Vec<_> = tokens.iter().map(|t| t.line).collect();
+        assert_eq!(ranges, vec![0..2, 3..4, 7..9]);
+        assert_eq!(lines, vec![0, 0, 2]);
+    }
+
+    #[test]
+    fn test_mutate_seeded() {
+        for kind in ALL_MUTATIONS {
+            for seed in 0..20 {
+                let first = kind.mutate(INPUT, &mut Rng::with_seed(seed));
+                let second = kind.mutate(INPUT, &mut Rng::with_seed(seed));
+                assert_eq!(
+                    first.map(|m| m.content),
+                    second.map(|m| m.content),
+                    "{} with seed {}",
+                    kind.name(),
+                    seed
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_mutate_empty() {
+        for kind in ALL_MUTATIONS {
+            assert!(mutants(kind, "").is_empty(), "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn test_off_by_one() {
+        let mutants = mutants(MutationKind::OffByOne, INPUT);
+        assert!(!mutants.is_empty());
+        for mutant in mutants {
+            assert_eq!(mutant.kind, MutationKind::OffByOne);
+            let changed: Vec<_> = words(INPUT)
+                .into_iter()
+                .zip(words(&mutant.content))
+                .filter(|(old, new)| old != new)
+                .collect();
+            assert!(
+                changed == [("5", "6")] || changed == [("1", "0")],
+                "{:?}",
+                mutant
+            );
+        }
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let mutants = mutants(MutationKind::OutOfRange, INPUT);
+        assert!(!mutants.is_empty());
+        for mutant in mutants {
+            let old = words(INPUT);
+            let new = words(&mutant.content);
+            assert_eq!(old.len(), new.len(), "{:?}", mutant);
+            let changed: Vec<_> = old.iter().zip(&new).filter(|(o, n)| o != n).collect();
+            assert_eq!(changed.len(), 1, "{:?}", mutant);
+            let (old, new) = changed[0];
+            let value: i128 = old.parse().unwrap();
+            let candidates = [
+                "-1".to_string(),
+                "0".to_string(),
+                (-value).to_string(),
+                (value * 2 + 1).to_string(),
+                "1000000001".to_string(),
+                "2147483648".to_string(),
+                "9223372036854775808".to_string(),
+            ];
+            assert!(candidates.contains(&new.to_string()), "{:?}", mutant);
+        }
+    }
+
+    #[test]
+    fn test_whitespace() {
+        let mutants = mutants(MutationKind::Whitespace, INPUT);
+        assert!(!mutants.is_empty());
+        let non_whitespace =
+            |s: &str| -> String { s.chars().filter(|c| !c.is_whitespace()).collect() };
+        for mutant in mutants {
+            assert_ne!(mutant.content, INPUT);
+            assert_eq!(
+                non_whitespace(&mutant.content),
+                non_whitespace(INPUT),
+                "{:?}",
+                mutant
+            );
+        }
+    }
+
+    #[test]
+    fn test_lines() {
+        let mutants = mutants(MutationKind::Lines, INPUT);
+        assert!(!mutants.is_empty());
+        let original: Vec<_> = INPUT.lines().collect();
+        for mutant in mutants {
+            let lines: Vec<_> = mutant.content.lines().collect();
+            assert_eq!(
+                (lines.len() as isize - original.len() as isize).abs(),
+                1,
+                "{:?}",
+                mutant
+            );
+            for line in lines {
+                assert!(line.is_empty() || original.contains(&line), "{:?}", mutant);
+            }
+        }
+    }
+
+    #[test]
+    fn test_counts() {
+        let mutants = mutants(MutationKind::Counts, INPUT);
+        assert!(!mutants.is_empty());
+        for mutant in mutants {
+            let old = words(INPUT);
+            let new = words(&mutant.content);
+            assert_eq!(
+                (new.len() as isize - old.len() as isize).abs(),
+                1,
+                "{:?}",
+                mutant
+            );
+            assert_eq!(
+                mutant.content.lines().count(),
+                INPUT.lines().count(),
+                "{:?}",
+                mutant
+            );
+            // the values are only added or removed at the end of a line
+            for (old_line, new_line) in INPUT.lines().zip(mutant.content.lines()) {
+                let old_line = words(old_line);
+                let new_line = words(new_line);
+                let common = old_line.len().min(new_line.len());
+                assert_eq!(old_line[..common], new_line[..common], "{:?}", mutant);
+            }
+        }
+    }
+}
@@ -0,0 +1,511 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Error};
+use clap::Parser;
+use itertools::Itertools;
+
+use task_maker_format::ioi::{SubtaskId, TestcaseId, UIState};
+use task_maker_format::ui::{StdoutPrinter, UIStateT, BLUE, BOLD, GREEN, RED, YELLOW};
+use task_maker_format::{cwrite, cwriteln, EvaluationConfig, TaskFormat, TestcaseEvaluationResult};
+
+use crate::context::RuntimeContext;
+use crate::{ExecutionOpt, FilterOpt, FindTaskOpt, LoggerOpt, StorageOpt, UIOpt};
+
+/// The maximum number of testcases of a subtask for searching the smallest covering subset, instead
+/// of a small one.
+const EXACT_COVER_MAX_TESTCASES: usize = 20;
+
+#[derive(Parser, Debug, Clone)]
+pub struct KillMatrixOpt {
+    #[clap(flatten, next_help_heading = Some("TASK SEARCH"))]
+    pub find_task: FindTaskOpt,
+
+    #[clap(flatten, next_help_heading = Some("UI"))]
+    pub ui: UIOpt,
+
+    #[clap(flatten, next_help_heading = Some("STORAGE"))]
+    pub storage: StorageOpt,
+
+    #[clap(flatten, next_help_heading = Some("FILTER"))]
+    pub filter: FilterOpt,
+
+    #[clap(flatten, next_help_heading = Some("EXECUTION"))]
+    pub execution: ExecutionOpt,
+
+    /// Don't print the matrices, only the analysis of the testcases.
+    #[clap(long)]
+    pub no_matrix: bool,
+}
+
+/// The results of the solutions on the testcases of a subtask.
+struct SubtaskMatrix {
+    /// The testcases of the subtask, sorted by id.
+    testcases: Vec<TestcaseId>,
+    /// For each solution (indexed as in the legend) the result on each testcase, in the same order
+    /// of `testcases`. `None` if the solution has not been evaluated on that testcase.
+    results: Vec<Vec<Option<TestcaseEvaluationResult>>>,
+}
+
+impl SubtaskMatrix {
+    /// Whether the testcase at the given position kills the solution, i.e. the solution doesn't
+    /// solve it correctly.
+    fn kills(&self, testcase: usize, solution: usize) -> bool {
+        match &self.results[solution][testcase] {
+            Some(result) => *result != TestcaseEvaluationResult::Accepted,
+            None => false,
+        }
+    }
+
+    /// The set of solutions killed by the testcase at the given position.
+    fn killed_by(&self, testcase: usize) -> BTreeSet<usize> {
+        (0..self.results.len())
+            .filter(|&solution| self.kills(testcase, solution))
+            .collect()
+    }
+
+    /// The positions of the testcases that kill the solution.
+    fn killers_of(&self, solution: usize) -> Vec<usize> {
+        (0..self.testcases.len())
+            .filter(|&testcase| self.kills(testcase, solution))
+            .collect()
+    }
+
+    /// The positions of the testcases that don't kill any solution.
+    fn useless_testcases(&self) -> Vec<usize> {
+        (0..self.testcases.len())
+            .filter(|&testcase| self.killed_by(testcase).is_empty())
+            .collect()
+    }
+
+    /// The positions of the testcases that kill some solutions, but all of them are killed also by
+    /// some other testcase of the subtask.
+    fn redundant_testcases(&self) -> Vec<usize> {
+        (0..self.testcases.len())
+            .filter(|&testcase| {
+                let killed = self.killed_by(testcase);
+                !killed.is_empty()
+                    && killed.iter().all(|&solution| {
+                        (0..self.testcases.len())
+                            .any(|other| other != testcase && self.kills(other, solution))
+                    })
+            })
+            .collect()
+    }
+
+    /// A small subset of the testcases that kills all the solutions killed by the subtask, and
+    /// whether it is the smallest one.
+    ///
+    /// The subset is found greedily, then, if the subtask has at most `EXACT_COVER_MAX_TESTCASES`
+    /// testcases, all the smaller subsets are tried to find the smallest one.
+    fn covering_subset(&self) -> (Vec<usize>, bool) {
+        let kill_sets: Vec<_> = (0..self.testcases.len())
+            .map(|testcase| self.killed_by(testcase))
+            .collect();
+        let greedy = greedy_cover(&kill_sets);
+        if kill_sets.len() > EXACT_COVER_MAX_TESTCASES {
+            return (greedy, false);
+        }
+        let to_kill: BTreeSet<usize> = kill_sets.iter().flatten().copied().collect();
+        for size in 0..greedy.len() {
+            // The combinations are in lexicographic order, so the testcases that come first are
+            // preferred on equal terms.
+            let subset = (0..kill_sets.len()).combinations(size).find(|subset| {
+                let killed: BTreeSet<_> = subset.iter().flat_map(|&tc| &kill_sets[tc]).collect();
+                killed.len() == to_kill.len()
+            });
+            if let Some(subset) = subset {
+                return (subset, true);
+            }
+        }
+        (greedy, true)
+    }
+}
+
+/// A small subset of the testcases that kills all the solutions killed by some testcase, given the
+/// set of solutions killed by each testcase.
+///
+/// The subset is found greedily, picking every time the testcase that kills the most solutions
+/// not yet killed, and then removing the testcases that became unnecessary.
+fn greedy_cover(kill_sets: &[BTreeSet<usize>]) -> Vec<usize> {
+    let mut to_kill: BTreeSet<usize> = kill_sets.iter().flatten().copied().collect();
+    let mut subset = vec![];
+    while !to_kill.is_empty() {
+        let best = (0..kill_sets.len())
+            .max_by_key(|&testcase| {
+                let killed = kill_sets[testcase].intersection(&to_kill).count();
+                // Prefer the testcases that come first on equal terms.
+                (killed, std::cmp::Reverse(testcase))
+            })
+            .expect("No testcases but some solutions to kill");
+        for solution in &kill_sets[best] {
+            to_kill.remove(solution);
+        }
+        subset.push(best);
+    }
+    // The greedy choice may leave some testcases whose solutions are all killed by the ones
+    // picked after them.
+    let mut index = 0;
+    while index < subset.len() {
+        let necessary = kill_sets[subset[index]].iter().any(|solution| {
+            subset
+                .iter()
+                .enumerate()
+                .all(|(other, &testcase)| other == index || !kill_sets[testcase].contains(solution))
+        });
+        if necessary {
+            index += 1;
+        } else {
+            subset.remove(index);
+        }
+    }
+    subset.sort_unstable();
+    subset
+}
+
+pub fn main_kill_matrix(mut opt: KillMatrixOpt, logger_opt: LoggerOpt) -> Result<(), Error> {
+    opt.ui.disable_if_needed(&logger_opt);
+    let eval_config = EvaluationConfig {
+        solution_filter: opt.filter.filter,
+        booklet_solutions: false,
+        no_statement: true,
+        solution_paths: opt.filter.solution,
+        disabled_sanity_checks: Default::default(),
+        sanity_check_levels: Default::default(),
+        deny_warnings: false,
+        seed: Default::default(),
+        dry_run: true,
+    };
+    let task = opt
+        .find_task
+        .find_task(&eval_config)
+        .context("Failed to locate the task")?;
+
+    // See add_solution_checks for why this is a mutex.
+    let ui_state = Arc::new(Mutex::new(None::<UIState>));
+
+    let context = RuntimeContext::new(task, &opt.execution, |task, eval| {
+        task.build_dag(eval, &eval_config)
+            .context("Cannot build the task DAG")?;
+        let ioi_task = match &task {
+            TaskFormat::IOI(task) => task,
+            _ => bail!("The kill-matrix tool only supports IOI-tasks for now"),
+        };
+        *ui_state.lock().unwrap() = Some(UIState::new(ioi_task, eval.dag.data.config.clone()));
+        Ok(())
+    })?;
+
+    let executor = context.connect_executor(&opt.execution, &opt.storage)?;
+    let executor = executor.start_ui(&opt.ui.ui, {
+        let ui_state = ui_state.clone();
+        move |ui, message| {
+            ui.on_message(message.clone());
+            ui_state.lock().unwrap().as_mut().unwrap().apply(message);
+        }
+    })?;
+    executor.execute()?;
+
+    let ui_state = ui_state.lock().unwrap().take().unwrap();
+    let solutions: Vec<PathBuf> = ui_state
+        .evaluations
+        .keys()
+        .sorted_by_key(|path| ui_state.solutions.get(*path).map(|s| s.name.clone()))
+        .cloned()
+        .collect();
+    if solutions.is_empty() {
+        bail!("No solution has been evaluated");
+    }
+
+    let mut printer = StdoutPrinter::default();
+    cwriteln!(printer, BLUE, "Solutions");
+    for (index, path) in solutions.iter().enumerate() {
+        let name = ui_state
+            .solutions
+            .get(path)
+            .map(|s| s.name.clone())
+            .unwrap_or_else(|| path.display().to_string());
+        cwrite!(printer, BOLD, "{:>4}", format!("S{}", index));
+        println!(" {}", name);
+    }
+    println!();
+
+    let mut killed_by_nothing: Vec<usize> = (0..solutions.len()).collect();
+    for subtask_id in ui_state.task.subtasks.keys().copied().sorted() {
+        let matrix = build_matrix(&ui_state, &solutions, subtask_id);
+        let subtask = &ui_state.task.subtasks[&subtask_id];
+        cwrite!(printer, BLUE, "Subtask {}", subtask_id);
+        if let Some(name) = &subtask.name {
+            cwrite!(printer, BLUE, " ({})", name);
+        }
+        println!();
+        if matrix.testcases.is_empty() {
+            println!("No testcases");
+            println!();
+            continue;
+        }
+
+        let redundant = matrix.redundant_testcases();
+        let useless = matrix.useless_testcases();
+        if !opt.no_matrix {
+            print_matrix(&matrix, &redundant, &useless, &mut printer);
+        }
+
+        killed_by_nothing.retain(|&solution| matrix.killers_of(solution).is_empty());
+        let killed: Vec<_> = (0..solutions.len())
+            .filter(|&solution| !matrix.killers_of(solution).is_empty())
+            .collect();
+        if killed.is_empty() {
+            println!("No solution fails in this subtask");
+            println!();
+            continue;
+        }
+
+        cwrite!(printer, BOLD, "Useless testcases: ");
+        if useless.is_empty() {
+            cwriteln!(printer, GREEN, "none");
+        } else {
+            cwriteln!(
+                printer,
+                YELLOW,
+                "{}",
+                useless.iter().map(|&tc| matrix.testcases[tc]).join(", ")
+            );
+        }
+
+        cwrite!(printer, BOLD, "Redundant testcases: ");
+        if redundant.is_empty() {
+            cwriteln!(printer, GREEN, "none");
+        } else {
+            cwriteln!(
+                printer,
+                YELLOW,
+                "{}",
+                redundant.iter().map(|&tc| matrix.testcases[tc]).join(", ")
+            );
+        }
+
+        let fragile: Vec<_> = killed
+            .iter()
+            .filter_map(|&solution| match matrix.killers_of(solution)[..] {
+                [testcase] => Some((solution, testcase)),
+                _ => None,
+            })
+            .collect();
+        cwrite!(printer, BOLD, "Killed by a single testcase: ");
+        if fragile.is_empty() {
+            cwriteln!(printer, GREEN, "none");
+        } else {
+            cwriteln!(
+                printer,
+                YELLOW,
+                "{}",
+                fragile
+                    .iter()
+                    .map(|(solution, testcase)| format!(
+                        "S{} (testcase {})",
+                        solution, matrix.testcases[*testcase]
+                    ))
+                    .join(", ")
+            );
+        }
+
+        let (subset, smallest) = matrix.covering_subset();
+        cwrite!(printer, BOLD, "Covering subset: ");
+        println!(
+            "{} ({}/{} testcases kill all the {} failing solutions, {})",
+            subset.iter().map(|&tc| matrix.testcases[tc]).join(", "),
+            subset.len(),
+            matrix.testcases.len(),
+            killed.len(),
+            if smallest {
+                "the smallest possible"
+            } else {
+                "approximate, a smaller one may exist"
+            }
+        );
+        println!();
+    }
+
+    // The solutions that pass everything are most likely the correct ones, tell the user in case
+    // some of them were expected to fail.
+    if !killed_by_nothing.is_empty() {
+        cwrite!(printer, BOLD, "Never killed: ");
+        println!(
+            "{}",
+            killed_by_nothing
+                .iter()
+                .map(|s| format!("S{}", s))
+                .join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Collect the results of the solutions on the testcases of the subtask, including the ones owned
+/// by other subtasks.
+fn build_matrix(state: &UIState, solutions: &[PathBuf], subtask_id: SubtaskId) -> SubtaskMatrix {
+    let subtask = &state.task.subtasks[&subtask_id];
+    let testcases: Vec<_> = subtask.testcase_ids().sorted().collect();
+    // The results of a testcase are stored in the subtask that owns it.
+    let owners: Vec<_> = testcases
+        .iter()
+        .map(|testcase| {
+            state
+                .task
+                .subtasks
+                .values()
+                .find(|st| st.testcases.contains_key(testcase))
+                .map(|st| st.id)
+        })
+        .collect();
+    let results = solutions
+        .iter()
+        .map(|solution| {
+            let evaluation = &state.evaluations[solution];
+            testcases
+                .iter()
+                .zip(owners.iter())
+                .map(|(testcase, owner)| {
+                    owner
+                        .and_then(|owner| evaluation.subtasks.get(&owner))
+                        .and_then(|st| st.testcases.get(testcase))
+                        .and_then(|tc| (&tc.status).into())
+                })
+                .collect()
+        })
+        .collect();
+    SubtaskMatrix { testcases, results }
+}
+
+/// Print the matrix with a row for each testcase and a column for each solution.
+fn print_matrix(
+    matrix: &SubtaskMatrix,
+    redundant: &[usize],
+    useless: &[usize],
+    printer: &mut StdoutPrinter,
+) {
+    let headers: Vec<_> = (0..matrix.results.len())
+        .map(|solution| format!("S{}", solution))
+        .collect();
+    print!("{:>8}", "");
+    for header in &headers {
+        cwrite!(printer, BOLD, " {:>2}", header);
+    }
+    println!();
+    for (position, testcase) in matrix.testcases.iter().enumerate() {
+        cwrite!(printer, BOLD, "{:>8}", format!("#{}", testcase));
+        for (header, results) in headers.iter().zip(matrix.results.iter()) {
+            let width = header.len().max(2);
+            let (code, color) = match &results[position] {
+                None => ("?", &*YELLOW),
+                Some(TestcaseEvaluationResult::Accepted) => (".", &*GREEN),
+                Some(result) => (result_code(result), &*RED),
+            };
+            cwrite!(printer, color, " {:>width$}", code, width = width);
+        }
+        if useless.contains(&position) {
+            cwrite!(printer, YELLOW, "  useless");
+        } else if redundant.contains(&position) {
+            cwrite!(printer, YELLOW, "  redundant");
+        }
+        println!();
+    }
+}
+
+/// A short code for the result of a solution on a testcase.
+fn result_code(result: &TestcaseEvaluationResult) -> &'static str {
+    match result {
+        TestcaseEvaluationResult::Accepted => "AC",
+        TestcaseEvaluationResult::Partial => "PA",
+        TestcaseEvaluationResult::WrongAnswer => "WA",
+        TestcaseEvaluationResult::TimeLimitExceeded => "TL",
+        TestcaseEvaluationResult::WallTimeLimitExceeded => "WT",
+        TestcaseEvaluationResult::MemoryLimitExceeded => "ML",
+        TestcaseEvaluationResult::RuntimeError => "RE",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a matrix from a row for each testcase, with a character for each solution: `x` if the
+    /// testcase kills the solution, `.` if the solution is accepted and `?` if it's not evaluated.
+    fn matrix(rows: &[&str]) -> SubtaskMatrix {
+        let solutions = rows.first().map_or(0, |row| row.len());
+        let results = (0..solutions)
+            .map(|solution| {
+                rows.iter()
+                    .map(|row| match row.as_bytes()[solution] {
+                        b'x' => Some(TestcaseEvaluationResult::WrongAnswer),
+                        b'.' => Some(TestcaseEvaluationResult::Accepted),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect();
+        SubtaskMatrix {
+            testcases: (0..rows.len() as TestcaseId).collect(),
+            results,
+        }
+    }
+
+    #[test]
+    fn test_kills() {
+        let matrix = matrix(&["x.?", ".xx"]);
+        assert!(matrix.kills(0, 0));
+        assert!(!matrix.kills(0, 1));
+        assert!(!matrix.kills(0, 2));
+        assert_eq!(matrix.killed_by(1), BTreeSet::from([1, 2]));
+        assert_eq!(matrix.killers_of(0), vec![0]);
+        assert_eq!(matrix.killers_of(2), vec![1]);
+    }
+
+    #[test]
+    fn test_useless_and_redundant() {
+        let matrix = matrix(&["xx.", ".x.", "...", "..?", "..x"]);
+        assert_eq!(matrix.useless_testcases(), vec![2, 3]);
+        assert_eq!(matrix.redundant_testcases(), vec![1]);
+    }
+
+    #[test]
+    fn test_redundant_each_other() {
+        // two identical testcases are both redundant, but only one of them can be removed
+        let matrix = matrix(&["x.", "x.", ".x"]);
+        assert_eq!(matrix.redundant_testcases(), vec![0, 1]);
+        assert_eq!(matrix.covering_subset(), (vec![0, 2], true));
+    }
+
+    #[test]
+    fn test_covering_subset_nothing_killed() {
+        let matrix = matrix(&["..", ".."]);
+        assert_eq!(matrix.covering_subset(), (vec![], true));
+    }
+
+    #[test]
+    fn test_covering_subset_smallest() {
+        // the greedy choice picks the first testcase, ending with three testcases
+        let matrix = matrix(&["xx..", "...x", ".xx.", "x..x"]);
+        let kill_sets: Vec<_> = (0..4).map(|tc| matrix.killed_by(tc)).collect();
+        assert_eq!(greedy_cover(&kill_sets), vec![0, 1, 2]);
+        assert_eq!(matrix.covering_subset(), (vec![2, 3], true));
+    }
+
+    #[test]
+    fn test_covering_subset_approximate() {
+        // too many testcases for the exact search, each kills a different solution
+        let rows: Vec<String> = (0..=EXACT_COVER_MAX_TESTCASES)
+            .map(|tc| {
+                (0..=EXACT_COVER_MAX_TESTCASES)
+                    .map(|solution| if solution == tc { 'x' } else { '.' })
+                    .collect()
+            })
+            .collect();
+        let rows: Vec<_> = rows.iter().map(String::as_str).collect();
+        let matrix = matrix(&rows);
+        let (subset, smallest) = matrix.covering_subset();
+        assert!(!smallest);
+        assert_eq!(subset, (0..=EXACT_COVER_MAX_TESTCASES).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,699 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Error};
+use clap::Parser;
+use serde_json::{json, Value};
+use url::Url;
+
+use task_maker_diagnostics::{CodeSpan, Diagnostic, DiagnosticLevel};
+use task_maker_format::ioi::italian_yaml::{
+    check_task_yaml, CasesGenManager, CasesGenManagerKind, CasesGenOutline, TASK_YAML_KEYS,
+};
+
+/// The JSON-RPC error code of an unknown method.
+const METHOD_NOT_FOUND: i64 = -32601;
+/// The JSON-RPC error code of a request that failed.
+const REQUEST_FAILED: i64 = -32803;
+
+/// The commands of the `cases.gen` files.
+const CASES_GEN_COMMANDS: &[&str] = &[
+    "GEN",
+    "VAL",
+    "CONSTRAINT",
+    "SUBTASK",
+    "COPY",
+    "RUN",
+    "MULTIGEN",
+    "FOR",
+    "END",
+    "INCLUDE",
+];
+
+#[derive(Parser, Debug)]
+pub struct LspOpt {
+    /// Communicate using stdin and stdout.
+    ///
+    /// This is the only supported transport, the flag is accepted since many editors pass it.
+    #[clap(long)]
+    pub stdio: bool,
+}
+
+/// The kind of the files handled by the language server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DocumentKind {
+    /// A `gen/cases.gen` file.
+    CasesGen,
+    /// A `task.yaml` file.
+    TaskYaml,
+}
+
+/// A file opened in the editor.
+#[derive(Debug)]
+struct Document {
+    /// The path of the file.
+    path: PathBuf,
+    /// The kind of the file.
+    kind: DocumentKind,
+    /// The current content of the file in the editor.
+    content: String,
+    /// The outline of the file, if it is a `cases.gen`.
+    outline: Option<CasesGenOutline>,
+}
+
+/// The state of the language server.
+#[derive(Debug, Default)]
+struct LanguageServer {
+    /// The files opened in the editor, indexed by URI.
+    documents: HashMap<String, Document>,
+    /// Whether the client asked to shut down the server.
+    shutdown: bool,
+}
+
+/// Run a Language Server for the `cases.gen` and `task.yaml` files, communicating with the editor
+/// over stdin and stdout.
+pub fn main_lsp(_opt: LspOpt) -> Result<(), Error> {
+    let stdin = std::io::stdin();
+    let mut input = stdin.lock();
+    let stdout = std::io::stdout();
+    let mut output = stdout.lock();
+    let mut server = LanguageServer::default();
+    while let Some(message) = read_message(&mut input)? {
+        let method = message["method"].as_str().unwrap_or_default();
+        if method == "exit" {
+            if !server.shutdown {
+                bail!("The client exited without shutting down the server");
+            }
+            return Ok(());
+        }
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        match message.get("id") {
+            Some(id) => {
+                let response = match server.handle_request(method, params) {
+                    Some(Ok(result)) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Some(Err(err)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {
+                            "code": REQUEST_FAILED,
+                            "message": format!("{:#}", err),
+                        },
+                    }),
+                    None => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {
+                            "code": METHOD_NOT_FOUND,
+                            "message": format!("Unknown method {}", method),
+                        },
+                    }),
+                };
+                write_message(&mut output, &response)?;
+            }
+            None => match server.handle_notification(method, params) {
+                Ok(notifications) => {
+                    for notification in notifications {
+                        write_message(&mut output, &notification)?;
+                    }
+                }
+                Err(err) => warn!("Failed to handle {}: {:?}", method, err),
+            },
+        }
+    }
+    Ok(())
+}
+
+impl LanguageServer {
+    /// Handle a request of the client, returning its result. Returns `None` if the method is not
+    /// supported.
+    fn handle_request(&mut self, method: &str, params: Value) -> Option<Result<Value, Error>> {
+        let result = match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    // The full content of the file is sent at every change.
+                    "textDocumentSync": 1,
+                    "completionProvider": { "triggerCharacters": ["$", ":", " "] },
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                },
+                "serverInfo": {
+                    "name": "task-maker-tools lsp",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/completion" => self.completion(&params),
+            "textDocument/hover" => self.hover(&params),
+            "textDocument/definition" => self.definition(&params),
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    /// Handle a notification of the client, returning the notifications to send back.
+    fn handle_notification(&mut self, method: &str, params: Value) -> Result<Vec<Value>, Error> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        match method {
+            "textDocument/didOpen" => {
+                let content = params["textDocument"]["text"]
+                    .as_str()
+                    .context("Missing text of the document")?;
+                self.update_document(&uri, content.to_string())
+            }
+            "textDocument/didChange" => {
+                let content = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                    .context("Missing text of the document")?;
+                self.update_document(&uri, content.to_string())
+            }
+            // The files referenced by the document may have changed on disk.
+            "textDocument/didSave" => match self.documents.get(&uri) {
+                Some(document) => {
+                    let content = document.content.clone();
+                    self.update_document(&uri, content)
+                }
+                None => Ok(vec![]),
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                Ok(vec![publish_diagnostics(&uri, vec![])])
+            }
+            _ => Ok(vec![]),
+        }
+    }
+
+    /// Store the new content of a document, returning the notification with its diagnostics.
+    fn update_document(&mut self, uri: &str, content: String) -> Result<Vec<Value>, Error> {
+        let path = Url::parse(uri)
+            .ok()
+            .and_then(|url| url.to_file_path().ok())
+            .ok_or_else(|| anyhow!("Unsupported document URI: {}", uri))?;
+        let kind = match path.file_name().and_then(|name| name.to_str()) {
+            Some("cases.gen") => DocumentKind::CasesGen,
+            Some("task.yaml") => DocumentKind::TaskYaml,
+            _ => return Ok(vec![]),
+        };
+        let (outline, diagnostics) = match kind {
+            DocumentKind::CasesGen => {
+                let outline = CasesGenOutline::new(&path, &content);
+                let diagnostics = outline.diagnostics.clone();
+                (Some(outline), diagnostics)
+            }
+            DocumentKind::TaskYaml => (None, check_task_yaml(&path, &content)),
+        };
+        let diagnostics = diagnostics
+            .iter()
+            .map(|diagnostic| lsp_diagnostic(&content, &path, diagnostic))
+            .collect();
+        self.documents.insert(
+            uri.to_string(),
+            Document {
+                path,
+                kind,
+                content,
+                outline,
+            },
+        );
+        Ok(vec![publish_diagnostics(uri, diagnostics)])
+    }
+
+    /// Find the document and the byte offset of the position of a request.
+    fn document_position(&self, params: &Value) -> Result<Option<(&Document, usize)>, Error> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .context("Missing document URI")?;
+        let document = match self.documents.get(uri) {
+            Some(document) => document,
+            None => return Ok(None),
+        };
+        let offset = position_to_offset(&document.content, &params["position"])
+            .context("Invalid position")?;
+        Ok(Some((document, offset)))
+    }
+
+    /// Handle a `textDocument/completion` request.
+    fn completion(&self, params: &Value) -> Result<Value, Error> {
+        let (document, offset) = match self.document_position(params)? {
+            Some(position) => position,
+            None => return Ok(Value::Null),
+        };
+        let line_start = line_start(&document.content, offset);
+        let prefix = &document.content[line_start..offset];
+        // The word being typed, which will be replaced by the completion.
+        let word_start = prefix
+            .rfind(|c: char| c.is_whitespace() || c == ':')
+            .map_or(0, |index| index + 1);
+        let word = &prefix[word_start..];
+        let range = json!({
+            "start": offset_to_position(&document.content, line_start + word_start),
+            "end": offset_to_position(&document.content, offset),
+        });
+        let item = |label: &str, kind: u8, detail: &str| {
+            json!({
+                "label": label,
+                "kind": kind,
+                "detail": detail,
+                "textEdit": { "range": range, "newText": label },
+            })
+        };
+        let items: Vec<_> = match document.kind {
+            DocumentKind::CasesGen => {
+                let outline = match &document.outline {
+                    Some(outline) => outline,
+                    None => return Ok(Value::Null),
+                };
+                if word.starts_with('$') {
+                    outline
+                        .variables()
+                        .iter()
+                        .map(|variable| item(&format!("${}", variable), 6, "variable"))
+                        .collect()
+                } else if let Some(command) = prefix.trim_start().strip_prefix(':') {
+                    let words: Vec<_> = command[..command.len() - word.len()]
+                        .split_whitespace()
+                        .collect();
+                    match words.as_slice() {
+                        [] => CASES_GEN_COMMANDS
+                            .iter()
+                            .map(|&command| item(command, 14, "command"))
+                            .collect(),
+                        ["GEN" | "RUN" | "MULTIGEN"] => outline
+                            .manager_names(CasesGenManagerKind::Generator)
+                            .into_iter()
+                            .map(|name| item(name, 3, "generator"))
+                            .collect(),
+                        ["VAL"] => outline
+                            .manager_names(CasesGenManagerKind::Validator)
+                            .into_iter()
+                            .map(|name| item(name, 3, "validator"))
+                            .collect(),
+                        _ => vec![],
+                    }
+                } else {
+                    vec![]
+                }
+            }
+            DocumentKind::TaskYaml => {
+                if word_start == 0 && !prefix.contains(':') {
+                    TASK_YAML_KEYS
+                        .iter()
+                        .map(|&(key, description)| item(key, 5, description))
+                        .collect()
+                } else {
+                    vec![]
+                }
+            }
+        };
+        Ok(Value::Array(items))
+    }
+
+    /// Handle a `textDocument/hover` request.
+    fn hover(&self, params: &Value) -> Result<Value, Error> {
+        let (document, offset) = match self.document_position(params)? {
+            Some(position) => position,
+            None => return Ok(Value::Null),
+        };
+        let markdown = match document.kind {
+            DocumentKind::CasesGen => match &document.outline {
+                Some(outline) => outline.manager_at(offset).map(describe_manager),
+                None => None,
+            },
+            DocumentKind::TaskYaml => {
+                let line_start = line_start(&document.content, offset);
+                let line = &document.content[line_start..];
+                let key = line.split(':').next().unwrap_or_default();
+                TASK_YAML_KEYS
+                    .iter()
+                    .find(|(name, _)| *name == key && offset <= line_start + key.len())
+                    .map(|(name, description)| format!("**{}**\n\n{}", name, description))
+            }
+        };
+        Ok(match markdown {
+            Some(markdown) => json!({ "contents": { "kind": "markdown", "value": markdown } }),
+            None => Value::Null,
+        })
+    }
+
+    /// Handle a `textDocument/definition` request. From a usage of a generator or a validator it
+    /// goes to its declaration, and from the declaration to its source file.
+    fn definition(&self, params: &Value) -> Result<Value, Error> {
+        let (document, offset) = match self.document_position(params)? {
+            Some(position) => position,
+            None => return Ok(Value::Null),
+        };
+        let manager = match document
+            .outline
+            .as_ref()
+            .and_then(|outline| outline.manager_at(offset))
+        {
+            Some(manager) => manager,
+            None => return Ok(Value::Null),
+        };
+        let is_declaration = manager.span.file_offset() <= offset
+            && offset <= manager.span.file_offset() + manager.span.len();
+        if is_declaration {
+            let task_dir = document
+                .path
+                .parent()
+                .and_then(Path::parent)
+                .context("Invalid cases.gen path")?;
+            let source = task_dir.join(&manager.path);
+            let uri = Url::from_file_path(&source)
+                .map_err(|_| anyhow!("Invalid path: {}", source.display()))?;
+            let start = json!({ "line": 0, "character": 0 });
+            Ok(json!({ "uri": uri.as_str(), "range": { "start": start, "end": start } }))
+        } else {
+            let uri = params["textDocument"]["uri"].clone();
+            Ok(json!({ "uri": uri, "range": span_range(&document.content, &manager.span) }))
+        }
+    }
+}
+
+/// Describe a generator or a validator, in Markdown.
+fn describe_manager(manager: &CasesGenManager) -> String {
+    let kind = match manager.kind {
+        CasesGenManagerKind::Generator => "generator",
+        CasesGenManagerKind::Validator => "validator",
+    };
+    let args = if manager.args.is_empty() {
+        "no arguments".to_string()
+    } else {
+        format!("arguments `{}`", manager.args.join(" "))
+    };
+    format!(
+        "**{}** `{}`\n\nSource: `{}`, {}",
+        kind, manager.name, manager.path, args
+    )
+}
+
+/// Make the notification with the diagnostics of a document.
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+/// Convert a diagnostic of the document at the provided path to the LSP format.
+fn lsp_diagnostic(content: &str, path: &Path, diagnostic: &Diagnostic) -> Value {
+    let severity = match diagnostic.level() {
+        DiagnosticLevel::Error => 1,
+        DiagnosticLevel::Warning => 2,
+    };
+    let mut message = diagnostic.message().to_string();
+    if let Some(note) = diagnostic.note() {
+        message += &format!("\nNote: {}", note);
+    }
+    if let Some(help) = diagnostic.help() {
+        message += &format!("\nHelp: {}", help);
+    }
+    let range = match diagnostic
+        .code_spans()
+        .iter()
+        .find(|span| span.file_name() == path)
+    {
+        Some(span) => span_range(content, span),
+        None => {
+            let start = json!({ "line": 0, "character": 0 });
+            json!({ "start": start, "end": start })
+        }
+    };
+    json!({
+        "range": range,
+        "severity": severity,
+        "source": "task-maker",
+        "message": message,
+    })
+}
+
+/// The LSP range of a span of the content.
+fn span_range(content: &str, span: &CodeSpan) -> Value {
+    json!({
+        "start": offset_to_position(content, span.file_offset()),
+        "end": offset_to_position(content, span.file_offset() + span.len()),
+    })
+}
+
+/// The offset of the start of the line containing the provided offset.
+fn line_start(content: &str, offset: usize) -> usize {
+    content[..offset].rfind('\n').map_or(0, |index| index + 1)
+}
+
+/// Convert an LSP position, a line and a column in UTF-16 code units, to a byte offset in the
+/// content.
+fn position_to_offset(content: &str, position: &Value) -> Option<usize> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+    let mut offset = 0;
+    for (index, text) in content.split('\n').enumerate() {
+        if index == line {
+            let mut units = 0;
+            for (byte, c) in text.char_indices() {
+                if units >= character {
+                    return Some(offset + byte);
+                }
+                units += c.len_utf16();
+            }
+            return Some(offset + text.len());
+        }
+        offset += text.len() + 1;
+    }
+    None
+}
+
+/// Convert a byte offset in the content to an LSP position.
+fn offset_to_position(content: &str, offset: usize) -> Value {
+    let before = content.get(..offset).unwrap_or(content);
+    let line = before.matches('\n').count();
+    let start = line_start(before, before.len());
+    let character = before[start..].encode_utf16().count();
+    json!({ "line": line, "character": character })
+}
+
+/// Read a message of the base protocol: some headers, an empty line and the JSON content.
+/// Returns `None` at the end of the input.
+fn read_message(input: &mut impl BufRead) -> Result<Option<Value>, Error> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = Some(
+                    value
+                        .trim()
+                        .parse::<usize>()
+                        .context("Invalid Content-Length")?,
+                );
+            }
+        }
+    }
+    let length = length.context("Missing Content-Length header")?;
+    let mut content = vec![0; length];
+    input
+        .read_exact(&mut content)
+        .context("Failed to read the message")?;
+    let message = serde_json::from_slice(&content).context("Invalid JSON message")?;
+    Ok(Some(message))
+}
+
+/// Write a message of the base protocol.
+fn write_message(output: &mut impl Write, message: &Value) -> Result<(), Error> {
+    let content = serde_json::to_string(message)?;
+    write!(
+        output,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )?;
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Build an LSP position.
+    fn position(line: usize, character: usize) -> Value {
+        json!({ "line": line, "character": character })
+    }
+
+    /// Make a server with a single open document, returning the server and the document URI.
+    fn open_document(path: &str, content: &str) -> (LanguageServer, String) {
+        let mut server = LanguageServer::default();
+        let uri = format!("file:///task/{}", path);
+        let params = json!({ "textDocument": { "uri": uri, "text": content } });
+        server
+            .handle_notification("textDocument/didOpen", params)
+            .unwrap();
+        (server, uri)
+    }
+
+    /// The labels of the items completed at the provided position.
+    fn complete(path: &str, content: &str, line: usize, character: usize) -> Vec<String> {
+        let (server, uri) = open_document(path, content);
+        let params =
+            json!({ "textDocument": { "uri": uri }, "position": position(line, character) });
+        let items = server.completion(&params).unwrap();
+        items
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    /// Frame some content as a message of the base protocol.
+    fn frame(content: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", content.len(), content)
+    }
+
+    #[test]
+    fn test_position_to_offset() {
+        let content = "ab\ncd\n";
+        assert_eq!(position_to_offset(content, &position(0, 0)), Some(0));
+        assert_eq!(position_to_offset(content, &position(0, 1)), Some(1));
+        assert_eq!(position_to_offset(content, &position(1, 0)), Some(3));
+        assert_eq!(position_to_offset(content, &position(1, 2)), Some(5));
+        assert_eq!(position_to_offset(content, &position(2, 0)), Some(6));
+        // Past the end of the line.
+        assert_eq!(position_to_offset(content, &position(0, 42)), Some(2));
+        // Past the end of the content.
+        assert_eq!(position_to_offset(content, &position(3, 0)), None);
+        assert_eq!(position_to_offset(content, &json!({ "line": 0 })), None);
+    }
+
+    #[test]
+    fn test_position_to_offset_utf16() {
+        // 'è' is 2 bytes and 1 UTF-16 unit, '😀' is 4 bytes and 2 UTF-16 units.
+        let content = "x\nè😀y";
+        assert_eq!(position_to_offset(content, &position(1, 0)), Some(2));
+        assert_eq!(position_to_offset(content, &position(1, 1)), Some(4));
+        assert_eq!(position_to_offset(content, &position(1, 3)), Some(8));
+        assert_eq!(position_to_offset(content, &position(1, 4)), Some(9));
+    }
+
+    #[test]
+    fn test_offset_to_position() {
+        let content = "ab\ncd\n";
+        assert_eq!(offset_to_position(content, 0), position(0, 0));
+        assert_eq!(offset_to_position(content, 2), position(0, 2));
+        assert_eq!(offset_to_position(content, 3), position(1, 0));
+        assert_eq!(offset_to_position(content, 6), position(2, 0));
+        // Past the end of the content.
+        assert_eq!(offset_to_position(content, 42), position(2, 0));
+    }
+
+    #[test]
+    fn test_offset_to_position_utf16() {
+        let content = "x\nè😀y";
+        assert_eq!(offset_to_position(content, 4), position(1, 1));
+        assert_eq!(offset_to_position(content, 8), position(1, 3));
+        assert_eq!(offset_to_position(content, 9), position(1, 4));
+        for offset in [0, 2, 4, 8, 9] {
+            let position = offset_to_position(content, offset);
+            assert_eq!(position_to_offset(content, &position), Some(offset));
+        }
+    }
+
+    #[test]
+    fn test_read_message() {
+        let input = frame(r#"{"id":1}"#) + &frame(r#"{"id":"è"}"#);
+        let mut input = Cursor::new(input);
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!({ "id": 1 })));
+        assert_eq!(
+            read_message(&mut input).unwrap(),
+            Some(json!({ "id": "è" }))
+        );
+        assert_eq!(read_message(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_message_headers() {
+        let input = "content-length: 2\r\nContent-Type: application/vscode-jsonrpc\r\n\r\n{}";
+        let mut input = Cursor::new(input);
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!({})));
+    }
+
+    #[test]
+    fn test_read_message_invalid() {
+        let mut input = Cursor::new("Content-Type: foo\r\n\r\n{}");
+        assert!(read_message(&mut input).is_err());
+        let mut input = Cursor::new("Content-Length: nope\r\n\r\n{}");
+        assert!(read_message(&mut input).is_err());
+        // The input ends in the middle of the content.
+        let mut input = Cursor::new("Content-Length: 10\r\n\r\n{}");
+        assert!(read_message(&mut input).is_err());
+        let mut input = Cursor::new("Content-Length: 2\r\n\r\n{]");
+        assert!(read_message(&mut input).is_err());
+    }
+
+    #[test]
+    fn test_write_read_message() {
+        let message = json!({ "jsonrpc": "2.0", "result": "😀" });
+        let mut output = vec![];
+        write_message(&mut output, &message).unwrap();
+        let mut input = Cursor::new(output);
+        assert_eq!(read_message(&mut input).unwrap(), Some(message));
+    }
+
+    #[test]
+    fn test_completion_task_yaml() {
+        let labels = complete("task.yaml", "title: è😀\nna", 1, 2);
+        assert!(labels.contains(&"name".to_string()));
+        assert!(labels.contains(&"nome_breve".to_string()));
+        assert!(complete("task.yaml", "name: na", 0, 8).is_empty());
+    }
+
+    #[test]
+    fn test_completion_task_yaml_range() {
+        let (server, uri) = open_document("task.yaml", "title: è😀\nna");
+        let params = json!({ "textDocument": { "uri": uri }, "position": position(1, 2) });
+        let items = server.completion(&params).unwrap();
+        let range = &items[0]["textEdit"]["range"];
+        assert_eq!(range["start"], position(1, 0));
+        assert_eq!(range["end"], position(1, 2));
+    }
+
+    #[test]
+    fn test_completion_cases_gen() {
+        let commands = complete("gen/cases.gen", ":GEN gen gen/gen.py\n:", 1, 1);
+        assert_eq!(commands, CASES_GEN_COMMANDS);
+
+        let content =
+            ":GEN gen gen/gen.py N M seed\n:SUBTASK 10\n:RUN gen 1 2 3\n:RUN gen $N 2 3\n";
+        assert_eq!(complete("gen/cases.gen", content, 2, 8), vec!["gen"]);
+        assert!(complete("gen/cases.gen", content, 2, 10).is_empty());
+        let variables = complete("gen/cases.gen", content, 3, 11);
+        for variable in ["$N", "$M", "$seed", "$ST_NUM"] {
+            assert!(variables.contains(&variable.to_string()), "{:?}", variables);
+        }
+    }
+
+    #[test]
+    fn test_completion_unknown_document() {
+        let server = LanguageServer::default();
+        let params =
+            json!({ "textDocument": { "uri": "file:///nope" }, "position": position(0, 0) });
+        assert_eq!(server.completion(&params).unwrap(), Value::Null);
+    }
+}
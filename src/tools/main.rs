@@ -1,37 +1,51 @@
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches};
 
 use task_maker_rust::error::NiceError;
 use task_maker_rust::tools::add_solution_checks::main_add_solution_checks;
 use task_maker_rust::tools::booklet::main_booklet;
+use task_maker_rust::tools::cache::main_cache;
 use task_maker_rust::tools::clear::main_clear;
+use task_maker_rust::tools::config::main_config;
 use task_maker_rust::tools::find_bad_case::main_find_bad_case;
 use task_maker_rust::tools::fuzz_checker::main_fuzz_checker;
+use task_maker_rust::tools::fuzz_validator::main_fuzz_validator;
 use task_maker_rust::tools::gen_autocompletion::main_get_autocompletion;
+use task_maker_rust::tools::kill_matrix::main_kill_matrix;
+use task_maker_rust::tools::lsp::main_lsp;
 use task_maker_rust::tools::opt::{Opt, Tool};
 use task_maker_rust::tools::reset::main_reset;
 use task_maker_rust::tools::sandbox::main_sandbox;
 use task_maker_rust::tools::server::main_server;
+use task_maker_rust::tools::store::main_store;
 use task_maker_rust::tools::task_info::main_task_info;
 use task_maker_rust::tools::typescriptify::main_typescriptify;
 use task_maker_rust::tools::worker::main_worker;
 
 fn main() {
-    let base_opt = Opt::parse();
+    let matches = Opt::command().get_matches();
+    let mut base_opt = Opt::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    base_opt.load_config(&matches).nice_unwrap();
     base_opt.logger.enable_log();
 
     match base_opt.tool {
         Tool::Clear(opt) => main_clear(opt),
+        Tool::Config(opt) => main_config(opt),
         Tool::GenAutocompletion(opt) => main_get_autocompletion(opt),
         Tool::Server(opt) => main_server(opt),
         Tool::Worker(opt) => main_worker(opt),
         Tool::Typescriptify => main_typescriptify(),
         Tool::Reset(opt) => main_reset(opt),
+        Tool::Cache(opt) => main_cache(opt),
+        Tool::Store(opt) => main_store(opt),
         Tool::Sandbox(opt) => main_sandbox(opt),
         Tool::TaskInfo(opt) => main_task_info(opt),
         Tool::Booklet(opt) => main_booklet(opt, base_opt.logger),
         Tool::FuzzChecker(opt) => main_fuzz_checker(opt),
+        Tool::FuzzValidator(opt) => main_fuzz_validator(opt),
         Tool::FindBadCase(opt) => main_find_bad_case(opt),
         Tool::AddSolutionChecks(opt) => main_add_solution_checks(opt, base_opt.logger),
+        Tool::KillMatrix(opt) => main_kill_matrix(opt, base_opt.logger),
+        Tool::Lsp(opt) => main_lsp(opt),
         Tool::InternalSandbox => return task_maker_rust::main_sandbox(),
     }
     .nice_unwrap()
@@ -1,13 +1,19 @@
 pub mod add_solution_checks;
 pub mod booklet;
+pub mod cache;
 pub mod clear;
+pub mod config;
 pub mod find_bad_case;
 pub mod fuzz_checker;
+pub mod fuzz_validator;
 pub mod gen_autocompletion;
+pub mod kill_matrix;
+pub mod lsp;
 pub mod opt;
 pub mod reset;
 pub mod sandbox;
 pub mod server;
+pub mod store;
 pub mod task_info;
 pub mod typescriptify;
 pub mod worker;
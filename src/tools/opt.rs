@@ -1,14 +1,22 @@
-use clap::Parser;
+use anyhow::Error;
+use clap::{ArgMatches, Parser};
 
+use crate::config::{ApplyConfig, Config};
 use crate::tools::add_solution_checks::AddSolutionChecksOpt;
 use crate::tools::booklet::BookletOpt;
+use crate::tools::cache::CacheOpt;
 use crate::tools::clear::ClearOpt;
+use crate::tools::config::ConfigOpt;
 use crate::tools::find_bad_case::FindBadCaseOpt;
 use crate::tools::fuzz_checker::FuzzCheckerOpt;
+use crate::tools::fuzz_validator::FuzzValidatorOpt;
 use crate::tools::gen_autocompletion::GenAutocompletionOpt;
+use crate::tools::kill_matrix::KillMatrixOpt;
+use crate::tools::lsp::LspOpt;
 use crate::tools::reset::ResetOpt;
 use crate::tools::sandbox::SandboxOpt;
 use crate::tools::server::ServerOpt;
+use crate::tools::store::StoreOpt;
 use crate::tools::task_info::TaskInfoOpt;
 use crate::tools::worker::WorkerOpt;
 use crate::LoggerOpt;
@@ -28,6 +36,8 @@ pub struct Opt {
 pub enum Tool {
     /// Clear a task directory
     Clear(ClearOpt),
+    /// Print the configuration loaded from the configuration files, and where each value comes from
+    Config(ConfigOpt),
     /// Generate the autocompletion files for the shell
     GenAutocompletion(GenAutocompletionOpt),
     /// Spawn an instance of the server
@@ -40,6 +50,10 @@ pub enum Tool {
     ///
     /// Warning: no other instances of task-maker should be running when this flag is provided.
     Reset(ResetOpt),
+    /// Inspect the cache and the store, or evict part of the cache
+    Cache(CacheOpt),
+    /// Verify and repair the store of task-maker
+    Store(StoreOpt),
     /// Run a command inside a sandbox similar to the one used by task-maker
     Sandbox(SandboxOpt),
     /// Obtain the information about a task.
@@ -48,13 +62,89 @@ pub enum Tool {
     Booklet(BookletOpt),
     /// Fuzz the checker of a task.
     FuzzChecker(FuzzCheckerOpt),
+    /// Fuzz the validator of a task, checking that it rejects invalid inputs.
+    FuzzValidator(FuzzValidatorOpt),
     /// Generate and search for an input file that make a solution fail.
     FindBadCase(FindBadCaseOpt),
     /// Add the @check comments to the solutions.
     AddSolutionChecks(AddSolutionChecksOpt),
+    /// Analyze which testcases make the solutions fail, finding the redundant ones.
+    KillMatrix(KillMatrixOpt),
+    /// Run a language server for the cases.gen and task.yaml files.
+    Lsp(LspOpt),
     /// Run the sandbox instead of the normal task-maker.
     ///
     /// This option is left as undocumented as it's not part of the public API.
     #[clap(hide = true)]
     InternalSandbox,
 }
+
+impl Opt {
+    /// Replace the options of the tool not provided on the command line with the ones from the
+    /// configuration files.
+    pub fn load_config(&mut self, matches: &ArgMatches) -> Result<(), Error> {
+        let matches = match matches.subcommand() {
+            Some((_, matches)) => matches,
+            None => return Ok(()),
+        };
+        let find_task = match &self.tool {
+            Tool::Clear(opt) => Some(&opt.find_task),
+            Tool::Config(opt) => Some(&opt.find_task),
+            Tool::TaskInfo(opt) => Some(&opt.find_task),
+            Tool::FuzzChecker(opt) => Some(&opt.find_task),
+            Tool::FuzzValidator(opt) => Some(&opt.find_task),
+            Tool::FindBadCase(opt) => Some(&opt.find_task),
+            Tool::AddSolutionChecks(opt) => Some(&opt.find_task),
+            Tool::KillMatrix(opt) => Some(&opt.find_task),
+            _ => None,
+        };
+        let config = match (find_task, &self.tool) {
+            (Some(find_task), _) => {
+                Config::load(find_task.task_dir.as_deref(), find_task.max_depth)?
+            }
+            (None, Tool::Booklet(opt)) => {
+                let base = opt.task_dir.first().or(opt.contest_dir.as_ref());
+                Config::load(base.map(|p| p.as_path()), opt.max_depth)?
+            }
+            (None, _) => Config::load(None, 1)?,
+        };
+        let config = config.values()?;
+
+        match &mut self.tool {
+            Tool::Server(opt) => opt.storage.apply_config(&config, matches)?,
+            Tool::Worker(opt) => opt.storage.apply_config(&config, matches)?,
+            Tool::Reset(opt) => opt.storage.apply_config(&config, matches)?,
+            Tool::Cache(opt) => opt.storage.apply_config(&config, matches)?,
+            Tool::Store(opt) => opt.storage.apply_config(&config, matches)?,
+            Tool::Booklet(opt) => {
+                opt.ui.apply_config(&config, matches)?;
+                opt.execution.apply_config(&config, matches)?;
+                opt.storage.apply_config(&config, matches)?;
+            }
+            Tool::AddSolutionChecks(opt) => {
+                opt.ui.apply_config(&config, matches)?;
+                opt.execution.apply_config(&config, matches)?;
+                opt.storage.apply_config(&config, matches)?;
+            }
+            Tool::KillMatrix(opt) => {
+                opt.ui.apply_config(&config, matches)?;
+                opt.execution.apply_config(&config, matches)?;
+                opt.storage.apply_config(&config, matches)?;
+            }
+            Tool::FuzzChecker(opt) => {
+                opt.execution.apply_config(&config, matches)?;
+                opt.storage.apply_config(&config, matches)?;
+            }
+            Tool::FuzzValidator(opt) => {
+                opt.execution.apply_config(&config, matches)?;
+                opt.storage.apply_config(&config, matches)?;
+            }
+            Tool::FindBadCase(opt) => {
+                opt.execution.apply_config(&config, matches)?;
+                opt.storage.apply_config(&config, matches)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,13 @@
+use std::path::PathBuf;
 use std::sync::Arc;
 
-use anyhow::{Context, Error};
+use anyhow::{bail, Context, Error};
 use clap::Parser;
 
 use task_maker_cache::Cache;
-use task_maker_exec::executors::RemoteExecutor;
+use task_maker_exec::executors::{AuditLog, RemoteExecutor};
+use task_maker_exec::tls::server_config;
+use task_maker_exec::SchedulingPolicy;
 use task_maker_store::FileStore;
 
 use crate::StorageOpt;
@@ -27,6 +30,34 @@ pub struct ServerOpt {
     #[clap(long = "worker-password")]
     pub worker_password: Option<String>,
 
+    /// Accept only TLS connections, using this certificate chain (PEM file)
+    #[clap(long = "tls-cert", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// Private key of the TLS certificate (PEM file)
+    #[clap(long = "tls-key", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Require the clients and the workers to present a certificate signed by this CA (PEM file).
+    /// Without --tokens, they are identified by the common name of their certificate
+    #[clap(long = "tls-client-ca", requires = "tls_cert")]
+    pub tls_client_ca: Option<PathBuf>,
+
+    /// File with the tokens the clients and the workers should authenticate with (TOML file).
+    /// It's read again at every connection, so the tokens can be revoked without a restart.
+    /// Requires TLS or the passwords, since the tokens would be sent in cleartext.
+    #[clap(long = "tokens")]
+    pub tokens: Option<PathBuf>,
+
+    /// Append to this file who connects to the server and which DAGs they submit
+    #[clap(long = "audit-log")]
+    pub audit_log: Option<PathBuf>,
+
+    /// File with the policy for sharing the workers between the clients (TOML file): their
+    /// weights, the maximum priority they can ask for and how many workers they can use.
+    #[clap(long = "policy")]
+    pub policy: Option<PathBuf>,
+
     #[clap(flatten, next_help_heading = Some("STORAGE"))]
     pub storage: StorageOpt,
 }
@@ -41,11 +72,41 @@ pub fn main_server(opt: ServerOpt) -> Result<(), Error> {
             opt.storage.max_cache * 1024 * 1024,
             opt.storage.min_cache * 1024 * 1024,
         )
-        .context("Cannot create the file store")?,
+        .context("Cannot create the file store")?
+        .with_compression(opt.storage.store_compression)
+        .with_pin_budget(opt.storage.max_pinned * 1024 * 1024)
+        .with_shared(opt.storage.shared_file_store_dir()),
     );
-    let cache = Cache::new(store_path.join("cache")).context("Cannot create the cache")?;
+    let cache = Cache::new(store_path.join("cache"))
+        .context("Cannot create the cache")?
+        .with_shared(opt.storage.shared_cache_dir());
+
+    let tls = match (&opt.tls_cert, &opt.tls_key) {
+        (Some(cert), Some(key)) => {
+            if opt.client_password.is_some() || opt.worker_password.is_some() {
+                bail!("The passwords cannot be used with TLS, use --tokens instead");
+            }
+            let config = server_config(cert, key, opt.tls_client_ca.as_deref())
+                .context("Cannot load the TLS configuration")?;
+            Some(config)
+        }
+        _ => None,
+    };
+    let audit = match &opt.audit_log {
+        Some(path) => Some(AuditLog::open(path)?),
+        None => None,
+    };
+
+    let policy = match &opt.policy {
+        Some(path) => SchedulingPolicy::load(path)?,
+        None => SchedulingPolicy::default(),
+    };
 
-    let remote_executor = RemoteExecutor::new(file_store);
+    let remote_executor = RemoteExecutor::new(file_store)
+        .with_tls(tls)
+        .with_tokens(opt.tokens)
+        .with_audit_log(audit)
+        .with_scheduling_policy(policy);
 
     remote_executor.start(
         &opt.client_addr,
@@ -0,0 +1,84 @@
+use anyhow::{Context, Error};
+use clap::Parser;
+
+use task_maker_cache::Cache;
+use task_maker_store::{FileStore, FileStoreKey};
+
+use crate::StorageOpt;
+
+#[derive(Parser, Debug, Clone)]
+pub struct StoreOpt {
+    #[clap(flatten, next_help_heading = Some("STORAGE"))]
+    pub storage: StorageOpt,
+
+    /// What to do with the store
+    #[clap(subcommand)]
+    pub command: StoreCommand,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub enum StoreCommand {
+    /// Verify the content of all the files in the store, repairing the problems found
+    ///
+    /// The corrupted files are removed, the index is reconciled with the content of the disk, and
+    /// the cache entries that refer to files no longer in the store are purged.
+    Fsck,
+}
+
+/// Handler of the `store` tool.
+pub fn main_store(opt: StoreOpt) -> Result<(), Error> {
+    let store_path = opt.storage.store_dir();
+    let file_store = FileStore::new(
+        store_path.join("store"),
+        opt.storage.max_cache * 1024 * 1024,
+        opt.storage.min_cache * 1024 * 1024,
+    )
+    .context("Cannot create the file store")?
+    .with_compression(opt.storage.store_compression)
+    .with_pin_budget(opt.storage.max_pinned * 1024 * 1024);
+
+    match opt.command {
+        StoreCommand::Fsck => {
+            let report = file_store.fsck().context("Failed to verify the store")?;
+            println!("Checked {} files", report.checked);
+            print_keys("Corrupted files removed", &report.corrupted);
+            print_keys("Missing files removed from the index", &report.missing);
+            print_keys("Untracked files added to the index", &report.untracked);
+            print_keys("Files with the wrong size in the index", &report.resized);
+            print_keys("Writable files made read-only", &report.writable);
+            if !report.orphans.is_empty() {
+                println!("Paths not belonging to the store removed:");
+                for path in &report.orphans {
+                    println!("    {}", path.display());
+                }
+            }
+
+            let mut cache =
+                Cache::new(store_path.join("cache")).context("Cannot create the cache")?;
+            let purged = cache
+                .evict(|entry| {
+                    entry
+                        .files
+                        .iter()
+                        .any(|key| file_store.file_size(key).is_none())
+                })
+                .context("Failed to purge the cache")?;
+            println!("Purged {} cache entries referring to missing files", purged);
+            if report.is_clean() {
+                println!("The store is clean");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Print a list of keys with its title, if not empty.
+fn print_keys(title: &str, keys: &[FileStoreKey]) {
+    if keys.is_empty() {
+        return;
+    }
+    println!("{}:", title);
+    for key in keys {
+        println!("    {}", key);
+    }
+}
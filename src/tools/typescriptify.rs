@@ -8,11 +8,14 @@ use regex::Regex;
 use typescript_definitions::TypeScriptifyTrait;
 
 use task_maker_dag::{ExecutionResourcesUsage, ExecutionResult, ExecutionStatus, File};
-use task_maker_exec::{ClientInfo, ExecutorStatus, ExecutorWorkerStatus, WorkerCurrentJobStatus};
+use task_maker_exec::{
+    ClientInfo, ExecutorClientStatus, ExecutorStatus, ExecutorWorkerStatus, WorkerCurrentJobStatus,
+};
 use task_maker_format::ioi::{
-    BatchTypeData, Booklet, BookletConfig, Checker, CommunicationTypeData, IOITask, InputGenerator,
-    InputValidator, OutputGenerator, Statement, StatementConfig, SubtaskInfo, TaskInfoScoring,
-    TaskInfoStatement, TaskType, TestcaseInfo, TestcaseScoreAggregator,
+    BatchTypeData, Booklet, BookletConfig, Checker, CommunicationTypeData, ConstraintCoverage,
+    IOITask, InputGenerator, InputValidator, MultiInputGenerator, OutputGenerator, Statement,
+    StatementConfig, SubtaskInfo, TaskInfoScoring, TaskInfoStatement, TaskType, TestcaseInfo,
+    TestcaseScoreAggregator,
 };
 use task_maker_format::ioi::{IOITaskInfo, TaskInfoAttachment, TaskInfoLimits, TaskInfoSubtask};
 use task_maker_format::terry::TerryTaskInfo;
@@ -57,12 +60,14 @@ pub fn main_typescriptify() -> Result<(), Error> {
     export_ts!(ExecutorWorkerStatus<SystemTime>);
     export_ts!(WorkerCurrentJobStatus<SystemTime>);
     export_ts!(ClientInfo);
+    export_ts!(ExecutorClientStatus);
     export_ts!(IOITask);
     export_ts!(TerryTask);
     export_ts!(SolutionOutcome);
     export_ts!(ExecutionResult);
     export_ts!(TaskType);
     export_ts!(SubtaskInfo);
+    export_ts!(ConstraintCoverage);
     export_ts!(TestcaseInfo);
     export_ts!(TestcaseScoreAggregator);
     export_ts!(GraderMap);
@@ -85,6 +90,7 @@ pub fn main_typescriptify() -> Result<(), Error> {
     export_ts!(Checker);
     export_ts!(SourceFile);
     export_ts!(InputGenerator);
+    export_ts!(MultiInputGenerator);
     export_ts!(InputValidator);
     export_ts!(OutputGenerator);
     export_ts!(TaskInfo);
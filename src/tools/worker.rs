@@ -1,13 +1,13 @@
+use std::path::PathBuf;
 use std::sync::Arc;
 
-use anyhow::{bail, Context, Error};
+use anyhow::{Context, Error};
 use clap::Parser;
 
-use task_maker_exec::executors::{RemoteEntityMessage, RemoteEntityMessageResponse};
 use task_maker_exec::Worker;
 use task_maker_store::FileStore;
 
-use crate::remote::connect_to_remote_server;
+use crate::remote::{connect_to_remote_server, load_token};
 use crate::sandbox::ToolsSandboxRunner;
 use crate::StorageOpt;
 
@@ -23,13 +23,15 @@ pub struct WorkerOpt {
     #[clap(long)]
     pub name: Option<String>,
 
+    /// File with the token to authenticate with the server. By default the token is read from the
+    /// TM_TOKEN environment variable
+    #[clap(long = "token-file")]
+    pub token_file: Option<PathBuf>,
+
     #[clap(flatten, next_help_heading = Some("STORAGE"))]
     pub storage: StorageOpt,
 }
 
-/// Version of task-maker
-const VERSION: &str = env!("CARGO_PKG_VERSION");
-
 /// Entry point for the worker.
 pub fn main_worker(opt: WorkerOpt) -> Result<(), Error> {
     let store_path = opt.storage.store_dir();
@@ -39,27 +41,20 @@ pub fn main_worker(opt: WorkerOpt) -> Result<(), Error> {
             opt.storage.max_cache * 1024 * 1024,
             opt.storage.min_cache * 1024 * 1024,
         )
-        .context("Cannot create the file store")?,
+        .context("Cannot create the file store")?
+        .with_compression(opt.storage.store_compression)
+        .with_pin_budget(opt.storage.max_pinned * 1024 * 1024)
+        .with_shared(opt.storage.shared_file_store_dir()),
     );
     let sandbox_path = store_path.join("sandboxes");
 
     let name = opt
         .name
         .unwrap_or_else(|| format!("{}@{}", whoami::username(), whoami::hostname()));
-    let (executor_tx, executor_rx) = connect_to_remote_server(&opt.server_addr, 27183)
-        .context("Failed to connect to the server")?;
-    executor_tx
-        .send(RemoteEntityMessage::Welcome {
-            name: name.clone(),
-            version: VERSION.into(),
-        })
-        .context("Cannot send welcome to the server")?;
-    if let RemoteEntityMessageResponse::Rejected(err) = executor_rx
-        .recv()
-        .context("Remote executor didn't reply to the welcome message")?
-    {
-        bail!("The server rejected the worker connection: {}", err);
-    }
+    let token = load_token(opt.token_file.as_deref())?;
+    let (executor_tx, executor_rx) =
+        connect_to_remote_server(&opt.server_addr, 27183, name.clone(), token)
+            .context("Failed to connect to the server")?;
 
     let name = if let Some(wid) = opt.worker_id {
         format!("{} {}", name, wid)
@@ -71,10 +66,11 @@ pub fn main_worker(opt: WorkerOpt) -> Result<(), Error> {
         name,
         file_store,
         sandbox_path,
-        executor_tx.change_type(),
-        executor_rx.change_type(),
+        executor_tx,
+        executor_rx,
         Arc::new(ToolsSandboxRunner::default()),
     )
-    .context("Failed to start worker")?;
+    .context("Failed to start worker")?
+    .with_transfer_compression(true);
     worker.work()
 }
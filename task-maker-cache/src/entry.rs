@@ -1,13 +1,14 @@
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use std::time::SystemTime;
 use task_maker_dag::{
     Execution, ExecutionGroup, ExecutionLimits, ExecutionResult, ExecutionStatus, FileUuid,
 };
 use task_maker_store::{FileStore, FileStoreHandle, FileStoreKey};
 
 /// The entry relative to an execution inside the group.
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct CacheEntryItem {
     /// The result of the `Execution`.
     pub result: ExecutionResult,
@@ -31,10 +32,18 @@ pub struct CacheEntryItem {
 ///
 /// The entry is composed by a number of item, one for each execution in the group. The order of the
 /// items is the same as the order of the executions in the group.
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct CacheEntry {
     /// The items of the entry, one for each execution in the group, in the same order.
     pub items: Vec<CacheEntryItem>,
+    /// The description of the group that produced this entry.
+    pub description: String,
+    /// The name of the tag of the group that produced this entry.
+    pub tag: Option<String>,
+    /// The directory of the task whose evaluation produced this entry.
+    pub task_dir: Option<PathBuf>,
+    /// When this entry has been inserted in the cache.
+    pub created: SystemTime,
 }
 
 impl CacheEntryItem {
@@ -82,7 +91,24 @@ impl CacheEntry {
         for (exec, res) in group.executions.iter().zip(result.into_iter()) {
             items.push(CacheEntryItem::from_execution(exec, file_keys, res));
         }
-        CacheEntry { items }
+        CacheEntry {
+            items,
+            description: group.description.clone(),
+            tag: group.tag().map(|tag| tag.name),
+            task_dir: group.config().task_dir.clone(),
+            created: SystemTime::now(),
+        }
+    }
+
+    /// The keys of all the files produced by the executions of this entry.
+    pub fn files(&self) -> Vec<FileStoreKey> {
+        let mut files = Vec::new();
+        for item in &self.items {
+            files.extend(item.stdout.iter().cloned());
+            files.extend(item.stderr.iter().cloned());
+            files.extend(item.outputs.values().cloned());
+        }
+        files
     }
 
     pub fn same_limits(&self, other: &CacheEntry) -> bool {
@@ -173,6 +199,7 @@ impl CacheEntry {
                 check_limit!($left.cpu_time, $right.cpu_time, $extra_time);
                 check_limit!($left.sys_time, $right.sys_time, $extra_time);
                 check_limit!($left.wall_time, $right.wall_time, $extra_time);
+                check_limit!($left.instructions, $right.instructions, 0);
                 check_limit!($left.memory, $right.memory, $extra_memory);
                 check_limit!($left.nofile, $right.nofile, 0);
                 check_limit!($left.fsize, $right.fsize, 0);
@@ -233,6 +260,7 @@ mod tests {
     use std::fs::File;
     use std::io::Write;
     use std::path::{Path, PathBuf};
+    use std::time::SystemTime;
     use task_maker_dag::{
         Execution, ExecutionCommand, ExecutionResourcesUsage, ExecutionResult, ExecutionStatus,
     };
@@ -262,6 +290,7 @@ mod tests {
                             sys_time: 0.0,
                             wall_time: 0.0,
                             memory: 0,
+                            instructions: None,
                         },
                         stdout: None,
                         stderr: None,
@@ -273,6 +302,10 @@ mod tests {
                     stderr: None,
                     outputs: Default::default(),
                 }],
+                description: exec.description.clone(),
+                tag: None,
+                task_dir: None,
+                created: SystemTime::now(),
             },
             exec,
         )
@@ -417,6 +450,29 @@ mod tests {
         assert!(entry.is_compatible(&exec4.into()));
     }
 
+    #[test]
+    fn test_compatible_success_instructions() {
+        let (mut entry, mut exec1) = empty_entry();
+        exec1.limits.instructions = Some(1000);
+        entry.items[0].result.status = ExecutionStatus::Success;
+        entry.items[0].limits.instructions = Some(1000);
+        assert!(entry.is_compatible(&exec1.into()));
+
+        let mut exec2 = Execution::new("exec", ExecutionCommand::local("foo"));
+        exec2.limits.instructions = Some(2000);
+        assert!(entry.is_compatible(&exec2.into()));
+
+        let mut exec3 = Execution::new("exec", ExecutionCommand::local("foo"));
+        exec3.limits.instructions = Some(500);
+        assert!(!entry.is_compatible(&exec3.into()));
+
+        // without an instruction limit in the entry the instructions were not counted
+        entry.items[0].limits.instructions = None;
+        let mut exec4 = Execution::new("exec", ExecutionCommand::local("foo"));
+        exec4.limits.instructions = Some(1000);
+        assert!(!entry.is_compatible(&exec4.into()));
+    }
+
     #[test]
     fn test_compatible_success_read_only() {
         let (mut entry, mut exec1) = empty_entry();
@@ -5,12 +5,16 @@ use std::path::PathBuf;
 use task_maker_dag::{Execution, ExecutionCommand, ExecutionGroup, FileUuid};
 use task_maker_store::{FileStoreHandle, FileStoreKey};
 
+use crate::toolchain::ToolchainFingerprints;
+
 /// The cache key of a single execution of a group.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 struct CacheKeyItem {
-    /// The command of the execution. Note that this assumes that the system commands are all the
-    /// same between the different workers.
+    /// The command of the execution.
     pub command: ExecutionCommand,
+    /// The fingerprint of the executable of the command, if it's a system command. The one reported
+    /// by the workers is used, if any.
+    pub toolchain: Option<String>,
     /// The list of command line arguments.
     pub args: Vec<String>,
     /// The key (aka the hash) of the stdin, if any.
@@ -38,6 +42,7 @@ impl CacheKeyItem {
         execution: &Execution,
         file_keys: &HashMap<FileUuid, FileStoreHandle>,
         group: Option<&ExecutionGroup>,
+        fingerprints: &mut ToolchainFingerprints,
     ) -> CacheKeyItem {
         let stdin = execution.stdin.as_ref().map(|f| file_keys[f].key().clone());
         let inputs = execution
@@ -68,6 +73,7 @@ impl CacheKeyItem {
         };
         CacheKeyItem {
             command: execution.command.clone(),
+            toolchain: fingerprints.fingerprint(&execution.command),
             args,
             stdin,
             inputs,
@@ -82,15 +88,31 @@ impl CacheKey {
     pub fn from_execution_group(
         group: &ExecutionGroup,
         file_keys: &HashMap<FileUuid, FileStoreHandle>,
+        fingerprints: &mut ToolchainFingerprints,
     ) -> CacheKey {
         CacheKey {
             items: group
                 .executions
                 .iter()
-                .map(|e| CacheKeyItem::from_execution(e, file_keys, Some(group)))
+                .map(|e| CacheKeyItem::from_execution(e, file_keys, Some(group), fingerprints))
                 .collect(),
         }
     }
+
+    /// The command line of each execution in the group, for displaying it.
+    pub fn command_lines(&self) -> Vec<String> {
+        self.items
+            .iter()
+            .map(|item| {
+                let command = match &item.command {
+                    ExecutionCommand::System(path) | ExecutionCommand::Local(path) => path,
+                };
+                std::iter::once(command.to_string_lossy().to_string())
+                    .chain(item.args.iter().cloned())
+                    .join(" ")
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -122,14 +144,15 @@ mod tests {
 
     #[test]
     fn test_command() {
+        let mut fingerprints = ToolchainFingerprints::default();
         let exec1 = Execution::new("exec1", ExecutionCommand::local("foo"));
         let exec2 = Execution::new("exec2", ExecutionCommand::local("foo"));
         let exec3 = Execution::new("exec3", ExecutionCommand::local("bar"));
         let exec4 = Execution::new("exec4", ExecutionCommand::system("foo"));
-        let key1 = CacheKeyItem::from_execution(&exec1, &HashMap::new(), None);
-        let key2 = CacheKeyItem::from_execution(&exec2, &HashMap::new(), None);
-        let key3 = CacheKeyItem::from_execution(&exec3, &HashMap::new(), None);
-        let key4 = CacheKeyItem::from_execution(&exec4, &HashMap::new(), None);
+        let key1 = CacheKeyItem::from_execution(&exec1, &HashMap::new(), None, &mut fingerprints);
+        let key2 = CacheKeyItem::from_execution(&exec2, &HashMap::new(), None, &mut fingerprints);
+        let key3 = CacheKeyItem::from_execution(&exec3, &HashMap::new(), None, &mut fingerprints);
+        let key4 = CacheKeyItem::from_execution(&exec4, &HashMap::new(), None, &mut fingerprints);
         assert_eq!(key1, key2);
         assert_ne!(key1, key3);
         assert_ne!(key1, key4);
@@ -140,6 +163,7 @@ mod tests {
 
     #[test]
     fn test_args() {
+        let mut fingerprints = ToolchainFingerprints::default();
         let mut exec1 = Execution::new("exec1", ExecutionCommand::local("foo"));
         exec1.args(vec!["bar", "baz"]);
         let mut exec2 = Execution::new("exec2", ExecutionCommand::local("foo"));
@@ -148,10 +172,10 @@ mod tests {
         exec3.args(vec!["baz", "bar"]);
         let mut exec4 = Execution::new("exec4", ExecutionCommand::local("foo"));
         exec4.args(vec!["bar", "bar"]);
-        let key1 = CacheKeyItem::from_execution(&exec1, &HashMap::new(), None);
-        let key2 = CacheKeyItem::from_execution(&exec2, &HashMap::new(), None);
-        let key3 = CacheKeyItem::from_execution(&exec3, &HashMap::new(), None);
-        let key4 = CacheKeyItem::from_execution(&exec4, &HashMap::new(), None);
+        let key1 = CacheKeyItem::from_execution(&exec1, &HashMap::new(), None, &mut fingerprints);
+        let key2 = CacheKeyItem::from_execution(&exec2, &HashMap::new(), None, &mut fingerprints);
+        let key3 = CacheKeyItem::from_execution(&exec3, &HashMap::new(), None, &mut fingerprints);
+        let key4 = CacheKeyItem::from_execution(&exec4, &HashMap::new(), None, &mut fingerprints);
         assert_eq!(key1, key2);
         assert_ne!(key1, key3);
         assert_ne!(key1, key4);
@@ -162,6 +186,7 @@ mod tests {
 
     #[test]
     fn test_stdin() {
+        let mut fingerprints = ToolchainFingerprints::default();
         let tmpdir = tempfile::TempDir::new().unwrap();
         let store = FileStore::new(tmpdir.path(), 1000, 1000).unwrap();
         let handle1 = fake_file(tmpdir.path().join("file1"), "foo", &store);
@@ -179,10 +204,10 @@ mod tests {
         let mut exec3 = Execution::new("exec3", ExecutionCommand::local("foo"));
         exec3.stdin(file2.uuid);
         let exec4 = Execution::new("exec4", ExecutionCommand::local("foo"));
-        let key1 = CacheKeyItem::from_execution(&exec1, &map, None);
-        let key2 = CacheKeyItem::from_execution(&exec2, &map, None);
-        let key3 = CacheKeyItem::from_execution(&exec3, &map, None);
-        let key4 = CacheKeyItem::from_execution(&exec4, &map, None);
+        let key1 = CacheKeyItem::from_execution(&exec1, &map, None, &mut fingerprints);
+        let key2 = CacheKeyItem::from_execution(&exec2, &map, None, &mut fingerprints);
+        let key3 = CacheKeyItem::from_execution(&exec3, &map, None, &mut fingerprints);
+        let key4 = CacheKeyItem::from_execution(&exec4, &map, None, &mut fingerprints);
         assert_eq!(key1, key2);
         assert_ne!(key1, key3);
         assert_ne!(key1, key4);
@@ -193,6 +218,7 @@ mod tests {
 
     #[test]
     fn test_inputs() {
+        let mut fingerprints = ToolchainFingerprints::default();
         let tmpdir = tempfile::TempDir::new().unwrap();
         let store = FileStore::new(tmpdir.path(), 1000, 1000).unwrap();
         let handle1 = fake_file(tmpdir.path().join("file1"), "foo", &store);
@@ -214,10 +240,10 @@ mod tests {
         let mut exec4 = Execution::new("exec4", ExecutionCommand::local("foo"));
         exec4.input(file1.uuid, "file1", true);
         exec4.input(file2.uuid, "file2", false);
-        let key1 = CacheKeyItem::from_execution(&exec1, &map, None);
-        let key2 = CacheKeyItem::from_execution(&exec2, &map, None);
-        let key3 = CacheKeyItem::from_execution(&exec3, &map, None);
-        let key4 = CacheKeyItem::from_execution(&exec4, &map, None);
+        let key1 = CacheKeyItem::from_execution(&exec1, &map, None, &mut fingerprints);
+        let key2 = CacheKeyItem::from_execution(&exec2, &map, None, &mut fingerprints);
+        let key3 = CacheKeyItem::from_execution(&exec3, &map, None, &mut fingerprints);
+        let key4 = CacheKeyItem::from_execution(&exec4, &map, None, &mut fingerprints);
         assert_eq!(key1, key2);
         assert_ne!(key1, key3);
         assert_ne!(key1, key4);
@@ -228,6 +254,7 @@ mod tests {
 
     #[test]
     fn test_env() {
+        let mut fingerprints = ToolchainFingerprints::default();
         let mut exec1 = Execution::new("exec1", ExecutionCommand::local("foo"));
         exec1.env("foo", "bar");
         exec1.env("baz", "biz");
@@ -239,10 +266,10 @@ mod tests {
         exec3.env("baz", "bizarre");
         let mut exec4 = Execution::new("exec4", ExecutionCommand::local("foo"));
         exec4.env("foo", "bar");
-        let key1 = CacheKeyItem::from_execution(&exec1, &HashMap::new(), None);
-        let key2 = CacheKeyItem::from_execution(&exec2, &HashMap::new(), None);
-        let key3 = CacheKeyItem::from_execution(&exec3, &HashMap::new(), None);
-        let key4 = CacheKeyItem::from_execution(&exec4, &HashMap::new(), None);
+        let key1 = CacheKeyItem::from_execution(&exec1, &HashMap::new(), None, &mut fingerprints);
+        let key2 = CacheKeyItem::from_execution(&exec2, &HashMap::new(), None, &mut fingerprints);
+        let key3 = CacheKeyItem::from_execution(&exec3, &HashMap::new(), None, &mut fingerprints);
+        let key4 = CacheKeyItem::from_execution(&exec4, &HashMap::new(), None, &mut fingerprints);
         assert_eq!(key1, key2);
         assert_ne!(key1, key3);
         assert_ne!(key1, key4);
@@ -253,6 +280,7 @@ mod tests {
 
     #[test]
     fn test_fifo_arg_replace() {
+        let mut fingerprints = ToolchainFingerprints::default();
         let mut group = ExecutionGroup::new("group");
         let fifo = group.new_fifo();
         let mut exec = Execution::new("exec1", ExecutionCommand::local("foo"));
@@ -262,20 +290,23 @@ mod tests {
         ]);
         group.add_execution(exec);
         let exec = &group.executions[0];
-        let key = CacheKeyItem::from_execution(exec, &HashMap::new(), Some(&group));
+        let key =
+            CacheKeyItem::from_execution(exec, &HashMap::new(), Some(&group), &mut fingerprints);
         assert_eq!(key.args[0], "tm_fifo_0");
         assert_eq!(key.args[1], "lol");
     }
 
     #[test]
     fn test_fifo() {
+        let mut fingerprints = ToolchainFingerprints::default();
         let mut group1 = ExecutionGroup::new("group");
         let fifo1 = group1.new_fifo();
         let mut exec1 = Execution::new("exec1", ExecutionCommand::local("foo"));
         exec1.args(vec![fifo1.sandbox_path().to_string_lossy()]);
         group1.add_execution(exec1);
         let exec1 = &group1.executions[0];
-        let key1 = CacheKeyItem::from_execution(exec1, &HashMap::new(), Some(&group1));
+        let key1 =
+            CacheKeyItem::from_execution(exec1, &HashMap::new(), Some(&group1), &mut fingerprints);
 
         let mut group2 = ExecutionGroup::new("group 2");
         let fifo2 = group2.new_fifo();
@@ -287,12 +318,31 @@ mod tests {
         exec2.args(vec![fifo2.sandbox_path().to_string_lossy()]);
         group2.add_execution(exec2);
         let exec2 = &group2.executions[0];
-        let key2 = CacheKeyItem::from_execution(exec2, &HashMap::new(), Some(&group2));
+        let key2 =
+            CacheKeyItem::from_execution(exec2, &HashMap::new(), Some(&group2), &mut fingerprints);
 
         assert_eq!(key1, key2);
 
-        let key1 = CacheKey::from_execution_group(&group1, &HashMap::new());
-        let key2 = CacheKey::from_execution_group(&group2, &HashMap::new());
+        let key1 = CacheKey::from_execution_group(&group1, &HashMap::new(), &mut fingerprints);
+        let key2 = CacheKey::from_execution_group(&group2, &HashMap::new(), &mut fingerprints);
         assert_eq!(key1, key2);
     }
+
+    #[test]
+    fn test_toolchain() {
+        let mut fingerprints = ToolchainFingerprints::default();
+        let dir = tempfile::TempDir::new().unwrap();
+        let compiler = dir.path().join("compiler");
+        std::fs::write(&compiler, "v1").unwrap();
+        let exec = Execution::new("exec", ExecutionCommand::System(compiler.clone()));
+
+        let key1 = CacheKeyItem::from_execution(&exec, &HashMap::new(), None, &mut fingerprints);
+        let key2 = CacheKeyItem::from_execution(&exec, &HashMap::new(), None, &mut fingerprints);
+        assert!(key1.toolchain.is_some());
+        assert_eq!(key1, key2);
+
+        std::fs::write(&compiler, "version 2").unwrap();
+        let key3 = CacheKeyItem::from_execution(&exec, &HashMap::new(), None, &mut fingerprints);
+        assert_ne!(key1, key3);
+    }
 }
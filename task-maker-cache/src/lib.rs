@@ -1,9 +1,16 @@
 //! Crate for managing the cache of the executions of a DAG.
 //!
-//! It provides the `Cache` struct which holds the cache data and stores it on disk on Drop. The
-//! executions are cached computing a cache key based on the execution command, arguments and
-//! inputs. For each cache key there may be more than one cache entry, allowing different execution
-//! limits to be used.
+//! It provides the `Cache` struct which holds the cache data, loading it lazily from disk and
+//! writing every new entry immediately. The executions are cached computing a cache key based on
+//! the execution command (including a fingerprint of the system commands, like the compilers),
+//! arguments and inputs. For each cache key there may be more than one cache entry, allowing
+//! different execution limits to be used.
+//!
+//! Each entry also remembers the description, the tag and the task directory of the group that
+//! produced it, so that the content of the cache can be inspected and partially evicted.
+//!
+//! A second, read-only, cache can be shared between many users (for example on a network mount).
+//! It's consulted when the local cache misses, and its hits are copied into the local cache.
 //!
 //! The algorithm for extending a cache entry for a different limit is the following:
 //! - call `E1` the cached execution's result and `L1` its limits
@@ -47,7 +54,8 @@
 //!         cpu_time: 1.123,
 //!         sys_time: 0.2,
 //!         wall_time: 1.5,
-//!         memory: 12345
+//!         memory: 12345,
+//!         instructions: None,
 //!     },
 //!     was_killed: false,
 //!     was_cached: false,
@@ -82,12 +90,15 @@ extern crate log;
 
 mod entry;
 mod key;
+mod stats;
 mod storage;
+mod toolchain;
 use entry::CacheEntry;
 use key::CacheKey;
-use storage::CacheFile;
+pub use stats::{CacheEntryInfo, CacheRunStats};
+use storage::CacheStorage;
+pub use toolchain::ToolchainFingerprints;
 
-use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::fs::create_dir_all;
 use std::path::PathBuf;
@@ -98,14 +109,17 @@ use itertools::Itertools;
 use task_maker_dag::{ExecutionGroup, ExecutionResult, ExecutionStatus, FileUuid};
 use task_maker_store::{FileStore, FileStoreHandle};
 
-/// The name of the file which holds the cache data.
-const CACHE_FILE: &str = "cache.bin";
-
 /// Handle the cached executions, loading and storing them to disk.
 #[derive(Debug)]
 pub struct Cache {
     /// Cache entries.
-    file: CacheFile,
+    storage: CacheStorage,
+    /// The fingerprints of the system commands, part of the cache keys.
+    fingerprints: ToolchainFingerprints,
+    /// The usage of the cache since it has been opened.
+    current_run: CacheRunStats,
+    /// The shared cache consulted after a miss, never modified.
+    shared: Option<CacheStorage>,
 }
 
 /// The result of a cache query, can be either successful (`Hit`) or unsuccessful (`Miss`).
@@ -129,36 +143,64 @@ impl Cache {
         create_dir_all(&cache_dir).with_context(|| {
             format!("Failed to create cache directory: {}", cache_dir.display())
         })?;
-        let path = cache_dir.join(CACHE_FILE);
-        let file = CacheFile::load(path).context("Failed to load cache file")?;
-        Ok(Self { file })
+        let storage = CacheStorage::open(cache_dir).context("Failed to load the cache")?;
+        Ok(Self {
+            storage,
+            fingerprints: Default::default(),
+            current_run: Default::default(),
+            shared: None,
+        })
+    }
+
+    /// Consult also the shared cache in the specified directory when an entry is not found, if
+    /// any. The shared cache is never modified, the files of its entries are searched in the
+    /// `FileStore` as usual.
+    pub fn with_shared<P: Into<PathBuf>>(mut self, shared_dir: Option<P>) -> Cache {
+        self.shared = shared_dir.map(|dir| CacheStorage::open_read_only(dir.into()));
+        self
     }
 
     /// Insert a new entry inside the cache. They key is computed based on the execution's metadata
     /// and on the hash of it's inputs, defined by the mapping `file_keys` from the UUIDs of the DAG
     /// to the persistent `FileStoreKey`s.
+    ///
+    /// Every insertion counts as a cache miss in the statistics of the current run.
     pub fn insert(
         &mut self,
         group: &ExecutionGroup,
         file_keys: &HashMap<FileUuid, FileStoreHandle>,
         result: Vec<ExecutionResult>,
     ) {
-        let key = CacheKey::from_execution_group(group, file_keys);
-        let set = self.file.entry(key).or_default();
+        let key = CacheKey::from_execution_group(group, file_keys, &mut self.fingerprints);
         let entry = CacheEntry::from_execution_group(group, file_keys, result);
-        // Do not insert duplicated keys, replace if the limits are the same.
-        let pos = set.iter().find_position(|e| e.same_limits(&entry));
-        if let Some((pos, _)) = pos {
-            set[pos] = entry;
-        } else {
-            set.push(entry);
+        let res = self.storage.update(key, |set| merge_entry(set, entry));
+        if let Err(e) = res {
+            warn!("Failed to store cache entry: {:?}", e);
+        }
+        self.current_run.misses += 1;
+    }
+
+    /// Store the fingerprints of the system commands of a group, as computed by the worker that
+    /// executed it, one for each execution. They are used for the cache keys of these commands from
+    /// now on, including the one of the group when it's inserted.
+    pub fn report_toolchains(&mut self, group: &ExecutionGroup, toolchains: Vec<Option<String>>) {
+        for (execution, fingerprint) in group.executions.iter().zip(toolchains) {
+            self.fingerprints.report(&execution.command, fingerprint);
         }
-        self.file.mark_dirty();
+    }
+
+    /// Search again the system commands in the `$PATH` when computing their fingerprints, since
+    /// they may have changed since the last time. This should be called for every new DAG.
+    pub fn refresh_toolchains(&mut self) {
+        self.fingerprints.refresh();
     }
 
     /// Search in the cache for a valid entry, returning a cache hit if it's found or a cache miss
     /// if not.
     ///
+    /// When the local cache misses, the shared cache is searched, if any. Its hits are copied into
+    /// the local cache.
+    ///
     /// The result contains the handles to the files in the `FileStore`, preventing the flushing
     /// from erasing them.
     pub fn get(
@@ -167,45 +209,167 @@ impl Cache {
         file_keys: &HashMap<FileUuid, FileStoreHandle>,
         file_store: &FileStore,
     ) -> CacheResult {
-        let key = CacheKey::from_execution_group(group, file_keys);
-        let entry = self.file.entry(key);
-        let entry = match &entry {
-            Entry::Vacant(_) => return CacheResult::Miss,
-            Entry::Occupied(entry) => entry.get(),
-        };
+        let key = CacheKey::from_execution_group(group, file_keys, &mut self.fingerprints);
+        match self.storage.get(&key) {
+            Ok(Some(entries)) => {
+                if let Some((_, hit)) = Cache::find_hit(entries, group, file_store) {
+                    self.current_run.hits += 1;
+                    return hit;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Failed to load cache entry: {:?}", e),
+        }
 
-        for entry in entry.iter() {
-            match entry.outputs(file_store, group) {
-                None => {
-                    // TODO: remove the entry because it's not valid anymore
+        let shared = match &mut self.shared {
+            Some(shared) => shared,
+            None => return CacheResult::Miss,
+        };
+        let entries = match shared.get(&key) {
+            Ok(Some(entries)) => entries,
+            Ok(None) => return CacheResult::Miss,
+            Err(e) => {
+                warn!("Failed to load shared cache entry: {:?}", e);
+                return CacheResult::Miss;
+            }
+        };
+        match Cache::find_hit(entries, group, file_store) {
+            Some((entry, hit)) => {
+                let entry = entry.clone();
+                if let Err(e) = self.storage.update(key, |set| merge_entry(set, entry)) {
+                    warn!("Failed to copy the shared cache entry: {:?}", e);
                 }
-                Some(outputs) => {
-                    if entry.is_compatible(group) {
-                        let mut results = Vec::new();
-                        for (exec, item) in group.executions.iter().zip(entry.items.iter()) {
-                            let (exit_status, signal) = match &item.result.status {
-                                ExecutionStatus::ReturnCode(c) => (*c, None),
-                                ExecutionStatus::Signal(s, name) => (0, Some((*s, name.clone()))),
-                                _ => (0, None),
-                            };
-                            results.push(ExecutionResult {
-                                status: exec.status(exit_status, signal, &item.result.resources),
-                                was_killed: item.result.was_killed,
-                                was_cached: true,
-                                resources: item.result.resources.clone(),
-                                stdout: item.result.stdout.clone(),
-                                stderr: item.result.stderr.clone(),
-                            });
-                        }
-                        return CacheResult::Hit {
-                            result: results,
-                            outputs,
-                        };
-                    }
+                self.current_run.hits += 1;
+                hit
+            }
+            None => CacheResult::Miss,
+        }
+    }
+
+    /// Search the first entry that is valid for the group, returning it with the cache hit.
+    fn find_hit<'a>(
+        entries: &'a [CacheEntry],
+        group: &ExecutionGroup,
+        file_store: &FileStore,
+    ) -> Option<(&'a CacheEntry, CacheResult)> {
+        for entry in entries {
+            if !entry.is_compatible(group) {
+                continue;
+            }
+            let outputs = match entry.outputs(file_store, group) {
+                Some(outputs) => outputs,
+                // TODO: remove the entry because it's not valid anymore
+                None => continue,
+            };
+            let mut results = Vec::new();
+            for (exec, item) in group.executions.iter().zip(entry.items.iter()) {
+                let (exit_status, signal) = match &item.result.status {
+                    ExecutionStatus::ReturnCode(c) => (*c, None),
+                    ExecutionStatus::Signal(s, name) => (0, Some((*s, name.clone()))),
+                    _ => (0, None),
+                };
+                results.push(ExecutionResult {
+                    status: exec.status(
+                        exit_status,
+                        signal,
+                        &item.result.resources,
+                        item.result.stderr.as_deref(),
+                    ),
+                    was_killed: item.result.was_killed,
+                    was_cached: true,
+                    resources: item.result.resources.clone(),
+                    stdout: item.result.stdout.clone(),
+                    stderr: item.result.stderr.clone(),
+                });
+            }
+            let hit = CacheResult::Hit {
+                result: results,
+                outputs,
+            };
+            return Some((entry, hit));
+        }
+        None
+    }
+
+    /// The summary of all the entries in the cache. This loads the whole cache from disk.
+    pub fn entries(&mut self) -> Result<Vec<CacheEntryInfo>, Error> {
+        self.storage.load_all()?;
+        Ok(self
+            .storage
+            .iter()
+            .flat_map(|(key, entries)| {
+                entries
+                    .iter()
+                    .map(move |entry| CacheEntryInfo::new(key, entry))
+            })
+            .collect())
+    }
+
+    /// Remove from the cache all the entries for which `filter` returns `true`, keeping the other
+    /// ones. Returns the number of removed entries.
+    ///
+    /// The files produced by the removed entries are left in the `FileStore`, they will be flushed
+    /// normally when they are not used anymore.
+    pub fn evict<F>(&mut self, mut filter: F) -> Result<usize, Error>
+    where
+        F: FnMut(&CacheEntryInfo) -> bool,
+    {
+        let mut removed = 0;
+        self.storage.retain(|key, entry| {
+            let evict = filter(&CacheEntryInfo::new(key, entry));
+            if evict {
+                removed += 1;
+            }
+            !evict
+        })?;
+        Ok(removed)
+    }
+
+    /// Copy into `dest` all the entries for which `filter` returns `true`, replacing the ones with
+    /// the same limits. Returns the number of copied entries.
+    ///
+    /// The files produced by the entries are not copied, `filter` should make sure they are
+    /// available to the users of `dest`.
+    pub fn export<F>(&mut self, dest: &mut Cache, mut filter: F) -> Result<usize, Error>
+    where
+        F: FnMut(&CacheEntryInfo) -> bool,
+    {
+        self.storage.load_all()?;
+        let mut exported = 0;
+        for (key, entries) in self.storage.iter() {
+            for entry in entries {
+                if !filter(&CacheEntryInfo::new(key, entry)) {
+                    continue;
                 }
+                let entry = entry.clone();
+                dest.storage
+                    .update(key.clone(), |set| merge_entry(set, entry))?;
+                exported += 1;
             }
         }
-        CacheResult::Miss
+        Ok(exported)
+    }
+
+    /// The usage statistics of the last runs, the most recent last. The current run is not
+    /// included.
+    pub fn runs(&self) -> &[CacheRunStats] {
+        self.storage.runs()
+    }
+
+    /// The usage statistics since the cache has been opened, or since the last flush.
+    pub fn current_run(&self) -> CacheRunStats {
+        self.current_run
+    }
+
+    /// Store the usage statistics of the current run, if it used the cache, and start a new one.
+    /// The entries are already on disk. This is done when the cache is dropped, a long-running user
+    /// of the cache should call this after each DAG.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if self.current_run != CacheRunStats::default() {
+            self.storage.push_run(self.current_run);
+            self.current_run = CacheRunStats::default();
+        }
+        self.storage.flush()
     }
 
     /// Checks whether a result is allowed in the cache.
@@ -214,10 +378,170 @@ impl Cache {
     }
 }
 
+/// Add an entry to the entries of a key. Do not insert duplicated entries, replace the one with the
+/// same limits, if any.
+fn merge_entry(set: &mut Vec<CacheEntry>, entry: CacheEntry) {
+    let pos = set.iter().find_position(|e| e.same_limits(&entry));
+    if let Some((pos, _)) = pos {
+        set[pos] = entry;
+    } else {
+        set.push(entry);
+    }
+}
+
 impl Drop for Cache {
     fn drop(&mut self) {
-        if let Err(e) = self.file.store() {
-            warn!("Failed to store cache file: {:?}", e);
+        if let Err(e) = self.flush() {
+            warn!("Failed to flush the cache: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use task_maker_dag::{
+        Execution, ExecutionCommand, ExecutionDAG, ExecutionResourcesUsage, ExecutionTag,
+    };
+    use task_maker_store::{FileStoreKey, ReadFileIterator};
+    use tempfile::TempDir;
+
+    fn execution(arg: &str, tag: &str) -> ExecutionGroup {
+        let mut dag = ExecutionDAG::new();
+        dag.config_mut().task_dir("/task");
+        let mut exec = Execution::new("exec", ExecutionCommand::local("foo"));
+        exec.args(vec![arg]).tag(ExecutionTag::from(tag));
+        dag.add_execution(exec);
+        dag.data.execution_groups.into_values().next().unwrap()
+    }
+
+    fn result() -> ExecutionResult {
+        ExecutionResult {
+            status: ExecutionStatus::Success,
+            was_killed: false,
+            was_cached: false,
+            resources: ExecutionResourcesUsage::default(),
+            stdout: None,
+            stderr: None,
         }
     }
+
+    #[test]
+    fn test_entries_and_evict() {
+        let dir = TempDir::new().unwrap();
+        let mut cache = Cache::new(dir.path()).unwrap();
+        let file_keys = HashMap::new();
+        cache.insert(&execution("1", "generation"), &file_keys, vec![result()]);
+        cache.insert(&execution("2", "evaluation"), &file_keys, vec![result()]);
+
+        let entries = cache.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        let entry = entries
+            .iter()
+            .find(|e| e.tag.as_deref() == Some("generation"))
+            .unwrap();
+        assert_eq!(entry.commands, vec!["foo 1".to_string()]);
+        assert_eq!(entry.task_dir.as_deref(), Some(Path::new("/task")));
+
+        let removed = cache.evict(|e| e.tag.as_deref() == Some("generation"));
+        assert_eq!(removed.unwrap(), 1);
+        drop(cache);
+        let mut cache = Cache::new(dir.path()).unwrap();
+        let entries = cache.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].tag.as_deref(), Some("evaluation"));
+    }
+
+    #[test]
+    fn test_runs() {
+        let dir = TempDir::new().unwrap();
+        let store = FileStore::new(dir.path().join("store"), 1000, 1000).unwrap();
+        let path = dir.path().join("file");
+        std::fs::write(&path, "input").unwrap();
+        let key = FileStoreKey::from_file(&path).unwrap();
+        let handle = store
+            .store(&key, ReadFileIterator::new(&path).unwrap())
+            .unwrap();
+
+        let mut exec = Execution::new("exec", ExecutionCommand::local("foo"));
+        let input = task_maker_dag::File::new("input");
+        exec.input(&input, "input", false);
+        let group: ExecutionGroup = exec.into();
+        let mut file_keys = HashMap::new();
+        file_keys.insert(input.uuid, handle);
+        {
+            let mut cache = Cache::new(dir.path().join("cache")).unwrap();
+            assert!(cache.runs().is_empty());
+            cache.insert(&group, &file_keys, vec![result()]);
+            assert!(matches!(
+                cache.get(&group, &file_keys, &store),
+                CacheResult::Hit { .. }
+            ));
+            assert_eq!(cache.current_run(), CacheRunStats { hits: 1, misses: 1 });
+        }
+        let mut cache = Cache::new(dir.path().join("cache")).unwrap();
+        assert_eq!(cache.runs(), &[CacheRunStats { hits: 1, misses: 1 }]);
+        assert_eq!(cache.current_run(), CacheRunStats::default());
+
+        // the statistics are stored by the flush, without closing the cache
+        assert!(matches!(
+            cache.get(&group, &file_keys, &store),
+            CacheResult::Hit { .. }
+        ));
+        cache.flush().unwrap();
+        assert_eq!(cache.current_run(), CacheRunStats::default());
+        let other = Cache::new(dir.path().join("cache")).unwrap();
+        assert_eq!(
+            other.runs(),
+            &[
+                CacheRunStats { hits: 1, misses: 1 },
+                CacheRunStats { hits: 1, misses: 0 }
+            ]
+        );
+        drop(other);
+        drop(cache);
+        let cache = Cache::new(dir.path().join("cache")).unwrap();
+        assert_eq!(cache.runs().len(), 2);
+    }
+    #[test]
+    fn test_shared() {
+        let dir = TempDir::new().unwrap();
+        let store = FileStore::new(dir.path().join("store"), 1000, 1000).unwrap();
+        let file_keys = HashMap::new();
+        let group = execution("1", "generation");
+        let other = execution("2", "evaluation");
+        {
+            let mut local = Cache::new(dir.path().join("local")).unwrap();
+            local.insert(&group, &file_keys, vec![result()]);
+            local.insert(&other, &file_keys, vec![result()]);
+            let mut shared = Cache::new(dir.path().join("shared")).unwrap();
+            let exported = local
+                .export(&mut shared, |entry| {
+                    entry.tag.as_deref() == Some("generation")
+                })
+                .unwrap();
+            assert_eq!(exported, 1);
+        }
+
+        let mut cache = Cache::new(dir.path().join("cache"))
+            .unwrap()
+            .with_shared(Some(dir.path().join("shared")));
+        assert!(matches!(
+            cache.get(&group, &file_keys, &store),
+            CacheResult::Hit { .. }
+        ));
+        assert!(matches!(
+            cache.get(&other, &file_keys, &store),
+            CacheResult::Miss
+        ));
+        drop(cache);
+
+        // the hit has been copied into the local cache
+        let mut cache = Cache::new(dir.path().join("cache")).unwrap();
+        assert!(matches!(
+            cache.get(&group, &file_keys, &store),
+            CacheResult::Hit { .. }
+        ));
+    }
 }
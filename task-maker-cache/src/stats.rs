@@ -0,0 +1,79 @@
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use task_maker_store::FileStoreKey;
+
+use crate::entry::CacheEntry;
+use crate::key::CacheKey;
+
+/// The number of past runs whose statistics are kept in the cache file.
+pub(crate) const MAX_RUNS: usize = 10;
+
+/// The usage of the cache during a run, i.e. while a `Cache` was open.
+///
+/// A hit is an execution whose result has been taken from the cache, a miss is an execution that
+/// has been run and then inserted in the cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheRunStats {
+    /// The number of cache hits.
+    pub hits: u64,
+    /// The number of cache misses.
+    pub misses: u64,
+}
+
+impl CacheRunStats {
+    /// The fraction of the lookups that were hits, `None` if the cache hasn't been used.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            None
+        } else {
+            Some(self.hits as f64 / total as f64)
+        }
+    }
+}
+
+/// A summary of an entry of the cache, for inspecting its content.
+#[derive(Debug, Clone)]
+pub struct CacheEntryInfo {
+    /// The description of the group that produced the entry.
+    pub description: String,
+    /// The name of the tag of the group that produced the entry.
+    pub tag: Option<String>,
+    /// The directory of the task whose evaluation produced the entry, if known.
+    pub task_dir: Option<PathBuf>,
+    /// The command line of each execution of the group.
+    pub commands: Vec<String>,
+    /// When the entry has been inserted in the cache.
+    pub created: SystemTime,
+    /// The keys of the files in the store produced by the executions.
+    pub files: Vec<FileStoreKey>,
+}
+
+impl CacheEntryInfo {
+    /// Build the summary of an entry with the provided key.
+    pub(crate) fn new(key: &CacheKey, entry: &CacheEntry) -> CacheEntryInfo {
+        CacheEntryInfo {
+            description: entry.description.clone(),
+            tag: entry.tag.clone(),
+            task_dir: entry.task_dir.clone(),
+            commands: key.command_lines(),
+            created: entry.created,
+            files: entry.files(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hit_rate() {
+        assert_eq!(CacheRunStats::default().hit_rate(), None);
+        let stats = CacheRunStats { hits: 3, misses: 1 };
+        assert_eq!(stats.hit_rate(), Some(0.75));
+    }
+}
@@ -1,96 +1,385 @@
+//! The on-disk storage of the cache entries.
+//!
+//! The entries are split in 256 shards by the first byte of the hash of their key. Each shard is
+//! an append-only log of records, stored in `entries/xx.log` inside the cache directory:
+//! - the magic string;
+//! - for each record, the hash of the key (32 bytes), the length of the payload (8 bytes, little
+//!   endian) and the payload, the bincode serialization of the key and of all its entries. A record
+//!   with an empty payload removes the key.
+//!
+//! The last record of a key wins. A shard is read only when one of its keys is looked up, and only
+//! the headers of its records are read: the payload of a record is deserialized only when its key
+//! is needed. Every change is appended to the log immediately, so a crash loses at most the record
+//! being written, and a log is compacted as soon as it has too many superseded records.
+//!
+//! A storage can also be opened read-only, for example when it's shared with other users: in that
+//! case the logs are never modified, the invalid ones are just ignored.
+
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
-use std::io::{BufReader, BufWriter, Read, Write};
-use std::path::PathBuf;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Error};
+use anyhow::{bail, Context, Error};
 use const_format::formatcp;
-use itertools::Itertools;
-use serde::{Deserialize, Serialize};
 
 use crate::entry::CacheEntry;
 use crate::key::CacheKey;
+use crate::stats::{CacheRunStats, MAX_RUNS};
 
-/// Magic string that is prepended to the cache file to avoid accidental loading of invalid cache
+/// Magic string that is prepended to the cache files to avoid accidental loading of invalid cache
 /// files.
 ///
 /// The newline at the end of the string is required. For example, let's say there are 2 versions:
 /// v0.1 and v0.11; running v0.11 first, and then v0.1, without the newline the magic of the old
 /// version is a prefix of the magic of the new version.
-const MAGIC: &[u8] = formatcp!("task-maker-cache v{}\n", env!("CARGO_PKG_VERSION")).as_bytes();
-
-/// A cache file.
-#[derive(Debug, Serialize, Deserialize)]
-pub(crate) struct CacheFile {
-    /// The set of entries in this cache file.
-    entries: HashMap<CacheKey, Vec<CacheEntry>>,
-    /// Where this file is stored.
+const MAGIC: &[u8] = formatcp!(
+    "task-maker-cache v{} r{}\n",
+    env!("CARGO_PKG_VERSION"),
+    FORMAT_REVISION
+)
+.as_bytes();
+
+/// Revision of the format of the cache files, to bump when it changes between two releases.
+const FORMAT_REVISION: u32 = 3;
+
+/// The name of the directory, inside the cache directory, with the logs of the shards.
+const SHARDS_DIR: &str = "entries";
+/// The name of the file with the usage statistics of the last runs.
+const RUNS_FILE: &str = "runs.bin";
+/// The name of the file where the old versions stored the whole cache.
+const LEGACY_CACHE_FILE: &str = "cache.bin";
+/// A shard is compacted when its log has more than this many records for each live key...
+const COMPACTION_RATIO: usize = 2;
+/// ...and at least this many superseded records.
+const COMPACTION_MIN_GARBAGE: usize = 64;
+
+/// The hash of a serialized `CacheKey`.
+type KeyHash = [u8; 32];
+
+/// The size of the header of a record: the hash of the key and the length of the payload.
+const HEADER_LEN: u64 = 32 + 8;
+
+/// Where the payload of a record is inside the log of a shard.
+#[derive(Debug, Clone, Copy)]
+struct RecordPosition {
+    /// The offset of the payload from the start of the file.
+    offset: u64,
+    /// The length of the payload.
+    len: u64,
+}
+
+/// A shard of the cache, with the keys whose hash starts with the same byte.
+#[derive(Debug)]
+struct Shard {
+    /// The path of the log of this shard.
     path: PathBuf,
-    /// Whether this file should be flushed.
-    dirty: bool,
+    /// The position of the last record of each live key.
+    index: HashMap<KeyHash, RecordPosition>,
+    /// The keys that have already been deserialized, with their entries.
+    loaded: HashMap<KeyHash, (CacheKey, Vec<CacheEntry>)>,
+    /// The number of records in the log, including the superseded ones.
+    records: usize,
+    /// The length of the log.
+    len: u64,
+    /// The log, opened for reading and appending, if it has been used.
+    file: Option<File>,
+    /// Whether the log must not be modified.
+    read_only: bool,
 }
 
-impl CacheFile {
-    /// Read the cache file, check the magic string and deserialize all the entries in it.
-    pub fn load(path: PathBuf) -> Result<CacheFile, Error> {
-        if !path.exists() {
-            return Ok(Self {
-                entries: Default::default(),
-                path,
-                dirty: false,
-            });
-        }
+/// The storage of the cache, loading the shards lazily.
+#[derive(Debug)]
+pub(crate) struct CacheStorage {
+    /// The cache directory.
+    dir: PathBuf,
+    /// The shards that have been opened, indexed by the first byte of the hashes of their keys.
+    shards: HashMap<u8, Shard>,
+    /// The usage statistics of the last runs, the most recent last.
+    runs: Vec<CacheRunStats>,
+    /// Whether the statistics have changed since they have been loaded.
+    runs_dirty: bool,
+    /// Whether the storage must not be modified.
+    read_only: bool,
+}
 
-        let file = std::fs::File::open(&path)
-            .with_context(|| format!("Cannot open cache file at {}", path.display()))?;
+impl Shard {
+    /// Open the log of a shard reading the headers of all its records. A log with a different
+    /// magic is discarded, and a partially written record at its end is truncated away, unless the
+    /// shard is read-only.
+    fn open(path: PathBuf, read_only: bool) -> Result<Shard, Error> {
+        let mut shard = Shard {
+            path,
+            index: HashMap::new(),
+            loaded: HashMap::new(),
+            records: 0,
+            len: 0,
+            file: None,
+            read_only,
+        };
+        let file = match File::open(&shard.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(shard),
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("Cannot open cache shard at {}", shard.path.display())
+                })
+            }
+        };
+        let file_len = file
+            .metadata()
+            .context("Failed to get the size of the cache shard")?
+            .len();
         let mut reader = BufReader::new(file);
         let mut magic = [0u8; MAGIC.len()];
-
-        if reader
-            .read_exact(&mut magic)
-            .map_or(false, |_| magic != MAGIC)
-        {
+        if reader.read_exact(&mut magic).is_err() || magic != MAGIC {
             info!(
-                "Cache version mismatch:\nExpected: {:?}\nFound: {:?}",
-                MAGIC, magic
+                "Cache version mismatch in {}:\nExpected: {:?}\nFound: {:?}",
+                shard.path.display(),
+                MAGIC,
+                magic
             );
-            return Ok(Self {
-                entries: Default::default(),
-                path,
-                dirty: false,
-            });
+            if !read_only {
+                std::fs::remove_file(&shard.path).with_context(|| {
+                    format!("Failed to remove cache shard {}", shard.path.display())
+                })?;
+            }
+            return Ok(shard);
         }
 
-        let entries = bincode::deserialize_from::<_, HashMap<CacheKey, Vec<CacheEntry>>>(reader)
-            .context("Failed to deserialize cache content")?;
+        let mut offset = MAGIC.len() as u64;
+        let mut header = [0u8; HEADER_LEN as usize];
+        while reader.read_exact(&mut header).is_ok() {
+            let mut hash = KeyHash::default();
+            hash.copy_from_slice(&header[..32]);
+            let mut len = [0u8; 8];
+            len.copy_from_slice(&header[32..]);
+            let len = u64::from_le_bytes(len);
+            let payload = offset + HEADER_LEN;
+            if payload.checked_add(len).map_or(true, |end| end > file_len) {
+                break;
+            }
+            reader
+                .seek_relative(len as i64)
+                .context("Failed to skip the payload of a cache record")?;
+            if len == 0 {
+                shard.index.remove(&hash);
+            } else {
+                shard.index.insert(
+                    hash,
+                    RecordPosition {
+                        offset: payload,
+                        len,
+                    },
+                );
+            }
+            shard.records += 1;
+            offset = payload + len;
+        }
+        // a read-only log may be still being written by someone else
+        if offset < file_len && !read_only {
+            warn!(
+                "Truncating the incomplete record at the end of {}",
+                shard.path.display()
+            );
+            OpenOptions::new()
+                .write(true)
+                .open(&shard.path)
+                .and_then(|file| file.set_len(offset))
+                .with_context(|| format!("Failed to truncate {}", shard.path.display()))?;
+        }
+        shard.len = offset;
+        Ok(shard)
+    }
 
-        Ok(Self {
-            entries,
-            path,
-            dirty: false,
-        })
+    /// The log of this shard, creating it if needed.
+    fn file(&mut self) -> Result<&mut File, Error> {
+        if self.file.is_none() && self.read_only {
+            let file = File::open(&self.path)
+                .with_context(|| format!("Cannot open cache shard at {}", self.path.display()))?;
+            self.file = Some(file);
+        }
+        if self.file.is_none() {
+            let dir = self.path.parent().context("Invalid cache shard path")?;
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create cache directory {}", dir.display()))?;
+            let mut file = OpenOptions::new()
+                .read(true)
+                .append(true)
+                .create(true)
+                .open(&self.path)
+                .with_context(|| format!("Cannot open cache shard at {}", self.path.display()))?;
+            if self.len == 0 {
+                file.write_all(MAGIC)
+                    .context("Failed to write cache magic number")?;
+                self.len = MAGIC.len() as u64;
+            }
+            self.file = Some(file);
+        }
+        Ok(self.file.as_mut().expect("The file has just been opened"))
+    }
+
+    /// Read the payload of a record.
+    fn read_payload(&mut self, position: RecordPosition) -> Result<Vec<u8>, Error> {
+        let file = self.file()?;
+        file.seek(SeekFrom::Start(position.offset))
+            .context("Failed to seek in the cache shard")?;
+        let mut payload = vec![0; position.len as usize];
+        file.read_exact(&mut payload)
+            .context("Failed to read cache record")?;
+        Ok(payload)
     }
 
-    /// Store the content of the cache to the cache file, including the magic string.
-    pub fn store(&self) -> Result<(), Error> {
-        // Do not write the file if it's not dirty.
-        if !self.dirty {
+    /// Deserialize the key with the provided hash, if it's not already loaded. A record that cannot
+    /// be deserialized is treated as missing, and it will be superseded by the next one of its key.
+    fn load(&mut self, hash: &KeyHash) -> Result<(), Error> {
+        if self.loaded.contains_key(hash) {
             return Ok(());
         }
+        let position = match self.index.get(hash) {
+            Some(position) => *position,
+            None => return Ok(()),
+        };
+        let payload = self.read_payload(position)?;
+        match bincode::deserialize(&payload) {
+            Ok(record) => {
+                self.loaded.insert(*hash, record);
+            }
+            Err(e) => {
+                warn!(
+                    "Ignoring invalid cache record in {}: {:?}",
+                    self.path.display(),
+                    e
+                );
+                self.index.remove(hash);
+            }
+        }
+        Ok(())
+    }
 
-        let path = &self.path;
-        std::fs::create_dir_all(path.parent().context("Invalid cache file")?)
-            .with_context(|| format!("Failed to create cache directory for {}", path.display()))?;
-        let tmp = path.with_extension("tmp");
-        let file = std::fs::File::create(&tmp).context("Failed to create cache file")?;
-        let mut writer = BufWriter::new(file);
+    /// Deserialize all the keys of this shard.
+    fn load_all(&mut self) -> Result<(), Error> {
+        let hashes: Vec<_> = self.index.keys().copied().collect();
+        for hash in hashes {
+            self.load(&hash)?;
+        }
+        Ok(())
+    }
+
+    /// The entries of the key, if present.
+    fn get(&mut self, hash: &KeyHash, key: &CacheKey) -> Result<Option<&Vec<CacheEntry>>, Error> {
+        self.load(hash)?;
+        Ok(self
+            .loaded
+            .get(hash)
+            .filter(|(stored, _)| stored == key)
+            .map(|(_, entries)| entries))
+    }
+
+    /// Replace the entries of the key, appending a new record to the log. If `entries` is empty
+    /// the key is removed.
+    fn put(&mut self, hash: KeyHash, key: CacheKey, entries: Vec<CacheEntry>) -> Result<(), Error> {
+        if self.read_only {
+            bail!("The cache at {} is read-only", self.path.display());
+        }
+        let payload = if entries.is_empty() {
+            vec![]
+        } else {
+            bincode::serialize(&(&key, &entries)).context("Failed to serialize cache record")?
+        };
+        let mut record = Vec::with_capacity(HEADER_LEN as usize + payload.len());
+        record.extend_from_slice(&hash);
+        record.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        record.extend_from_slice(&payload);
+        self.file()?
+            .write_all(&record)
+            .context("Failed to write cache record")?;
+
+        let position = RecordPosition {
+            offset: self.len + HEADER_LEN,
+            len: payload.len() as u64,
+        };
+        self.len += record.len() as u64;
+        self.records += 1;
+        if entries.is_empty() {
+            self.index.remove(&hash);
+            self.loaded.remove(&hash);
+        } else {
+            self.index.insert(hash, position);
+            self.loaded.insert(hash, (key, entries));
+        }
+        // the record is already stored, a failed compaction will be retried at the next one
+        if self.needs_compaction() {
+            if let Err(e) = self.compact() {
+                warn!("Failed to compact {}: {:?}", self.path.display(), e);
+            }
+        }
+        Ok(())
+    }
 
+    /// Change the entries of the key, appending a new record to the log.
+    fn update<F>(&mut self, hash: KeyHash, key: CacheKey, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Vec<CacheEntry>),
+    {
+        self.load(&hash)?;
+        let mut entries = match self.loaded.remove(&hash) {
+            Some((stored, entries)) if stored == key => entries,
+            _ => vec![],
+        };
+        f(&mut entries);
+        self.put(hash, key, entries)
+    }
+
+    /// Whether the log has enough superseded records to be worth compacting.
+    fn needs_compaction(&self) -> bool {
+        let live = self.index.len();
+        self.records - live >= COMPACTION_MIN_GARBAGE && self.records > live * COMPACTION_RATIO
+    }
+
+    /// Rewrite the log keeping only the last record of each live key.
+    fn compact(&mut self) -> Result<(), Error> {
+        debug!(
+            "Compacting {}: {} records, {} live",
+            self.path.display(),
+            self.records,
+            self.index.len()
+        );
+        let mut positions: Vec<_> = self.index.iter().map(|(h, p)| (*h, *p)).collect();
+        positions.sort_by_key(|(_, position)| position.offset);
+
+        let tmp = self.path.with_extension("tmp");
+        let file =
+            File::create(&tmp).with_context(|| format!("Failed to create {}", tmp.display()))?;
+        let mut writer = BufWriter::new(file);
         writer
             .write_all(MAGIC)
             .context("Failed to write cache magic number")?;
-
-        bincode::serialize_into(writer, &self.entries.iter().collect_vec())
-            .context("Failed to write cache content")?;
+        let mut offset = MAGIC.len() as u64;
+        let mut index = HashMap::new();
+        for (hash, position) in positions {
+            let payload = self.read_payload(position)?;
+            writer
+                .write_all(&hash)
+                .context("Failed to write cache record")?;
+            writer
+                .write_all(&position.len.to_le_bytes())
+                .context("Failed to write cache record")?;
+            writer
+                .write_all(&payload)
+                .context("Failed to write cache record")?;
+            index.insert(
+                hash,
+                RecordPosition {
+                    offset: offset + HEADER_LEN,
+                    len: position.len,
+                },
+            );
+            offset += HEADER_LEN + position.len;
+        }
+        writer.flush().context("Failed to write cache shard")?;
+        drop(writer);
         std::fs::rename(&tmp, &self.path).with_context(|| {
             format!(
                 "Failed to move {} -> {}",
@@ -98,41 +387,420 @@ impl CacheFile {
                 self.path.display()
             )
         })?;
+
+        self.file = None;
+        self.records = index.len();
+        self.index = index;
+        self.len = offset;
+        Ok(())
+    }
+}
+
+impl CacheStorage {
+    /// Open the storage inside the cache directory. The shards are opened only when used.
+    pub fn open(dir: PathBuf) -> Result<CacheStorage, Error> {
+        let legacy = dir.join(LEGACY_CACHE_FILE);
+        if legacy.exists() {
+            info!("Removing the cache file of an old version");
+            std::fs::remove_file(&legacy)
+                .with_context(|| format!("Failed to remove {}", legacy.display()))?;
+        }
+        let runs = load_runs(&dir.join(RUNS_FILE))?;
+        Ok(CacheStorage {
+            dir,
+            shards: HashMap::new(),
+            runs,
+            runs_dirty: false,
+            read_only: false,
+        })
+    }
+
+    /// Open the storage inside the cache directory without ever modifying it. The usage statistics
+    /// are not loaded.
+    pub fn open_read_only(dir: PathBuf) -> CacheStorage {
+        CacheStorage {
+            dir,
+            shards: HashMap::new(),
+            runs: vec![],
+            runs_dirty: false,
+            read_only: true,
+        }
+    }
+
+    /// The shard with the provided id, opening it if needed.
+    fn shard(&mut self, id: u8) -> Result<&mut Shard, Error> {
+        match self.shards.entry(id) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let path = self.dir.join(SHARDS_DIR).join(format!("{:02x}.log", id));
+                Ok(entry.insert(Shard::open(path, self.read_only)?))
+            }
+        }
+    }
+
+    /// The entries of the key, if present.
+    pub fn get(&mut self, key: &CacheKey) -> Result<Option<&Vec<CacheEntry>>, Error> {
+        let hash = key_hash(key)?;
+        self.shard(hash[0])?.get(&hash, key)
+    }
+
+    /// Replace the entries of the key, writing them to disk immediately. If `entries` is empty the
+    /// key is removed.
+    pub fn put(&mut self, key: CacheKey, entries: Vec<CacheEntry>) -> Result<(), Error> {
+        let hash = key_hash(&key)?;
+        self.shard(hash[0])?.put(hash, key, entries)
+    }
+
+    /// Change the entries of the key, writing them to disk immediately. The key is removed if no
+    /// entries are left.
+    pub fn update<F>(&mut self, key: CacheKey, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Vec<CacheEntry>),
+    {
+        let hash = key_hash(&key)?;
+        self.shard(hash[0])?.update(hash, key, f)
+    }
+
+    /// Open all the shards and deserialize all their keys, making them available to `iter`.
+    pub fn load_all(&mut self) -> Result<(), Error> {
+        for id in 0..=u8::MAX {
+            self.shard(id)?.load_all()?;
+        }
         Ok(())
     }
 
-    pub fn entry(&mut self, key: CacheKey) -> Entry<CacheKey, Vec<CacheEntry>> {
-        self.entries.entry(key)
+    /// Iterate over the keys that have been loaded.
+    pub fn iter(&self) -> impl Iterator<Item = (&CacheKey, &Vec<CacheEntry>)> {
+        self.shards
+            .values()
+            .flat_map(|shard| shard.loaded.values())
+            .map(|(key, entries)| (key, entries))
+    }
+
+    /// Keep only the entries for which the predicate returns `true`, removing the keys left without
+    /// entries.
+    pub fn retain<F>(&mut self, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(&CacheKey, &CacheEntry) -> bool,
+    {
+        self.load_all()?;
+        for shard in self.shards.values_mut() {
+            let hashes: Vec<_> = shard.loaded.keys().copied().collect();
+            for hash in hashes {
+                let (key, entries) = shard.loaded.remove(&hash).expect("The key is loaded");
+                let before = entries.len();
+                let entries: Vec<_> = entries.into_iter().filter(|e| f(&key, e)).collect();
+                if entries.len() == before {
+                    shard.loaded.insert(hash, (key, entries));
+                } else {
+                    shard.put(hash, key, entries)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The usage statistics of the last runs, the most recent last.
+    pub fn runs(&self) -> &[CacheRunStats] {
+        &self.runs
+    }
+
+    /// Record the usage statistics of a run, forgetting the oldest ones.
+    pub fn push_run(&mut self, run: CacheRunStats) {
+        self.runs.push(run);
+        if self.runs.len() > MAX_RUNS {
+            self.runs.remove(0);
+        }
+        self.runs_dirty = true;
+    }
+
+    /// Store the usage statistics, if they have changed. The entries are already on disk, and their
+    /// shards are compacted while they are written.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if self.read_only {
+            return Ok(());
+        }
+        if self.runs_dirty {
+            store_runs(&self.dir.join(RUNS_FILE), &self.runs)?;
+            self.runs_dirty = false;
+        }
+        Ok(())
     }
+}
+
+/// The hash of a key, used to find its shard and its records.
+fn key_hash(key: &CacheKey) -> Result<KeyHash, Error> {
+    let data = bincode::serialize(key).context("Failed to serialize cache key")?;
+    Ok(*blake3::hash(&data).as_bytes())
+}
 
-    pub fn mark_dirty(&mut self) {
-        self.dirty = true;
+/// Read the usage statistics of the last runs, checking the magic string. An invalid file is
+/// discarded, since the statistics are not worth failing for.
+fn load_runs(path: &Path) -> Result<Vec<CacheRunStats>, Error> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let file = File::open(path)
+        .with_context(|| format!("Cannot open cache file at {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let mut magic = [0u8; MAGIC.len()];
+    if reader.read_exact(&mut magic).is_err() || magic != MAGIC {
+        info!(
+            "Cache version mismatch in {}:\nExpected: {:?}\nFound: {:?}",
+            path.display(),
+            MAGIC,
+            magic
+        );
+        return Ok(vec![]);
+    }
+    match bincode::deserialize_from(reader) {
+        Ok(runs) => Ok(runs),
+        Err(e) => {
+            warn!(
+                "Discarding the invalid cache statistics in {}: {:?}",
+                path.display(),
+                e
+            );
+            Ok(vec![])
+        }
     }
 }
 
+/// Store the usage statistics of the last runs, including the magic string.
+fn store_runs(path: &Path, runs: &[CacheRunStats]) -> Result<(), Error> {
+    std::fs::create_dir_all(path.parent().context("Invalid cache file")?)
+        .with_context(|| format!("Failed to create cache directory for {}", path.display()))?;
+    let tmp = path.with_extension("tmp");
+    let file = File::create(&tmp).context("Failed to create cache file")?;
+    let mut writer = BufWriter::new(file);
+    writer
+        .write_all(MAGIC)
+        .context("Failed to write cache magic number")?;
+    bincode::serialize_into(&mut writer, runs).context("Failed to write cache content")?;
+    writer.flush().context("Failed to write cache content")?;
+    drop(writer);
+    std::fs::rename(&tmp, path)
+        .with_context(|| format!("Failed to move {} -> {}", tmp.display(), path.display()))?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs::File;
+    use std::time::SystemTime;
+    use task_maker_dag::{Execution, ExecutionCommand};
+
+    fn key(arg: &str) -> CacheKey {
+        let mut exec = Execution::new("exec", ExecutionCommand::local("foo"));
+        exec.args(vec![arg]);
+        CacheKey::from_execution_group(&exec.into(), &HashMap::new(), &mut Default::default())
+    }
+
+    fn entry(description: &str) -> CacheEntry {
+        CacheEntry {
+            items: vec![],
+            description: description.into(),
+            tag: None,
+            task_dir: None,
+            created: SystemTime::now(),
+        }
+    }
+
+    fn shard_path(dir: &Path, key: &CacheKey) -> PathBuf {
+        let hash = key_hash(key).unwrap();
+        dir.join(SHARDS_DIR).join(format!("{:02x}.log", hash[0]))
+    }
 
     #[test]
-    fn test_load_reject_wrong_magic() {
+    fn test_put_get_reopen() {
         let tmpdir = tempfile::TempDir::new().unwrap();
-        let path = tmpdir.path().join("cache");
-        let mut f = File::create(&path).unwrap();
-        f.write_all(b"totally-not-the-magic").unwrap();
+        {
+            let mut storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+            storage.put(key("1"), vec![entry("one")]).unwrap();
+            storage.put(key("2"), vec![entry("two")]).unwrap();
+            storage.put(key("2"), vec![entry("two bis")]).unwrap();
+            storage.put(key("3"), vec![entry("three")]).unwrap();
+            storage.put(key("3"), vec![]).unwrap();
+            // dropped without flushing, as if it crashed
+        }
+        let mut storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+        assert!(storage.shards.is_empty());
+        let entries = storage.get(&key("2")).unwrap().unwrap();
+        assert_eq!(entries[0].description, "two bis");
+        assert!(storage.get(&key("3")).unwrap().is_none());
+        assert!(storage.get(&key("4")).unwrap().is_none());
 
-        assert!(CacheFile::load(path).is_err());
+        storage.load_all().unwrap();
+        assert_eq!(storage.iter().count(), 2);
     }
 
     #[test]
-    fn test_load_reject_wrong_version() {
+    fn test_truncated_record() {
         let tmpdir = tempfile::TempDir::new().unwrap();
-        let path = tmpdir.path().join("cache");
+        let path = shard_path(tmpdir.path(), &key("1"));
+        {
+            let mut storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+            storage.put(key("1"), vec![entry("one")]).unwrap();
+        }
+        let len = std::fs::metadata(&path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[42; 50]).unwrap();
+
+        let mut storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+        assert!(storage.get(&key("1")).unwrap().is_some());
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+    }
+
+    #[test]
+    fn test_invalid_record() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let path = shard_path(tmpdir.path(), &key("1"));
+        {
+            let mut storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+            storage.put(key("1"), vec![entry("one")]).unwrap();
+        }
+        // overwrite the payload of the record, keeping its length
+        let mut content = std::fs::read(&path).unwrap();
+        let payload = MAGIC.len() + HEADER_LEN as usize;
+        content[payload..].fill(0xff);
+        std::fs::write(&path, content).unwrap();
+
+        let mut storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+        assert!(storage.get(&key("1")).unwrap().is_none());
+        storage
+            .update(key("1"), |entries| entries.push(entry("two")))
+            .unwrap();
+        drop(storage);
+        let mut storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+        let entries = storage.get(&key("1")).unwrap().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].description, "two");
+    }
+
+    #[test]
+    fn test_huge_record_length() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let path = shard_path(tmpdir.path(), &key("1"));
+        {
+            let mut storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+            storage.put(key("1"), vec![entry("one")]).unwrap();
+        }
+        let len = std::fs::metadata(&path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[0; 32]).unwrap();
+        file.write_all(&u64::MAX.to_le_bytes()).unwrap();
+
+        let mut storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+        assert!(storage.get(&key("1")).unwrap().is_some());
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+    }
+
+    #[test]
+    fn test_read_only() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let path = shard_path(tmpdir.path(), &key("1"));
+        {
+            let mut storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+            storage.put(key("1"), vec![entry("one")]).unwrap();
+        }
+        // a record still being written by someone else
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[42; 50]).unwrap();
+        let len = std::fs::metadata(&path).unwrap().len();
+
+        let mut storage = CacheStorage::open_read_only(tmpdir.path().into());
+        assert!(storage.get(&key("1")).unwrap().is_some());
+        assert!(storage.get(&key("2")).unwrap().is_none());
+        assert!(storage.put(key("1"), vec![entry("two")]).is_err());
+        storage.flush().unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+    }
+
+    #[test]
+    fn test_reject_wrong_magic() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let path = shard_path(tmpdir.path(), &key("1"));
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"totally-not-the-magic").unwrap();
+
+        let mut storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+        assert!(storage.get(&key("1")).unwrap().is_none());
+        storage.put(key("1"), vec![entry("one")]).unwrap();
+        drop(storage);
+        let mut storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+        assert!(storage.get(&key("1")).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_compaction() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let path = shard_path(tmpdir.path(), &key("1"));
+        let mut storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+        for i in 0..COMPACTION_MIN_GARBAGE {
+            storage
+                .put(key("1"), vec![entry(&format!("entry {}", i))])
+                .unwrap();
+        }
+        let before = std::fs::metadata(&path).unwrap().len();
+        // the log is compacted while it's written, without waiting for the flush
+        storage
+            .put(
+                key("1"),
+                vec![entry(&format!("entry {}", COMPACTION_MIN_GARBAGE))],
+            )
+            .unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() < before);
+
+        let last = format!("entry {}", COMPACTION_MIN_GARBAGE);
+        assert_eq!(
+            storage.get(&key("1")).unwrap().unwrap()[0].description,
+            last
+        );
+        drop(storage);
+        let mut storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+        assert_eq!(
+            storage.get(&key("1")).unwrap().unwrap()[0].description,
+            last
+        );
+        assert_eq!(storage.shards.values().next().unwrap().records, 1);
+    }
+
+    #[test]
+    fn test_runs() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let mut storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+        for hits in 0..MAX_RUNS as u64 + 2 {
+            storage.push_run(CacheRunStats { hits, misses: 0 });
+        }
+        storage.flush().unwrap();
+        let storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+        assert_eq!(storage.runs().len(), MAX_RUNS);
+        assert_eq!(storage.runs()[0].hits, 2);
+    }
+
+    #[test]
+    fn test_runs_discard_invalid() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let path = tmpdir.path().join(RUNS_FILE);
         let mut f = File::create(&path).unwrap();
         f.write_all(MAGIC).unwrap();
         f.write_all(b"wrong-version").unwrap();
 
-        assert!(CacheFile::load(path).is_err());
+        let mut storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+        assert!(storage.runs().is_empty());
+        storage.push_run(CacheRunStats { hits: 1, misses: 2 });
+        storage.flush().unwrap();
+        let storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+        assert_eq!(storage.runs(), &[CacheRunStats { hits: 1, misses: 2 }]);
+    }
+
+    #[test]
+    fn test_runs_discard_truncated() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let path = tmpdir.path().join(RUNS_FILE);
+        std::fs::write(&path, &MAGIC[..5]).unwrap();
+
+        let storage = CacheStorage::open(tmpdir.path().into()).unwrap();
+        assert!(storage.runs().is_empty());
     }
 }
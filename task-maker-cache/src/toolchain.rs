@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use task_maker_dag::ExecutionCommand;
+
+/// Fingerprints of the system commands (compilers, interpreters, ...) used by the executions.
+///
+/// They are part of the cache keys, so that upgrading a toolchain invalidates only the entries of
+/// the executions that used it. The fingerprints depend only on the content of the executables, so
+/// the machines with the same toolchain agree on them, wherever and whenever it was installed.
+///
+/// The workers report the fingerprints of the commands they run, and those take precedence over the
+/// ones computed on the machine that holds the cache, which may have a different toolchain. With
+/// many workers the latest report wins.
+#[derive(Debug, Default)]
+pub struct ToolchainFingerprints {
+    /// The canonical path of each command, to avoid searching it in the `$PATH` every time.
+    resolved: HashMap<PathBuf, Option<PathBuf>>,
+    /// The hash of each executable, with the size and the modification time it was computed for,
+    /// to avoid reading the executable every time.
+    hashes: HashMap<PathBuf, (u64, SystemTime, String)>,
+    /// The fingerprints of the commands reported by the workers.
+    reported: HashMap<PathBuf, Option<String>>,
+}
+
+impl ToolchainFingerprints {
+    /// The fingerprint of the command: the hash of the content of its executable. `None` if the
+    /// command is not a system command, or if it cannot be read.
+    ///
+    /// If a worker reported the fingerprint of the command, that one is returned instead.
+    pub fn fingerprint(&mut self, command: &ExecutionCommand) -> Option<String> {
+        let command = match command {
+            ExecutionCommand::System(command) => command,
+            ExecutionCommand::Local(_) => return None,
+        };
+        if let Some(fingerprint) = self.reported.get(command) {
+            return fingerprint.clone();
+        }
+        let path = self
+            .resolved
+            .entry(command.clone())
+            .or_insert_with(|| resolve_command(command))
+            .as_ref()?;
+        // The metadata is read every time, so that a long running server notices the upgrades.
+        let metadata = std::fs::metadata(path).ok()?;
+        let size = metadata.len();
+        let mtime = metadata.modified().ok()?;
+        if let Some((hash_size, hash_mtime, hash)) = self.hashes.get(path) {
+            if (*hash_size, *hash_mtime) == (size, mtime) {
+                return Some(hash.clone());
+            }
+        }
+        let hash = hash_file(path)?;
+        self.hashes
+            .insert(path.clone(), (size, mtime, hash.clone()));
+        Some(hash)
+    }
+
+    /// Store the fingerprint of a command as computed by the worker that executed it.
+    pub fn report(&mut self, command: &ExecutionCommand, fingerprint: Option<String>) {
+        if let ExecutionCommand::System(command) = command {
+            self.reported.insert(command.clone(), fingerprint);
+        }
+    }
+
+    /// Forget the paths of the commands, so that they are searched again in the `$PATH`. For
+    /// example a new version of a compiler may have been installed in a different directory.
+    pub fn refresh(&mut self) {
+        self.resolved.clear();
+    }
+}
+
+/// The hash of the content of a file, in hex.
+fn hash_file(path: &Path) -> Option<String> {
+    let mut file = File::open(path).ok()?;
+    let mut hasher = blake3::Hasher::new();
+    std::io::copy(&mut file, &mut hasher).ok()?;
+    Some(hasher.finalize().to_hex().to_string())
+}
+
+/// Find the executable of a system command like the workers do, searching it in the `$PATH` if it's
+/// just a name, and following the symlinks (e.g. `g++` -> `g++-12`).
+fn resolve_command(command: &Path) -> Option<PathBuf> {
+    let path = if command.components().count() > 1 {
+        command.to_path_buf()
+    } else {
+        let dirs = std::env::var_os("PATH")?;
+        std::env::split_paths(&dirs)
+            .map(|dir| dir.join(command))
+            .find(|path| path.is_file())?
+    };
+    path.canonicalize().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_local() {
+        let mut fingerprints = ToolchainFingerprints::default();
+        assert!(fingerprints
+            .fingerprint(&ExecutionCommand::local("sh"))
+            .is_none());
+    }
+
+    #[test]
+    fn test_fingerprint_missing() {
+        let mut fingerprints = ToolchainFingerprints::default();
+        let command = ExecutionCommand::system("surely-this-command-does-not-exist");
+        assert!(fingerprints.fingerprint(&command).is_none());
+    }
+
+    #[test]
+    fn test_fingerprint_changes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("compiler");
+        std::fs::write(&path, "v1").unwrap();
+        let command = ExecutionCommand::System(path.clone());
+
+        let mut fingerprints = ToolchainFingerprints::default();
+        let v1 = fingerprints.fingerprint(&command).unwrap();
+        assert_eq!(fingerprints.fingerprint(&command).unwrap(), v1);
+        std::fs::write(&path, "version 2").unwrap();
+        let v2 = fingerprints.fingerprint(&command).unwrap();
+        assert_ne!(v1, v2);
+    }
+
+    #[test]
+    fn test_fingerprint_same_content() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let first = dir.path().join("first");
+        let second = dir.path().join("second");
+        std::fs::write(&first, "compiler").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        std::fs::write(&second, "compiler").unwrap();
+
+        // the same toolchain installed in different places and at different times
+        let mut fingerprints = ToolchainFingerprints::default();
+        let first = fingerprints
+            .fingerprint(&ExecutionCommand::System(first))
+            .unwrap();
+        let mut other = ToolchainFingerprints::default();
+        let second = other
+            .fingerprint(&ExecutionCommand::System(second))
+            .unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_fingerprint_reported() {
+        let mut fingerprints = ToolchainFingerprints::default();
+        let command = ExecutionCommand::system("surely-this-command-does-not-exist");
+        fingerprints.report(&command, Some("worker".into()));
+        assert_eq!(fingerprints.fingerprint(&command).unwrap(), "worker");
+        fingerprints.report(&command, None);
+        assert!(fingerprints.fingerprint(&command).is_none());
+
+        let local = ExecutionCommand::local("sh");
+        fingerprints.report(&local, Some("worker".into()));
+        assert!(fingerprints.fingerprint(&local).is_none());
+    }
+
+    #[test]
+    fn test_fingerprint_refresh() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let old = dir.path().join("old");
+        let new = dir.path().join("new");
+        std::fs::write(&old, "v1").unwrap();
+        std::fs::write(&new, "v2").unwrap();
+        let link = dir.path().join("compiler");
+        std::os::unix::fs::symlink(&old, &link).unwrap();
+        let command = ExecutionCommand::System(link.clone());
+
+        let mut fingerprints = ToolchainFingerprints::default();
+        let v1 = fingerprints.fingerprint(&command).unwrap();
+        let new_fingerprint = fingerprints
+            .fingerprint(&ExecutionCommand::System(new.clone()))
+            .unwrap();
+        assert_ne!(v1, new_fingerprint);
+        std::fs::remove_file(&link).unwrap();
+        std::os::unix::fs::symlink(&new, &link).unwrap();
+        // The old path is still used until the commands are resolved again.
+        assert_eq!(fingerprints.fingerprint(&command).unwrap(), v1);
+        fingerprints.refresh();
+        let v2 = fingerprints.fingerprint(&command).unwrap();
+        assert_eq!(v2, new_fingerprint);
+    }
+}
@@ -40,8 +40,13 @@ pub struct ExecutionDAGConfig {
     pub copy_exe: bool,
     /// Whether to copy the log files of some interesting executions.
     pub copy_logs: bool,
+    /// Whether to compile the C/C++ sources with the address and undefined behavior sanitizers.
+    pub sanitize: bool,
     /// Priority of this DAG.
     pub priority: DagPriority,
+    /// The directory of the task this DAG evaluates, used to tag the cache entries of its
+    /// executions.
+    pub task_dir: Option<PathBuf>,
 }
 
 /// A wrapper around a `File` provided by the client, this means that the client knows the
@@ -272,6 +277,11 @@ impl ExecutionDAG {
             .push(Box::new(callback));
     }
 
+    /// Get a reference to the config of this DAG.
+    pub fn config(&self) -> &ExecutionDAGConfig {
+        &self.data.config
+    }
+
     /// Get a mutable reference to the config of this DAG.
     pub fn config_mut(&mut self) -> &mut ExecutionDAGConfig {
         &mut self.data.config
@@ -344,7 +354,9 @@ impl ExecutionDAGConfig {
             extra_memory: 8 * 1024, // 8 MiB
             copy_exe: false,
             copy_logs: false,
+            sanitize: false,
             priority: 0,
+            task_dir: None,
         }
     }
 
@@ -391,11 +403,23 @@ impl ExecutionDAGConfig {
         self
     }
 
+    /// Set whether to compile the C/C++ sources with the sanitizers.
+    pub fn sanitize(&mut self, sanitize: bool) -> &mut Self {
+        self.sanitize = sanitize;
+        self
+    }
+
     /// Set the priority of this DAG.
     pub fn priority(&mut self, priority: DagPriority) -> &mut Self {
         self.priority = priority;
         self
     }
+
+    /// Set the directory of the task this DAG evaluates.
+    pub fn task_dir<P: Into<PathBuf>>(&mut self, task_dir: P) -> &mut Self {
+        self.task_dir = Some(task_dir.into());
+        self
+    }
 }
 
 impl Default for ExecutionDAGConfig {
@@ -438,6 +462,17 @@ impl CacheMode {
             }
         }
     }
+
+    /// Disable the cache also for the executions with the specified tag.
+    pub fn disable_tag(&mut self, tag: ExecutionTag) {
+        match self {
+            CacheMode::Everything => *self = CacheMode::Except(vec![tag].into_iter().collect()),
+            CacheMode::Nothing => {}
+            CacheMode::Except(tags) => {
+                tags.insert(tag);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -637,4 +672,26 @@ mod tests {
         );
         assert!(CacheMode::try_from(&Some(Some("tag1".to_string())), &[]).is_err());
     }
+
+    #[test]
+    fn test_cache_mode_disable_tag() {
+        let mut mode = CacheMode::Everything;
+        mode.disable_tag(ExecutionTag::from("tag1"));
+        assert_eq!(
+            mode,
+            CacheMode::Except(vec![ExecutionTag::from("tag1")].into_iter().collect())
+        );
+        mode.disable_tag(ExecutionTag::from("tag2"));
+        assert_eq!(
+            mode,
+            CacheMode::Except(
+                vec![ExecutionTag::from("tag1"), ExecutionTag::from("tag2")]
+                    .into_iter()
+                    .collect()
+            )
+        );
+        let mut mode = CacheMode::Nothing;
+        mode.disable_tag(ExecutionTag::from("tag1"));
+        assert_eq!(mode, CacheMode::Nothing);
+    }
 }
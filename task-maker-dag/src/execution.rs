@@ -19,6 +19,19 @@ pub type ExecutionUuid = Uuid;
 
 pub type WorkerUuid = Uuid;
 
+/// How many times the memory limit is relaxed for the executables compiled with the sanitizers,
+/// which use some extra memory for their bookkeeping (AddressSanitizer maps one byte of shadow
+/// memory every 8 bytes, and surrounds each allocation with redzones and a quarantine).
+pub const SANITIZER_MEMORY_FACTOR: u64 = 3;
+
+/// The messages printed by AddressSanitizer when it aborts the execution for exceeding its
+/// `hard_rss_limit_mb` or failing to allocate memory.
+const SANITIZER_MEMORY_EXHAUSTED: &[&[u8]] = &[
+    b"AddressSanitizer: hard rss limit exhausted",
+    b"AddressSanitizer: out of memory",
+    b"AddressSanitizer: requested allocation size",
+];
+
 /// Type of the callback called when an [`Execution`](struct.Execution.html) starts.
 pub type OnStartCallback = Box<dyn FnOnce(WorkerUuid) -> Result<(), Error> + 'static>;
 
@@ -181,6 +194,10 @@ pub struct Execution {
     /// priority order is followed only between ready executions, i.e. a lower priority one can be
     /// executed before if its dependencies are ready earlier.
     pub priority: Priority,
+    /// Whether the executable has been compiled with the sanitizers. Those executables reserve a
+    /// huge amount of virtual memory, so the sandbox won't limit their address space: the resident
+    /// memory is limited by the sanitizer runtime instead, relaxed by `SANITIZER_MEMORY_FACTOR`.
+    pub sanitized: bool,
 }
 
 /// Limits on an [`Execution`](struct.Execution.html). On some worker platforms some of the fields
@@ -194,6 +211,12 @@ pub struct ExecutionLimits {
     /// Limit on the total time of execution, in seconds. This will include the io-wait time and
     /// other non-cpu times.
     pub wall_time: Option<f64>,
+    /// Limit on the number of user-space instructions retired by the process. When set, the
+    /// sandbox counts the instructions using the hardware performance counters, which makes the
+    /// verdict independent of the speed of the machine. Exceeding this limit is reported as a time
+    /// limit exceeded and kills the process. Without a time limit the sandbox derives one from
+    /// this limit, so that a process stuck without retiring instructions is killed as well.
+    pub instructions: Option<u64>,
     /// Limit on the number of KiB the process can use in any moment. This can be page-aligned by
     /// the sandbox.
     pub memory: Option<u64>,
@@ -250,6 +273,9 @@ pub struct ExecutionResourcesUsage {
     pub wall_time: f64,
     /// Number of KiB used _at most_ by the process.
     pub memory: u64,
+    /// Number of user-space instructions retired by the process, available only if they have been
+    /// counted (i.e. with an instruction limit).
+    pub instructions: Option<u64>,
 }
 
 /// The result of an [`Execution`](struct.Execution.html).
@@ -277,6 +303,7 @@ impl ExecutionLimits {
             cpu_time: None,
             sys_time: None,
             wall_time: None,
+            instructions: None,
             memory: None,
             allow_multiprocess: true,
             nofile: None,
@@ -308,6 +335,12 @@ impl ExecutionLimits {
         self
     }
 
+    /// Set the limit on the number of user-space instructions.
+    pub fn instructions(&mut self, limit: u64) -> &mut Self {
+        self.instructions = Some(limit);
+        self
+    }
+
     /// Set the memory limit in KiB.
     pub fn memory(&mut self, limit: u64) -> &mut Self {
         self.memory = Some(limit);
@@ -384,6 +417,7 @@ impl Default for ExecutionLimits {
             cpu_time: None,
             sys_time: None,
             wall_time: None,
+            instructions: None,
             memory: None,
             allow_multiprocess: false,
             nofile: None,
@@ -463,6 +497,7 @@ impl Execution {
 
             tag: None,
             priority: Priority::default(),
+            sanitized: false,
         }
     }
 
@@ -767,13 +802,23 @@ impl Execution {
         self
     }
 
+    /// Mark this `Execution` as running an executable compiled with the sanitizers.
+    pub fn sanitized(&mut self, sanitized: bool) -> &mut Self {
+        self.sanitized = sanitized;
+        self
+    }
+
     /// Compute the [`ExecutionStatus`](struct.ExecutionStatus.html) based on the result of the
     /// execution, checking the signals, the return code and the time/memory constraints.
+    ///
+    /// The captured standard error, if any, tells when the sanitizers aborted the execution for
+    /// exceeding the resident memory limit they enforce instead of the sandbox.
     pub fn status(
         &self,
         exit_status: u32,
         signal: Option<(u32, String)>,
         resources: &ExecutionResourcesUsage,
+        stderr: Option<&[u8]>,
     ) -> ExecutionStatus {
         // it's important to check those before the signals because exceeding those
         // limits may trigger a SIGKILL from the sandbox
@@ -792,11 +837,25 @@ impl Execution {
                 return ExecutionStatus::WallTimeLimitExceeded;
             }
         }
+        if let Some(instructions_limit) = self.limits.instructions {
+            if resources.instructions.unwrap_or(0) > instructions_limit {
+                return ExecutionStatus::TimeLimitExceeded;
+            }
+        }
         if let Some(memory_limit) = self.limits.memory {
+            let memory_limit = if self.sanitized {
+                memory_limit * SANITIZER_MEMORY_FACTOR
+            } else {
+                memory_limit
+            };
             if resources.memory > memory_limit {
                 return ExecutionStatus::MemoryLimitExceeded;
             }
         }
+        // the sanitizer runtime aborts the execution by itself, before the sandbox notices
+        if self.sanitized && stderr.map_or(false, sanitizer_memory_exhausted) {
+            return ExecutionStatus::MemoryLimitExceeded;
+        }
         if let Some((signal, name)) = signal {
             return ExecutionStatus::Signal(signal, name);
         }
@@ -807,6 +866,14 @@ impl Execution {
     }
 }
 
+/// Check whether the standard error of a sanitized execution reports that the sanitizer runtime
+/// aborted it because of the memory limit.
+fn sanitizer_memory_exhausted(stderr: &[u8]) -> bool {
+    SANITIZER_MEMORY_EXHAUSTED
+        .iter()
+        .any(|message| stderr.windows(message.len()).any(|w| w == *message))
+}
+
 impl std::fmt::Debug for ExecutionCallbacks {
     fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         formatter
@@ -865,7 +932,9 @@ mod tests {
                 sys_time: 0.0,
                 wall_time: 0.0,
                 memory: 0,
+                instructions: None,
             },
+            None,
         );
         assert_eq!(ExecutionStatus::Success, status);
     }
@@ -882,7 +951,28 @@ mod tests {
                 sys_time: 0.0,
                 wall_time: 0.0,
                 memory: 0,
+                instructions: None,
             },
+            None,
+        );
+        assert_eq!(ExecutionStatus::TimeLimitExceeded, status);
+    }
+
+    #[test]
+    fn test_status_instructions() {
+        let mut exec = Execution::new("foo", ExecutionCommand::local("foo"));
+        exec.limits_mut().instructions(1000);
+        let status = exec.status(
+            0,
+            Some((9, "Killed".into())),
+            &ExecutionResourcesUsage {
+                cpu_time: 0.0,
+                sys_time: 0.0,
+                wall_time: 0.0,
+                memory: 0,
+                instructions: Some(1001),
+            },
+            None,
         );
         assert_eq!(ExecutionStatus::TimeLimitExceeded, status);
     }
@@ -899,7 +989,9 @@ mod tests {
                 sys_time: 1.1,
                 wall_time: 0.0,
                 memory: 0,
+                instructions: None,
             },
+            None,
         );
         assert_eq!(ExecutionStatus::SysTimeLimitExceeded, status);
     }
@@ -916,7 +1008,9 @@ mod tests {
                 sys_time: 0.0,
                 wall_time: 1.1,
                 memory: 0,
+                instructions: None,
             },
+            None,
         );
         assert_eq!(ExecutionStatus::WallTimeLimitExceeded, status);
     }
@@ -933,11 +1027,72 @@ mod tests {
                 sys_time: 0.0,
                 wall_time: 0.0,
                 memory: 1235,
+                instructions: None,
             },
+            None,
         );
         assert_eq!(ExecutionStatus::MemoryLimitExceeded, status);
     }
 
+    #[test]
+    fn test_status_memory_sanitized() {
+        let mut exec = Execution::new("foo", ExecutionCommand::local("foo"));
+        exec.limits_mut().memory(1234);
+        exec.sanitized(true);
+        let mut resources = ExecutionResourcesUsage {
+            cpu_time: 0.0,
+            sys_time: 0.0,
+            wall_time: 0.0,
+            memory: 1235,
+            instructions: None,
+        };
+        assert_eq!(
+            ExecutionStatus::Success,
+            exec.status(0, None, &resources, None)
+        );
+        resources.memory = 1234 * SANITIZER_MEMORY_FACTOR + 1;
+        assert_eq!(
+            ExecutionStatus::MemoryLimitExceeded,
+            exec.status(0, None, &resources, None)
+        );
+    }
+
+    #[test]
+    fn test_status_sanitizer_memory_exhausted() {
+        let mut exec = Execution::new("foo", ExecutionCommand::local("foo"));
+        exec.limits_mut().memory(1234);
+        let resources = ExecutionResourcesUsage {
+            cpu_time: 0.0,
+            sys_time: 0.0,
+            wall_time: 0.0,
+            memory: 1000,
+            instructions: None,
+        };
+        let stderr = b"==42==AddressSanitizer: hard rss limit exhausted (3mb vs 3mb)\n";
+        assert_eq!(
+            ExecutionStatus::ReturnCode(1),
+            exec.status(1, None, &resources, Some(stderr))
+        );
+        exec.sanitized(true);
+        assert_eq!(
+            ExecutionStatus::MemoryLimitExceeded,
+            exec.status(1, None, &resources, Some(stderr))
+        );
+        assert_eq!(
+            ExecutionStatus::ReturnCode(1),
+            exec.status(
+                1,
+                None,
+                &resources,
+                Some(b"==42==ERROR: AddressSanitizer: heap-use-after-free")
+            )
+        );
+        assert_eq!(
+            ExecutionStatus::ReturnCode(1),
+            exec.status(1, None, &resources, None)
+        );
+    }
+
     #[test]
     fn test_status_signal() {
         let exec = Execution::new("foo", ExecutionCommand::local("foo"));
@@ -949,7 +1104,9 @@ mod tests {
                 sys_time: 0.0,
                 wall_time: 0.0,
                 memory: 0,
+                instructions: None,
             },
+            None,
         );
         assert_eq!(ExecutionStatus::Signal(11, "Killed".into()), status);
     }
@@ -965,7 +1122,9 @@ mod tests {
                 sys_time: 0.0,
                 wall_time: 0.0,
                 memory: 0,
+                instructions: None,
             },
+            None,
         );
         assert_eq!(ExecutionStatus::ReturnCode(1), status);
     }
@@ -3,6 +3,7 @@
 
 #![deny(missing_docs)]
 
+mod sarif;
 mod span;
 
 use std::fmt::{Display, Formatter};
@@ -10,6 +11,7 @@ use std::fmt::{Display, Formatter};
 use colored::{Color, Colorize};
 use serde::{Deserialize, Serialize};
 
+pub use sarif::SarifLog;
 pub use span::CodeSpan;
 
 /// The level of the message.
@@ -64,6 +66,9 @@ pub struct Diagnostic {
     help_attachment: Option<Vec<u8>>,
     /// Spans to the relevant parts of the code of where the error is generated.
     code_spans: Vec<CodeSpan>,
+    /// The identifier of the check that emitted this diagnostic, e.g. the name of a sanity check.
+    #[serde(default)]
+    code: Option<String>,
 }
 
 impl Diagnostic {
@@ -76,6 +81,7 @@ impl Diagnostic {
             help: None,
             help_attachment: None,
             code_spans: Default::default(),
+            code: None,
         }
     }
 
@@ -88,6 +94,7 @@ impl Diagnostic {
             help: None,
             help_attachment: None,
             code_spans: Default::default(),
+            code: None,
         }
     }
 
@@ -115,6 +122,18 @@ impl Diagnostic {
         self
     }
 
+    /// Change the level of the diagnostic.
+    pub fn with_level(mut self, level: DiagnosticLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Set the identifier of the check that emitted this diagnostic.
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
     /// Print this diagnostic to the formatter. This is used by the [`std::fmt::Display`] trait.
     pub fn print(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         // TODO: additional printing options (e.g. no colors, compact, ...)
@@ -174,6 +193,26 @@ impl Diagnostic {
     pub fn message(&self) -> &str {
         &self.message
     }
+
+    /// Get the additional notes of this diagnostic, if any.
+    pub fn note(&self) -> Option<&str> {
+        self.note.as_deref()
+    }
+
+    /// Get the help for diagnosing the problem, if any.
+    pub fn help(&self) -> Option<&str> {
+        self.help.as_deref()
+    }
+
+    /// Get the spans to the relevant parts of the code.
+    pub fn code_spans(&self) -> &[CodeSpan] {
+        &self.code_spans
+    }
+
+    /// Get the identifier of the check that emitted this diagnostic, if any.
+    pub fn code(&self) -> Option<&str> {
+        self.code.as_deref()
+    }
 }
 
 impl Display for Diagnostic {
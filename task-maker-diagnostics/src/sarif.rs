@@ -0,0 +1,315 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Component, Path};
+
+use serde::Serialize;
+
+use crate::{CodeSpan, Diagnostic, DiagnosticContext, DiagnosticLevel};
+
+/// The URL of the JSON schema of SARIF 2.1.0.
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+/// The rule used for the diagnostics that don't come from a specific check.
+const GENERIC_RULE_ID: &str = "task-maker";
+/// The symbolic name of the directory the relative paths are resolved against.
+const SRCROOT: &str = "%SRCROOT%";
+
+/// A [SARIF 2.1.0](https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html) log built
+/// from a [`DiagnosticContext`], ready to be serialized.
+///
+/// Every diagnostic becomes a result, the code of the diagnostic (e.g. the name of the sanity
+/// check that emitted it) becomes its rule id and the code spans become its locations.
+#[derive(Debug, Clone, Serialize)]
+pub struct SarifLog {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: Vec<Run>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Run {
+    tool: Tool,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    original_uri_base_ids: BTreeMap<&'static str, ArtifactLocation>,
+    column_kind: &'static str,
+    results: Vec<SarifResult>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Tool {
+    driver: Driver,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Driver {
+    name: &'static str,
+    version: &'static str,
+    information_uri: &'static str,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Rule {
+    id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult {
+    rule_id: String,
+    rule_index: usize,
+    level: &'static str,
+    message: Message,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    locations: Vec<Location>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Message {
+    text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    physical_location: PhysicalLocation,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation {
+    artifact_location: ArtifactLocation,
+    region: Region,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArtifactLocation {
+    uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uri_base_id: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+    start_line: usize,
+    start_column: usize,
+    end_column: usize,
+}
+
+impl SarifLog {
+    /// Build the SARIF log with all the diagnostics of the context.
+    ///
+    /// The paths of the code spans inside `base_dir` are made relative to it, the other ones are
+    /// kept absolute.
+    pub fn new(context: &DiagnosticContext, base_dir: impl AsRef<Path>) -> Self {
+        let base_dir = base_dir.as_ref();
+        let mut rules: Vec<Rule> = vec![];
+        let mut results = vec![];
+        for diagnostic in context.diagnostics() {
+            let rule_id = diagnostic.code().unwrap_or(GENERIC_RULE_ID);
+            let rule_index = match rules.iter().position(|rule| rule.id == rule_id) {
+                Some(index) => index,
+                None => {
+                    rules.push(Rule { id: rule_id.into() });
+                    rules.len() - 1
+                }
+            };
+            results.push(SarifResult {
+                rule_id: rule_id.into(),
+                rule_index,
+                level: match diagnostic.level() {
+                    DiagnosticLevel::Error => "error",
+                    DiagnosticLevel::Warning => "warning",
+                },
+                message: Message {
+                    text: message_text(diagnostic),
+                },
+                locations: diagnostic
+                    .code_spans()
+                    .iter()
+                    .map(|span| location(span, base_dir))
+                    .collect(),
+            });
+        }
+
+        let mut original_uri_base_ids = BTreeMap::new();
+        let uses_base_dir = results
+            .iter()
+            .flat_map(|result| &result.locations)
+            .any(|location| {
+                location
+                    .physical_location
+                    .artifact_location
+                    .uri_base_id
+                    .is_some()
+            });
+        if uses_base_dir {
+            original_uri_base_ids.insert(
+                SRCROOT,
+                ArtifactLocation {
+                    uri: absolute_uri(base_dir) + "/",
+                    uri_base_id: None,
+                },
+            );
+        }
+
+        Self {
+            schema: SARIF_SCHEMA,
+            version: "2.1.0",
+            runs: vec![Run {
+                tool: Tool {
+                    driver: Driver {
+                        name: "task-maker",
+                        version: env!("CARGO_PKG_VERSION"),
+                        information_uri: "https://github.com/dp1/task-maker-rust",
+                        rules,
+                    },
+                },
+                original_uri_base_ids,
+                column_kind: "utf16CodeUnits",
+                results,
+            }],
+        }
+    }
+}
+
+/// Join the message of the diagnostic with its note and help, since SARIF has only one text.
+fn message_text(diagnostic: &Diagnostic) -> String {
+    let mut text = diagnostic.message().to_string();
+    if let Some(note) = diagnostic.note() {
+        let _ = write!(text, "\nNote: {}", note);
+    }
+    if let Some(help) = diagnostic.help() {
+        let _ = write!(text, "\nHelp: {}", help);
+    }
+    text
+}
+
+/// Convert a [`CodeSpan`] into a SARIF location.
+fn location(span: &CodeSpan, base_dir: &Path) -> Location {
+    let artifact_location = match span.file_name().strip_prefix(base_dir) {
+        Ok(relative) => ArtifactLocation {
+            uri: encode_path(relative),
+            uri_base_id: Some(SRCROOT),
+        },
+        Err(_) => ArtifactLocation {
+            uri: absolute_uri(span.file_name()),
+            uri_base_id: None,
+        },
+    };
+    let (start_column, end_column) = span.utf16_columns();
+    Location {
+        physical_location: PhysicalLocation {
+            artifact_location,
+            region: Region {
+                start_line: span.line_number(),
+                start_column,
+                end_column,
+            },
+        },
+    }
+}
+
+/// Build a `file://` URI for the provided absolute path.
+fn absolute_uri(path: &Path) -> String {
+    format!("file:///{}", encode_path(path))
+}
+
+/// Percent-encode the components of the path, joining them with `/`.
+fn encode_path(path: &Path) -> String {
+    let mut components = vec![];
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push(encode_component(&name.to_string_lossy())),
+            Component::ParentDir => components.push("..".into()),
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    components.join("/")
+}
+
+/// Percent-encode all the characters that are not unreserved in a URI.
+fn encode_component(component: &str) -> String {
+    let mut encoded = String::with_capacity(component.len());
+    for byte in component.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_sarif() {
+        let content = "GEN gen 1 2\n# àè\nVAL val\n";
+        let mut context = DiagnosticContext::new();
+        context.add_diagnostic(
+            Diagnostic::error("Bad generator")
+                .with_code("GenCheck")
+                .with_help("Fix it")
+                .with_code_span(CodeSpan::from_str("/task/gen/cases.gen", content, 4, 3).unwrap()),
+        );
+        context.add_diagnostic(Diagnostic::warning("Something else"));
+        context.add_diagnostic(
+            Diagnostic::warning("Outside")
+                .with_code("GenCheck")
+                .with_code_span(CodeSpan::from_str("/other dir/file", content, 16, 2).unwrap()),
+        );
+
+        let sarif = SarifLog::new(&context, "/task");
+        let sarif = serde_json::to_value(&sarif).unwrap();
+        assert_eq!(sarif["version"], "2.1.0");
+        let run = &sarif["runs"][0];
+        assert_eq!(
+            run["tool"]["driver"]["rules"],
+            json!([{ "id": "GenCheck" }, { "id": "task-maker" }])
+        );
+        assert_eq!(
+            run["originalUriBaseIds"]["%SRCROOT%"]["uri"],
+            "file:///task/"
+        );
+
+        let results = &run["results"];
+        assert_eq!(
+            results[0],
+            json!({
+                "ruleId": "GenCheck",
+                "ruleIndex": 0,
+                "level": "error",
+                "message": { "text": "Bad generator\nHelp: Fix it" },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": "gen/cases.gen", "uriBaseId": "%SRCROOT%" },
+                        "region": { "startLine": 1, "startColumn": 5, "endColumn": 8 }
+                    }
+                }]
+            })
+        );
+        assert_eq!(results[1]["ruleId"], "task-maker");
+        assert_eq!(results[1]["ruleIndex"], 1);
+        assert_eq!(results[1]["level"], "warning");
+        assert!(results[1].get("locations").is_none());
+
+        let location = &results[2]["locations"][0]["physicalLocation"];
+        assert_eq!(
+            location["artifactLocation"],
+            json!({ "uri": "file:///other%20dir/file" })
+        );
+        // The span covers "è", which is preceded by 4 bytes but only 3 UTF-16 code units.
+        assert_eq!(location["region"]["startLine"], 2);
+        assert_eq!(location["region"]["startColumn"], 4);
+        assert_eq!(location["region"]["endColumn"], 5);
+    }
+}
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::fmt::Write;
 use std::num::NonZeroUsize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Error};
 use colored::Colorize;
@@ -64,6 +64,33 @@ impl CodeSpan {
         bail!("The offset exceeds the length of the file")
     }
 
+    /// Create a new [`CodeSpan`] covering the whole line that contains the provided offset. Fails
+    /// if that line is empty.
+    pub fn from_line(
+        file_name: impl Into<PathBuf>,
+        content: impl AsRef<str>,
+        offset: usize,
+    ) -> Result<Self, Error> {
+        let content = content.as_ref();
+        let bytes = content.as_bytes();
+        let offset = offset.min(bytes.len());
+        // The newlines are single bytes, so the line boundaries are always char-aligned.
+        let start = bytes[..offset]
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |newline| newline + 1);
+        let end = bytes[start..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map_or(bytes.len(), |newline| start + newline);
+        #[allow(clippy::string_slice)]
+        let len = content[start..end].trim_end_matches('\r').len();
+        if len == 0 {
+            bail!("The line is empty");
+        }
+        Self::from_str(file_name, content, start, len)
+    }
+
     /// Get the content of the span as a `&str`.
     pub fn as_str(&self) -> &str {
         #[allow(clippy::string_slice)] // All the offsets are byte-related
@@ -97,6 +124,39 @@ impl CodeSpan {
     pub fn line_number(&self) -> usize {
         self.line_number.get()
     }
+
+    /// Get the path of the file where this span comes from.
+    pub fn file_name(&self) -> &Path {
+        &self.file_name
+    }
+
+    /// Get the offset of the first byte of the span, relative to the start of the file.
+    pub fn file_offset(&self) -> usize {
+        self.file_offset
+    }
+
+    /// Get the offset of the first byte of the span, relative to the start of the line.
+    pub fn line_offset(&self) -> usize {
+        self.line_offset
+    }
+
+    /// Get the length in bytes of the span.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the span has zero length.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the 1-based columns of the start of the span and of the character right after its end,
+    /// counted in UTF-16 code units.
+    pub fn utf16_columns(&self) -> (usize, usize) {
+        #[allow(clippy::string_slice)] // All the offsets are byte-related
+        let start = self.line[..self.line_offset].encode_utf16().count() + 1;
+        (start, start + self.as_str().encode_utf16().count())
+    }
 }
 
 #[cfg(test)]
@@ -193,4 +253,18 @@ mod tests {
         );
         assert_eq!(span.as_str(), "op");
     }
+
+    #[test]
+    fn test_from_line() {
+        let content = "content\r\nnope\n\nlast";
+        let span = CodeSpan::from_line("file.txt", content, 3).unwrap();
+        assert_eq!(span.as_str(), "content");
+        let span = CodeSpan::from_line("file.txt", content, 11).unwrap();
+        assert_eq!(span.as_str(), "nope");
+        assert_eq!(span.line_number(), 2);
+        assert_eq!(span.file_offset(), 9);
+        let span = CodeSpan::from_line("file.txt", content, content.len()).unwrap();
+        assert_eq!(span.as_str(), "last");
+        assert!(CodeSpan::from_line("file.txt", content, 14).is_err());
+    }
 }
@@ -33,6 +33,8 @@ impl ExecutorClient {
     /// * `eval` - The EvaluationData to evaluate.
     /// * `sender` - A channel that sends messages to the server.
     /// * `receiver` - A channel that receives messages from the server.
+    /// * `compress_transfers` - Whether to compress the files sent to the server, worth it only
+    ///   when the server is remote.
     ///
     /// ```
     /// use task_maker_dag::ExecutionDAG;
@@ -63,7 +65,7 @@ impl ExecutorClient {
     ///     executor.evaluate(tx_remote, rx_remote).unwrap();
     /// });
     ///
-    /// ExecutorClient::evaluate(dag, tx, &rx, file_store, |_| Ok(())).unwrap(); // this will block!
+    /// ExecutorClient::evaluate(dag, tx, &rx, file_store, false, |_| Ok(())).unwrap(); // this will block!
     ///
     /// server.join().expect("Server paniced");
     /// ```
@@ -73,6 +75,7 @@ impl ExecutorClient {
         sender: ChannelSender<ExecutorClientMessage>,
         receiver: &ChannelReceiver<ExecutorServerMessage>,
         file_store: Arc<FileStore>,
+        compress_transfers: bool,
         mut status_callback: F,
     ) -> Result<(), Error>
     where
@@ -106,9 +109,10 @@ impl ExecutorClient {
                         .lock()
                         .map_err(|_| anyhow!("Failed to obtain file_mode lock"))?;
                     let provided_files = &dag.data.provided_files;
-                    handle_server_ask_file(uuid, provided_files, &sender).with_context(|| {
-                        format!("Failed to process AskFile({}) from the server", uuid)
-                    })?;
+                    handle_server_ask_file(uuid, provided_files, &sender, compress_transfers)
+                        .with_context(|| {
+                            format!("Failed to process AskFile({}) from the server", uuid)
+                        })?;
                 }
                 Ok(ExecutorServerMessage::ProvideFile(uuid, success)) => {
                     info!("Server sent the file {}, success: {}", uuid, success);
@@ -185,7 +189,7 @@ impl ExecutorClient {
                                 dag.file_callbacks(),
                                 uuid,
                                 success,
-                                iterator,
+                                iterator.map(Ok),
                                 None,
                             )
                             .with_context(|| {
@@ -241,7 +245,7 @@ impl ExecutorClient {
                         &mut dag.callbacks.as_mut().unwrap().file_callbacks,
                         *uuid,
                         true,
-                        iterator,
+                        iterator.map(Ok),
                         Some(local_path),
                     )
                     .context("Failed to process local file")?;
@@ -251,7 +255,7 @@ impl ExecutorClient {
                         &mut dag.callbacks.as_mut().unwrap().file_callbacks,
                         *uuid,
                         true,
-                        vec![content.clone()],
+                        vec![Ok(content.clone())],
                         None,
                     )
                     .context("Failed to process file content")?;
@@ -302,6 +306,7 @@ fn handle_server_ask_file(
     uuid: FileUuid,
     provided_files: &HashMap<FileUuid, ProvidedFile>,
     sender: &ChannelSender<ExecutorClientMessage>,
+    compress: bool,
 ) -> Result<(), Error> {
     match &provided_files[&uuid] {
         ProvidedFile::LocalFile {
@@ -310,7 +315,7 @@ fn handle_server_ask_file(
             sender
                 .send(ExecutorClientMessage::ProvideFile(uuid, key.clone()))
                 .context("Failed to send ExecutorClientMessage::ProvideFile")?;
-            ChannelFileSender::send(local_path, sender).with_context(|| {
+            ChannelFileSender::send(local_path, sender, compress).with_context(|| {
                 format!("Failed to send local file from {}", local_path.display())
             })?;
         }
@@ -318,7 +323,7 @@ fn handle_server_ask_file(
             sender
                 .send(ExecutorClientMessage::ProvideFile(uuid, key.clone()))
                 .context("Failed to send ExecutorClientMessage::ProvideFile")?;
-            ChannelFileSender::send_data(content.clone(), sender)
+            ChannelFileSender::send_data(content.clone(), sender, compress)
                 .context("Failed to send file content")?;
         }
     }
@@ -345,6 +350,7 @@ where
             .collect(),
         ready_execs: status.ready_execs,
         waiting_execs: status.waiting_execs,
+        clients: status.clients,
     })
 }
 
@@ -355,7 +361,7 @@ where
 /// truncated, for this reason a best-effort approach is implemented: if the iterator reads a local
 /// file pass to this function also the path to the file. The file wont be truncated if write_to
 /// points to the same file as the hint.
-fn process_provided_file<I: IntoIterator<Item = Vec<u8>>>(
+fn process_provided_file<I: IntoIterator<Item = Result<Vec<u8>, Error>>>(
     file_callbacks: &mut HashMap<FileUuid, FileCallbacks>,
     uuid: FileUuid,
     success: bool,
@@ -412,6 +418,7 @@ fn process_provided_file<I: IntoIterator<Item = Vec<u8>>>(
             _ => (None, None),
         };
         for chunk in iterator {
+            let chunk = chunk.context("Failed to read the file")?;
             if let (Some(file), Some(dest)) = (&mut file, &dest) {
                 file.write_all(&chunk)
                     .with_context(|| format!("Failed to write chunk to {}", dest.display()))?;
@@ -448,7 +455,9 @@ fn process_provided_file<I: IntoIterator<Item = Vec<u8>>>(
                 .with_context(|| format!("get_content callback for file {} failed", uuid))?;
         }
     } else {
-        iterator.into_iter().last();
+        for chunk in iterator {
+            chunk.context("Failed to read the file")?;
+        }
     }
     Ok(())
 }
@@ -15,6 +15,8 @@ use task_maker_dag::{ExecutionGroup, ExecutionUuid, FileUuid, ProvidedFile, Work
 use task_maker_store::{FileStore, FileStoreHandle, FileStoreKey};
 
 use crate::check_dag::check_dag;
+use crate::executors::{AuditEvent, AuditLog};
+use crate::fair_share::SchedulingPolicy;
 use crate::proto::{
     ChannelFileIterator, ChannelFileSender, ExecutorClientMessage, ExecutorServerMessage,
 };
@@ -83,6 +85,24 @@ pub struct ExecutorWorkerStatus<T> {
     pub current_job: Option<WorkerCurrentJobStatus<T>>,
 }
 
+/// The share of the workers of an `Executor` that goes to an identity of clients: the owner of the
+/// token or of the TLS certificate they authenticated with, or all the clients that didn't.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, TypeScriptify)]
+pub struct ExecutorClientStatus {
+    /// The owner of the token or of the certificate, or `anonymous`.
+    pub identity: String,
+    /// Whether the clients authenticated with a token or a certificate.
+    pub authenticated: bool,
+    /// The weight of the identity in the fair share of the workers.
+    pub weight: u32,
+    /// The maximum number of workers the identity can use at the same time, if limited.
+    pub max_workers: Option<usize>,
+    /// The number of workers running the jobs of the identity.
+    pub running: usize,
+    /// The number of executions of the identity waiting for a worker.
+    pub ready: usize,
+}
+
 /// The current status of the `Executor`, this is sent to the user when the server status is asked.
 ///
 /// The type parameter `T` is either `SystemTime` for local usage or `Duration` for serialization.
@@ -95,8 +115,13 @@ pub struct ExecutorStatus<T> {
     pub ready_execs: usize,
     /// Number of executions waiting for dependencies.
     pub waiting_execs: usize,
+    /// The share of the workers of the clients that are evaluating a DAG.
+    pub clients: Vec<ExecutorClientStatus>,
 }
 
+/// The channels to the connected clients, and whether to compress the files sent to them.
+type ClientSenders = Arc<Mutex<HashMap<ClientUuid, (ChannelSender<ExecutorServerMessage>, bool)>>>;
+
 /// Message telling the executor that a new client connected or a new worker connected. The handling
 /// of the new peer is done by this executor.
 pub enum ExecutorInMessage {
@@ -109,6 +134,8 @@ pub enum ExecutorInMessage {
         sender: ChannelSender<ExecutorServerMessage>,
         /// A channel for received the messages from the client.
         receiver: ChannelReceiver<ExecutorClientMessage>,
+        /// Whether to compress the files sent to the client, worth it only for the remote clients.
+        compress_transfers: bool,
     },
     /// A new worker has connected, the executor starts listening for the messages and will directly
     /// interact with it.
@@ -135,6 +162,10 @@ pub(crate) struct Executor {
     /// flag is set to false, after the first client is done the Scheduler, the WorkerManager and
     /// this Executor will exit.
     long_running: bool,
+    /// Where to record the DAGs submitted by the clients.
+    audit: Option<Arc<AuditLog>>,
+    /// How the Scheduler shares the workers between the clients.
+    policy: SchedulingPolicy,
 }
 
 impl Executor {
@@ -153,9 +184,23 @@ impl Executor {
             cache,
             receiver,
             long_running,
+            audit: None,
+            policy: SchedulingPolicy::default(),
         }
     }
 
+    /// Record in the audit log the DAGs submitted by the clients.
+    pub fn with_audit_log(mut self, audit: Option<Arc<AuditLog>>) -> Executor {
+        self.audit = audit;
+        self
+    }
+
+    /// Share the workers between the clients following the provided policy.
+    pub fn with_scheduling_policy(mut self, policy: SchedulingPolicy) -> Executor {
+        self.policy = policy;
+        self
+    }
+
     /// Run the `Executor`, listening for client and worker connections. This will block until the
     /// first client is done (if `long_running` is false) or until the scheduler is stopped.
     pub fn run(self) -> Result<(), Error> {
@@ -171,7 +216,8 @@ impl Executor {
             scheduler_rx,
             sched_executor_tx,
             worker_manager_tx.clone(),
-        );
+        )
+        .with_scheduling_policy(self.policy);
         let worker_manager = WorkerManager::new(
             self.file_store.clone(),
             scheduler_tx.clone(),
@@ -198,13 +244,15 @@ impl Executor {
                     client,
                     sender,
                     receiver,
+                    compress_transfers,
                 } => {
                     {
                         let mut clients = clients.lock().unwrap();
-                        clients.insert(client.uuid, sender.clone());
+                        clients.insert(client.uuid, (sender.clone(), compress_transfers));
                     }
                     let scheduler = scheduler_tx.clone();
                     let file_store = self.file_store.clone();
+                    let audit = self.audit.clone();
                     let long_running = self.long_running;
                     // handle the new client in a new thread called "Client Manager"
                     // FIXME: this thread is leaked, maybe we can join it as well
@@ -219,7 +267,9 @@ impl Executor {
                                 client,
                                 sender,
                                 receiver,
+                                compress_transfers,
                                 scheduler.clone(),
+                                audit,
                             )
                             .unwrap();
                             // if not in long running mode, the first client should tear down the
@@ -263,14 +313,14 @@ impl Executor {
     #[allow(clippy::unnecessary_wraps)]
     fn handle_scheduler_messages(
         receiver: Receiver<SchedulerExecutorMessage>,
-        clients: Arc<Mutex<HashMap<ClientUuid, ChannelSender<ExecutorServerMessage>>>>,
+        clients: ClientSenders,
     ) -> Result<(), Error> {
         let mut ready_files: HashMap<ClientUuid, Vec<(FileUuid, FileStoreHandle, bool)>> =
             HashMap::new();
         while let Ok((client_uuid, message)) = receiver.recv() {
             let clients = clients.lock().unwrap();
-            let client = if let Some(client) = clients.get(&client_uuid) {
-                client
+            let (client, compress) = if let Some((client, compress)) = clients.get(&client_uuid) {
+                (client, *compress)
             } else {
                 // ignore messages for a disconnected client
                 continue;
@@ -296,7 +346,9 @@ impl Executor {
                             client.send(ExecutorServerMessage::ProvideFile(file, successful))
                         {
                             warn!("Failed to send urgent file: {:?}", e);
-                        } else if let Err(e) = ChannelFileSender::send(handle.path(), client) {
+                        } else if let Err(e) =
+                            ChannelFileSender::send(handle.path(), client, compress)
+                        {
                             warn!("Failed to send urgent file content: {:?}", e);
                         }
                     } else {
@@ -334,7 +386,9 @@ impl Executor {
         client: ClientInfo,
         sender: ChannelSender<ExecutorServerMessage>,
         receiver: ChannelReceiver<ExecutorClientMessage>,
+        compress_transfers: bool,
         scheduler: Sender<SchedulerInMessage>,
+        audit: Option<Arc<AuditLog>>,
     ) -> Result<(), Error> {
         let mut scheduler = Some(scheduler);
         while let Ok(message) = receiver.recv() {
@@ -349,6 +403,9 @@ impl Executor {
                     } else {
                         trace!("DAG looks valid!");
                     }
+                    if let Some(audit) = &audit {
+                        audit.record(&AuditEvent::evaluate(&client, &dag));
+                    }
                     // for each file marked as provided check if a local copy is present, otherwise
                     // ask the client to send it.
                     let mut ready_files = Vec::new();
@@ -395,7 +452,7 @@ impl Executor {
                     // the client provided a file that was not present locally, store it and tell
                     // the scheduler that it's now ready.
                     let handle = file_store
-                        .store(&key, ChannelFileIterator::new(&receiver))
+                        .try_store(&key, ChannelFileIterator::new(&receiver))
                         .with_context(|| {
                             format!("Failed to store client provided file {} ({})", uuid, key)
                         })?;
@@ -417,9 +474,10 @@ impl Executor {
                         sender
                             .send(ExecutorServerMessage::ProvideFile(uuid, success))
                             .context("Failed to send ProvideFile to the client")?;
-                        ChannelFileSender::send(handle.path(), &sender).with_context(|| {
-                            format!("Failed to send file {} to the client", handle)
-                        })?;
+                        ChannelFileSender::send(handle.path(), &sender, compress_transfers)
+                            .with_context(|| {
+                                format!("Failed to send file {} to the client", handle)
+                            })?;
                     } else {
                         sender
                             .send(ExecutorServerMessage::Error(format!(
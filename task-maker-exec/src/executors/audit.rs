@@ -0,0 +1,169 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Error};
+use serde::Serialize;
+use uuid::Uuid;
+
+use task_maker_dag::{DagPriority, ExecutionDAGData};
+
+use crate::executors::RemoteEntityKind;
+use crate::scheduler::ClientInfo;
+
+/// An event recorded in the audit log of a `RemoteExecutor`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// A client or a worker has been accepted by the server.
+    Connected {
+        /// Whether a client or a worker connected.
+        kind: RemoteEntityKind,
+        /// The identifier given by the server to the connection.
+        uuid: Uuid,
+        /// The name sent by the client or the worker.
+        name: String,
+        /// The owner of the token or of the TLS certificate used for connecting, if any.
+        user: Option<String>,
+        /// The address of the peer.
+        addr: String,
+    },
+    /// A client or a worker has been rejected by the server.
+    Rejected {
+        /// Whether a client or a worker tried to connect.
+        kind: RemoteEntityKind,
+        /// The name sent by the client or the worker.
+        name: String,
+        /// The address of the peer.
+        addr: String,
+        /// Why the connection has been rejected.
+        reason: String,
+    },
+    /// A client submitted a DAG for the evaluation.
+    Evaluate {
+        /// The client that submitted the DAG.
+        client: ClientInfo,
+        /// The directory of the task the DAG evaluates, on the client.
+        task_dir: Option<PathBuf>,
+        /// The priority of the DAG.
+        priority: DagPriority,
+        /// The number of execution groups of the DAG.
+        groups: usize,
+        /// The total number of executions of the DAG.
+        executions: usize,
+    },
+}
+
+impl AuditEvent {
+    /// The event of a client submitting a DAG.
+    pub(crate) fn evaluate(client: &ClientInfo, dag: &ExecutionDAGData) -> AuditEvent {
+        AuditEvent::Evaluate {
+            client: client.clone(),
+            task_dir: dag.config.task_dir.clone(),
+            priority: dag.config.priority,
+            groups: dag.execution_groups.len(),
+            executions: dag
+                .execution_groups
+                .values()
+                .map(|group| group.executions.len())
+                .sum(),
+        }
+    }
+}
+
+/// An append-only log with who connected to the server and which DAGs they submitted. Each line
+/// of the file is a JSON object with the time of the event, in seconds since the UNIX epoch, and
+/// the fields of the `AuditEvent`.
+#[derive(Debug)]
+pub struct AuditLog {
+    /// The file of the log, opened in append mode.
+    file: Mutex<File>,
+}
+
+/// A line of the audit log.
+#[derive(Serialize)]
+struct AuditLine<'a> {
+    /// When the event happened.
+    time: u64,
+    /// What happened.
+    #[serde(flatten)]
+    event: &'a AuditEvent,
+}
+
+impl AuditLog {
+    /// Open the audit log at the specified path, creating it if it doesn't exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<AuditLog, Error> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open the audit log at {}", path.display()))?;
+        Ok(AuditLog {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Append an event to the log. A failure is only reported in the logs of the server, it doesn't
+    /// stop it.
+    pub fn record(&self, event: &AuditEvent) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let line = match serde_json::to_string(&AuditLine { time, event }) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Cannot serialize audit event {:?}: {:?}", event, e);
+                return;
+            }
+        };
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = writeln!(file, "{}", line) {
+            warn!("Cannot write to the audit log: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_audit_log() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("audit.log");
+        let log = AuditLog::open(&path).unwrap();
+        log.record(&AuditEvent::Rejected {
+            kind: RemoteEntityKind::Client,
+            name: "client".into(),
+            addr: "127.0.0.1:1234".into(),
+            reason: "Invalid or revoked token".into(),
+        });
+        drop(log);
+        let log = AuditLog::open(&path).unwrap();
+        log.record(&AuditEvent::Connected {
+            kind: RemoteEntityKind::Worker,
+            uuid: Uuid::new_v4(),
+            name: "worker".into(),
+            user: Some("lab".into()),
+            addr: "127.0.0.1:1235".into(),
+        });
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["event"], "rejected");
+        assert_eq!(lines[0]["kind"], "client");
+        assert!(lines[0]["time"].as_u64().unwrap() > 0);
+        assert_eq!(lines[1]["event"], "connected");
+        assert_eq!(lines[1]["user"], "lab");
+    }
+}
@@ -100,9 +100,11 @@ impl LocalExecutor {
                 client: ClientInfo {
                     uuid: Uuid::new_v4(),
                     name: "Local client".to_string(),
+                    user: None,
                 },
                 sender,
                 receiver,
+                compress_transfers: false,
             })
             .map_err(|e| anyhow!("Failed to send ClientConnected: {:?}", e))?;
 
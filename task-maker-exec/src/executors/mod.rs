@@ -35,8 +35,12 @@
 //! # server.join().unwrap();
 //! ```
 
+mod audit;
 mod local_executor;
 mod remote_executor;
+mod tokens;
 
+pub use audit::*;
 pub use local_executor::*;
 pub use remote_executor::*;
+pub use tokens::*;
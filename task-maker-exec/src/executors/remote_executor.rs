@@ -1,7 +1,10 @@
-use std::sync::mpsc::{channel, Sender};
+use std::net::Shutdown;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use ductile::{ChannelSender, ChannelServer};
+use ductile::{ChannelReceiver, ChannelSender, ChannelServer};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -9,16 +12,30 @@ use task_maker_cache::Cache;
 use task_maker_store::FileStore;
 
 use crate::executor::{Executor, ExecutorInMessage};
+use crate::executors::{AccessTokens, AuditEvent, AuditLog};
 use crate::scheduler::ClientInfo;
+use crate::tls::{ServerConfig, TlsIncoming, TlsListener};
+use crate::SchedulingPolicy;
 use crate::{derive_key_from_password, WorkerConn};
-use anyhow::{anyhow, Context, Error};
+use anyhow::{anyhow, bail, Context, Error};
 
 /// Version of task-maker
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// The maximum time a TLS peer has for completing the handshake and sending the welcome message,
+/// after that the connection is dropped.
+const SETUP_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// An executor that accepts remote connections from clients and workers.
 pub struct RemoteExecutor {
     file_store: Arc<FileStore>,
+    /// The TLS configuration of the server, if the connections should use TLS.
+    tls: Option<Arc<ServerConfig>>,
+    /// The file with the tokens the clients and the workers should authenticate with.
+    tokens: Option<PathBuf>,
+    /// Where to record the connections and the submitted DAGs.
+    audit: Option<Arc<AuditLog>>,
+    /// How the workers are shared between the clients.
+    policy: SchedulingPolicy,
 }
 
 /// Message sent only by remote clients and workers for connecting to the server.
@@ -30,6 +47,8 @@ pub enum RemoteEntityMessage {
         name: String,
         /// The required version of task-maker.
         version: String,
+        /// The token for authenticating to the server, if any.
+        token: Option<String>,
     },
 }
 
@@ -42,13 +61,111 @@ pub enum RemoteEntityMessageResponse {
     Rejected(String),
 }
 
+/// The kind of peer connecting to a `RemoteExecutor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoteEntityKind {
+    /// A client that wants to evaluate some DAGs.
+    Client,
+    /// A worker that executes the jobs.
+    Worker,
+}
+
+impl std::fmt::Display for RemoteEntityKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemoteEntityKind::Client => write!(f, "Client"),
+            RemoteEntityKind::Worker => write!(f, "Worker"),
+        }
+    }
+}
+
+/// A new connection to the server: the channels, the address of the peer and the user of its
+/// verified TLS certificate, if any.
+type Connection = (
+    ChannelSender<RemoteEntityMessageResponse>,
+    ChannelReceiver<RemoteEntityMessage>,
+    String,
+    Option<String>,
+);
+
+/// A new connection to the server, whose setup may not be complete yet.
+enum Incoming {
+    /// A connection ready to receive the welcome message.
+    Ready(Connection),
+    /// A TLS connection that still needs the handshake.
+    Tls(TlsIncoming),
+}
+
+/// The connections of a peer that has been accepted by the server.
+struct AcceptedPeer {
+    /// The identifier of the connection.
+    uuid: Uuid,
+    /// The name sent by the peer.
+    name: String,
+    /// The owner of the token or of the TLS certificate used by the peer, if any.
+    user: Option<String>,
+    /// The channel for sending messages to the peer.
+    sender: ChannelSender<RemoteEntityMessageResponse>,
+    /// The channel for receiving messages from the peer.
+    receiver: ChannelReceiver<RemoteEntityMessage>,
+}
+
+/// How the listeners of the server accept and authenticate the connections.
+#[derive(Clone)]
+struct ListenerConfig {
+    /// The TLS configuration of the server, if any.
+    tls: Option<Arc<ServerConfig>>,
+    /// The file with the tokens, if any.
+    tokens: Option<PathBuf>,
+    /// The audit log, if any.
+    audit: Option<Arc<AuditLog>>,
+}
+
 impl RemoteExecutor {
     /// Make a new `RemoteExecutor`.
     pub fn new(file_store: Arc<FileStore>) -> Self {
-        RemoteExecutor { file_store }
+        RemoteExecutor {
+            file_store,
+            tls: None,
+            tokens: None,
+            audit: None,
+            policy: SchedulingPolicy::default(),
+        }
+    }
+
+    /// Accept only TLS connections from the clients and the workers, with the provided
+    /// configuration (see [`crate::tls::server_config`]). The unix sockets are not affected.
+    pub fn with_tls(mut self, config: Option<Arc<ServerConfig>>) -> Self {
+        self.tls = config;
+        self
+    }
+
+    /// Require the clients and the workers to authenticate with one of the tokens in the provided
+    /// file (see [`AccessTokens`]). The file is read again at every connection, so the tokens can
+    /// be added and revoked without restarting the server.
+    pub fn with_tokens<P: Into<PathBuf>>(mut self, path: Option<P>) -> Self {
+        self.tokens = path.map(Into::into);
+        self
+    }
+
+    /// Record in the audit log who connects to the server and which DAGs they submit.
+    pub fn with_audit_log(mut self, audit: Option<AuditLog>) -> Self {
+        self.audit = audit.map(Arc::new);
+        self
+    }
+
+    /// Share the workers between the clients following the provided policy (see
+    /// [`SchedulingPolicy`]). By default all the clients have the same share and no limits.
+    pub fn with_scheduling_policy(mut self, policy: SchedulingPolicy) -> Self {
+        self.policy = policy;
+        self
     }
 
     /// Start the executor binding the TCP sockets and waiting for clients and workers connections.
+    ///
+    /// The access tokens are refused on the TCP addresses protected neither by TLS nor by a
+    /// password, since they would be sent in cleartext.
     pub fn start<S: Into<String>, S2: Into<String>>(
         self,
         bind_client_addr: S,
@@ -60,20 +177,49 @@ impl RemoteExecutor {
         let file_store = self.file_store;
         let bind_client_addr = bind_client_addr.into();
         let bind_worker_addr = bind_worker_addr.into();
+        let config = ListenerConfig {
+            tls: self.tls,
+            tokens: self.tokens,
+            audit: self.audit,
+        };
+        if config.tokens.is_some() && config.tls.is_none() {
+            let addresses = [
+                (&bind_client_addr, &client_password),
+                (&bind_worker_addr, &worker_password),
+            ];
+            for (addr, password) in addresses {
+                if password.is_none() && !addr.starts_with("unix://") {
+                    bail!(
+                        "The tokens would be sent in cleartext to {}, use TLS or a password",
+                        addr
+                    );
+                }
+            }
+        }
 
         let (executor_tx, executor_rx) = channel();
-        let executor = Executor::new(file_store, cache, executor_rx, true);
+        let executor = Executor::new(file_store, cache, executor_rx, true)
+            .with_audit_log(config.audit.clone())
+            .with_scheduling_policy(self.policy);
 
         let client_executor_tx = executor_tx.clone();
+        let client_config = config.clone();
         let client_listener_thread = std::thread::Builder::new()
             .name("Client listener".to_string())
             .spawn(move || {
-                Self::client_listener(client_password, bind_client_addr, client_executor_tx)
+                Self::client_listener(
+                    client_password,
+                    bind_client_addr,
+                    client_config,
+                    client_executor_tx,
+                )
             })
             .context("Cannot spawn client listener thread")?;
         let worker_listener_thread = std::thread::Builder::new()
             .name("Worker listener".to_string())
-            .spawn(move || Self::worker_listener(worker_password, bind_worker_addr, executor_tx))
+            .spawn(move || {
+                Self::worker_listener(worker_password, bind_worker_addr, config, executor_tx)
+            })
             .context("Cannot spawn worker listener thread")?;
 
         executor.run()?;
@@ -92,60 +238,28 @@ impl RemoteExecutor {
     fn client_listener(
         client_password: Option<String>,
         bind_client_addr: String,
+        config: ListenerConfig,
         client_executor_tx: Sender<ExecutorInMessage>,
     ) -> Result<(), Error> {
-        let server = if let Some(path) = bind_client_addr.strip_prefix("unix://") {
-            ChannelServer::bind_unix(path)
-                .with_context(|| format!("Failed to bind client unix socket at {}", path))?
-        } else {
-            match client_password {
-                Some(password) => {
-                    let key = derive_key_from_password(password);
-                    ChannelServer::bind_with_enc(&bind_client_addr, key)
-                        .context("Failed to bind client address")?
-                }
-                None => ChannelServer::bind(&bind_client_addr)
-                    .context("Failed to bind client address")?,
-            }
-        };
-
-        let local_addr = server
-            .local_addr()
-            .context("Failed to get client address")?;
-        info!(
-            "Accepting client connections at {}",
-            if let Some(addr) = local_addr {
-                format!("tcp://{}", addr)
-            } else {
-                bind_client_addr
-            }
-        );
-        for (sender, receiver, addr) in server {
-            let addr = addr
-                .map(|s| s.to_string())
-                .unwrap_or_else(|| "(local)".into());
-            info!("Client connected from {}", addr);
-            let uuid = Uuid::new_v4();
-            let name = if let Ok(RemoteEntityMessage::Welcome { name, version }) = receiver.recv() {
-                if !validate_welcome(&addr, &name, version, &sender, "Client") {
-                    continue;
-                }
-                name
-            } else {
-                warn!(
-                    "Client at {} has not sent the correct welcome message!",
-                    addr
-                );
-                continue;
-            };
-            let client = ClientInfo { uuid, name };
-            client_executor_tx
-                .send(ExecutorInMessage::ClientConnected {
-                    client,
-                    sender: sender.change_type(),
-                    receiver: receiver.change_type(),
-                })
-                .map_err(|e| anyhow!("Executor is gone: {:?}", e))?;
+        let kind = RemoteEntityKind::Client;
+        let connections = bind(kind, client_password, bind_client_addr, &config)?;
+        for incoming in connections {
+            let executor_tx = client_executor_tx.clone();
+            spawn_setup(kind, incoming, config.clone(), move |peer| {
+                let client = ClientInfo {
+                    uuid: peer.uuid,
+                    name: peer.name,
+                    user: peer.user,
+                };
+                executor_tx
+                    .send(ExecutorInMessage::ClientConnected {
+                        client,
+                        sender: peer.sender.change_type(),
+                        receiver: peer.receiver.change_type(),
+                        compress_transfers: true,
+                    })
+                    .map_err(|e| anyhow!("Executor is gone: {:?}", e))
+            })?;
         }
         Ok(())
     }
@@ -153,84 +267,309 @@ impl RemoteExecutor {
     fn worker_listener(
         worker_password: Option<String>,
         bind_worker_addr: String,
+        config: ListenerConfig,
         executor_tx: Sender<ExecutorInMessage>,
     ) -> Result<(), Error> {
-        let server = if let Some(path) = bind_worker_addr.strip_prefix("unix://") {
-            ChannelServer::bind_unix(path)
-                .with_context(|| format!("Failed to bind worker unix socket at {}", path))?
+        let kind = RemoteEntityKind::Worker;
+        let connections = bind(kind, worker_password, bind_worker_addr, &config)?;
+        for incoming in connections {
+            let executor_tx = executor_tx.clone();
+            spawn_setup(kind, incoming, config.clone(), move |peer| {
+                let worker = WorkerConn {
+                    uuid: peer.uuid,
+                    name: peer.name,
+                    sender: peer.sender.change_type(),
+                    receiver: peer.receiver.change_type(),
+                    compress_transfers: true,
+                };
+                executor_tx
+                    .send(ExecutorInMessage::WorkerConnected { worker })
+                    .map_err(|e| anyhow!("Executor is gone: {:?}", e))
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Bind the listener for a kind of peers, returning an iterator over the new connections.
+fn bind(
+    kind: RemoteEntityKind,
+    password: Option<String>,
+    bind_addr: String,
+    config: &ListenerConfig,
+) -> Result<Box<dyn Iterator<Item = Incoming>>, Error> {
+    let kind_name = kind.to_string().to_lowercase();
+    if let Some(path) = bind_addr.strip_prefix("unix://") {
+        let server = ChannelServer::bind_unix(path)
+            .with_context(|| format!("Failed to bind {} unix socket at {}", kind_name, path))?;
+        info!("Accepting {} connections at {}", kind_name, bind_addr);
+        return Ok(Box::new(server.map(|(sender, receiver, _)| {
+            Incoming::Ready((sender, receiver, "(local)".to_string(), None))
+        })));
+    }
+    if let Some(tls) = &config.tls {
+        let listener = TlsListener::bind(&bind_addr, tls.clone())
+            .with_context(|| format!("Failed to bind {} address", kind_name))?;
+        let local_addr = listener
+            .local_addr()
+            .with_context(|| format!("Failed to get {} address", kind_name))?;
+        info!(
+            "Accepting {} connections at tls://{}",
+            kind_name, local_addr
+        );
+        return Ok(Box::new(TlsConnections { listener }));
+    }
+    let server = match password {
+        Some(password) => {
+            let key = derive_key_from_password(password);
+            ChannelServer::bind_with_enc(&bind_addr, key)
+                .with_context(|| format!("Failed to bind {} address", kind_name))?
+        }
+        None => ChannelServer::bind(&bind_addr)
+            .with_context(|| format!("Failed to bind {} address", kind_name))?,
+    };
+    let local_addr = server
+        .local_addr()
+        .with_context(|| format!("Failed to get {} address", kind_name))?;
+    info!(
+        "Accepting {} connections at {}",
+        kind_name,
+        if let Some(addr) = local_addr {
+            format!("tcp://{}", addr)
         } else {
-            match worker_password {
-                Some(password) => {
-                    let key = derive_key_from_password(password);
-                    ChannelServer::bind_with_enc(&bind_worker_addr, key)
-                        .context("Failed to bind worker address")?
+            bind_addr
+        }
+    );
+    Ok(Box::new(server.map(|(sender, receiver, addr)| {
+        let addr = addr
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| "(local)".into());
+        Incoming::Ready((sender, receiver, addr, None))
+    })))
+}
+
+/// The TCP connections accepted by a `TlsListener`, their handshake is done by `setup`.
+struct TlsConnections {
+    /// The listener of the connections.
+    listener: TlsListener,
+}
+
+impl Iterator for TlsConnections {
+    type Item = Incoming;
+
+    fn next(&mut self) -> Option<Incoming> {
+        loop {
+            match self.listener.accept_tcp() {
+                Ok(incoming) => return Some(Incoming::Tls(incoming)),
+                Err(e) => warn!("Failed to accept a TLS connection: {:?}", e),
+            }
+        }
+    }
+}
+
+/// Complete the setup of a new connection in a new thread, so that a slow peer doesn't prevent the
+/// others from connecting. If the peer is accepted, `on_accepted` is called with it.
+fn spawn_setup<F>(
+    kind: RemoteEntityKind,
+    incoming: Incoming,
+    config: ListenerConfig,
+    on_accepted: F,
+) -> Result<(), Error>
+where
+    F: FnOnce(AcceptedPeer) -> Result<(), Error> + Send + 'static,
+{
+    std::thread::Builder::new()
+        .name(format!("{} setup", kind))
+        .spawn(move || {
+            if let Some(peer) = setup(kind, incoming, &config) {
+                if let Err(e) = on_accepted(peer) {
+                    warn!("Cannot handle the new {}: {:?}", kind, e);
                 }
-                None => ChannelServer::bind(&bind_worker_addr)
-                    .context("Failed to bind worker address")?,
             }
-        };
-        let local_addr = server
-            .local_addr()
-            .context("Failed to get worker address")?;
-        info!(
-            "Accepting worker connections at {}",
-            if let Some(addr) = local_addr {
-                format!("tcp://{}", addr)
-            } else {
-                bind_worker_addr
+        })
+        .with_context(|| format!("Cannot spawn the {} setup thread", kind))?;
+    Ok(())
+}
+
+/// Complete the TLS handshake of a new connection, if needed, and wait for its welcome message.
+/// The TLS peers must complete both before `SETUP_TIMEOUT`, otherwise they are disconnected.
+fn setup(
+    kind: RemoteEntityKind,
+    incoming: Incoming,
+    config: &ListenerConfig,
+) -> Option<AcceptedPeer> {
+    let incoming = match incoming {
+        Incoming::Ready(connection) => return accept(kind, connection, config),
+        Incoming::Tls(incoming) => incoming,
+    };
+    let deadline = Instant::now() + SETUP_TIMEOUT;
+    let addr = incoming.peer_addr();
+    let res = incoming.try_clone_tcp().and_then(|tcp| {
+        let (mut server, user) =
+            incoming.complete(deadline, |path| Ok(ChannelServer::bind_unix(path)?))?;
+        let (sender, receiver, _) = server
+            .next()
+            .ok_or_else(|| anyhow!("The bridge of {} is gone", addr))?;
+        Ok((tcp, (sender, receiver, addr.to_string(), user)))
+    });
+    let (tcp, connection) = match res {
+        Ok(res) => res,
+        Err(e) => {
+            warn!("Failed to accept a TLS connection: {:?}", e);
+            return None;
+        }
+    };
+    // disconnect the peer if the welcome message doesn't arrive in time
+    let (done_tx, done_rx) = channel::<()>();
+    let watchdog = std::thread::Builder::new()
+        .name(format!("{} setup watchdog", kind))
+        .spawn(move || {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if let Err(RecvTimeoutError::Timeout) = done_rx.recv_timeout(timeout) {
+                warn!("{} at {} is too slow to connect, dropping it", kind, addr);
+                let _ = tcp.shutdown(Shutdown::Both);
             }
-        );
-        for (sender, receiver, addr) in server {
-            let addr = addr
-                .map(|s| s.to_string())
-                .unwrap_or_else(|| "(local)".into());
-            info!("Worker connected from {}", addr);
+        });
+    if let Err(e) = watchdog {
+        warn!("Cannot spawn the setup watchdog: {:?}", e);
+        return None;
+    }
+    let peer = accept(kind, connection, config);
+    drop(done_tx);
+    peer
+}
+
+/// Wait for the welcome message of a new connection and check if the peer is allowed to connect,
+/// replying to it. Returns the peer if it has been accepted.
+fn accept(
+    kind: RemoteEntityKind,
+    connection: Connection,
+    config: &ListenerConfig,
+) -> Option<AcceptedPeer> {
+    let (sender, receiver, addr, certificate_user) = connection;
+    info!("{} connected from {}", kind, addr);
+    let (name, version, token) = match receiver.recv() {
+        Ok(RemoteEntityMessage::Welcome {
+            name,
+            version,
+            token,
+        }) => (name, version, token),
+        _ => {
+            warn!(
+                "{} at {} has not sent the correct welcome message!",
+                kind, addr
+            );
+            return None;
+        }
+    };
+    let user = authenticate(
+        kind,
+        &version,
+        token.as_deref(),
+        config.tokens.as_deref(),
+        certificate_user.as_deref(),
+    );
+    match user {
+        Ok(user) => {
             let uuid = Uuid::new_v4();
-            let name = if let Ok(RemoteEntityMessage::Welcome { name, version }) = receiver.recv() {
-                if !validate_welcome(&addr, &name, version, &sender, "Worker") {
-                    continue;
-                }
-                name
-            } else {
-                warn!(
-                    "Worker at {} has not sent the correct welcome message!",
-                    addr
-                );
-                continue;
-            };
-            let worker = WorkerConn {
+            let _ = sender.send(RemoteEntityMessageResponse::Accepted);
+            if let Some(audit) = &config.audit {
+                audit.record(&AuditEvent::Connected {
+                    kind,
+                    uuid,
+                    name: name.clone(),
+                    user: user.clone(),
+                    addr,
+                });
+            }
+            Some(AcceptedPeer {
                 uuid,
                 name,
-                sender: sender.change_type(),
-                receiver: receiver.change_type(),
-            };
-            executor_tx
-                .send(ExecutorInMessage::WorkerConnected { worker })
-                .map_err(|e| anyhow!("Executor is gone: {:?}", e))?;
+                user,
+                sender,
+                receiver,
+            })
+        }
+        Err(reason) => {
+            warn!("{} '{}' from {} rejected: {}", kind, name, addr, reason);
+            let _ = sender.send(RemoteEntityMessageResponse::Rejected(reason.clone()));
+            if let Some(audit) = &config.audit {
+                audit.record(&AuditEvent::Rejected {
+                    kind,
+                    name,
+                    addr,
+                    reason,
+                });
+            }
+            None
         }
-        Ok(())
     }
 }
 
-fn validate_welcome(
-    addr: &str,
-    name: &str,
-    version: String,
-    sender: &ChannelSender<RemoteEntityMessageResponse>,
-    client: &str,
-) -> bool {
+/// Check the version and the token sent by a peer, returning its user, or why the peer has been
+/// rejected. The user is the owner of the token if the server uses the tokens, otherwise the user
+/// of the verified TLS certificate of the peer, if any.
+fn authenticate(
+    kind: RemoteEntityKind,
+    version: &str,
+    token: Option<&str>,
+    tokens: Option<&Path>,
+    certificate_user: Option<&str>,
+) -> Result<Option<String>, String> {
     if version != VERSION {
-        warn!(
-            "{} '{}' from {} connected with version {}, server has {}",
-            client, name, addr, version, VERSION
-        );
-        let _ = sender.send(RemoteEntityMessageResponse::Rejected(format!(
+        return Err(format!(
             "Wrong task-maker version, you have {}, server has {}",
             version, VERSION
-        )));
-        false
-    } else {
-        let _ = sender.send(RemoteEntityMessageResponse::Accepted);
-        true
+        ));
+    }
+    let tokens = match tokens {
+        Some(tokens) => AccessTokens::load(tokens).map_err(|e| {
+            error!("Cannot load the tokens: {:?}", e);
+            "The server cannot verify the tokens".to_string()
+        })?,
+        None => return Ok(certificate_user.map(String::from)),
+    };
+    let token = token.ok_or_else(|| "This server requires a token".to_string())?;
+    match tokens.owner(kind, token) {
+        Some(owner) => Ok(Some(owner.to_string())),
+        None => Err("Invalid or revoked token".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_authenticate() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("tokens.toml");
+        std::fs::write(&path, "[clients]\nalice = \"secret\"\n").unwrap();
+        let client = RemoteEntityKind::Client;
+        let worker = RemoteEntityKind::Worker;
+
+        assert_eq!(authenticate(client, VERSION, None, None, None), Ok(None));
+        assert!(authenticate(client, "0.0.0", None, None, None).is_err());
+        assert_eq!(
+            authenticate(client, VERSION, Some("secret"), Some(&path), None),
+            Ok(Some("alice".to_string()))
+        );
+        assert!(authenticate(client, VERSION, None, Some(&path), None).is_err());
+        assert!(authenticate(worker, VERSION, Some("secret"), Some(&path), None).is_err());
+        // the certificate identifies the peer when the tokens are not used
+        assert_eq!(
+            authenticate(client, VERSION, None, None, Some("bob")),
+            Ok(Some("bob".to_string()))
+        );
+        assert_eq!(
+            authenticate(client, VERSION, Some("secret"), Some(&path), Some("bob")),
+            Ok(Some("alice".to_string()))
+        );
+        assert!(authenticate(client, VERSION, None, Some(&path), Some("bob")).is_err());
+        // revoking the token
+        std::fs::write(&path, "[clients]\n").unwrap();
+        assert!(authenticate(client, VERSION, Some("secret"), Some(&path), None).is_err());
     }
 }
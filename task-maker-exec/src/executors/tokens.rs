@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Error};
+use serde::Deserialize;
+
+use crate::executors::RemoteEntityKind;
+
+/// The tokens that authenticate the clients and the workers of a `RemoteExecutor`, each with the
+/// name of its owner. They are loaded from a TOML file like:
+///
+/// ```toml
+/// [clients]
+/// alice = "the token of alice"
+/// team-b = "the token shared by team b"
+///
+/// [workers]
+/// lab = "the token of the workers of the lab"
+/// ```
+///
+/// A token is revoked by removing it from the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccessTokens {
+    /// The tokens of the clients, indexed by the name of their owner.
+    #[serde(default)]
+    clients: HashMap<String, String>,
+    /// The tokens of the workers, indexed by the name of their owner.
+    #[serde(default)]
+    workers: HashMap<String, String>,
+}
+
+impl AccessTokens {
+    /// Load the tokens from the TOML file at the provided path.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<AccessTokens, Error> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("Invalid tokens file {}", path.display()))
+    }
+
+    /// The name of the owner of the token, if it's a valid token for that kind of connection.
+    pub fn owner(&self, kind: RemoteEntityKind, token: &str) -> Option<&str> {
+        let tokens = match kind {
+            RemoteEntityKind::Client => &self.clients,
+            RemoteEntityKind::Worker => &self.workers,
+        };
+        // the hashes are compared in constant time, not leaking the content of the tokens
+        let hash = blake3::hash(token.as_bytes());
+        tokens
+            .iter()
+            .filter(|(_, valid)| !valid.is_empty())
+            .find(|(_, valid)| blake3::hash(valid.as_bytes()) == hash)
+            .map(|(owner, _)| owner.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_load_tokens() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("tokens.toml");
+        std::fs::write(
+            &path,
+            "[clients]\nalice = \"secret\"\nbob = \"\"\n[workers]\nlab = \"other\"\n",
+        )
+        .unwrap();
+        let tokens = AccessTokens::load(&path).unwrap();
+        assert_eq!(
+            tokens.owner(RemoteEntityKind::Client, "secret"),
+            Some("alice")
+        );
+        assert_eq!(tokens.owner(RemoteEntityKind::Client, "other"), None);
+        assert_eq!(tokens.owner(RemoteEntityKind::Client, ""), None);
+        assert_eq!(tokens.owner(RemoteEntityKind::Worker, "other"), Some("lab"));
+        assert_eq!(tokens.owner(RemoteEntityKind::Worker, "secret"), None);
+    }
+
+    #[test]
+    fn test_load_tokens_invalid() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("tokens.toml");
+        std::fs::write(&path, "[admins]\nalice = \"secret\"\n").unwrap();
+        assert!(AccessTokens::load(&path).is_err());
+        assert!(AccessTokens::load(tmp.path().join("missing.toml")).is_err());
+    }
+}
@@ -0,0 +1,565 @@
+use std::collections::{BinaryHeap, HashMap};
+use std::path::Path;
+
+use anyhow::{Context, Error};
+use serde::{Deserialize, Serialize};
+
+use task_maker_dag::{DagPriority, ExecutionGroupUuid, Priority};
+
+use crate::executor::ExecutorClientStatus;
+use crate::scheduler::{ClientInfo, ClientUuid};
+
+/// The limits a server applies to the clients, split by identity: the user the client
+/// authenticated as, with a token or a TLS certificate. The clients that didn't authenticate all
+/// share a single identity. It's loaded from a TOML file like:
+///
+/// ```toml
+/// # applied to every client
+/// [default]
+/// max_priority = 0
+/// max_workers = 4
+///
+/// # applied to the clients authenticated as alice, overriding the default
+/// [users.alice]
+/// weight = 2
+/// max_workers = 8
+/// ```
+///
+/// The `users` sections only apply to the authenticated clients, since the names are chosen by
+/// the clients themselves.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SchedulingPolicy {
+    /// The policy of all the clients.
+    #[serde(default)]
+    pub default: ClientPolicy,
+    /// The policy of the clients of some users, indexed by the user they authenticated as.
+    #[serde(default)]
+    pub users: HashMap<String, ClientPolicy>,
+}
+
+/// The limits of a single identity of clients. The fields that are not set are inherited from the
+/// default policy, or are unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientPolicy {
+    /// How many workers the identity gets with respect to the others, when they all have work to
+    /// do. Defaults to 1.
+    pub weight: Option<u32>,
+    /// The maximum priority the DAGs of the identity may ask for, higher priorities are lowered to
+    /// this. The priorities only order the DAGs of the same identity.
+    pub max_priority: Option<DagPriority>,
+    /// The maximum number of workers running the jobs of the identity at the same time.
+    pub max_workers: Option<usize>,
+}
+
+impl SchedulingPolicy {
+    /// Load the policy from the TOML file at the provided path.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SchedulingPolicy, Error> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Invalid scheduling policy {}", path.display()))
+    }
+
+    /// The policy that applies to a client, merging the default one with the one of its user.
+    pub fn client_policy(&self, client: &ClientInfo) -> ClientPolicy {
+        let user = client.user.as_ref().and_then(|user| self.users.get(user));
+        match user {
+            Some(user) => ClientPolicy {
+                weight: user.weight.or(self.default.weight),
+                max_priority: user.max_priority.or(self.default.max_priority),
+                max_workers: user.max_workers.or(self.default.max_workers),
+            },
+            None => self.default.clone(),
+        }
+    }
+}
+
+/// The identity the fair share is computed for: the user the client authenticated as, if any.
+/// All the clients that didn't authenticate share the `None` identity, since their names are
+/// chosen by the clients themselves and connecting many times with different names must not give
+/// more workers.
+type Identity = Option<String>;
+
+/// The name shown in the status for the clients that didn't authenticate.
+const ANONYMOUS_IDENTITY: &str = "anonymous";
+
+/// The identity of a client.
+fn identity(client: &ClientInfo) -> Identity {
+    client.user.clone()
+}
+
+/// The scheduling state of a client in the `ReadyQueue`.
+#[derive(Debug)]
+struct QueueClient {
+    /// The identity of the client.
+    identity: Identity,
+    /// The priority of the DAG of the client, already capped by the policy.
+    priority: DagPriority,
+    /// The ready executions of the client.
+    ready: BinaryHeap<(DagPriority, Priority, ExecutionGroupUuid)>,
+}
+
+/// The scheduling state of an identity in the `ReadyQueue`.
+#[derive(Debug)]
+struct QueueIdentity {
+    /// The policy of the identity.
+    policy: ClientPolicy,
+    /// The number of clients of this identity in the queue.
+    clients: usize,
+    /// When the identity was last given a worker, for breaking the ties in round-robin.
+    last_served: u64,
+}
+
+/// The executions ready to be run, waiting for the workers, shared fairly between the identities
+/// of the clients.
+///
+/// The next execution is taken from the identity with the fewest running jobs relative to its
+/// weight, so the priorities of the DAGs never let an identity take more than its share. Inside an
+/// identity the executions follow the priorities of the DAGs (after capping them with the policy)
+/// and of the executions, as usual.
+#[derive(Debug, Default)]
+pub(crate) struct ReadyQueue {
+    /// The scheduling policy of the server.
+    policy: SchedulingPolicy,
+    /// The clients with a DAG being evaluated.
+    clients: HashMap<ClientUuid, QueueClient>,
+    /// The identities of the clients.
+    identities: HashMap<Identity, QueueIdentity>,
+    /// The number of executions given to the workers, for tracking the round-robin.
+    served: u64,
+}
+
+impl ReadyQueue {
+    /// Make a new queue that follows the provided policy.
+    pub fn new(policy: SchedulingPolicy) -> ReadyQueue {
+        ReadyQueue {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Start tracking a new client, returning the priority its DAG will actually have.
+    pub fn add_client(&mut self, client: &ClientInfo, priority: DagPriority) -> DagPriority {
+        self.remove_client(client.uuid);
+        let policy = self.policy.client_policy(client);
+        let priority = match policy.max_priority {
+            Some(max_priority) if priority > max_priority => {
+                info!(
+                    "Priority of client '{}' lowered from {} to {}",
+                    client.name, priority, max_priority
+                );
+                max_priority
+            }
+            _ => priority,
+        };
+        let identity = identity(client);
+        self.identities
+            .entry(identity.clone())
+            .or_insert_with(|| QueueIdentity {
+                policy,
+                clients: 0,
+                last_served: 0,
+            })
+            .clients += 1;
+        self.clients.insert(
+            client.uuid,
+            QueueClient {
+                identity,
+                priority,
+                ready: BinaryHeap::new(),
+            },
+        );
+        priority
+    }
+
+    /// Stop tracking a client, dropping all its ready executions.
+    pub fn remove_client(&mut self, client: ClientUuid) {
+        if let Some(client) = self.clients.remove(&client) {
+            if let Some(identity) = self.identities.get_mut(&client.identity) {
+                identity.clients -= 1;
+                if identity.clients == 0 {
+                    self.identities.remove(&client.identity);
+                }
+            }
+        }
+    }
+
+    /// Add a ready execution of a client. The executions of unknown clients are ignored.
+    pub fn push(
+        &mut self,
+        client: ClientUuid,
+        dag_priority: DagPriority,
+        priority: Priority,
+        group: ExecutionGroupUuid,
+    ) {
+        if let Some(client) = self.clients.get_mut(&client) {
+            client.ready.push((dag_priority, priority, group));
+        }
+    }
+
+    /// Remove all the ready executions, in no particular order.
+    pub fn drain(&mut self) -> Vec<(DagPriority, Priority, ExecutionGroupUuid, ClientUuid)> {
+        let mut result = Vec::new();
+        for (uuid, client) in self.clients.iter_mut() {
+            result.extend(
+                client
+                    .ready
+                    .drain()
+                    .map(|(dag_priority, priority, group)| (dag_priority, priority, group, *uuid)),
+            );
+        }
+        result
+    }
+
+    /// The share of the workers of each identity, knowing how many jobs of each client are running
+    /// and how many are ready.
+    pub fn status(
+        &self,
+        running: &HashMap<ClientUuid, usize>,
+        ready: &HashMap<ClientUuid, usize>,
+    ) -> Vec<ExecutorClientStatus> {
+        let mut status: HashMap<&Identity, ExecutorClientStatus> = HashMap::new();
+        for (uuid, client) in &self.clients {
+            let policy = &self.identities[&client.identity].policy;
+            let entry = status
+                .entry(&client.identity)
+                .or_insert_with(|| ExecutorClientStatus {
+                    identity: client
+                        .identity
+                        .clone()
+                        .unwrap_or_else(|| ANONYMOUS_IDENTITY.to_string()),
+                    authenticated: client.identity.is_some(),
+                    weight: policy.weight.unwrap_or(1),
+                    max_workers: policy.max_workers,
+                    running: 0,
+                    ready: 0,
+                });
+            entry.running += running.get(uuid).copied().unwrap_or(0);
+            entry.ready += ready.get(uuid).copied().unwrap_or(0);
+        }
+        let mut status: Vec<_> = status.into_values().collect();
+        status.sort_by(|a, b| a.identity.cmp(&b.identity));
+        status
+    }
+
+    /// Take the next execution to give to a worker, knowing how many jobs of each client are
+    /// running. The identities that reached their maximum number of workers are skipped.
+    pub fn pop(
+        &mut self,
+        running: &HashMap<ClientUuid, usize>,
+    ) -> Option<(ExecutionGroupUuid, ClientUuid)> {
+        let mut running_by_identity: HashMap<&Identity, usize> = HashMap::new();
+        for (uuid, count) in running {
+            if let Some(client) = self.clients.get(uuid) {
+                *running_by_identity.entry(&client.identity).or_default() += count;
+            }
+        }
+
+        // the best client of each identity, with the highest priorities
+        let mut candidates: HashMap<&Identity, (ClientUuid, &QueueClient)> = HashMap::new();
+        for (uuid, client) in &self.clients {
+            let head = match client.ready.peek() {
+                Some(head) => head,
+                None => continue,
+            };
+            let better = match candidates.get(&client.identity) {
+                Some((_, best)) => {
+                    (client.priority, head) > (best.priority, best.ready.peek().unwrap())
+                }
+                None => true,
+            };
+            if better {
+                candidates.insert(&client.identity, (*uuid, client));
+            }
+        }
+
+        let mut best: Option<Candidate> = None;
+        for (identity, (uuid, _)) in candidates {
+            let state = &self.identities[identity];
+            let running = running_by_identity.get(identity).copied().unwrap_or(0);
+            if let Some(max_workers) = state.policy.max_workers {
+                if running >= max_workers {
+                    continue;
+                }
+            }
+            let weight = state.policy.weight.unwrap_or(1).max(1);
+            let candidate = Candidate {
+                identity,
+                client: uuid,
+                running,
+                weight,
+                last_served: state.last_served,
+            };
+            best = match best {
+                Some(current) if !is_fairer(&candidate, &current) => Some(current),
+                _ => Some(candidate),
+            };
+        }
+
+        let best = best?;
+        let (identity, uuid) = (best.identity.clone(), best.client);
+        self.served += 1;
+        if let Some(state) = self.identities.get_mut(&identity) {
+            state.last_served = self.served;
+        }
+        let client = self.clients.get_mut(&uuid)?;
+        let (_, _, group) = client.ready.pop()?;
+        Some((group, uuid))
+    }
+}
+
+/// An identity that may receive the next worker.
+struct Candidate<'a> {
+    /// The identity.
+    identity: &'a Identity,
+    /// The client of the identity whose execution would be run.
+    client: ClientUuid,
+    /// The number of jobs of the identity that are running.
+    running: usize,
+    /// The weight of the identity.
+    weight: u32,
+    /// When the identity was last served.
+    last_served: u64,
+}
+
+/// Whether the first candidate should be served before the second one: the one with the fewest
+/// running jobs relative to its weight, or the one served least recently.
+fn is_fairer(a: &Candidate, b: &Candidate) -> bool {
+    // running / weight, compared without dividing
+    let a_share = a.running as u64 * b.weight as u64;
+    let b_share = b.running as u64 * a.weight as u64;
+    (a_share, a.last_served) < (b_share, b.last_served)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn client(name: &str, user: Option<&str>) -> ClientInfo {
+        ClientInfo {
+            uuid: Uuid::new_v4(),
+            name: name.into(),
+            user: user.map(String::from),
+        }
+    }
+
+    /// Pop `n` executions, simulating them as running.
+    fn pop_n(queue: &mut ReadyQueue, running: &mut HashMap<ClientUuid, usize>, n: usize) {
+        for _ in 0..n {
+            if let Some((_, client)) = queue.pop(running) {
+                *running.entry(client).or_default() += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_fair_share() {
+        let mut queue = ReadyQueue::new(SchedulingPolicy::default());
+        let alice = client("alice", Some("alice"));
+        let bob = client("bob", Some("bob"));
+        queue.add_client(&alice, 0);
+        queue.add_client(&bob, 0);
+        // alice has a huge DAG, bob a small one
+        for i in 0..100 {
+            queue.push(alice.uuid, 0, i, Uuid::new_v4());
+        }
+        for i in 0..3 {
+            queue.push(bob.uuid, 0, i, Uuid::new_v4());
+        }
+        let mut running = HashMap::new();
+        pop_n(&mut queue, &mut running, 4);
+        assert_eq!(running[&alice.uuid], 2);
+        assert_eq!(running[&bob.uuid], 2);
+    }
+
+    #[test]
+    fn test_fair_share_same_user() {
+        let mut queue = ReadyQueue::new(SchedulingPolicy::default());
+        let alice1 = client("pc1", Some("alice"));
+        let alice2 = client("pc2", Some("alice"));
+        let bob = client("bob", Some("bob"));
+        for client in [&alice1, &alice2, &bob] {
+            queue.add_client(client, 0);
+            for i in 0..10 {
+                queue.push(client.uuid, 0, i, Uuid::new_v4());
+            }
+        }
+        let mut running = HashMap::new();
+        pop_n(&mut queue, &mut running, 6);
+        let alice =
+            running.get(&alice1.uuid).unwrap_or(&0) + running.get(&alice2.uuid).unwrap_or(&0);
+        assert_eq!(alice, 3);
+        assert_eq!(running[&bob.uuid], 3);
+    }
+
+    #[test]
+    fn test_priority_cap() {
+        let policy: SchedulingPolicy = toml::from_str(
+            "[default]\nmax_priority = 5\n[users.admin]\nmax_priority = 100\nweight = 3\n",
+        )
+        .unwrap();
+        let mut queue = ReadyQueue::new(policy);
+        let greedy = client("greedy", None);
+        let admin = client("admin", Some("admin"));
+        let spoofed = client("admin", None);
+        assert_eq!(queue.add_client(&greedy, 1000), 5);
+        assert_eq!(queue.add_client(&admin, 1000), 100);
+        assert_eq!(queue.add_client(&spoofed, 1000), 5);
+        let status = queue.status(&HashMap::new(), &HashMap::new());
+        let weights: Vec<_> = status
+            .iter()
+            .map(|s| (s.identity.as_str(), s.authenticated, s.weight))
+            .collect();
+        assert_eq!(weights, vec![("admin", true, 3), ("anonymous", false, 1)]);
+    }
+
+    #[test]
+    fn test_priority_between_identities() {
+        let mut queue = ReadyQueue::new(SchedulingPolicy::default());
+        let low = client("low", Some("low"));
+        let high = client("high", Some("high"));
+        queue.add_client(&low, 0);
+        queue.add_client(&high, 10);
+        for i in 0..5 {
+            queue.push(low.uuid, 0, i, Uuid::new_v4());
+            queue.push(high.uuid, 10, i, Uuid::new_v4());
+        }
+        // the priority doesn't give more than the fair share
+        let mut running = HashMap::new();
+        pop_n(&mut queue, &mut running, 6);
+        assert_eq!(running[&low.uuid], 3);
+        assert_eq!(running[&high.uuid], 3);
+    }
+
+    #[test]
+    fn test_priority_inside_identity() {
+        let mut queue = ReadyQueue::new(SchedulingPolicy::default());
+        let low = client("pc1", Some("alice"));
+        let high = client("pc2", Some("alice"));
+        let bob = client("bob", Some("bob"));
+        queue.add_client(&low, 0);
+        queue.add_client(&high, 10);
+        queue.add_client(&bob, 0);
+        for i in 0..5 {
+            queue.push(low.uuid, 0, i, Uuid::new_v4());
+            queue.push(high.uuid, 10, i, Uuid::new_v4());
+            queue.push(bob.uuid, 0, i, Uuid::new_v4());
+        }
+        let mut running = HashMap::new();
+        pop_n(&mut queue, &mut running, 8);
+        assert_eq!(running[&high.uuid], 4);
+        assert!(!running.contains_key(&low.uuid));
+        assert_eq!(running[&bob.uuid], 4);
+    }
+
+    #[test]
+    fn test_weights_and_max_workers() {
+        let policy: SchedulingPolicy = toml::from_str(
+            "[default]\nmax_workers = 2\n[users.alice]\nweight = 3\nmax_workers = 10\n",
+        )
+        .unwrap();
+        let mut queue = ReadyQueue::new(policy);
+        let alice = client("pc", Some("alice"));
+        let bob = client("bob", Some("bob"));
+        let carol = client("carol", Some("carol"));
+        for client in [&alice, &bob, &carol] {
+            queue.add_client(client, 0);
+            for i in 0..20 {
+                queue.push(client.uuid, 0, i, Uuid::new_v4());
+            }
+        }
+        let mut running = HashMap::new();
+        pop_n(&mut queue, &mut running, 8);
+        assert_eq!(running[&alice.uuid], 4);
+        assert_eq!(running[&bob.uuid], 2);
+        assert_eq!(running[&carol.uuid], 2);
+        // bob and carol are at their limit
+        pop_n(&mut queue, &mut running, 12);
+        assert_eq!(running[&alice.uuid], 10);
+        assert_eq!(running[&bob.uuid], 2);
+        assert!(queue.pop(&running).is_none());
+
+        let ready = HashMap::from([(alice.uuid, 10), (bob.uuid, 18)]);
+        let status = queue.status(&running, &ready);
+        assert_eq!(status.len(), 3);
+        assert_eq!(status[0].identity, "alice");
+        assert!(status[0].authenticated);
+        assert_eq!(status[0].weight, 3);
+        assert_eq!(status[0].running, 10);
+        assert_eq!(status[0].ready, 10);
+        assert_eq!(status[1].identity, "bob");
+        assert_eq!(status[1].max_workers, Some(2));
+        assert_eq!(status[2].ready, 0);
+    }
+
+    #[test]
+    fn test_anonymous_clients_share_identity() {
+        let policy: SchedulingPolicy = toml::from_str("[default]\nmax_workers = 2\n").unwrap();
+        let mut queue = ReadyQueue::new(policy);
+        let alice = client("alice", Some("alice"));
+        let bob = client("bob", None);
+        let mallory = client("mallory", None);
+        for client in [&alice, &bob, &mallory] {
+            queue.add_client(client, 0);
+            for i in 0..10 {
+                queue.push(client.uuid, 0, i, Uuid::new_v4());
+            }
+        }
+        let mut running = HashMap::new();
+        pop_n(&mut queue, &mut running, 10);
+        // the clients without a token share the cap, whatever their names are
+        let anonymous =
+            running.get(&bob.uuid).unwrap_or(&0) + running.get(&mallory.uuid).unwrap_or(&0);
+        assert_eq!(anonymous, 2);
+        assert_eq!(running[&alice.uuid], 2);
+
+        let status = queue.status(&running, &HashMap::new());
+        assert_eq!(status.len(), 2);
+        assert_eq!(status[1].identity, "anonymous");
+        assert!(!status[1].authenticated);
+        assert_eq!(status[1].running, 2);
+    }
+
+    #[test]
+    fn test_remove_client() {
+        let mut queue = ReadyQueue::new(SchedulingPolicy::default());
+        let alice = client("alice", None);
+        queue.add_client(&alice, 0);
+        queue.push(alice.uuid, 0, 0, Uuid::new_v4());
+        queue.remove_client(alice.uuid);
+        assert!(queue.pop(&HashMap::new()).is_none());
+        assert!(queue.identities.is_empty());
+        // the executions of unknown clients are ignored
+        queue.push(alice.uuid, 0, 0, Uuid::new_v4());
+        assert!(queue.drain().is_empty());
+    }
+
+    #[test]
+    fn test_load_policy() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("policy.toml");
+        std::fs::write(
+            &path,
+            "[default]\nmax_workers = 2\n[users.alice]\nweight = 2\n",
+        )
+        .unwrap();
+        let policy = SchedulingPolicy::load(&path).unwrap();
+        let alice = client("pc", Some("alice"));
+        assert_eq!(
+            policy.client_policy(&alice),
+            ClientPolicy {
+                weight: Some(2),
+                max_priority: None,
+                max_workers: Some(2),
+            }
+        );
+        std::fs::write(&path, "[default]\nmax_cores = 2\n").unwrap();
+        assert!(SchedulingPolicy::load(&path).is_err());
+    }
+}
@@ -0,0 +1,300 @@
+//! Count the user-space instructions retired by the sandboxed process using the hardware
+//! performance counters of the CPU.
+//!
+//! The counter is opened on the process that spawns the sandbox, disabled, with the `inherit` and
+//! `enable_on_exec` flags. This way the sandbox process itself is not measured, while all its
+//! children start counting as soon as they `exec` the program. When the children exit their counts
+//! are added to the counter of the parent, where they are read.
+//!
+//! When a limit is given, the counter also overflows as soon as a process retires more than that
+//! number of instructions. The kernel notifies the overflow to the process that opened the counter
+//! with a `SIGIO`, which then kills all its children, stopping the sandbox while it is running
+//! instead of checking the limit only after the exit.
+
+use anyhow::{Context, Error};
+
+/// A counter of the user-space instructions retired by the processes spawned (and exec-ed) after
+/// its creation.
+#[derive(Debug)]
+pub struct InstructionCounter {
+    /// The file descriptor of the performance counter.
+    #[cfg(target_os = "linux")]
+    fd: std::os::unix::io::RawFd,
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    use nix::libc;
+
+    /// `PERF_TYPE_HARDWARE` from `linux/perf_event.h`.
+    pub const PERF_TYPE_HARDWARE: u32 = 0;
+    /// `PERF_COUNT_HW_INSTRUCTIONS` from `linux/perf_event.h`.
+    pub const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+    /// `PERF_FLAG_FD_CLOEXEC` from `linux/perf_event.h`.
+    pub const PERF_FLAG_FD_CLOEXEC: u64 = 1 << 3;
+
+    /// Bit of the `disabled` flag in `perf_event_attr`.
+    pub const FLAG_DISABLED: u64 = 1 << 0;
+    /// Bit of the `inherit` flag in `perf_event_attr`.
+    pub const FLAG_INHERIT: u64 = 1 << 1;
+    /// Bit of the `exclude_kernel` flag in `perf_event_attr`.
+    pub const FLAG_EXCLUDE_KERNEL: u64 = 1 << 5;
+    /// Bit of the `exclude_hv` flag in `perf_event_attr`.
+    pub const FLAG_EXCLUDE_HV: u64 = 1 << 6;
+    /// Bit of the `enable_on_exec` flag in `perf_event_attr`.
+    pub const FLAG_ENABLE_ON_EXEC: u64 = 1 << 12;
+
+    /// The first version of `struct perf_event_attr` (`PERF_ATTR_SIZE_VER0`), which contains all
+    /// the fields needed for counting instructions.
+    #[repr(C)]
+    #[derive(Debug, Default)]
+    pub struct PerfEventAttr {
+        pub type_: u32,
+        pub size: u32,
+        pub config: u64,
+        pub sample_period: u64,
+        pub sample_type: u64,
+        pub read_format: u64,
+        pub flags: u64,
+        pub wakeup_events: u32,
+        pub bp_type: u32,
+        pub config1: u64,
+    }
+
+    /// The write end of the pipe used to notify the overflow of the counter, -1 if not set up.
+    pub static OVERFLOW_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+    /// Handler of the `SIGIO` sent by the kernel when the counter overflows. Only async-signal-safe
+    /// functions can be called here, so the actual work is done by the thread reading the pipe.
+    pub extern "C" fn on_overflow(_signal: libc::c_int) {
+        let fd = OVERFLOW_PIPE.load(Ordering::SeqCst);
+        if fd >= 0 {
+            // SAFETY: write is async-signal-safe and the buffer is a valid static byte.
+            unsafe {
+                libc::write(fd, b"x".as_ptr() as *const libc::c_void, 1);
+            }
+        }
+    }
+}
+
+impl InstructionCounter {
+    /// Open a new disabled counter that will measure all the processes that are spawned from now
+    /// on by the current process, starting from their `exec`.
+    ///
+    /// If `limit` is set, all the children of the current process are killed as soon as one of
+    /// them retires more than `limit` instructions. This can be done only once per process.
+    #[cfg(target_os = "linux")]
+    pub fn new(limit: Option<u64>) -> Result<InstructionCounter, Error> {
+        use nix::libc;
+
+        let attr = sys::PerfEventAttr {
+            type_: sys::PERF_TYPE_HARDWARE,
+            size: std::mem::size_of::<sys::PerfEventAttr>() as u32,
+            config: sys::PERF_COUNT_HW_INSTRUCTIONS,
+            // overflow right after the limit, zero means no overflow at all
+            sample_period: limit.map_or(0, |limit| limit.saturating_add(1)),
+            flags: sys::FLAG_DISABLED
+                | sys::FLAG_INHERIT
+                | sys::FLAG_EXCLUDE_KERNEL
+                | sys::FLAG_EXCLUDE_HV
+                | sys::FLAG_ENABLE_ON_EXEC,
+            ..Default::default()
+        };
+        // SAFETY: attr is a valid perf_event_attr that lives for the whole call.
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_perf_event_open,
+                &attr as *const sys::PerfEventAttr,
+                0 as libc::pid_t,
+                -1 as libc::c_int,
+                -1 as libc::c_int,
+                sys::PERF_FLAG_FD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            let error = std::io::Error::last_os_error();
+            anyhow::bail!(
+                "Cannot open the instruction counter (the hardware counters must be available and \
+                 kernel.perf_event_paranoid must be at most 2): {}",
+                error
+            );
+        }
+        let counter = InstructionCounter { fd: fd as _ };
+        if limit.is_some() {
+            counter
+                .kill_on_overflow()
+                .context("Cannot enforce the instruction limit")?;
+        }
+        Ok(counter)
+    }
+
+    /// Counting the instructions is supported only on Linux.
+    #[cfg(not(target_os = "linux"))]
+    pub fn new(_limit: Option<u64>) -> Result<InstructionCounter, Error> {
+        anyhow::bail!("Counting the instructions is supported only on Linux")
+    }
+
+    /// Read the number of instructions counted so far. The children processes are accounted only
+    /// after they exit.
+    #[cfg(target_os = "linux")]
+    pub fn read(&self) -> Result<u64, Error> {
+        use nix::libc;
+
+        let mut value = 0u64;
+        // SAFETY: the buffer is a valid u64, which is the format of the counter without any
+        // read_format flag.
+        let res = unsafe {
+            libc::read(
+                self.fd,
+                &mut value as *mut u64 as *mut libc::c_void,
+                std::mem::size_of::<u64>(),
+            )
+        };
+        if res != std::mem::size_of::<u64>() as isize {
+            let error = std::io::Error::last_os_error();
+            anyhow::bail!("Cannot read the instruction counter: {}", error);
+        }
+        Ok(value)
+    }
+
+    /// Counting the instructions is supported only on Linux.
+    #[cfg(not(target_os = "linux"))]
+    pub fn read(&self) -> Result<u64, Error> {
+        anyhow::bail!("Counting the instructions is supported only on Linux")
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl InstructionCounter {
+    /// Ask the kernel to send a `SIGIO` to the current process when the counter overflows, and
+    /// spawn a thread that kills all the children of the current process when that happens.
+    fn kill_on_overflow(&self) -> Result<(), Error> {
+        use std::sync::atomic::Ordering;
+
+        use nix::libc;
+
+        let mut fds = [-1 as libc::c_int; 2];
+        // SAFETY: fds is a valid array of two file descriptors.
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            let error = std::io::Error::last_os_error();
+            anyhow::bail!("Cannot create the overflow pipe: {}", error);
+        }
+        let [read_end, write_end] = fds;
+        sys::OVERFLOW_PIPE.store(write_end, Ordering::SeqCst);
+        // SAFETY: the handler performs only async-signal-safe operations, and the action is fully
+        // initialized before being installed. SA_RESTART makes the wait of the sandbox resume
+        // after the signal.
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction =
+                sys::on_overflow as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(libc::SIGIO, &action, std::ptr::null_mut()) != 0 {
+                let error = std::io::Error::last_os_error();
+                anyhow::bail!("Cannot install the SIGIO handler: {}", error);
+            }
+            if libc::fcntl(self.fd, libc::F_SETOWN, libc::getpid()) != 0
+                || libc::fcntl(self.fd, libc::F_SETFL, libc::O_ASYNC) != 0
+            {
+                let error = std::io::Error::last_os_error();
+                anyhow::bail!("Cannot enable the overflow signal: {}", error);
+            }
+        }
+        std::thread::Builder::new()
+            .name("Instruction limit".into())
+            .spawn(move || {
+                let mut buf = [0u8; 1];
+                // SAFETY: buf is a valid buffer of one byte.
+                let res = unsafe { libc::read(read_end, buf.as_mut_ptr() as *mut libc::c_void, 1) };
+                if res == 1 {
+                    kill_children();
+                }
+            })
+            .context("Failed to spawn the overflow thread")?;
+        Ok(())
+    }
+}
+
+/// Send a `SIGKILL` to all the direct children of the current process. The sandboxed process is
+/// killed together with the sandbox that contains it.
+#[cfg(target_os = "linux")]
+fn kill_children() {
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+
+    let me = std::process::id();
+    let entries = match std::fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Cannot list the processes to kill: {}", e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let pid = match entry.file_name().to_str().and_then(|pid| pid.parse().ok()) {
+            Some(pid) => pid,
+            None => continue,
+        };
+        let stat = match std::fs::read_to_string(entry.path().join("stat")) {
+            Ok(stat) => stat,
+            Err(_) => continue,
+        };
+        if parse_parent_pid(&stat) == Some(me) {
+            if let Err(e) = kill(Pid::from_raw(pid), Signal::SIGKILL) {
+                error!("Cannot kill process {}: {}", pid, e);
+            }
+        }
+    }
+}
+
+/// Extract the parent PID from the content of `/proc/<pid>/stat`.
+#[cfg(target_os = "linux")]
+fn parse_parent_pid(stat: &str) -> Option<u32> {
+    // the name of the executable is between parentheses and it may contain spaces and parentheses
+    let (_, rest) = stat.rsplit_once(')')?;
+    // the state comes first, then the parent PID
+    rest.split_whitespace().nth(1)?.parse().ok()
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for InstructionCounter {
+    fn drop(&mut self) {
+        // SAFETY: the file descriptor is owned by this struct.
+        unsafe {
+            nix::libc::close(self.fd);
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_parent_pid() {
+        let stat = "1234 (sol) R 42 1234 1234 0 -1 4194304 105 0 0 0 0 0 0 0 20 0 1 0";
+        assert_eq!(parse_parent_pid(stat), Some(42));
+    }
+
+    #[test]
+    fn test_parse_parent_pid_weird_name() {
+        let stat = "1234 (a) b (c) S 7 1234 1234 0 -1 4194304 105 0 0 0 0 0 0 0 20 0 1 0";
+        assert_eq!(parse_parent_pid(stat), Some(7));
+    }
+
+    #[test]
+    fn test_parse_parent_pid_invalid() {
+        assert_eq!(parse_parent_pid("1234 (sol"), None);
+        assert_eq!(parse_parent_pid("1234 (sol) R"), None);
+    }
+
+    #[test]
+    fn test_parse_parent_pid_self() {
+        let stat = std::fs::read_to_string("/proc/self/stat").unwrap();
+        let parent = nix::unistd::getppid().as_raw() as u32;
+        assert_eq!(parse_parent_pid(&stat), Some(parent));
+    }
+}
@@ -82,8 +82,11 @@ pub use ductile;
 use ductile::new_local_channel;
 
 pub use client::ExecutorClient;
-pub use executor::{ExecutorStatus, ExecutorWorkerStatus, WorkerCurrentJobStatus};
-pub use sandbox::RawSandboxResult;
+pub use executor::{
+    ExecutorClientStatus, ExecutorStatus, ExecutorWorkerStatus, WorkerCurrentJobStatus,
+};
+pub use fair_share::{ClientPolicy, SchedulingPolicy};
+pub use sandbox::{RawSandboxConfiguration, RawSandboxResult};
 pub use sandbox_runner::{ErrorSandboxRunner, SandboxRunner, SuccessSandboxRunner};
 pub use scheduler::ClientInfo;
 use task_maker_cache::Cache;
@@ -96,11 +99,14 @@ mod client;
 mod detect_exe;
 mod executor;
 pub mod executors;
+mod fair_share;
 pub mod find_tools;
+pub mod instruction_counter;
 pub mod proto;
 pub mod sandbox;
 mod sandbox_runner;
 mod scheduler;
+pub mod tls;
 mod worker;
 mod worker_manager;
 
@@ -150,7 +156,7 @@ pub fn eval_dag_locally<P: Into<PathBuf>, P2: Into<PathBuf>, R>(
                 .expect("Executor failed");
         })
         .expect("Failed to spawn local executor thread");
-    ExecutorClient::evaluate(dag, tx, &rx, file_store, |_| Ok(())).expect("Client failed");
+    ExecutorClient::evaluate(dag, tx, &rx, file_store, false, |_| Ok(())).expect("Client failed");
     server.join().expect("Server panicked");
 }
 
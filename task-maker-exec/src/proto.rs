@@ -28,16 +28,22 @@
 //! file from `B`:
 //! - `A` sends a `AskFile` to `B`
 //! - `B` answers with `ProvideFile` which triggers a protocol switch for sending the file
+//! - `B` sends a one-byte raw header telling how the content is encoded
 //! - `B` sends raw data (`send_raw`) zero or more times
 //! - `B` sends empty raw data which triggers a protocol switch, back into normal mode
+//!
+//! The raw data is the content of the file, compressed as a zstd stream if the header says so. The
+//! sender compresses only the files sent over remote connections, since on the local channels it
+//! would just waste CPU time.
 
 use crate::executor::{ExecutionDAGWatchSet, ExecutorStatus, WorkerJob};
 use crate::*;
-use anyhow::Context;
+use anyhow::{bail, Context};
 use ductile::{ChannelReceiver, ChannelSender};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::io::{BufReader, Read, Write};
 use std::path::Path;
 use std::time::Duration;
 use task_maker_dag::*;
@@ -100,8 +106,12 @@ pub enum WorkerClientMessage {
     /// The worker completed the job with this result producing those files. The actual files will
     /// be sent immediately after using `ProvideFile` messages.
     /// The list of `ExecutionResult` contains the results of all the executions inside the group,
-    /// in the same order.
-    WorkerDone(Vec<ExecutionResult>, HashMap<FileUuid, FileStoreKey>),
+    /// in the same order, and so do the fingerprints of their system commands.
+    WorkerDone(
+        Vec<ExecutionResult>,
+        HashMap<FileUuid, FileStoreKey>,
+        Vec<Option<String>>,
+    ),
     /// The worker is sending a file to the server. After this message there is a protocol switch
     /// for the file transmission.
     ProvideFile(FileUuid, FileStoreKey),
@@ -127,13 +137,125 @@ pub enum WorkerServerMessage {
     Exit,
 }
 
-/// An iterator over the byte chunks sent during the file transfer mode in a channel.
-pub struct ChannelFileIterator<'a, T>
+/// The zstd level used for compressing the files sent in a channel. A fast level is used since the
+/// files are compressed on the fly.
+const TRANSFER_COMPRESSION_LEVEL: i32 = 1;
+/// The header of a file transfer whose content is sent as it is.
+const TRANSFER_PLAIN: u8 = 0;
+/// The header of a file transfer whose content is compressed as a zstd stream.
+const TRANSFER_COMPRESSED: u8 = 1;
+/// The size of the decompressed chunks yielded by `ChannelFileIterator`.
+const TRANSFER_CHUNK_SIZE: usize = 8 * 1024;
+
+/// A reader over the raw chunks sent during the file transfer mode in a channel, until the empty
+/// chunk that terminates the transfer.
+struct ChannelChunkReader<'a, T>
 where
     T: Send + Sync + DeserializeOwned,
 {
     /// Reference to the channel from where to read
     reader: &'a ChannelReceiver<T>,
+    /// The last chunk received.
+    chunk: Vec<u8>,
+    /// How much of `chunk` has already been read.
+    pos: usize,
+    /// Whether the terminator of the transfer has been received.
+    done: bool,
+}
+
+impl<'a, T> Read for ChannelChunkReader<'a, T>
+where
+    T: 'static + Send + Sync + DeserializeOwned,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.chunk = self
+                .reader
+                .recv_raw()
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+            self.pos = 0;
+            self.done = self.chunk.is_empty();
+        }
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+impl<'a, T> ChannelChunkReader<'a, T>
+where
+    T: 'static + Send + Sync + DeserializeOwned,
+{
+    /// Skip the remaining chunks of the transfer, so that the channel can be used for the next
+    /// messages.
+    fn drain(&mut self) {
+        self.pos = self.chunk.len();
+        while !self.done {
+            match self.reader.recv_raw() {
+                Ok(chunk) => self.done = chunk.is_empty(),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+/// A writer that sends the data to a channel as raw chunks.
+struct ChannelChunkWriter<'a, T>
+where
+    T: Send + Sync + Serialize,
+{
+    /// Reference to the channel where to write
+    sender: &'a ChannelSender<T>,
+}
+
+impl<'a, T> Write for ChannelChunkWriter<'a, T>
+where
+    T: 'static + Send + Sync + Serialize,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // an empty chunk would terminate the transfer
+        if !buf.is_empty() {
+            self.sender
+                .send_raw(buf)
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The reader of the content of a file transfer, decompressing it if needed.
+enum ChannelContentReader<'a, T>
+where
+    T: 'static + Send + Sync + DeserializeOwned,
+{
+    /// The header of the transfer has not been received yet.
+    Start(ChannelChunkReader<'a, T>),
+    /// The content is sent as it is.
+    Plain(ChannelChunkReader<'a, T>),
+    /// The content is compressed as a zstd stream.
+    Compressed(zstd::stream::read::Decoder<'static, BufReader<ChannelChunkReader<'a, T>>>),
+    /// The transfer has ended, successfully or not.
+    Done,
+}
+
+/// An iterator over the decompressed byte chunks sent during the file transfer mode in a channel.
+///
+/// If the transfer cannot be received or decoded, the error is yielded and the iteration stops,
+/// after skipping the rest of the transfer.
+pub struct ChannelFileIterator<'a, T>
+where
+    T: 'static + Send + Sync + DeserializeOwned,
+{
+    /// The reader of the content of the file.
+    reader: ChannelContentReader<'a, T>,
 }
 
 impl<'a, T> ChannelFileIterator<'a, T>
@@ -142,7 +264,57 @@ where
 {
     /// Create a new iterator over a receiver channel.
     pub fn new(reader: &'a ChannelReceiver<T>) -> ChannelFileIterator<'a, T> {
-        ChannelFileIterator { reader }
+        let reader = ChannelChunkReader {
+            reader,
+            chunk: vec![],
+            pos: 0,
+            done: false,
+        };
+        ChannelFileIterator {
+            reader: ChannelContentReader::Start(reader),
+        }
+    }
+
+    /// Read the next chunk of the file, `None` if the transfer has ended.
+    fn read_chunk(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        if let ChannelContentReader::Start(reader) = &mut self.reader {
+            let header = reader
+                .reader
+                .recv_raw()
+                .context("Failed to receive the file transfer header")?;
+            reader.done = header.is_empty();
+            let reader = match std::mem::replace(&mut self.reader, ChannelContentReader::Done) {
+                ChannelContentReader::Start(reader) => reader,
+                _ => unreachable!(),
+            };
+            self.reader = match header.as_slice() {
+                [TRANSFER_PLAIN] => ChannelContentReader::Plain(reader),
+                [TRANSFER_COMPRESSED] => ChannelContentReader::Compressed(
+                    zstd::stream::read::Decoder::new(reader)
+                        .context("Failed to create the zstd decoder")?,
+                ),
+                _ => {
+                    self.reader = ChannelContentReader::Start(reader);
+                    bail!("Invalid file transfer header: {:?}", header);
+                }
+            };
+        }
+        let mut data = vec![0; TRANSFER_CHUNK_SIZE];
+        let len = match &mut self.reader {
+            ChannelContentReader::Plain(reader) => reader
+                .read(&mut data)
+                .context("Failed to receive the file chunk")?,
+            ChannelContentReader::Compressed(decoder) => decoder
+                .read(&mut data)
+                .context("Failed to decompress the file chunk")?,
+            _ => 0,
+        };
+        if len == 0 {
+            Ok(None)
+        } else {
+            data.truncate(len);
+            Ok(Some(data))
+        }
     }
 }
 
@@ -150,14 +322,26 @@ impl<'a, T> Iterator for ChannelFileIterator<'a, T>
 where
     T: 'static + Send + Sync + DeserializeOwned,
 {
-    type Item = Vec<u8>;
+    type Item = Result<Vec<u8>, Error>;
     fn next(&mut self) -> Option<Self::Item> {
-        // errors cannot be handled in this iterator yet
-        let data = self.reader.recv_raw().expect("deserialize error");
-        if data.is_empty() {
-            None
-        } else {
-            Some(data)
+        let result = self.read_chunk();
+        match result {
+            Ok(Some(data)) => Some(Ok(data)),
+            Ok(None) => {
+                self.reader = ChannelContentReader::Done;
+                None
+            }
+            Err(e) => {
+                match std::mem::replace(&mut self.reader, ChannelContentReader::Done) {
+                    ChannelContentReader::Start(mut reader)
+                    | ChannelContentReader::Plain(mut reader) => reader.drain(),
+                    ChannelContentReader::Compressed(decoder) => {
+                        decoder.finish().into_inner().drain()
+                    }
+                    ChannelContentReader::Done => {}
+                }
+                Some(Err(e))
+            }
         }
     }
 }
@@ -166,38 +350,66 @@ where
 pub struct ChannelFileSender;
 
 impl ChannelFileSender {
-    /// Send a local file to a channel using `send_raw`.
-    pub fn send<P: AsRef<Path>, T>(path: P, sender: &ChannelSender<T>) -> Result<(), Error>
+    /// Send a local file to a channel using `send_raw`, compressing it if `compress` is set.
+    pub fn send<P: AsRef<Path>, T>(
+        path: P,
+        sender: &ChannelSender<T>,
+        compress: bool,
+    ) -> Result<(), Error>
     where
         T: 'static + Send + Sync + Serialize,
     {
         let path = path.as_ref();
         let iterator = ReadFileIterator::new(path)
             .with_context(|| format!("Failed to read file to send: {}", path.display()))?;
-        for buf in iterator {
-            sender.send_raw(&buf).context("Failed to send file chunk")?;
-        }
-        sender
-            .send_raw(&[])
-            .context("Failed to send file terminator")?;
-        Ok(())
+        ChannelFileSender::send_chunks(iterator, sender, compress)
     }
 
-    /// Send the file content to a channel using `send_raw`.
-    pub fn send_data<T>(data: Vec<u8>, sender: &ChannelSender<T>) -> Result<(), Error>
+    /// Send the file content to a channel using `send_raw`, compressing it if `compress` is set.
+    pub fn send_data<T>(
+        data: Vec<u8>,
+        sender: &ChannelSender<T>,
+        compress: bool,
+    ) -> Result<(), Error>
     where
         T: 'static + Send + Sync + Serialize,
     {
-        sender
-            .send_raw(&data)
-            .context("Failed to send file chunk")?;
-        // Send the EOF chunk only if the buffer is not empty (otherwise we would send EOF twice
-        // breaking the protocol).
-        if !data.is_empty() {
+        ChannelFileSender::send_chunks(std::iter::once(data), sender, compress)
+    }
+
+    /// Send the header and the chunks of a file to a channel, followed by the terminator.
+    fn send_chunks<I, T>(chunks: I, sender: &ChannelSender<T>, compress: bool) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        T: 'static + Send + Sync + Serialize,
+    {
+        if compress {
+            sender
+                .send_raw(&[TRANSFER_COMPRESSED])
+                .context("Failed to send file header")?;
+            let writer = ChannelChunkWriter { sender };
+            let mut encoder = zstd::stream::write::Encoder::new(writer, TRANSFER_COMPRESSION_LEVEL)
+                .context("Failed to create the zstd encoder")?;
+            for buf in chunks {
+                encoder
+                    .write_all(&buf)
+                    .context("Failed to send file chunk")?;
+            }
+            encoder.finish().context("Failed to send file chunk")?;
+        } else {
             sender
-                .send_raw(&[])
-                .context("Failed to send file terminator")?;
+                .send_raw(&[TRANSFER_PLAIN])
+                .context("Failed to send file header")?;
+            for buf in chunks {
+                // an empty chunk would terminate the transfer
+                if !buf.is_empty() {
+                    sender.send_raw(&buf).context("Failed to send file chunk")?;
+                }
+            }
         }
+        sender
+            .send_raw(&[])
+            .context("Failed to send file terminator")?;
         Ok(())
     }
 }
@@ -206,24 +418,90 @@ impl ChannelFileSender {
 mod tests {
     use super::*;
 
+    fn receive<T>(receiver: &ChannelReceiver<T>) -> Result<Vec<u8>, Error>
+    where
+        T: 'static + Send + Sync + DeserializeOwned,
+    {
+        let mut data = vec![];
+        for chunk in ChannelFileIterator::new(receiver) {
+            data.extend(chunk?);
+        }
+        Ok(data)
+    }
+
     #[test]
     fn test_send_file() {
         let tmpdir = tempfile::TempDir::new().unwrap();
         std::fs::write(tmpdir.path().join("file.txt"), "hello world").unwrap();
 
-        let (sender, receiver) = new_local_channel::<()>();
-        let receiver = ChannelFileIterator::new(&receiver);
-        ChannelFileSender::send(tmpdir.path().join("file.txt"), &sender).unwrap();
-        let data: Vec<u8> = receiver.flat_map(|d| d.into_iter()).collect();
-        assert_eq!(String::from_utf8(data).unwrap(), "hello world");
+        for compress in [false, true] {
+            let (sender, receiver) = new_local_channel::<()>();
+            ChannelFileSender::send(tmpdir.path().join("file.txt"), &sender, compress).unwrap();
+            let data = receive(&receiver).unwrap();
+            assert_eq!(String::from_utf8(data).unwrap(), "hello world");
+        }
     }
 
     #[test]
     fn test_send_content() {
+        for compress in [false, true] {
+            let (sender, receiver) = new_local_channel::<()>();
+            ChannelFileSender::send_data(b"hello world".to_vec(), &sender, compress).unwrap();
+            let data = receive(&receiver).unwrap();
+            assert_eq!(String::from_utf8(data).unwrap(), "hello world");
+        }
+    }
+
+    #[test]
+    fn test_send_empty_content() {
+        for compress in [false, true] {
+            let (sender, receiver) = new_local_channel::<()>();
+            ChannelFileSender::send_data(vec![], &sender, compress).unwrap();
+            ChannelFileSender::send_data(b"next".to_vec(), &sender, compress).unwrap();
+            assert!(receive(&receiver).unwrap().is_empty());
+            // the first transfer must not leave anything in the channel
+            assert_eq!(receive(&receiver).unwrap(), b"next");
+        }
+    }
+
+    #[test]
+    fn test_send_big_file() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let content: Vec<u8> = (0..1_000_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(tmpdir.path().join("file.bin"), &content).unwrap();
+
+        for compress in [false, true] {
+            let (sender, receiver) = new_local_channel::<()>();
+            ChannelFileSender::send(tmpdir.path().join("file.bin"), &sender, compress).unwrap();
+            assert_eq!(receive(&receiver).unwrap(), content);
+        }
+    }
+
+    #[test]
+    fn test_receive_corrupted() {
         let (sender, receiver) = new_local_channel::<()>();
-        let receiver = ChannelFileIterator::new(&receiver);
-        ChannelFileSender::send_data(b"hello world".to_vec(), &sender).unwrap();
-        let data: Vec<u8> = receiver.flat_map(|d| d.into_iter()).collect();
-        assert_eq!(String::from_utf8(data).unwrap(), "hello world");
+        sender.send_raw(&[TRANSFER_COMPRESSED]).unwrap();
+        sender.send_raw(b"not a zstd stream").unwrap();
+        sender.send_raw(b"at all").unwrap();
+        sender.send_raw(&[]).unwrap();
+        ChannelFileSender::send_data(b"next".to_vec(), &sender, true).unwrap();
+
+        let mut iterator = ChannelFileIterator::new(&receiver);
+        assert!(iterator.next().unwrap().is_err());
+        assert!(iterator.next().is_none());
+        // the rest of the broken transfer is skipped
+        assert_eq!(receive(&receiver).unwrap(), b"next");
+    }
+
+    #[test]
+    fn test_receive_invalid_header() {
+        let (sender, receiver) = new_local_channel::<()>();
+        sender.send_raw(&[42]).unwrap();
+        sender.send_raw(b"data").unwrap();
+        sender.send_raw(&[]).unwrap();
+        ChannelFileSender::send_data(b"next".to_vec(), &sender, false).unwrap();
+
+        assert!(receive(&receiver).is_err());
+        assert_eq!(receive(&receiver).unwrap(), b"next");
     }
 }
@@ -38,6 +38,11 @@ pub const READABLE_DIRS: &[&str] = &[
     "/var/lib/texmf/",
 ];
 
+/// A pessimistic lower bound on the number of user-space instructions retired per second, used
+/// to derive the time limits of the executions that only have an instruction limit. Any real CPU
+/// is way faster than this, so the derived limits kill only the processes that are stuck.
+const MIN_INSTRUCTIONS_PER_SECOND: f64 = 1e8;
+
 /// Result of the execution of the sandbox.
 #[derive(Debug)]
 pub enum SandboxResult {
@@ -76,11 +81,22 @@ struct SandboxData {
     box_pid: Arc<AtomicU32>,
 }
 
+/// Request sent to the internal implementation of the sandbox.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RawSandboxConfiguration {
+    /// The configuration of the sandbox.
+    pub config: SandboxConfiguration,
+    /// If set, the user-space instructions retired by the sandboxed process are counted and the
+    /// process is killed as soon as it retires more than this number of instructions.
+    pub instruction_limit: Option<u64>,
+}
+
 /// Response of the internal implementation of the sandbox.
 #[derive(Debug, Serialize, Deserialize)]
 pub enum RawSandboxResult {
-    /// The sandbox has been executed successfully.
-    Success(SandboxExecutionResult),
+    /// The sandbox has been executed successfully. The number of user-space instructions retired
+    /// by the process is included, if they have been counted.
+    Success(SandboxExecutionResult, Option<u64>),
     /// There was an error executing the sandbox.
     Error(String),
 }
@@ -128,12 +144,13 @@ impl Sandbox {
     /// Starts the sandbox and blocks the thread until the sandbox exits.
     pub fn run(&self, runner: &dyn SandboxRunner) -> Result<SandboxResult, Error> {
         let mut config = SandboxConfiguration::default();
-        let (boxdir, pid, keep, cmd) = {
+        let (boxdir, pid, keep, instruction_limit, cmd) = {
             let data = self.data.lock().unwrap();
             (
                 data.path().to_owned(),
                 data.box_pid.clone(),
                 data.keep_sandbox,
+                data.execution.limits.instructions,
                 self.build_command(
                     data.path(),
                     &data.execution,
@@ -151,15 +168,15 @@ impl Sandbox {
         }
         trace!("Sandbox configuration: {:#?}", config);
 
-        let raw_result = runner.run(config.build(), pid);
+        let raw_result = runner.run(config.build(), instruction_limit, pid);
         if keep {
             let target = boxdir.join("result.txt");
             std::fs::write(&target, format!("{:#?}", raw_result))
                 .with_context(|| format!("Failed to write {}", target.display()))?;
         }
 
-        let res = match raw_result {
-            RawSandboxResult::Success(res) => res,
+        let (res, instructions) = match raw_result {
+            RawSandboxResult::Success(res, instructions) => (res, instructions),
             RawSandboxResult::Error(e) => bail!("Sandbox failed: {}", e),
         };
         trace!("Sandbox output: {:?}", res);
@@ -169,6 +186,7 @@ impl Sandbox {
             sys_time: res.resource_usage.system_cpu_time,
             wall_time: res.resource_usage.wall_time_usage,
             memory: res.resource_usage.memory_usage / 1024,
+            instructions,
         };
 
         use tabox::result::ExitStatus::*;
@@ -359,7 +377,24 @@ impl Sandbox {
                 config.env(key, value);
             }
         }
-        for (key, value) in execution.env.iter() {
+        // the sanitizers reserve terabytes of virtual memory for the shadow memory, so limiting
+        // the address space would prevent them from starting: their runtime limits the resident
+        // memory instead, relaxed by SANITIZER_MEMORY_FACTOR for their bookkeeping
+        let sanitizer_memory = execution
+            .limits
+            .memory
+            .filter(|_| execution.sanitized)
+            .map(|mem| (mem + execution.config().extra_memory) * SANITIZER_MEMORY_FACTOR);
+        let mut env = execution.env.clone();
+        if let Some(mem) = sanitizer_memory {
+            let options = env.entry("ASAN_OPTIONS".to_string()).or_default();
+            if !options.is_empty() {
+                options.push(':');
+            }
+            // KiB -> MiB, rounding up
+            options.push_str(&format!("hard_rss_limit_mb={}", (mem + 1023) / 1024));
+        }
+        for (key, value) in env.iter() {
             config.env(key, value);
         }
 
@@ -369,15 +404,26 @@ impl Sandbox {
             (None, Some(sys)) => Some(sys),
             (None, None) => None,
         };
-        if let Some(cpu) = cpu_limit {
+        // the instruction limit alone cannot stop a process stuck in a blocking syscall, nor one
+        // whose instructions are spread between many processes, so a time limit derived from it is
+        // always set
+        let instructions_time = execution
+            .limits
+            .instructions
+            .map(|instructions| instructions as f64 / MIN_INSTRUCTIONS_PER_SECOND);
+        if let Some(cpu) = cpu_limit.or(instructions_time) {
             let cpu = cpu + execution.config().extra_time;
             config.time_limit(cpu.ceil() as u64);
         }
-        if let Some(wall) = execution.limits.wall_time {
+        let wall_limit = execution
+            .limits
+            .wall_time
+            .or_else(|| instructions_time.map(|time| time * 2.0));
+        if let Some(wall) = wall_limit {
             let wall = wall + execution.config().extra_time;
             config.wall_time_limit(wall.ceil() as u64);
         }
-        if let Some(mem) = execution.limits.memory {
+        if let Some(mem) = execution.limits.memory.filter(|_| !execution.sanitized) {
             let mem = mem + execution.config().extra_memory;
             config.memory_limit(mem * 1024);
         }
@@ -605,9 +651,9 @@ mod tests {
     use tabox::configuration::{DirectoryMount, SandboxConfiguration};
     use tabox::syscall_filter::SyscallFilterAction;
 
-    use task_maker_dag::{Execution, ExecutionCommand};
+    use task_maker_dag::{Execution, ExecutionCommand, SANITIZER_MEMORY_FACTOR};
 
-    use crate::sandbox::Sandbox;
+    use crate::sandbox::{Sandbox, MIN_INSTRUCTIONS_PER_SECOND};
     use crate::ErrorSandboxRunner;
 
     #[test]
@@ -675,4 +721,97 @@ mod tests {
         assert_eq!(config.executable, Path::new("/bin/sh"));
         assert_eq!(config.args, vec!["bar", "baz"]);
     }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn test_command_sanitized() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let mut exec = Execution::new("test", ExecutionCommand::system("/bin/sh"));
+        exec.limits_mut().memory(1234);
+        exec.sanitized(true);
+        let sandbox = Sandbox::new(tmpdir.path(), &exec, &HashMap::new(), None).unwrap();
+        let mut config = SandboxConfiguration::default();
+        sandbox
+            .build_command(tmpdir.path(), &exec, &mut config, None)
+            .unwrap();
+        assert_eq!(config.memory_limit, None);
+        let extra_memory = exec.config().extra_memory;
+        let rss_limit = ((1234 + extra_memory) * SANITIZER_MEMORY_FACTOR + 1023) / 1024;
+        assert!(config.env.contains(&(
+            "ASAN_OPTIONS".to_string(),
+            format!("hard_rss_limit_mb={}", rss_limit)
+        )));
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn test_command_sanitized_options() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let mut exec = Execution::new("test", ExecutionCommand::system("/bin/sh"));
+        exec.limits_mut().memory(1024);
+        exec.sanitized(true);
+        exec.env("ASAN_OPTIONS", "detect_leaks=0");
+        let sandbox = Sandbox::new(tmpdir.path(), &exec, &HashMap::new(), None).unwrap();
+        let mut config = SandboxConfiguration::default();
+        sandbox
+            .build_command(tmpdir.path(), &exec, &mut config, None)
+            .unwrap();
+        let extra_memory = exec.config().extra_memory;
+        let rss_limit = ((1024 + extra_memory) * SANITIZER_MEMORY_FACTOR + 1023) / 1024;
+        let options: Vec<_> = config
+            .env
+            .iter()
+            .filter(|(key, _)| key == "ASAN_OPTIONS")
+            .collect();
+        assert_eq!(options.len(), 1);
+        assert_eq!(
+            options[0].1,
+            format!("detect_leaks=0:hard_rss_limit_mb={}", rss_limit)
+        );
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn test_command_instructions_only() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let mut exec = Execution::new("test", ExecutionCommand::system("/bin/sh"));
+        exec.limits_mut().instructions(3_000_000_000);
+        let sandbox = Sandbox::new(tmpdir.path(), &exec, &HashMap::new(), None).unwrap();
+        let mut config = SandboxConfiguration::default();
+        sandbox
+            .build_command(tmpdir.path(), &exec, &mut config, None)
+            .unwrap();
+        let extra_time = exec.config().extra_time;
+        let cpu_time = 3_000_000_000.0 / MIN_INSTRUCTIONS_PER_SECOND;
+        assert_eq!(
+            config.time_limit,
+            Some((cpu_time + extra_time).ceil() as u64)
+        );
+        assert_eq!(
+            config.wall_time_limit,
+            Some((cpu_time * 2.0 + extra_time).ceil() as u64)
+        );
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn test_command_instructions_with_time_limit() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let mut exec = Execution::new("test", ExecutionCommand::system("/bin/sh"));
+        exec.limits_mut()
+            .instructions(3_000_000_000)
+            .cpu_time(1.0)
+            .wall_time(2.0);
+        let sandbox = Sandbox::new(tmpdir.path(), &exec, &HashMap::new(), None).unwrap();
+        let mut config = SandboxConfiguration::default();
+        sandbox
+            .build_command(tmpdir.path(), &exec, &mut config, None)
+            .unwrap();
+        let extra_time = exec.config().extra_time;
+        assert_eq!(config.time_limit, Some((1.0 + extra_time).ceil() as u64));
+        assert_eq!(
+            config.wall_time_limit,
+            Some((2.0 + extra_time).ceil() as u64)
+        );
+    }
 }
@@ -10,7 +10,16 @@ use crate::RawSandboxResult;
 pub trait SandboxRunner: Send + Sync {
     /// Spawn a sandbox with the provided configuration, set the PID as soon as possible and wait
     /// for it to exit. Parse the outcome of the sandbox and return it.
-    fn run(&self, config: SandboxConfiguration, pid: Arc<AtomicU32>) -> RawSandboxResult;
+    ///
+    /// If `instruction_limit` is set, the user-space instructions retired by the sandboxed
+    /// process have to be counted and included in the result, and the process has to be killed
+    /// as soon as it exceeds the limit.
+    fn run(
+        &self,
+        config: SandboxConfiguration,
+        instruction_limit: Option<u64>,
+        pid: Arc<AtomicU32>,
+    ) -> RawSandboxResult;
 }
 
 /// A fake sandbox that don't actually spawn anything and always return an error.
@@ -18,7 +27,12 @@ pub trait SandboxRunner: Send + Sync {
 pub struct ErrorSandboxRunner;
 
 impl SandboxRunner for ErrorSandboxRunner {
-    fn run(&self, _config: SandboxConfiguration, _pid: Arc<AtomicU32>) -> RawSandboxResult {
+    fn run(
+        &self,
+        _config: SandboxConfiguration,
+        _instruction_limit: Option<u64>,
+        _pid: Arc<AtomicU32>,
+    ) -> RawSandboxResult {
         RawSandboxResult::Error("Nope".to_owned())
     }
 }
@@ -29,16 +43,24 @@ impl SandboxRunner for ErrorSandboxRunner {
 pub struct SuccessSandboxRunner;
 
 impl SandboxRunner for SuccessSandboxRunner {
-    fn run(&self, _config: SandboxConfiguration, _pid: Arc<AtomicU32>) -> RawSandboxResult {
-        RawSandboxResult::Success(SandboxExecutionResult {
-            status: ExitStatus::ExitCode(0),
-            resource_usage: ResourceUsage {
-                memory_usage: 0,
-                user_cpu_time: 0.0,
-                system_cpu_time: 0.0,
-                wall_time_usage: 0.0,
+    fn run(
+        &self,
+        _config: SandboxConfiguration,
+        _instruction_limit: Option<u64>,
+        _pid: Arc<AtomicU32>,
+    ) -> RawSandboxResult {
+        RawSandboxResult::Success(
+            SandboxExecutionResult {
+                status: ExitStatus::ExitCode(0),
+                resource_usage: ResourceUsage {
+                    memory_usage: 0,
+                    user_cpu_time: 0.0,
+                    system_cpu_time: 0.0,
+                    wall_time_usage: 0.0,
+                },
             },
-        })
+            None,
+        )
     }
 }
 
@@ -50,7 +72,12 @@ pub struct UnsafeSandboxRunner;
 
 #[cfg(test)]
 impl SandboxRunner for UnsafeSandboxRunner {
-    fn run(&self, config: SandboxConfiguration, _pid: Arc<AtomicU32>) -> RawSandboxResult {
+    fn run(
+        &self,
+        config: SandboxConfiguration,
+        _instruction_limit: Option<u64>,
+        _pid: Arc<AtomicU32>,
+    ) -> RawSandboxResult {
         use std::fs::{File, OpenOptions};
         use std::process::Stdio;
 
@@ -77,15 +104,23 @@ impl SandboxRunner for UnsafeSandboxRunner {
             system_cpu_time: 0.0,
             wall_time_usage: 0.0,
         };
-        RawSandboxResult::Success(SandboxExecutionResult {
-            status: ExitStatus::ExitCode(res.code().unwrap()),
-            resource_usage,
-        })
+        RawSandboxResult::Success(
+            SandboxExecutionResult {
+                status: ExitStatus::ExitCode(res.code().unwrap()),
+                resource_usage,
+            },
+            None,
+        )
     }
 }
 
 impl<S: SandboxRunner> SandboxRunner for Arc<S> {
-    fn run(&self, conf: SandboxConfiguration, pid: Arc<AtomicU32>) -> RawSandboxResult {
-        self.as_ref().run(conf, pid)
+    fn run(
+        &self,
+        conf: SandboxConfiguration,
+        instruction_limit: Option<u64>,
+        pid: Arc<AtomicU32>,
+    ) -> RawSandboxResult {
+        self.as_ref().run(conf, instruction_limit, pid)
     }
 }
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::collections::{HashMap, HashSet};
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -10,14 +10,15 @@ use uuid::Uuid;
 
 use task_maker_cache::{Cache, CacheResult};
 use task_maker_dag::{
-    CacheMode, DagPriority, ExecutionDAGData, ExecutionGroup, ExecutionGroupUuid, ExecutionResult,
-    ExecutionUuid, FileUuid, Priority, WorkerUuid, HIGH_PRIORITY,
+    CacheMode, ExecutionDAGData, ExecutionGroup, ExecutionGroupUuid, ExecutionResult,
+    ExecutionUuid, FileUuid, WorkerUuid, HIGH_PRIORITY,
 };
 use task_maker_store::{FileStore, FileStoreHandle, FileStoreKey};
 
 use crate::executor::{
     ExecutionDAGWatchSet, ExecutorStatus, ExecutorWorkerStatus, WorkerCurrentJobStatus, WorkerJob,
 };
+use crate::fair_share::{ReadyQueue, SchedulingPolicy};
 use crate::worker_manager::WorkerManagerInMessage;
 
 pub type ClientUuid = Uuid;
@@ -29,6 +30,8 @@ pub struct ClientInfo {
     pub uuid: ClientUuid,
     /// The name of the client.
     pub name: String,
+    /// The owner of the token or of the TLS certificate the client authenticated with, if any.
+    pub user: Option<String>,
 }
 
 /// Message coming in for the `Scheduler` from either an `Executor` or a `WorkerManager`.
@@ -65,6 +68,9 @@ pub(crate) enum SchedulerInMessage {
         result: Vec<ExecutionResult>,
         /// The outputs that the worker produced.
         outputs: HashMap<FileUuid, FileStoreHandle>,
+        /// The fingerprints of the system commands of the executions on the worker, in the same
+        /// order.
+        toolchains: Vec<Option<String>>,
     },
     /// A new worker is ready for executing some work.
     WorkerConnected {
@@ -142,8 +148,8 @@ struct ConnectedWorker {
 /// The scheduling information about the DAG of a single client.
 #[derive(Debug)]
 struct SchedulerClientData {
-    /// The name of the client.
-    name: String,
+    /// The information about the client.
+    info: ClientInfo,
     /// The DAGs the scheduler is currently working on.
     dag: ExecutionDAGData,
     /// The set of callbacks the client is interested in.
@@ -168,12 +174,12 @@ struct SchedulerClientData {
 impl SchedulerClientData {
     /// Make a new `SchedulerClientData` based on the DAG the client sent.
     fn new(
-        name: String,
+        info: ClientInfo,
         dag: ExecutionDAGData,
         callbacks: ExecutionDAGWatchSet,
     ) -> SchedulerClientData {
         SchedulerClientData {
-            name,
+            info,
             dag,
             callbacks,
             input_of: HashMap::new(),
@@ -213,8 +219,8 @@ pub(crate) struct Scheduler {
     /// Sender of the messages to the WorkerManager, aka the messages to the workers.
     worker_manager: Sender<WorkerManagerInMessage>,
 
-    /// The priority queue of the ready tasks, waiting for the workers.
-    ready_execs: BinaryHeap<(DagPriority, Priority, ExecutionGroupUuid, ClientUuid)>,
+    /// The queue of the ready tasks, waiting for the workers, shared fairly between the clients.
+    ready_execs: ReadyQueue,
     /// The data about the clients currently working.
     clients: HashMap<ClientUuid, SchedulerClientData>,
 
@@ -241,13 +247,19 @@ impl Scheduler {
             executor,
             worker_manager,
 
-            ready_execs: BinaryHeap::new(),
+            ready_execs: ReadyQueue::default(),
             clients: HashMap::new(),
 
             connected_workers: HashMap::new(),
         }
     }
 
+    /// Share the workers between the clients following the provided policy.
+    pub fn with_scheduling_policy(mut self, policy: SchedulingPolicy) -> Scheduler {
+        self.ready_execs = ReadyQueue::new(policy);
+        self
+    }
+
     /// Run the `Scheduler` listening for incoming messages and blocking util the scheduler is
     /// asked to exit. When the scheduler exits it will turn down the worker manager too.
     pub fn run(mut self) -> Result<(), Error> {
@@ -277,8 +289,9 @@ impl Scheduler {
                     worker,
                     result,
                     outputs,
+                    toolchains,
                 } => {
-                    self.handle_worker_result(worker, result, outputs)
+                    self.handle_worker_result(worker, result, outputs, toolchains)
                         .context("Failed to handle WorkerResult")?;
                 }
                 SchedulerInMessage::WorkerConnected { uuid, name } => {
@@ -314,10 +327,12 @@ impl Scheduler {
         callbacks: ExecutionDAGWatchSet,
     ) -> Result<(), Error> {
         info!("Client '{}' asked to evaluate a new DAG", client.name);
+        self.cache.refresh_toolchains();
         // build the scheduler structures, insert the client in the list of working
         // clients and schedule all the already cached executions.
-        let dag_priority = dag.config.priority;
-        let mut client_data = SchedulerClientData::new(client.name, dag, callbacks);
+        let dag_priority = self.ready_execs.add_client(&client, dag.config.priority);
+        let client_uuid = client.uuid;
+        let mut client_data = SchedulerClientData::new(client, dag, callbacks);
         for group in client_data.dag.execution_groups.values() {
             let missing_dep = client_data.missing_deps.entry(group.uuid).or_default();
             for exec in &group.executions {
@@ -333,13 +348,13 @@ impl Scheduler {
                 client_data.ready_groups.insert(group.uuid);
                 for exec in &group.executions {
                     self.ready_execs
-                        .push((dag_priority, exec.priority, group.uuid, client.uuid));
+                        .push(client_uuid, dag_priority, exec.priority, group.uuid);
                 }
             }
         }
-        self.clients.insert(client.uuid, client_data);
+        self.clients.insert(client_uuid, client_data);
         // the client may have sent and empty DAG
-        self.check_completion(client.uuid)?;
+        self.check_completion(client_uuid)?;
 
         self.schedule_cached()?;
         self.assign_jobs()?;
@@ -369,6 +384,7 @@ impl Scheduler {
         worker: WorkerUuid,
         result: Vec<ExecutionResult>,
         outputs: HashMap<FileUuid, FileStoreHandle>,
+        toolchains: Vec<Option<String>>,
     ) -> Result<(), Error> {
         let worker = match self.connected_workers.remove(&worker) {
             Some(worker) => worker,
@@ -407,6 +423,7 @@ impl Scheduler {
             bail!("Invalid worker result: the number of results ({}) does not match the number of executions ({})", result.len(), group.executions.len());
         }
         client.running_groups.remove(&group_uuid);
+        self.cache.report_toolchains(&group, toolchains);
         self.exec_completed(client_uuid, &group, result, outputs, false)?;
         self.assign_jobs()?;
         self.check_completion(client_uuid)?;
@@ -442,7 +459,7 @@ impl Scheduler {
                 };
                 let priority = client.dag.execution_groups[&job].priority();
                 self.ready_execs
-                    .push((HIGH_PRIORITY, priority, job, client_uuid));
+                    .push(client_uuid, HIGH_PRIORITY, priority, job);
                 client.ready_groups.insert(job);
                 client.running_groups.remove(&job);
             }
@@ -464,13 +481,11 @@ impl Scheduler {
             }
         }
         self.clients.remove(&client_uuid);
-        let mut remaining = BinaryHeap::new();
-        while let Some((dag_priority, priority, exec, client)) = self.ready_execs.pop() {
-            if self.clients.contains_key(&client) {
-                remaining.push((dag_priority, priority, exec, client));
-            }
+        self.ready_execs.remove_client(client_uuid);
+        // a server keeps the cache open, store the statistics of each DAG
+        if let Err(e) = self.cache.flush() {
+            warn!("Failed to flush the cache: {:?}", e);
         }
-        self.ready_execs = remaining;
         // stop the jobs that are still running in the workers
         for (uuid, worker) in self.connected_workers.iter() {
             if let Some((owner, exec, _)) = worker.current_job {
@@ -495,10 +510,15 @@ impl Scheduler {
     fn handle_status_request(&mut self, client_uuid: ClientUuid) -> Result<(), Error> {
         let mut ready_execs = 0;
         let mut waiting_execs = 0;
-        for client in self.clients.values() {
+        let mut ready_by_client = HashMap::new();
+        for (uuid, client) in &self.clients {
             ready_execs += client.ready_groups.len();
             waiting_execs += client.missing_deps.len();
+            ready_by_client.insert(*uuid, client.ready_groups.len());
         }
+        let clients = self
+            .ready_execs
+            .status(&self.running_jobs(), &ready_by_client);
         let status = ExecutorStatus {
             connected_workers: self
                 .connected_workers
@@ -512,10 +532,7 @@ impl Scheduler {
                             let exec = &client.dag.execution_groups[exec_uuid];
                             Some(WorkerCurrentJobStatus {
                                 job: exec.description.clone(),
-                                client: ClientInfo {
-                                    uuid: *client_uuid,
-                                    name: client.name.clone(),
-                                },
+                                client: client.info.clone(),
                                 duration: start.elapsed(),
                             })
                         },
@@ -524,6 +541,7 @@ impl Scheduler {
                 .collect(),
             ready_execs,
             waiting_execs,
+            clients,
         };
 
         if let Err(e) = self
@@ -625,12 +643,12 @@ impl Scheduler {
                 files.remove(&file);
                 if files.is_empty() {
                     client.missing_deps.remove(group_uuid);
-                    self.ready_execs.push((
+                    self.ready_execs.push(
+                        client_uuid,
                         HIGH_PRIORITY,
                         group.priority(),
                         *group_uuid,
-                        client_uuid,
-                    ));
+                    );
                     client.ready_groups.insert(*group_uuid);
                 }
             }
@@ -758,11 +776,11 @@ impl Scheduler {
     /// Look at all the ready executions and mark as completed all the ones that are inside the
     /// cache.
     fn schedule_cached(&mut self) -> Result<(), Error> {
-        let mut not_cached = BinaryHeap::new();
+        let mut not_cached = Vec::new();
         let mut cached = Vec::new();
 
-        for (dag_priority, priority, group_uuid, client_uuid) in self.ready_execs.iter() {
-            let client = if let Some(client) = self.clients.get_mut(client_uuid) {
+        for (dag_priority, priority, group_uuid, client_uuid) in self.ready_execs.drain() {
+            let client = if let Some(client) = self.clients.get_mut(&client_uuid) {
                 client
             } else {
                 // client is gone, dont worry to much about it
@@ -772,12 +790,12 @@ impl Scheduler {
             let cache_mode = &dag.config.cache_mode;
             // disable the cache for the execution
             if let CacheMode::Nothing = cache_mode {
-                not_cached.push((*dag_priority, *priority, *group_uuid, *client_uuid));
+                not_cached.push((dag_priority, priority, group_uuid, client_uuid));
                 continue;
             }
-            let group = dag.execution_groups[group_uuid].clone();
+            let group = dag.execution_groups[&group_uuid].clone();
             if !Scheduler::is_cacheable(&group, cache_mode) {
-                not_cached.push((*dag_priority, *priority, group.uuid, *client_uuid));
+                not_cached.push((dag_priority, priority, group.uuid, client_uuid));
                 continue;
             }
             let result = self
@@ -787,15 +805,18 @@ impl Scheduler {
                 CacheResult::Hit { result, outputs } => {
                     info!("Execution {} is a cache hit!", group.uuid);
                     client.ready_groups.remove(&group.uuid);
-                    cached.push((*client_uuid, group, result, outputs));
+                    cached.push((client_uuid, group, result, outputs));
                 }
                 CacheResult::Miss => {
-                    not_cached.push((*dag_priority, *priority, group.uuid, *client_uuid));
+                    not_cached.push((dag_priority, priority, group.uuid, client_uuid));
                 }
             }
         }
 
-        self.ready_execs = not_cached;
+        for (dag_priority, priority, group_uuid, client_uuid) in not_cached {
+            self.ready_execs
+                .push(client_uuid, dag_priority, priority, group_uuid);
+        }
         for (client, exec, result, outputs) in cached.into_iter() {
             self.exec_completed(client, &exec, result, outputs, true)?;
         }
@@ -813,16 +834,30 @@ impl Scheduler {
         true
     }
 
-    /// Give to each free worker a job from the ready executions.
+    /// The number of jobs of each client that are running in the workers.
+    fn running_jobs(&self) -> HashMap<ClientUuid, usize> {
+        let mut running = HashMap::new();
+        for worker in self.connected_workers.values() {
+            if let Some((client_uuid, _, _)) = worker.current_job {
+                *running.entry(client_uuid).or_default() += 1;
+            }
+        }
+        running
+    }
+
+    /// Give to each free worker a job from the ready executions, sharing the workers fairly
+    /// between the clients.
     fn assign_jobs(&mut self) -> Result<(), Error> {
+        let mut running = self.running_jobs();
         for (worker_uuid, worker) in self.connected_workers.iter_mut() {
             if worker.current_job.is_some() {
                 continue;
             }
-            let (_, _, group_uuid, client_uuid) = match self.ready_execs.pop() {
+            let (group_uuid, client_uuid) = match self.ready_execs.pop(&running) {
                 Some(exec) => exec,
                 None => break,
             };
+            *running.entry(client_uuid).or_default() += 1;
             trace!("Assigning {} to worker {}", group_uuid, worker_uuid);
             worker.current_job = Some((client_uuid, group_uuid, Instant::now()));
             let client = if let Some(client) = self.clients.get_mut(&client_uuid) {
@@ -0,0 +1,572 @@
+//! TLS transport for the remote connections.
+//!
+//! `ductile` channels can only run over plain TCP or unix sockets, so a TLS connection is bridged
+//! to a private unix socket: a pair of threads moves the data between the socket and the TLS
+//! stream, encrypting and decrypting it, while the channel is created on the unix socket as usual.
+
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Error};
+use ductile::{connect_unix_channel, ChannelReceiver, ChannelSender};
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls::{
+    Certificate, ClientConnection, Connection, PrivateKey, RootCertStore, ServerConnection,
+    ServerName,
+};
+use tempfile::TempDir;
+use x509_parser::prelude::{FromDer, GeneralName, X509Certificate};
+
+pub use rustls::{ClientConfig, ServerConfig};
+
+/// The maximum time the TLS handshake can take, after that the connection is dropped.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// The size of the buffers used for moving the data between the sockets.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Load the configuration of a TLS server from the PEM files with its certificate chain and its
+/// private key. If `client_ca` is provided, the clients must present a certificate signed by that
+/// CA (mutual TLS), and they are identified by it (see `TlsIncoming::complete`).
+pub fn server_config(
+    cert: &Path,
+    key: &Path,
+    client_ca: Option<&Path>,
+) -> Result<Arc<ServerConfig>, Error> {
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match client_ca {
+        Some(client_ca) => {
+            let roots = load_roots(client_ca)?;
+            builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder
+        .with_single_cert(load_certs(cert)?, load_key(key)?)
+        .context("Invalid server certificate or key")?;
+    Ok(Arc::new(config))
+}
+
+/// Load the configuration of a TLS client. The server certificate is verified against the CA in
+/// the `ca` PEM file if provided, otherwise against the certificates of the system. The client
+/// certificate, for mutual TLS, is the pair of PEM files with the certificate chain and the
+/// private key.
+pub fn client_config(
+    ca: Option<&Path>,
+    identity: Option<(&Path, &Path)>,
+) -> Result<Arc<ClientConfig>, Error> {
+    let roots = match ca {
+        Some(ca) => load_roots(ca)?,
+        None => {
+            let mut roots = RootCertStore::empty();
+            let certs = rustls_native_certs::load_native_certs()
+                .context("Failed to load the certificates of the system")?;
+            let certs: Vec<_> = certs.into_iter().map(|cert| cert.0).collect();
+            roots.add_parsable_certificates(&certs);
+            roots
+        }
+    };
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots);
+    let config = match identity {
+        Some((cert, key)) => builder
+            .with_client_auth_cert(load_certs(cert)?, load_key(key)?)
+            .context("Invalid client certificate or key")?,
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(config))
+}
+
+/// A TCP listener that accepts TLS connections.
+pub struct TlsListener {
+    /// The underlying TCP listener.
+    listener: TcpListener,
+    /// The configuration of the TLS server.
+    config: Arc<ServerConfig>,
+    /// The directory with the unix sockets of the bridges.
+    sockets: Arc<TempDir>,
+    /// The number of accepted connections, for naming their sockets.
+    accepted: usize,
+}
+
+/// A TCP connection accepted by a `TlsListener`, whose TLS handshake has not been done yet.
+pub struct TlsIncoming {
+    /// The TCP connection with the peer.
+    tcp: TcpStream,
+    /// The address of the peer.
+    addr: SocketAddr,
+    /// The configuration of the TLS server.
+    config: Arc<ServerConfig>,
+    /// The directory with the unix sockets of the bridges, kept alive by the connections.
+    sockets: Arc<TempDir>,
+    /// The path of the unix socket of the bridge of this connection.
+    path: PathBuf,
+}
+
+impl TlsListener {
+    /// Bind a TLS server to the specified address.
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        config: Arc<ServerConfig>,
+    ) -> Result<TlsListener, Error> {
+        let listener = TcpListener::bind(addr)?;
+        let sockets = TempDir::new().context("Failed to create the directory for the sockets")?;
+        Ok(TlsListener {
+            listener,
+            config,
+            sockets: Arc::new(sockets),
+            accepted: 0,
+        })
+    }
+
+    /// The local address the listener is bound to.
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Wait for the next TCP connection, without doing its TLS handshake. Since the handshake can
+    /// take up to `HANDSHAKE_TIMEOUT`, it should be completed in another thread, so that a slow
+    /// peer doesn't prevent the others from connecting.
+    pub fn accept_tcp(&mut self) -> Result<TlsIncoming, Error> {
+        let (tcp, addr) = self.listener.accept()?;
+        self.accepted += 1;
+        Ok(TlsIncoming {
+            tcp,
+            addr,
+            config: self.config.clone(),
+            sockets: self.sockets.clone(),
+            path: self.sockets.path().join(format!("{}.sock", self.accepted)),
+        })
+    }
+
+    /// Wait for the next connection and complete its TLS handshake, returning also the address and
+    /// the user of the peer. See
+    /// [`TlsIncoming::complete`](struct.TlsIncoming.html#method.complete) for the meaning of
+    /// `bind` and of the user.
+    ///
+    /// A connection whose handshake fails is an error, but the listener can still be used.
+    pub fn accept<F, T>(&mut self, bind: F) -> Result<(T, SocketAddr, Option<String>), Error>
+    where
+        F: FnOnce(&Path) -> Result<T, Error>,
+    {
+        let incoming = self.accept_tcp()?;
+        let addr = incoming.peer_addr();
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        let (listening, user) = incoming.complete(deadline, bind)?;
+        Ok((listening, addr, user))
+    }
+}
+
+impl TlsIncoming {
+    /// The address of the peer.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// A handle to the TCP connection, that can be used for closing the connection while it's in
+    /// use, for example if the peer is taking too long.
+    pub fn try_clone_tcp(&self) -> Result<TcpStream, Error> {
+        Ok(self.tcp.try_clone()?)
+    }
+
+    /// Complete the TLS handshake, failing if it doesn't end before `deadline`. The connection is
+    /// then forwarded to a fresh unix socket, whose path is passed to `bind` that should start
+    /// listening on it (for example binding a `ductile::ChannelServer`). After `bind` returns, the
+    /// bridge connects to the socket, and the socket file is removed.
+    ///
+    /// Returns what `bind` returned and the user of the certificate of the peer, if it presented
+    /// one: its common name, or its first DNS name or email. The certificate is verified only if
+    /// the server requires the client certificates, otherwise the peer doesn't present one.
+    pub fn complete<F, T>(self, deadline: Instant, bind: F) -> Result<(T, Option<String>), Error>
+    where
+        F: FnOnce(&Path) -> Result<T, Error>,
+    {
+        let TlsIncoming {
+            mut tcp,
+            addr,
+            config,
+            sockets: _sockets,
+            path,
+        } = self;
+        let mut conn = Connection::from(ServerConnection::new(config)?);
+        handshake(&mut conn, &mut tcp, deadline)
+            .with_context(|| format!("TLS handshake with {} failed", addr))?;
+        let user = conn
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .and_then(certificate_user);
+
+        let res = bind(&path).and_then(|listening| {
+            let local = UnixStream::connect(&path).context("Failed to connect the bridge")?;
+            bridge(conn, tcp, local)?;
+            Ok((listening, user))
+        });
+        let _ = std::fs::remove_file(&path);
+        res
+    }
+}
+
+/// Connect to a TLS server, returning a channel to it.
+pub fn connect_tls_channel<A: ToSocketAddrs, S, R>(
+    addr: A,
+    server_name: &str,
+    config: Arc<ClientConfig>,
+) -> Result<(ChannelSender<S>, ChannelReceiver<R>), Error> {
+    let mut tcp = TcpStream::connect(addr)?;
+    let name = ServerName::try_from(server_name)
+        .map_err(|_| anyhow!("Invalid server name: {}", server_name))?;
+    let mut conn = Connection::from(ClientConnection::new(config, name)?);
+    let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+    handshake(&mut conn, &mut tcp, deadline).context("TLS handshake failed")?;
+
+    let sockets = TempDir::new().context("Failed to create the directory for the socket")?;
+    let path = sockets.path().join("channel.sock");
+    let listener = UnixListener::bind(&path).context("Failed to bind the bridge socket")?;
+    let bridge_thread = thread::Builder::new()
+        .name("TLS bridge".into())
+        .spawn(move || -> Result<(), Error> {
+            let (local, _) = listener.accept()?;
+            bridge(conn, tcp, local)
+        })
+        .context("Failed to spawn the bridge thread")?;
+    let channel = connect_unix_channel(&path)?;
+    bridge_thread
+        .join()
+        .map_err(|e| anyhow!("Bridge thread panicked: {:?}", e))??;
+    Ok(channel)
+}
+
+/// The user a certificate identifies: its common name, or its first DNS name or email.
+fn certificate_user(cert: &Certificate) -> Option<String> {
+    let (_, cert) = X509Certificate::from_der(&cert.0).ok()?;
+    let common_name = cert
+        .subject()
+        .iter_common_name()
+        .next()
+        .and_then(|name| name.as_str().ok());
+    if let Some(name) = common_name {
+        return Some(name.to_string());
+    }
+    let names = cert.subject_alternative_name().ok()??;
+    names
+        .value
+        .general_names
+        .iter()
+        .find_map(|name| match name {
+            GeneralName::DNSName(name) | GeneralName::RFC822Name(name) => Some(name.to_string()),
+            _ => None,
+        })
+}
+
+/// Complete the TLS handshake, failing if it doesn't end before `deadline`, however the peer sends
+/// the data.
+fn handshake(conn: &mut Connection, tcp: &mut TcpStream, deadline: Instant) -> Result<(), Error> {
+    while conn.is_handshaking() {
+        let remaining = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())
+            .ok_or_else(|| anyhow!("The TLS handshake timed out"))?;
+        tcp.set_read_timeout(Some(remaining))?;
+        tcp.set_write_timeout(Some(remaining))?;
+        // a single read at a time, `complete_io` would wait for a whole TLS record
+        if conn.wants_write() {
+            conn.write_tls(tcp)?;
+        } else if conn.read_tls(tcp)? == 0 {
+            bail!("The connection was closed during the TLS handshake");
+        } else if let Err(e) = conn.process_new_packets() {
+            // try to tell the peer what went wrong
+            let _ = conn.write_tls(tcp);
+            return Err(e.into());
+        }
+    }
+    while conn.wants_write() {
+        conn.write_tls(tcp)?;
+    }
+    tcp.set_read_timeout(None)?;
+    tcp.set_write_timeout(None)?;
+    tcp.set_nodelay(true)?;
+    Ok(())
+}
+
+/// Move the data between the local socket and the TLS connection, until one of the two is closed.
+///
+/// Two threads are spawned, one for each direction. The state of the TLS connection is shared, but
+/// the sockets are never read while holding its lock, so one direction never waits for the other
+/// one to receive some data. The encrypted data is written under a second lock, taken before
+/// releasing the first one, so the TLS records reach the socket in the order they are produced.
+fn bridge(conn: Connection, tcp: TcpStream, local: UnixStream) -> Result<(), Error> {
+    let conn = Arc::new(Mutex::new(conn));
+    let tcp_writer = Arc::new(Mutex::new(tcp.try_clone()?));
+    {
+        let conn = conn.clone();
+        let tcp_writer = tcp_writer.clone();
+        let tcp = tcp.try_clone()?;
+        let local = local.try_clone()?;
+        thread::Builder::new()
+            .name("TLS bridge reader".into())
+            .spawn(move || {
+                if let Err(e) = receive_tls(&conn, &tcp, &tcp_writer, &local) {
+                    debug!("TLS connection closed: {:?}", e);
+                }
+                let _ = local.shutdown(Shutdown::Write);
+            })
+            .context("Failed to spawn the bridge reader")?;
+    }
+    thread::Builder::new()
+        .name("TLS bridge writer".into())
+        .spawn(move || {
+            if let Err(e) = send_tls(&conn, &tcp_writer, &local) {
+                debug!("Local connection closed: {:?}", e);
+            }
+            let _ = tcp.shutdown(Shutdown::Write);
+        })
+        .context("Failed to spawn the bridge writer")?;
+    Ok(())
+}
+
+/// Decrypt the data coming from the TLS peer, writing it to the local socket.
+fn receive_tls(
+    conn: &Mutex<Connection>,
+    mut tcp: &TcpStream,
+    tcp_writer: &Mutex<TcpStream>,
+    mut local: &UnixStream,
+) -> Result<(), Error> {
+    let mut buffer = vec![0; BUFFER_SIZE];
+    let mut plain = Vec::new();
+    loop {
+        let len = match tcp.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(len) => len,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let mut closed = false;
+        let mut data = &buffer[..len];
+        let mut conn = conn.lock().unwrap();
+        while !data.is_empty() {
+            conn.read_tls(&mut data)?;
+            let state = match conn.process_new_packets() {
+                Ok(state) => state,
+                Err(e) => {
+                    // try to tell the peer what went wrong
+                    let mut tcp_writer = tcp_writer.lock().unwrap();
+                    let _ = conn.write_tls(&mut *tcp_writer);
+                    bail!("Invalid TLS data: {}", e);
+                }
+            };
+            let start = plain.len();
+            plain.resize(start + state.plaintext_bytes_to_read(), 0);
+            conn.reader().read_exact(&mut plain[start..])?;
+            closed |= state.peer_has_closed();
+        }
+        // the peer may need an answer, like for a key update
+        if conn.wants_write() {
+            let mut encrypted = Vec::new();
+            while conn.wants_write() {
+                conn.write_tls(&mut encrypted)?;
+            }
+            let mut tcp_writer = tcp_writer.lock().unwrap();
+            drop(conn);
+            tcp_writer.write_all(&encrypted)?;
+        } else {
+            drop(conn);
+        }
+        local.write_all(&plain)?;
+        plain.clear();
+        if closed {
+            return Ok(());
+        }
+    }
+}
+
+/// Encrypt the data coming from the local socket, sending it to the TLS peer.
+fn send_tls(
+    conn: &Mutex<Connection>,
+    tcp_writer: &Mutex<TcpStream>,
+    mut local: &UnixStream,
+) -> Result<(), Error> {
+    let mut buffer = vec![0; BUFFER_SIZE];
+    loop {
+        let len = match local.read(&mut buffer) {
+            Ok(len) => len,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let mut encrypted = Vec::new();
+        let mut conn = conn.lock().unwrap();
+        if len == 0 {
+            conn.send_close_notify();
+        } else {
+            conn.writer().write_all(&buffer[..len])?;
+        }
+        while conn.wants_write() {
+            conn.write_tls(&mut encrypted)?;
+        }
+        let mut tcp_writer = tcp_writer.lock().unwrap();
+        drop(conn);
+        tcp_writer.write_all(&encrypted)?;
+        if len == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// Load all the certificates from a PEM file.
+fn load_certs(path: &Path) -> Result<Vec<Certificate>, Error> {
+    let file = File::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .with_context(|| format!("Invalid certificates in {}", path.display()))?;
+    if certs.is_empty() {
+        bail!("No certificate found in {}", path.display());
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// Load the first private key from a PEM file.
+fn load_key(path: &Path) -> Result<PrivateKey, Error> {
+    let file = File::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    loop {
+        let item = rustls_pemfile::read_one(&mut reader)
+            .with_context(|| format!("Invalid private key in {}", path.display()))?;
+        match item {
+            Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => return Ok(PrivateKey(key)),
+            Some(_) => continue,
+            None => bail!("No private key found in {}", path.display()),
+        }
+    }
+}
+
+/// Load the trusted certificates from a PEM file.
+fn load_roots(path: &Path) -> Result<RootCertStore, Error> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots
+            .add(&cert)
+            .with_context(|| format!("Invalid CA certificate in {}", path.display()))?;
+    }
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use ductile::ChannelServer;
+
+    use super::*;
+
+    /// Write a self-signed certificate for localhost, with `name` as common name, returning the
+    /// paths of the certificate and of the key.
+    fn make_cert(dir: &Path, name: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+        let mut params = rcgen::CertificateParams::new(vec!["localhost".into()]);
+        params.distinguished_name = rcgen::DistinguishedName::new();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, name);
+        let cert = rcgen::Certificate::from_params(params).unwrap();
+        let cert_path = dir.join(format!("{}.pem", name));
+        let key_path = dir.join(format!("{}.key", name));
+        std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+        (cert_path, key_path)
+    }
+
+    fn echo_server(config: Arc<ServerConfig>) -> (SocketAddr, thread::JoinHandle<()>) {
+        let mut listener = TlsListener::bind("127.0.0.1:0", config).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut server, _, _) = listener
+                .accept(|path| Ok(ChannelServer::<String, String>::bind_unix(path)?))
+                .unwrap();
+            let (sender, receiver, _) = server.next().unwrap();
+            while let Ok(message) = receiver.recv() {
+                sender.send(message.to_uppercase()).unwrap();
+            }
+        });
+        (addr, server)
+    }
+
+    #[test]
+    fn test_tls_channel() {
+        let tmp = TempDir::new().unwrap();
+        let (cert, key) = make_cert(tmp.path(), "server");
+        let (addr, server) = echo_server(server_config(&cert, &key, None).unwrap());
+
+        let config = client_config(Some(&cert), None).unwrap();
+        let (sender, receiver) =
+            connect_tls_channel::<_, String, String>(addr, "localhost", config).unwrap();
+        sender.send("hello".to_string()).unwrap();
+        assert_eq!(receiver.recv().unwrap(), "HELLO");
+        let big = "x".repeat(1_000_000);
+        sender.send(big.clone()).unwrap();
+        assert_eq!(receiver.recv().unwrap(), big.to_uppercase());
+        drop(sender);
+        drop(receiver);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_tls_handshake_deadline() {
+        let tmp = TempDir::new().unwrap();
+        let (cert, key) = make_cert(tmp.path(), "server");
+        let config = server_config(&cert, &key, None).unwrap();
+        let mut listener = TlsListener::bind("127.0.0.1:0", config).unwrap();
+        let addr = listener.local_addr().unwrap();
+        // a peer that sends the start of a handshake one byte at a time
+        let client = thread::spawn(move || {
+            let mut tcp = TcpStream::connect(addr).unwrap();
+            for byte in [0x16, 0x03, 0x01, 0x02, 0x00].into_iter().chain([0; 100]) {
+                if tcp.write_all(&[byte]).is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        });
+
+        let incoming = listener.accept_tcp().unwrap();
+        let start = Instant::now();
+        let deadline = start + Duration::from_millis(300);
+        let res = incoming.complete(deadline, |path| {
+            Ok(ChannelServer::<String, String>::bind_unix(path)?)
+        });
+        assert!(res.is_err());
+        assert!(start.elapsed() < Duration::from_secs(2));
+        drop(listener);
+        client.join().unwrap();
+    }
+
+    #[test]
+    fn test_tls_client_certificate() {
+        let tmp = TempDir::new().unwrap();
+        let (cert, key) = make_cert(tmp.path(), "server");
+        let (client_cert, client_key) = make_cert(tmp.path(), "client");
+        let config = server_config(&cert, &key, Some(&client_cert)).unwrap();
+        let mut listener = TlsListener::bind("127.0.0.1:0", config).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let res = listener.accept(|path| Ok(ChannelServer::<String, String>::bind_unix(path)?));
+            assert!(res.is_err());
+            let res = listener.accept(|path| Ok(ChannelServer::<String, String>::bind_unix(path)?));
+            assert_eq!(res.unwrap().2.as_deref(), Some("client"));
+        });
+
+        // without the client certificate the server drops the connection, with TLS 1.3 this happens
+        // after the client has completed the handshake
+        let config = client_config(Some(&cert), None).unwrap();
+        if let Ok((_, receiver)) =
+            connect_tls_channel::<_, String, String>(addr, "localhost", config)
+        {
+            assert!(receiver.recv().is_err());
+        }
+        let config = client_config(Some(&cert), Some((&client_cert, &client_key))).unwrap();
+        assert!(connect_tls_channel::<_, String, String>(addr, "localhost", config).is_ok());
+        server.join().unwrap();
+    }
+}
@@ -13,6 +13,7 @@ use ductile::{new_local_channel, ChannelReceiver, ChannelSender};
 use tempfile::TempDir;
 use uuid::Uuid;
 
+use task_maker_cache::ToolchainFingerprints;
 use task_maker_dag::*;
 use task_maker_store::*;
 
@@ -53,6 +54,11 @@ pub struct Worker {
     sandbox_path: PathBuf,
     /// The function that spawns an actual sandbox.
     sandbox_runner: Arc<dyn SandboxRunner>,
+    /// Whether to compress the files sent to the server.
+    compress_transfers: bool,
+    /// The fingerprints of the system commands, kept between the jobs so that the executables are
+    /// hashed only when they change.
+    fingerprints: ToolchainFingerprints,
     /// The join handle of the currently running sandbox, if any.
     current_sandbox_thread: Option<JoinHandle<()>>,
 }
@@ -67,6 +73,8 @@ pub struct WorkerConn {
     pub sender: ChannelSender<WorkerServerMessage>,
     /// The channel that receives messages from the server.
     pub receiver: ChannelReceiver<WorkerClientMessage>,
+    /// Whether to compress the files sent to the worker, worth it only for the remote workers.
+    pub compress_transfers: bool,
 }
 
 /// An error generated by the worker.
@@ -122,6 +130,7 @@ impl Worker {
                 name,
                 sender: tx,
                 receiver: rx,
+                compress_transfers: false,
             },
         ))
     }
@@ -148,10 +157,18 @@ impl Worker {
             current_job: Arc::new(Mutex::new(WorkerCurrentJob::new())),
             sandbox_path,
             sandbox_runner,
+            compress_transfers: false,
+            fingerprints: ToolchainFingerprints::default(),
             current_sandbox_thread: None,
         })
     }
 
+    /// Compress the files sent to the server, worth it only when the server is remote.
+    pub fn with_transfer_compression(mut self, compress: bool) -> Worker {
+        self.compress_transfers = compress;
+        self
+    }
+
     /// Start the sandbox thread for the current job.
     fn start_job(&mut self) -> Result<(), Error> {
         self.current_sandbox_thread = Some(execute_job(
@@ -159,6 +176,8 @@ impl Worker {
             &self.sender,
             &self.sandbox_path,
             self.sandbox_runner.clone(),
+            self.compress_transfers,
+            &mut self.fingerprints,
         )?);
         Ok(())
     }
@@ -227,7 +246,7 @@ impl Worker {
                     let reader = ChannelFileIterator::new(&self.receiver);
                     let handle = self
                         .file_store
-                        .store(&key, reader)
+                        .try_store(&key, reader)
                         .with_context(|| format!("Failed to store server-provided file {}", key))?;
                     let should_start = {
                         let mut job = self.current_job.lock().unwrap();
@@ -310,8 +329,10 @@ fn execute_job(
     sender: &ChannelSender<WorkerClientMessage>,
     sandbox_path: &Path,
     runner: Arc<dyn SandboxRunner>,
+    compress_transfers: bool,
+    fingerprints: &mut ToolchainFingerprints,
 ) -> Result<JoinHandle<()>, Error> {
-    let (job, sandboxes, fifo_dir, server_asked_files) = {
+    let (job, sandboxes, fifo_dir, server_asked_files, toolchains) = {
         let mut current_job = current_job.lock().unwrap();
         let job = current_job
             .current_job
@@ -351,10 +372,18 @@ fn execute_job(
             boxes.push(sandbox);
         }
         let job = job.0.clone();
+        // The toolchain of this worker may differ from the one of the server, which uses these
+        // fingerprints in the cache keys. The commands may have been upgraded since the last job.
+        fingerprints.refresh();
+        let toolchains: Vec<_> = group
+            .executions
+            .iter()
+            .map(|exec| fingerprints.fingerprint(&exec.command))
+            .collect();
         current_job.current_sandboxes = Some(boxes.clone());
         let (sender, receiver) = channel();
         current_job.server_asked_files = Some(sender);
-        (job, boxes, fifo_dir, receiver)
+        (job, boxes, fifo_dir, receiver, toolchains)
     };
     let sender = sender.clone();
     let description = job.group.description.clone();
@@ -369,6 +398,8 @@ fn execute_job(
                 sandboxes,
                 runner,
                 fifo_dir,
+                compress_transfers,
+                toolchains,
             )
             .with_context(|| format!("Sandbox group for {} failed", description))
             // FIXME: find a better way to propagate the error to the server
@@ -383,6 +414,7 @@ fn execute_job(
 ///
 /// Note that this function owns `fifo_dir`, the `TempDir` where the FIFOs are stored, it has not to
 /// be dropped before all the sandboxes end.
+#[allow(clippy::too_many_arguments)]
 fn sandbox_group_manager(
     current_job: Arc<Mutex<WorkerCurrentJob>>,
     job: WorkerJob,
@@ -391,6 +423,8 @@ fn sandbox_group_manager(
     mut sandboxes: Vec<Sandbox>,
     runner: Arc<dyn SandboxRunner>,
     fifo_dir: Option<TempDir>,
+    compress_transfers: bool,
+    toolchains: Vec<Option<String>>,
 ) -> Result<(), Error> {
     assert_eq!(sandboxes.len(), job.group.executions.len());
     let mut results = vec![None; job.group.executions.len()];
@@ -485,6 +519,7 @@ fn sandbox_group_manager(
         .send(WorkerClientMessage::WorkerDone(
             results.into_iter().map(Option::unwrap).collect(),
             outputs.clone(),
+            toolchains,
         ))
         .context("Failed to send WorkerDone")?;
     // wait for the list of files to send
@@ -495,7 +530,7 @@ fn sandbox_group_manager(
                     sender
                         .send(WorkerClientMessage::ProvideFile(uuid, key.clone()))
                         .context("Failed to send ProvideFile")?;
-                    ChannelFileSender::send(&output_paths[&uuid], &sender)
+                    ChannelFileSender::send(&output_paths[&uuid], &sender, compress_transfers)
                         .context("Failed to send missing file")?;
                 } else {
                     error!(
@@ -571,7 +606,9 @@ fn compute_execution_result(
             let stdout = capture_stream(&sandbox.stdout_path(), execution.capture_stdout);
             let stderr = capture_stream(&sandbox.stderr_path(), execution.capture_stderr);
             let status = match (&stdout, &stderr) {
-                (Ok(_), Ok(_)) => execution.status(exit_status, signal, &resources),
+                (Ok(_), Ok(stderr)) => {
+                    execution.status(exit_status, signal, &resources, stderr.as_deref())
+                }
                 (Err(err), _) => ExecutionStatus::internal_error(format!(
                     "Failed to read stdout file: {:?}",
                     err
@@ -167,15 +167,19 @@ impl WorkerManager {
                         .sender
                         .send(WorkerServerMessage::ProvideFile(key))
                         .context("Failed to send ProvideFile to worker")?;
-                    ChannelFileSender::send(handle.path(), &worker.sender)
-                        .context("Failed to send file to worker")?;
+                    ChannelFileSender::send(
+                        handle.path(),
+                        &worker.sender,
+                        worker.compress_transfers,
+                    )
+                    .context("Failed to send file to worker")?;
                 }
                 WorkerClientMessage::ProvideFile(_, _) => {
                     // the worker should not provide files unless just after a WorkerDone message is
                     // received
                     unreachable!("Unexpected ProvideFile from worker");
                 }
-                WorkerClientMessage::WorkerDone(result, outputs) => {
+                WorkerClientMessage::WorkerDone(result, outputs, toolchains) => {
                     // the worker completed its job and will send the produced files
                     let mut output_handlers = HashMap::new();
                     let mut missing_files = Vec::new();
@@ -202,7 +206,7 @@ impl WorkerManager {
                             .context("Failed to receive file from worker")?;
                         if let WorkerClientMessage::ProvideFile(uuid, key) = message {
                             let handle = file_store
-                                .store(&key, ChannelFileIterator::new(&worker.receiver))
+                                .try_store(&key, ChannelFileIterator::new(&worker.receiver))
                                 .context("Failed to store worker-provided file")?;
                             output_handlers.insert(uuid, handle);
                         } else {
@@ -213,6 +217,7 @@ impl WorkerManager {
                         worker: worker.uuid,
                         result,
                         outputs: output_handlers,
+                        toolchains,
                     };
                     if let Err(e) = scheduler.send(mex) {
                         warn!("Failed to send message to scheduler: {:?}", e);
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{bail, Context, Error};
@@ -21,6 +21,28 @@ pub enum InputGenerator {
     StaticFile(PathBuf),
     /// Use a custom command to generate the input file. The file has to be printed to stdout.
     Custom(Arc<SourceFile>, Vec<String>),
+    /// Use one of the files produced by a command that generates many input files at once. The
+    /// second field is the index of the file among the ones produced by the generator.
+    MultiCustom(Arc<MultiInputGenerator>, usize),
+}
+
+/// A custom command that generates many input files with a single execution, useful for the
+/// generators that are expensive to start.
+///
+/// The generator is executed with the directory where to write the files as first argument,
+/// followed by its own arguments. It has to write the files `input0.txt`, `input1.txt`, ... inside
+/// that directory, one for each testcase.
+#[derive(Debug, Clone, Serialize, Deserialize, TypeScriptify)]
+pub struct MultiInputGenerator {
+    /// The id of the first testcase generated by this generator, the following ones have
+    /// consecutive ids. This identifies the generator.
+    pub first_testcase: TestcaseId,
+    /// The source file of the generator.
+    pub source: Arc<SourceFile>,
+    /// The arguments to pass to the generator, after the output directory.
+    pub args: Vec<String>,
+    /// The number of input files the generator produces.
+    pub count: usize,
 }
 
 impl InputGenerator {
@@ -62,6 +84,11 @@ impl InputGenerator {
                 let stdout = exec.stdout();
                 Ok((stdout.uuid, Some(exec)))
             }
+            InputGenerator::MultiCustom(generator, _) => bail!(
+                "The inputs of testcases {}..{} are generated together, not one by one",
+                generator.first_testcase,
+                generator.last_testcase()
+            ),
         }
     }
 
@@ -116,3 +143,81 @@ impl InputGenerator {
         Ok(input)
     }
 }
+
+impl MultiInputGenerator {
+    /// The directory, inside the sandbox, where the generator writes the input files.
+    pub const OUTPUT_DIR: &'static str = "inputs";
+
+    /// The id of the last testcase generated by this generator.
+    pub fn last_testcase(&self) -> TestcaseId {
+        self.first_testcase + self.count as TestcaseId - 1
+    }
+
+    /// The path, relative to the sandbox, of the input file with the provided index.
+    pub fn output_path(index: usize) -> PathBuf {
+        Path::new(Self::OUTPUT_DIR).join(format!("input{}.txt", index))
+    }
+
+    /// Add the generation of all the input files to the DAG and the callbacks to the UI of all the
+    /// testcases, returning the handles to the input files in order.
+    pub(crate) fn generate_and_bind(
+        &self,
+        eval: &mut EvaluationData,
+        subtask_id: SubtaskId,
+    ) -> Result<Vec<FileUuid>, Error> {
+        let mut args = vec![Self::OUTPUT_DIR.to_string()];
+        args.extend(self.args.iter().cloned());
+        let mut exec = self
+            .source
+            .execute(
+                eval,
+                format!(
+                    "Generation of input files of testcases {}..{}, subtask {}",
+                    self.first_testcase,
+                    self.last_testcase(),
+                    subtask_id
+                ),
+                args,
+            )
+            .context("Failed to execute generator source file")?;
+        exec.limits_mut().allow_multiprocess();
+        exec.tag(Tag::Generation.into());
+        exec.priority(GENERATION_PRIORITY - self.first_testcase as Priority);
+        exec.capture_stderr(STDERR_CONTENT_LENGTH);
+        let mut inputs = Vec::with_capacity(self.count);
+        for index in 0..self.count {
+            let testcase_id = self.first_testcase + index as TestcaseId;
+            let input = exec.output(Self::output_path(index));
+            eval.dag.write_file_to(
+                &input,
+                eval.task_root
+                    .join("input")
+                    .join(format!("input{}.txt", testcase_id)),
+                false,
+            );
+            bind_exec_callbacks!(eval, exec.uuid, |status| UIMessage::IOIGeneration {
+                subtask: subtask_id,
+                testcase: testcase_id,
+                status
+            })?;
+            inputs.push(input.uuid);
+        }
+        let sender = eval.sender.clone();
+        let args = exec.args.join(" ");
+        let (first, last) = (self.first_testcase, self.last_testcase());
+        eval.dag.on_execution_done(&exec.uuid, move |result| {
+            if !result.status.is_success() {
+                let mut diagnostic =
+                    Diagnostic::error(format!("Failed to generate inputs {} to {}", first, last))
+                        .with_note(format!("Generator arguments are: {}", args));
+                if let Some(stderr) = result.stderr {
+                    diagnostic = diagnostic.with_help_attachment(stderr);
+                }
+                sender.add_diagnostic(diagnostic)?;
+            }
+            Ok(())
+        });
+        eval.dag.add_execution(exec);
+        Ok(inputs)
+    }
+}
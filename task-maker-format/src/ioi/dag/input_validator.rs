@@ -31,7 +31,7 @@ impl InputValidator {
     /// Build the execution for the validation of the input file. Return the handle to the standard
     /// output of the validator, if any and the `Execution` if any. The execution does not send UI
     /// messages yet and it's not added to the DAG.
-    pub(crate) fn validate(
+    pub fn validate(
         &self,
         eval: &mut EvaluationData,
         description: String,
@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use typescript_definitions::TypeScriptify;
 
 pub use checker::Checker;
-pub use input_generator::InputGenerator;
+pub use input_generator::{InputGenerator, MultiInputGenerator};
 pub use input_validator::{InputValidator, TM_VALIDATION_FILE_NAME};
 pub use output_generator::OutputGenerator;
 use task_maker_dag::Priority;
@@ -111,6 +111,7 @@ mod tests {
             title: "".to_string(),
             time_limit: None,
             memory_limit: None,
+            instruction_limit: None,
             infile: None,
             outfile: None,
             subtasks: Default::default(),
@@ -207,6 +208,38 @@ mod tests {
             .is_some());
     }
 
+    #[test]
+    fn test_input_generator_multi_custom() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let path = tmpdir.path().join("gen.py");
+        std::fs::write(&path, "x").unwrap();
+        let source = SourceFile::new(&path, "", "", None, None::<PathBuf>).unwrap();
+        let generator = MultiInputGenerator {
+            first_testcase: 5,
+            source: Arc::new(source),
+            args: vec!["42".into()],
+            count: 3,
+        };
+        let (mut eval, _recv) = EvaluationData::new(tmpdir.path());
+        let out = generator.generate_and_bind(&mut eval, 0).unwrap();
+        assert_eq!(out.len(), 3);
+        assert_eq!(eval.dag.data.execution_groups.len(), 1);
+        let group = eval.dag.data.execution_groups.values().next().unwrap();
+        let exec = group.executions[0].clone();
+        assert_eq!(exec.args, vec!["inputs", "42"]);
+        for (index, file) in out.iter().enumerate() {
+            let path = MultiInputGenerator::output_path(index);
+            assert_eq!(exec.outputs[&path].uuid, *file);
+            let write_to = eval.dag.file_callbacks()[file].write_to.as_ref().unwrap();
+            assert!(write_to
+                .dest
+                .ends_with(format!("input/input{}.txt", 5 + index)));
+        }
+        // The single inputs cannot be generated one by one.
+        let single = InputGenerator::MultiCustom(Arc::new(generator), 0);
+        assert!(single.generate_and_bind(&mut eval, 0, 5).is_err());
+    }
+
     #[test]
     fn test_input_validator_assume_valid() {
         let validator = InputValidator::AssumeValid;
@@ -393,6 +426,7 @@ mod tests {
                     sys_time: 0.0,
                     wall_time: 0.0,
                     memory: 0,
+                    instructions: None,
                 },
                 stdout: None,
                 stderr: None,
@@ -431,6 +465,7 @@ mod tests {
                     sys_time: 0.0,
                     wall_time: 0.0,
                     memory: 0,
+                    instructions: None,
                 },
                 stdout: None,
                 stderr: None,
@@ -148,9 +148,15 @@ pub fn evaluate(
         sol_exec.priority(EVALUATION_PRIORITY - testcase_id as Priority);
         let limits = sol_exec.limits_mut();
         if let Some(time_limit) = task.time_limit {
-            limits.cpu_time(time_limit);
+            // with an instruction limit the time limit is used only for killing the solution
+            if task.instruction_limit.is_none() {
+                limits.cpu_time(time_limit);
+            }
             limits.wall_time(time_limit * 1.5 + 1.0); // some margin
         }
+        if let Some(instruction_limit) = task.instruction_limit {
+            limits.instructions(instruction_limit);
+        }
         if let Some(memory_limit) = task.memory_limit {
             limits.memory(memory_limit * 1024); // MiB -> KiB
         }
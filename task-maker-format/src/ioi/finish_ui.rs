@@ -88,6 +88,10 @@ impl FinishUI {
                 .map(|t| format!("{}MiB", t))
                 .unwrap_or_else(|| "unlimited".to_string())
         );
+        if let Some(instruction_limit) = state.task.instruction_limit {
+            cwrite!(self, BOLD, "Instr. limit: ");
+            println!("{}", instruction_limit);
+        }
     }
 
     /// Print all the booklet states.
@@ -290,6 +294,9 @@ impl FinishUI {
                     "{:3.1}MiB",
                     (result.resources.memory as f64) / 1024.0
                 );
+                if let Some(instructions) = result.resources.instructions {
+                    print!(" | {:.1}M instr", instructions as f64 / 1e6);
+                }
                 print!("]");
             } else {
                 print!(" [???]")
@@ -1,7 +1,8 @@
-use std::collections::HashMap;
-use std::fmt::Debug;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Formatter;
 use std::fmt::Write;
+use std::fmt::{Debug, Display};
 use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
@@ -9,13 +10,14 @@ use std::sync::Arc;
 
 use anyhow::{anyhow, bail, Context, Error};
 use pest::Parser;
+use wildmatch::WildMatch;
 
-use task_maker_diagnostics::CodeSpan;
+use task_maker_diagnostics::{CodeSpan, Diagnostic, DiagnosticLevel};
 
 use crate::ioi::format::italian_yaml::TaskInputEntry;
 use crate::ioi::{
-    InputGenerator, InputValidator, OutputGenerator, SubtaskId, SubtaskInfo, TestcaseId,
-    TestcaseInfo, TM_VALIDATION_FILE_NAME,
+    ConstraintCoverage, InputGenerator, InputValidator, MultiInputGenerator, OutputGenerator,
+    SubtaskId, SubtaskInfo, TestcaseId, TestcaseInfo, TM_VALIDATION_FILE_NAME,
 };
 use crate::SourceFile;
 
@@ -34,9 +36,16 @@ mod parser {
 /// Helper type for lightening the types.
 type Pair<'a> = pest::iterators::Pair<'a, parser::Rule>;
 
+/// Maximum number of iterations of all the `:FOR` loops together, since the nested loops multiply
+/// their iterations.
+const MAX_LOOP_ITERATIONS: i64 = 100_000;
+
+/// Maximum number of testcases of a `cases.gen` file.
+const MAX_TESTCASES: usize = 100_000;
+
 /// A manager is either a generator or a validator, since they have the same internal structure they
 /// are abstracted as a `Manager`.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Manager {
     /// Source file of the manager.
     source: Arc<SourceFile>,
@@ -45,19 +54,65 @@ struct Manager {
     args: Vec<String>,
 }
 
-/// Operand of a constraint. It is either a constant integer value or a symbolic variable to
-/// substitute.
-#[derive(Debug, PartialEq, Eq)]
+/// A numeric value in a constraint, either a constant or the value of a variable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ConstraintValue {
+    /// An integer value.
+    Integer(i64),
+    /// A floating point value, always finite.
+    Float(f64),
+}
+
+/// Operand of a constraint. It is an arithmetic expression made of constant values and symbolic
+/// variables to substitute.
+#[derive(Debug, Clone, PartialEq)]
 enum ConstraintOperand {
-    /// This operand is a constant integer value.
-    Constant(i64),
+    /// This operand is a constant value.
+    Constant(ConstraintValue),
     /// This operand is a symbolic variable. It is stored the variable name, without the dollar
     /// sign.
     Variable(String),
+    /// The opposite of an operand.
+    Negate(Box<ConstraintOperand>),
+    /// An arithmetic operation between two operands, with the span of the operator.
+    Binary(
+        ArithmeticOperator,
+        Box<ConstraintOperand>,
+        Box<ConstraintOperand>,
+        Option<CodeSpan>,
+    ),
+    /// A function applied to a non-empty list of operands.
+    Function(ConstraintFunction, Vec<ConstraintOperand>),
+}
+
+/// An arithmetic operator of the expressions of a constraint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArithmeticOperator {
+    /// Operator `+`.
+    Add,
+    /// Operator `-`.
+    Sub,
+    /// Operator `*`.
+    Mul,
+    /// Operator `/`, the exact division: the result is a float if the operands are not divisible.
+    Div,
+    /// Operator `%`.
+    Rem,
+    /// Operator `^`, the power.
+    Pow,
+}
+
+/// A function that can be used in the expressions of a constraint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConstraintFunction {
+    /// The minimum of the arguments.
+    Min,
+    /// The maximum of the arguments.
+    Max,
 }
 
 /// The operator of a constraint.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum ConstraintOperator {
     /// Operator `<`.
     Less,
@@ -76,12 +131,81 @@ enum ConstraintOperator {
 /// Note that the number of operands is one more than the operators.
 /// All the operators must be _in the same direction_: in the same constraint there cannot be both
 /// a _less_ operator and a _greater_ one.
-#[derive(Default)]
+#[derive(Default, Clone)]
 struct Constraint {
     /// List of the operands of the constraint.
     operands: Vec<ConstraintOperand>,
     /// List of the operators of the contraint.
     operators: Vec<ConstraintOperator>,
+    /// The span of the constraint inside the `cases.gen` file.
+    span: Option<CodeSpan>,
+}
+
+/// The generators, the validator and the constraints of a subtask of a `cases.gen` file. This
+/// allows making new testcases for that subtask, as if they were written in the file.
+#[derive(Debug, Clone)]
+pub struct CasesGenSubtask {
+    /// The id of the subtask.
+    pub id: SubtaskId,
+    /// The description of the subtask, if any.
+    description: Option<String>,
+    /// All the generators declared up to the end of the subtask, indexed by generator name.
+    generators: HashMap<String, Manager>,
+    /// The name of the generator used by the last testcases of the subtask.
+    current_generator: Option<String>,
+    /// The validator used by the last testcases of the subtask.
+    validator: Option<Manager>,
+    /// The global constraints and the ones of the subtask.
+    constraints: Vec<Constraint>,
+}
+
+/// Whether a manager of a `cases.gen` file is a generator or a validator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CasesGenManagerKind {
+    /// The manager is declared with `:GEN`.
+    Generator,
+    /// The manager is declared with `:VAL`.
+    Validator,
+}
+
+/// A generator or a validator declared in a `cases.gen` file.
+#[derive(Debug, Clone)]
+pub struct CasesGenManager {
+    /// Whether this is a generator or a validator.
+    pub kind: CasesGenManagerKind,
+    /// The name of the manager.
+    pub name: String,
+    /// The path of the source file of the manager, relative to the task directory.
+    pub path: String,
+    /// The symbolic arguments of the manager.
+    pub args: Vec<String>,
+    /// The span of the name of the manager in its declaration.
+    pub span: CodeSpan,
+}
+
+/// A usage of a generator or a validator by name in a `cases.gen` file.
+#[derive(Debug, Clone)]
+pub struct CasesGenReference {
+    /// Whether the referenced manager is a generator or a validator.
+    pub kind: CasesGenManagerKind,
+    /// The name of the referenced manager.
+    pub name: String,
+    /// The span of the name.
+    pub span: CodeSpan,
+}
+
+/// The structure of a `cases.gen` file, with the problems found in it. This is meant for the
+/// editor integrations, so it is computed even if the file is not valid.
+#[derive(Debug, Clone, Default)]
+pub struct CasesGenOutline {
+    /// The generators and the validators declared in the file.
+    pub managers: Vec<CasesGenManager>,
+    /// The usages of the generators and of the validators by name.
+    pub references: Vec<CasesGenReference>,
+    /// The names of the variables of the `:FOR` loops, without the dollar sign.
+    pub loop_variables: Vec<String>,
+    /// The problems found in the file.
+    pub diagnostics: Vec<Diagnostic>,
 }
 
 /// Temporary structure with the metadata of the parsing of the `cases.gen` file. The internal data
@@ -128,6 +252,21 @@ where
     subtask_description: Option<String>,
     /// The identifier of the next testcase to process.
     testcase_id: TestcaseId,
+    /// The subtasks that have been completely parsed.
+    subtasks: Vec<CasesGenSubtask>,
+    /// The variables of each testcase of the current subtask.
+    subtask_variables: Vec<HashMap<String, String>>,
+    /// The variables of all the testcases parsed so far, used for the included testcases.
+    testcase_variables: HashMap<TestcaseId, HashMap<String, String>>,
+    /// The variables of the `:FOR` loops containing the current line, from the outermost, with
+    /// their current value.
+    loop_variables: Vec<(String, String)>,
+    /// The values of the loop variables of the testcases generated inside a `:FOR` loop.
+    loop_bindings: HashMap<TestcaseId, String>,
+    /// The number of iterations of all the `:FOR` loops parsed so far.
+    loop_iterations: i64,
+    /// The offset of the line being parsed, used for locating the errors.
+    current_line: usize,
 }
 
 impl<OutGen> CasesGen<OutGen>
@@ -140,22 +279,26 @@ where
         output_gen: OutGen,
     ) -> Result<CasesGen<OutGen>, Error> {
         let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut cases = CasesGen::empty(path, content, output_gen)?;
+        cases.parse()?;
+        Ok(cases)
+    }
+
+    /// Prepare the parsing of the `cases.gen` file at the specified path, with the provided
+    /// content.
+    fn empty(path: &Path, content: String, output_gen: OutGen) -> Result<CasesGen<OutGen>, Error> {
         let task_dir = path
             .parent()
             .context("Invalid gen/cases.gen path")?
             .parent()
             .context("Invalid gen/cases.gen path")?;
-        let content = std::fs::read_to_string(path)
-            .with_context(|| format!("Failed to read {}", path.display()))?;
-        let mut file = parser::CasesGenParser::parse(parser::Rule::file, &content)
-            .context("Cannot parse cases.gen")?;
-        let file = file.next().ok_or_else(|| anyhow!("Corrupted parser"))?; // extract the real file
-
-        let mut cases = CasesGen {
+        Ok(CasesGen {
             task_dir: task_dir.into(),
             get_output_gen: output_gen,
             file_path: path.into(),
-            file_content: content.clone(),
+            file_content: content,
             result: vec![],
             constraints: vec![],
             subtask_constraints: vec![],
@@ -168,36 +311,30 @@ where
             subtask_id: 0,
             subtask_description: None,
             testcase_id: 0,
-        };
+            subtasks: vec![],
+            subtask_variables: vec![],
+            testcase_variables: HashMap::new(),
+            loop_variables: vec![],
+            loop_bindings: HashMap::new(),
+            loop_iterations: 0,
+            current_line: 0,
+        })
+    }
+
+    /// Parse the content of the file.
+    fn parse(&mut self) -> Result<(), Error> {
+        let content = self.file_content.clone();
+        let mut file = parser::CasesGenParser::parse(parser::Rule::file, &content)
+            .context("Cannot parse cases.gen")?;
+        let file = file.next().ok_or_else(|| anyhow!("Corrupted parser"))?; // extract the real file
 
-        for line in file.into_inner() {
-            match line.as_rule() {
-                parser::Rule::line => {
-                    let line = line
-                        .into_inner()
-                        .next()
-                        .ok_or_else(|| anyhow!("Corrupted parser"))?;
-                    match line.as_rule() {
-                        parser::Rule::command => {
-                            let command = line
-                                .into_inner()
-                                .next()
-                                .ok_or_else(|| anyhow!("Corrupted parser"))?;
-                            cases.parse_command(command)?;
-                        }
-                        parser::Rule::testcase => {
-                            cases.parse_testcase(line.as_str(), cases.current_generator.clone())?;
-                        }
-                        parser::Rule::comment => {}
-                        parser::Rule::empty => {}
-                        _ => unreachable!(),
-                    }
-                }
-                parser::Rule::EOI => {}
-                _ => unreachable!(),
-            }
-        }
-        Ok(cases)
+        let lines: Vec<_> = file
+            .into_inner()
+            .filter(|line| line.as_rule() == parser::Rule::line)
+            .collect();
+        self.parse_lines(&lines)?;
+        self.end_subtask();
+        Ok(())
     }
 
     /// Return the list of `TaskInputEntry` from the `cases.gen` file.
@@ -205,6 +342,11 @@ where
         self.result.clone()
     }
 
+    /// Return the generators, the validator and the constraints of each subtask.
+    pub(crate) fn get_subtasks(&self) -> Vec<CasesGenSubtask> {
+        self.subtasks.clone()
+    }
+
     /// Write an auto-generated version of the gen/GEN file inside the task directory.
     pub(crate) fn write_gen_gen(&self) -> Result<(), Error> {
         let dest = self.task_dir.join("gen/GEN");
@@ -233,6 +375,15 @@ where
                         let _ = writeln!(gen, "\n# Subtask {}", subtask.id);
                     }
                     let _ = writeln!(gen, "#ST: {}", subtask.max_score);
+                    if !subtask.included_testcases.is_empty() {
+                        let included = subtask
+                            .included_testcases
+                            .iter()
+                            .map(|id| id.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        let _ = writeln!(gen, "# Included testcases: {}", included);
+                    }
                     if let Some(constraints) = self.subtask_constraints.get(subtask.id as usize) {
                         for constr in constraints {
                             let _ = writeln!(gen, "# {:?}", constr);
@@ -240,7 +391,11 @@ where
                     }
                 }
                 TaskInputEntry::Testcase(testcase) => {
-                    let _ = writeln!(gen, "# Testcase {}", testcase.id);
+                    if let Some(binding) = self.loop_bindings.get(&testcase.id) {
+                        let _ = writeln!(gen, "# Testcase {} ({})", testcase.id, binding);
+                    } else {
+                        let _ = writeln!(gen, "# Testcase {}", testcase.id);
+                    }
                     match &testcase.input_generator {
                         InputGenerator::StaticFile(path) => {
                             if let Ok(path) = path.strip_prefix(&self.task_dir) {
@@ -252,6 +407,17 @@ where
                         InputGenerator::Custom(source, args) => {
                             let _ = writeln!(gen, "{} {}", source.name(), args.join(" "));
                         }
+                        // The gen/GEN format cannot express a generator producing many testcases.
+                        InputGenerator::MultiCustom(generator, index) => {
+                            let _ = writeln!(
+                                gen,
+                                "# MULTIGEN: {} {} {} (file {})",
+                                generator.source.name(),
+                                generator.count,
+                                generator.args.join(" "),
+                                index
+                            );
+                        }
                     }
                 }
             }
@@ -260,6 +426,114 @@ where
         Ok(())
     }
 
+    /// Parse a sequence of lines of the file, expanding the `:FOR` loops.
+    fn parse_lines(&mut self, lines: &[Pair]) -> Result<(), Error> {
+        let mut index = 0;
+        while index < lines.len() {
+            self.current_line = lines[index].as_span().start();
+            let line = lines[index]
+                .clone()
+                .into_inner()
+                .next()
+                .ok_or_else(|| anyhow!("Corrupted parser"))?;
+            match line.as_rule() {
+                parser::Rule::command => {
+                    let command = line
+                        .into_inner()
+                        .next()
+                        .ok_or_else(|| anyhow!("Corrupted parser"))?;
+                    match command.as_rule() {
+                        parser::Rule::FOR => {
+                            let end = find_loop_end(lines, index)?;
+                            self.parse_for(command, &lines[index + 1..end])
+                                .context("Failed to parse FOR command")?;
+                            index = end;
+                        }
+                        parser::Rule::END => bail!("Found END without a matching FOR"),
+                        parser::Rule::COPY | parser::Rule::RUN | parser::Rule::MULTIGEN => {
+                            self.parse_command(command)?
+                        }
+                        _ if !self.loop_variables.is_empty() => {
+                            bail!(
+                                "Only testcases, RUN, MULTIGEN and COPY can be inside a FOR loop, \
+                                 not '{}'",
+                                command.as_str().trim()
+                            );
+                        }
+                        _ => self.parse_command(command)?,
+                    }
+                }
+                parser::Rule::testcase => {
+                    self.parse_testcase(line.as_str(), self.current_generator.clone())?;
+                }
+                parser::Rule::comment => {}
+                parser::Rule::empty => {}
+                _ => unreachable!(),
+            }
+            index += 1;
+        }
+        Ok(())
+    }
+
+    /// Parse a `:FOR` command, parsing the lines of its body once for each value of the loop
+    /// variable.
+    fn parse_for(&mut self, line: Pair, body: &[Pair]) -> Result<(), Error> {
+        let line: Vec<_> = line.into_inner().collect();
+        let variable = line[0].as_str();
+        if self.loop_variables.iter().any(|(name, _)| name == variable) {
+            bail!(
+                "The variable '{}' is already used by an outer loop",
+                variable
+            );
+        }
+        let values: Vec<String> = match line[1].as_rule() {
+            parser::Rule::range => {
+                let bounds = line[1]
+                    .clone()
+                    .into_inner()
+                    .map(|bound| {
+                        i64::from_str(bound.as_str())
+                            .with_context(|| format!("Invalid loop bound '{}'", bound.as_str()))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let (start, end) = (bounds[0], bounds[1]);
+                if start > end {
+                    bail!("Empty loop range {}..{}", start, end);
+                }
+                match end.checked_sub(start) {
+                    Some(len) if len < MAX_LOOP_ITERATIONS => {}
+                    _ => bail!(
+                        "The loop range {}..{} has more than {} values",
+                        start,
+                        end,
+                        MAX_LOOP_ITERATIONS
+                    ),
+                }
+                (start..=end).map(|value| value.to_string()).collect()
+            }
+            parser::Rule::loop_values => line[1]
+                .clone()
+                .into_inner()
+                .map(|value| value.as_str().to_string())
+                .collect(),
+            _ => unreachable!(),
+        };
+        self.loop_iterations += values.len() as i64;
+        if self.loop_iterations > MAX_LOOP_ITERATIONS {
+            bail!(
+                "The loops have more than {} iterations in total",
+                MAX_LOOP_ITERATIONS
+            );
+        }
+        for value in values {
+            self.loop_variables.push((variable.to_string(), value));
+            let result = self.parse_lines(body);
+            self.loop_variables.pop();
+            result?;
+        }
+        Ok(())
+    }
+
     /// Parse a line with a command: one of the `:` prefixed actions.
     fn parse_command(&mut self, line: Pair) -> Result<(), Error> {
         match line.as_rule() {
@@ -287,6 +561,15 @@ where
                 self.parse_run(line)
                     .context("Failed to parse RUN command")?;
             }
+            parser::Rule::MULTIGEN => {
+                self.parse_multigen(line)
+                    .context("Failed to parse MULTIGEN command")?;
+            }
+            parser::Rule::INCLUDE => {
+                self.parse_include(line)
+                    .context("Failed to parse INCLUDE command")?;
+            }
+            // The loops are handled while parsing the lines, since they span multiple lines.
             _ => unreachable!(),
         }
         Ok(())
@@ -306,6 +589,8 @@ where
         } else {
             bail!("Cannot generate testcase: no default generator set");
         };
+        self.check_testcase_count(1)?;
+        let line = &self.substitute_loop_variables(line);
         let args = shell_words::split(line)
             .with_context(|| format!("Invalid command arguments for testcase '{}'", line))?;
         let generator = &self
@@ -313,23 +598,18 @@ where
             .get(&current_generator)
             .context("invalid current generator")?;
         let variables = self.get_variables(&generator.args, &args);
-        for constr in self
-            .constraints
-            .iter()
-            .chain(self.subtask_constraints.last().unwrap())
-        {
-            if let Some(false) = constr.is_valid(&variables) {
-                let mut error = format!(
-                    "Testcase '{}' violates constraint {:?}\nWith:",
-                    line, constr
-                );
-                for (var, val) in &variables {
-                    let _ = write!(error, "\n  ${} = {}", var, val);
-                }
-                bail!("{}", error)
-            }
-        }
+        check_constraints(
+            self.constraints
+                .iter()
+                .chain(self.subtask_constraints.last().unwrap()),
+            line,
+            &variables,
+        )?;
         let generator = InputGenerator::Custom(generator.source.clone(), args);
+        self.subtask_variables.push(variables.clone());
+        self.testcase_variables
+            .insert(self.testcase_id, variables.clone());
+        self.bind_loop_variables();
         self.result.push(TaskInputEntry::Testcase(TestcaseInfo::new(
             self.testcase_id,
             generator,
@@ -443,30 +723,20 @@ where
 
     /// Parse a `:CONSTRAINT` command.
     fn parse_constraint(&mut self, line: Pair) -> Result<(), Error> {
-        let line_str = line.as_str().to_string();
+        let line_str = line.as_str().trim_end().to_string();
+        let mut constraint = Constraint {
+            span: self.code_span(line.as_span()),
+            ..Default::default()
+        };
         let line: Vec<_> = line.into_inner().collect();
-        let mut constraint = Constraint::default();
         let mut direction = None;
         for item in line {
             match item.as_rule() {
-                parser::Rule::number => {
-                    constraint.operands.push(ConstraintOperand::Constant(
-                        i64::from_str(item.as_str()).with_context(|| {
-                            format!(
-                                "Invalid integer constant '{}' in constraint '{}'",
-                                item.as_str(),
-                                line_str,
-                            )
-                        })?,
-                    ));
-                }
-                parser::Rule::variable => {
-                    constraint.operands.push(ConstraintOperand::Variable(
-                        item.as_str()
-                            .strip_prefix('$')
-                            .expect("Invalid parser")
-                            .into(),
-                    ));
+                parser::Rule::expression => {
+                    let operand = self
+                        .parse_operand(item)
+                        .with_context(|| format!("Invalid constraint '{}'", line_str))?;
+                    constraint.operands.push(operand);
                 }
                 parser::Rule::comp_operator => {
                     let operator = ConstraintOperator::from_str(item.as_str())
@@ -507,8 +777,88 @@ where
         Ok(())
     }
 
+    /// Parse an arithmetic expression of a constraint, or one of its parts.
+    fn parse_operand(&self, item: Pair) -> Result<ConstraintOperand, Error> {
+        match item.as_rule() {
+            parser::Rule::expression | parser::Rule::product | parser::Rule::power => {
+                let mut items = item.into_inner();
+                let first = items.next().context("Corrupted parser")?;
+                let mut operand = self.parse_operand(first)?;
+                // The operators are left-associative, except for the power which has a single
+                // operator whose right operand already contains the following ones.
+                while let Some(operator) = items.next() {
+                    let span = self.code_span(operator.as_span());
+                    let operator = ArithmeticOperator::from_str(operator.as_str())?;
+                    let rhs = self.parse_operand(items.next().context("Corrupted parser")?)?;
+                    operand =
+                        ConstraintOperand::Binary(operator, Box::new(operand), Box::new(rhs), span);
+                }
+                Ok(operand)
+            }
+            parser::Rule::unary => {
+                let items: Vec<_> = item.into_inner().collect();
+                let (operand, operators) = items.split_last().context("Corrupted parser")?;
+                let mut operand = self.parse_operand(operand.clone())?;
+                for operator in operators.iter().rev() {
+                    if operator.as_str() == "-" {
+                        operand = ConstraintOperand::Negate(Box::new(operand));
+                    }
+                }
+                Ok(operand)
+            }
+            parser::Rule::function => {
+                let mut items = item.into_inner();
+                let function = match items.next().context("Corrupted parser")?.as_str() {
+                    "min" => ConstraintFunction::Min,
+                    "max" => ConstraintFunction::Max,
+                    name => bail!("Unknown function '{}'", name),
+                };
+                let args = items
+                    .map(|arg| self.parse_operand(arg))
+                    .collect::<Result<_, _>>()?;
+                Ok(ConstraintOperand::Function(function, args))
+            }
+            parser::Rule::constant => {
+                let constant = item.as_str();
+                let value = if constant.contains(|c| matches!(c, '.' | 'e' | 'E')) {
+                    f64::from_str(constant)
+                        .ok()
+                        .filter(|value| value.is_finite())
+                        .map(ConstraintValue::Float)
+                        .ok_or_else(|| anyhow!("Invalid float constant '{}'", constant))
+                } else {
+                    i64::from_str(constant)
+                        .map(ConstraintValue::Integer)
+                        .map_err(|_| anyhow!("Invalid integer constant '{}'", constant))
+                };
+                value
+                    .map(ConstraintOperand::Constant)
+                    .map_err(|err| with_code_span(err, self.code_span(item.as_span()).as_ref()))
+            }
+            parser::Rule::variable => Ok(ConstraintOperand::Variable(
+                item.as_str()
+                    .strip_prefix('$')
+                    .expect("Invalid parser")
+                    .into(),
+            )),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Make the `CodeSpan` of a part of the `cases.gen` file.
+    fn code_span(&self, span: pest::Span) -> Option<CodeSpan> {
+        CodeSpan::from_str(
+            &self.file_path,
+            &self.file_content,
+            span.start(),
+            span.end() - span.start(),
+        )
+        .ok()
+    }
+
     /// Parse a `:SUBTASK` command.
     fn parse_subtask(&mut self, line: Pair) -> Result<(), Error> {
+        self.end_subtask();
         let span = line.as_span();
         let line: Vec<_> = line.into_inner().collect();
         self.current_generator = self.default_generator.clone();
@@ -542,28 +892,75 @@ where
             )
             .ok(),
             is_default: false,
+            constraint_coverage: vec![],
+            included_testcases: vec![],
         }));
         self.subtask_id += 1;
         Ok(())
     }
 
+    /// Store the state of the current subtask, if any, since it has been completely parsed.
+    fn end_subtask(&mut self) {
+        if self.subtask_id == 0 {
+            return;
+        }
+        let subtask = self.result.iter_mut().rev().find_map(|entry| match entry {
+            TaskInputEntry::Subtask(subtask) => Some(subtask),
+            _ => None,
+        });
+        let mut variables = std::mem::take(&mut self.subtask_variables);
+        if let Some(subtask) = subtask {
+            // The included testcases count towards the coverage of this subtask as well.
+            variables.extend(
+                subtask
+                    .included_testcases
+                    .iter()
+                    .filter_map(|id| self.testcase_variables.get(id).cloned()),
+            );
+            subtask.constraint_coverage = constraint_coverage(
+                self.constraints
+                    .iter()
+                    .chain(self.subtask_constraints.last().unwrap()),
+                &variables,
+            );
+        }
+        self.subtasks.push(CasesGenSubtask {
+            id: self.subtask_id - 1,
+            description: self.subtask_description.clone(),
+            generators: self.generators.clone(),
+            current_generator: self.current_generator.clone(),
+            validator: self
+                .current_validator
+                .as_ref()
+                .map(|val| self.validators[val].clone()),
+            constraints: self
+                .constraints
+                .iter()
+                .chain(self.subtask_constraints.last().unwrap())
+                .cloned()
+                .collect(),
+        });
+    }
+
     /// Parse a `:COPY` command.
     fn parse_copy(&mut self, line: Pair) -> Result<(), Error> {
         if self.subtask_id == 0 {
             bail!("Cannot add a COPY testcase outside a subtask");
         }
+        self.check_testcase_count(1)?;
         let path = line
             .into_inner()
             .next()
             .context("corrupted parser")?
             .as_str();
-        let path = self.task_dir.join(path);
+        let path = self.task_dir.join(self.substitute_loop_variables(path));
         if !path.exists() {
             bail!(
                 "Cannot copy testcase from '{}': file not found",
                 path.display()
             );
         }
+        self.bind_loop_variables();
         self.result.push(TaskInputEntry::Testcase(TestcaseInfo::new(
             self.testcase_id,
             InputGenerator::StaticFile(path),
@@ -575,35 +972,73 @@ where
         Ok(())
     }
 
-    /// Get the current validator for the next testcase.
-    fn get_validator(&self, variables: &HashMap<String, String>) -> Result<InputValidator, Error> {
-        match &self.current_validator {
-            Some(val) => {
-                let validator = &self.validators[val];
-                let args = if validator.args.is_empty() {
-                    vec![variables["INPUT"].clone(), variables["ST_NUM"].clone()]
-                } else {
-                    let mut args = Vec::new();
-                    for arg in &validator.args {
-                        // variables may (and should!) start with `$`, remove it before accessing
-                        // the `variables` map.
-                        let arg = if let Some(rest) = arg.strip_prefix('$') {
-                            rest
-                        } else {
-                            arg.as_str()
-                        };
-                        if let Some(value) = variables.get(arg) {
-                            args.push(value.clone());
-                        } else {
-                            bail!("Unknown variable in validator arguments: ${}", arg);
-                        }
+    /// Parse an `:INCLUDE` command, adding to the current subtask the testcases of the previous
+    /// subtasks whose number or description matches the pattern.
+    fn parse_include(&mut self, line: Pair) -> Result<(), Error> {
+        if self.subtask_id == 0 {
+            bail!("Cannot include testcases outside a subtask");
+        }
+        let pattern = line
+            .into_inner()
+            .next()
+            .context("corrupted parser")?
+            .as_str()
+            .trim();
+        let wildmatch = WildMatch::new(pattern);
+        let current = self.subtask_id - 1;
+        let mut included = BTreeSet::new();
+        let mut matched = false;
+        let mut owner_matches = false;
+        for entry in &self.result {
+            match entry {
+                TaskInputEntry::Subtask(subtask) => {
+                    owner_matches = subtask.id != current
+                        && (wildmatch.matches(&subtask.id.to_string())
+                            || subtask
+                                .description
+                                .as_deref()
+                                .map(|descr| wildmatch.matches(descr))
+                                .unwrap_or(false));
+                    matched |= owner_matches;
+                    // The testcases included by the matched subtask are included as well.
+                    if owner_matches {
+                        included.extend(subtask.included_testcases.iter().copied());
                     }
-                    args
-                };
-                Ok(InputValidator::Custom(validator.source.clone(), args))
+                }
+                TaskInputEntry::Testcase(testcase) => {
+                    if owner_matches {
+                        included.insert(testcase.id);
+                    }
+                }
             }
-            None => Ok(InputValidator::AssumeValid),
         }
+        if !matched {
+            bail!("No previous subtask matches '{}'", pattern);
+        }
+        let subtask = self
+            .result
+            .iter_mut()
+            .rev()
+            .find_map(|entry| match entry {
+                TaskInputEntry::Subtask(subtask) => Some(subtask),
+                _ => None,
+            })
+            .context("corrupted parser state")?;
+        included.extend(subtask.included_testcases.iter().copied());
+        // A subtask cannot include its own testcases.
+        included.retain(|id| !subtask.testcases.contains_key(id));
+        subtask.included_testcases = included.into_iter().collect();
+        Ok(())
+    }
+
+    /// Get the current validator for the next testcase.
+    fn get_validator(&self, variables: &HashMap<String, String>) -> Result<InputValidator, Error> {
+        make_validator(
+            self.current_validator
+                .as_ref()
+                .map(|val| &self.validators[val]),
+            variables,
+        )
     }
 
     /// Parse a `:RUN` command.
@@ -621,6 +1056,93 @@ where
         Ok(())
     }
 
+    /// Parse a `:MULTIGEN` command, adding a testcase for each of the files produced by a single
+    /// execution of the generator.
+    fn parse_multigen(&mut self, line: Pair) -> Result<(), Error> {
+        if self.subtask_id == 0 {
+            bail!("Cannot add a testcase outside a subtask");
+        }
+        let line: Vec<_> = line.into_inner().collect();
+        let name = line[0].as_str();
+        let count = self.substitute_loop_variables(line[1].as_str());
+        let count = usize::from_str(&count)
+            .with_context(|| format!("Invalid number of testcases '{}'", count))?;
+        if count == 0 {
+            bail!("MULTIGEN must generate at least one testcase");
+        }
+        self.check_testcase_count(count)?;
+        let args = match line.get(2) {
+            Some(args) => self.substitute_loop_variables(args.as_str()),
+            None => String::new(),
+        };
+        let args = shell_words::split(&args)
+            .with_context(|| format!("Invalid command arguments for MULTIGEN '{}'", args))?;
+        let generator = self
+            .generators
+            .get(name)
+            .with_context(|| format!("Generator '{}' not declared", name))?;
+        let variables = self.get_variables(&generator.args, &args);
+        check_constraints(
+            self.constraints
+                .iter()
+                .chain(self.subtask_constraints.last().unwrap()),
+            &args.join(" "),
+            &variables,
+        )?;
+        let definition = generator.args.clone();
+        let generator = Arc::new(MultiInputGenerator {
+            first_testcase: self.testcase_id,
+            source: generator.source.clone(),
+            args,
+            count,
+        });
+        for index in 0..count {
+            // The automatic variables, like $TC_NUM, change for each testcase.
+            let variables = self.get_variables(&definition, &generator.args);
+            self.subtask_variables.push(variables.clone());
+            self.testcase_variables
+                .insert(self.testcase_id, variables.clone());
+            self.bind_loop_variables();
+            self.result.push(TaskInputEntry::Testcase(TestcaseInfo::new(
+                self.testcase_id,
+                InputGenerator::MultiCustom(generator.clone(), index),
+                self.get_validator(&variables)
+                    .context("Cannot get testcase validator")?,
+                (self.get_output_gen)(self.testcase_id),
+            )));
+            self.testcase_id += 1;
+        }
+        Ok(())
+    }
+
+    /// Make sure that `count` more testcases don't exceed the maximum number of testcases.
+    fn check_testcase_count(&self, count: usize) -> Result<(), Error> {
+        if (self.testcase_id as usize).saturating_add(count) > MAX_TESTCASES {
+            bail!("The file generates more than {} testcases", MAX_TESTCASES);
+        }
+        Ok(())
+    }
+
+    /// Replace the loop variables in the line with their current value. The `$$` escapes are
+    /// replaced also outside the loops, so that they mean the same everywhere.
+    fn substitute_loop_variables(&self, line: &str) -> String {
+        substitute_variables(line, &self.loop_variables)
+    }
+
+    /// Remember the values of the loop variables for the current testcase.
+    fn bind_loop_variables(&mut self) {
+        if self.loop_variables.is_empty() {
+            return;
+        }
+        let binding = self
+            .loop_variables
+            .iter()
+            .map(|(name, value)| format!("${} = {}", name, value))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.loop_bindings.insert(self.testcase_id, binding);
+    }
+
     /// Compute the list of all the variables accessible for the current testcase, including the
     /// automatic ones and the ones extracted from the command line arguments of the generator.
     fn get_variables(&self, definition: &[String], args: &[String]) -> HashMap<String, String> {
@@ -633,17 +1155,411 @@ where
 
     /// Obtain the automatic variables for the current testcase.
     fn get_auto_variables(&self) -> HashMap<String, String> {
-        let mut vars = HashMap::new();
-        vars.insert("INPUT".to_string(), TM_VALIDATION_FILE_NAME.to_string());
-        vars.insert("ST_NUM".to_string(), (self.subtask_id - 1).to_string());
-        vars.insert("TC_NUM".to_string(), self.testcase_id.to_string());
-        if let Some(descr) = &self.subtask_description {
-            vars.insert("ST_DESCRIPTION".to_string(), descr.clone());
+        auto_variables(
+            self.subtask_id - 1,
+            self.testcase_id,
+            self.subtask_description.as_deref(),
+        )
+    }
+}
+
+impl CasesGenSubtask {
+    /// The names of the generators that can be used in this subtask.
+    pub fn generator_names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.generators.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Check that the arguments for the generator with the provided name (or the one of the
+    /// subtask, if not specified) don't violate the constraints of the subtask.
+    pub fn check_constraints(&self, generator: Option<&str>, args: &[String]) -> Result<(), Error> {
+        let generator = self.generator(generator)?;
+        let variables = self.variables(generator, args, 0);
+        check_constraints(&self.constraints, &args.join(" "), &variables)
+    }
+
+    /// Make the generator and the validator of a new testcase of this subtask, with the provided
+    /// arguments for the generator with the provided name (or the one of the subtask, if not
+    /// specified). Fails if the arguments violate the constraints of the subtask.
+    pub fn testcase(
+        &self,
+        generator: Option<&str>,
+        args: Vec<String>,
+        testcase_id: TestcaseId,
+    ) -> Result<(InputGenerator, InputValidator), Error> {
+        let generator = self.generator(generator)?;
+        let variables = self.variables(generator, &args, testcase_id);
+        check_constraints(&self.constraints, &args.join(" "), &variables)?;
+        let validator = make_validator(self.validator.as_ref(), &variables)
+            .context("Cannot get testcase validator")?;
+        Ok((
+            InputGenerator::Custom(generator.source.clone(), args),
+            validator,
+        ))
+    }
+
+    /// Find the generator with the provided name, or the one of the subtask.
+    fn generator(&self, name: Option<&str>) -> Result<&Manager, Error> {
+        let name = match name.or(self.current_generator.as_deref()) {
+            Some(name) => name,
+            None => bail!("No default generator set for subtask {}", self.id),
+        };
+        self.generators.get(name).ok_or_else(|| {
+            anyhow!(
+                "Unknown generator '{}', the available ones are: {}",
+                name,
+                self.generator_names().join(", ")
+            )
+        })
+    }
+
+    /// Compute the variables of a testcase generated by the generator with the provided arguments.
+    fn variables(
+        &self,
+        generator: &Manager,
+        args: &[String],
+        testcase_id: TestcaseId,
+    ) -> HashMap<String, String> {
+        let mut vars = auto_variables(self.id, testcase_id, self.description.as_deref());
+        for (var, val) in generator.args.iter().zip(args.iter()) {
+            vars.insert(var.clone(), val.clone());
         }
         vars
     }
 }
 
+impl CasesGenOutline {
+    /// Analyze the content of the `cases.gen` file at the provided path. The content may differ
+    /// from the one on disk, for example when it is being edited.
+    pub fn new(path: &Path, content: &str) -> CasesGenOutline {
+        let mut outline = CasesGenOutline::default();
+        let file = match parser::CasesGenParser::parse(parser::Rule::file, content) {
+            Ok(mut file) => file.next(),
+            Err(err) => {
+                let offset = match err.location {
+                    pest::error::InputLocation::Pos(pos) => pos,
+                    pest::error::InputLocation::Span((start, _)) => start,
+                };
+                let mut diagnostic =
+                    Diagnostic::error(format!("Invalid syntax: {}", err.variant.message()));
+                if let Ok(span) = CodeSpan::from_line(path, content, offset) {
+                    diagnostic = diagnostic.with_code_span(span);
+                }
+                outline.diagnostics.push(diagnostic);
+                return outline;
+            }
+        };
+        let commands = file
+            .into_iter()
+            .flat_map(|file| file.into_inner())
+            .filter_map(|line| line.into_inner().next())
+            .filter(|line| line.as_rule() == parser::Rule::command)
+            .filter_map(|command| command.into_inner().next());
+        for command in commands {
+            outline.add_command(path, content, command);
+        }
+
+        let mut cases =
+            match CasesGen::empty(path, content.to_string(), |_| OutputGenerator::NotAvailable) {
+                Ok(cases) => cases,
+                Err(_) => return outline,
+            };
+        if let Err(err) = cases.parse() {
+            let mut diagnostic = Diagnostic::error(format!("{:#}", err));
+            if let Ok(span) = CodeSpan::from_line(path, content, cases.current_line) {
+                diagnostic = diagnostic.with_code_span(span);
+            }
+            outline.diagnostics.push(diagnostic);
+        }
+        outline
+    }
+
+    /// The names of all the variables that can be used in the file, without the dollar sign.
+    pub fn variables(&self) -> Vec<String> {
+        // the arguments of the generators are the names of the variables, the ones of the
+        // validators are references to them
+        let manager_variables = self.managers.iter().flat_map(|manager| {
+            manager
+                .args
+                .iter()
+                .filter_map(move |arg| match manager.kind {
+                    CasesGenManagerKind::Generator => Some(arg.as_str()),
+                    CasesGenManagerKind::Validator => arg.strip_prefix('$'),
+                })
+        });
+        let mut variables: Vec<_> = auto_variables(0, 0, Some(""))
+            .into_keys()
+            .chain(manager_variables.map(String::from))
+            .chain(self.loop_variables.iter().cloned())
+            .collect();
+        variables.sort();
+        variables.dedup();
+        variables
+    }
+
+    /// The names of the declared managers of the provided kind.
+    pub fn manager_names(&self, kind: CasesGenManagerKind) -> Vec<&str> {
+        self.managers
+            .iter()
+            .filter(|manager| manager.kind == kind)
+            .map(|manager| manager.name.as_str())
+            .collect()
+    }
+
+    /// Find the manager whose name, either in its declaration or in a usage, contains the byte at
+    /// the provided offset of the file.
+    pub fn manager_at(&self, offset: usize) -> Option<&CasesGenManager> {
+        let contains = |span: &CodeSpan| {
+            span.file_offset() <= offset && offset <= span.file_offset() + span.len()
+        };
+        if let Some(manager) = self.managers.iter().find(|manager| contains(&manager.span)) {
+            return Some(manager);
+        }
+        let reference = self
+            .references
+            .iter()
+            .find(|reference| contains(&reference.span))?;
+        self.managers
+            .iter()
+            .find(|manager| manager.kind == reference.kind && manager.name == reference.name)
+    }
+
+    /// Add to the outline the declarations and the usages of a command.
+    fn add_command(&mut self, path: &Path, content: &str, command: Pair) {
+        let kind = match command.as_rule() {
+            parser::Rule::GEN | parser::Rule::RUN | parser::Rule::MULTIGEN => {
+                CasesGenManagerKind::Generator
+            }
+            parser::Rule::VAL => CasesGenManagerKind::Validator,
+            parser::Rule::FOR => {
+                if let Some(variable) = command.into_inner().next() {
+                    let variable = variable.as_str().to_string();
+                    if !self.loop_variables.contains(&variable) {
+                        self.loop_variables.push(variable);
+                    }
+                }
+                return;
+            }
+            _ => return,
+        };
+        let is_declaration = matches!(command.as_rule(), parser::Rule::GEN | parser::Rule::VAL);
+        let items: Vec<_> = command.into_inner().collect();
+        let name = match items.first() {
+            Some(name) => name,
+            None => return,
+        };
+        let span =
+            match CodeSpan::from_str(path, content, name.as_span().start(), name.as_str().len()) {
+                Ok(span) => span,
+                Err(_) => return,
+            };
+        let name = name.as_str().to_string();
+        match items.get(1) {
+            Some(manager_path) if is_declaration => {
+                let args = items
+                    .get(2)
+                    .map(|args| {
+                        args.clone()
+                            .into_inner()
+                            .map(|arg| arg.as_str().to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                self.managers.push(CasesGenManager {
+                    kind,
+                    name,
+                    path: manager_path.as_str().to_string(),
+                    args,
+                    span,
+                });
+            }
+            _ => self.references.push(CasesGenReference { kind, name, span }),
+        }
+    }
+}
+
+/// Find the index of the `:END` line that closes the `:FOR` loop at the provided index.
+fn find_loop_end(lines: &[Pair], start: usize) -> Result<usize, Error> {
+    let mut depth = 0;
+    for (index, line) in lines.iter().enumerate().skip(start) {
+        let command = line
+            .clone()
+            .into_inner()
+            .next()
+            .filter(|line| line.as_rule() == parser::Rule::command)
+            .and_then(|command| command.into_inner().next());
+        match command.map(|command| command.as_rule()) {
+            Some(parser::Rule::FOR) => depth += 1,
+            Some(parser::Rule::END) => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(index);
+                }
+            }
+            _ => {}
+        }
+    }
+    bail!("Missing END for the FOR loop")
+}
+
+/// Replace the `$name` occurrences of the provided variables in the line with their value. The
+/// innermost variables (the last ones) take precedence, the unknown variables are left untouched.
+/// A `$$` is replaced with a single `$`, so that `$$name` becomes a literal `$name`.
+#[allow(clippy::string_slice)] // The indexes come from char-aligned functions.
+fn substitute_variables(line: &str, variables: &[(String, String)]) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(position) = rest.find('$') {
+        result.push_str(&rest[..position]);
+        let after = &rest[position + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            result.push('$');
+            rest = after;
+            continue;
+        }
+        let name_len = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        let name = &after[..name_len];
+        match variables.iter().rev().find(|(var, _)| var == name) {
+            Some((_, value)) => result.push_str(value),
+            None => {
+                result.push('$');
+                result.push_str(name);
+            }
+        }
+        rest = &after[name_len..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Obtain the automatic variables for a testcase of a subtask.
+fn auto_variables(
+    subtask_id: SubtaskId,
+    testcase_id: TestcaseId,
+    subtask_description: Option<&str>,
+) -> HashMap<String, String> {
+    let mut vars = HashMap::new();
+    vars.insert("INPUT".to_string(), TM_VALIDATION_FILE_NAME.to_string());
+    vars.insert("ST_NUM".to_string(), subtask_id.to_string());
+    vars.insert("TC_NUM".to_string(), testcase_id.to_string());
+    if let Some(descr) = subtask_description {
+        vars.insert("ST_DESCRIPTION".to_string(), descr.to_string());
+    }
+    vars
+}
+
+/// Check that the variables of the testcase defined by `line` don't violate any of the
+/// constraints.
+fn check_constraints<'a>(
+    constraints: impl IntoIterator<Item = &'a Constraint>,
+    line: &str,
+    variables: &HashMap<String, String>,
+) -> Result<(), Error> {
+    for constr in constraints {
+        let valid = constr.is_valid(variables).with_context(|| {
+            format!(
+                "Cannot check constraint {:?} for testcase '{}'",
+                constr, line
+            )
+        })?;
+        if let Some(false) = valid {
+            let mut error = format!(
+                "Testcase '{}' violates constraint {:?}\nWith:",
+                line, constr
+            );
+            for (var, val) in variables {
+                let _ = write!(error, "\n  ${} = {}", var, val);
+            }
+            if let Some(span) = &constr.span {
+                let _ = write!(error, "\n{}", span.to_string(DiagnosticLevel::Error));
+            }
+            bail!("{}", error)
+        }
+    }
+    Ok(())
+}
+
+/// Compute the bounds of the variables constrained by `constraints`, and the values they take in the
+/// testcases with the provided variables.
+fn constraint_coverage<'a>(
+    constraints: impl IntoIterator<Item = &'a Constraint>,
+    testcases: &[HashMap<String, String>],
+) -> Vec<ConstraintCoverage> {
+    // The tightest bounds of each variable among all the constraints.
+    let mut bounds: BTreeMap<String, (Option<i64>, Option<i64>)> = BTreeMap::new();
+    for constraint in constraints {
+        for (variable, lower, upper) in constraint.bounds() {
+            let (current_lower, current_upper) = bounds.entry(variable).or_default();
+            if let Some(lower) = lower {
+                *current_lower = Some(current_lower.map_or(lower, |l| l.max(lower)));
+            }
+            if let Some(upper) = upper {
+                *current_upper = Some(current_upper.map_or(upper, |u| u.min(upper)));
+            }
+        }
+    }
+    bounds
+        .into_iter()
+        .map(|(variable, (lower_bound, upper_bound))| {
+            let values = testcases
+                .iter()
+                .filter_map(|vars| i64::from_str(vars.get(&variable)?).ok())
+                .collect();
+            ConstraintCoverage {
+                variable,
+                lower_bound,
+                upper_bound,
+                values,
+            }
+        })
+        .collect()
+}
+
+/// Add to the error the location in the `cases.gen` file it refers to, if known.
+fn with_code_span(error: Error, span: Option<&CodeSpan>) -> Error {
+    match span {
+        Some(span) => anyhow!("{:#}\n{}", error, span.to_string(DiagnosticLevel::Error)),
+        None => error,
+    }
+}
+
+/// Make the validator of a testcase, replacing the variables in the arguments of the validator. If
+/// there is no validator the input file is assumed valid.
+fn make_validator(
+    validator: Option<&Manager>,
+    variables: &HashMap<String, String>,
+) -> Result<InputValidator, Error> {
+    match validator {
+        Some(validator) => {
+            let args = if validator.args.is_empty() {
+                vec![variables["INPUT"].clone(), variables["ST_NUM"].clone()]
+            } else {
+                let mut args = Vec::new();
+                for arg in &validator.args {
+                    // variables may (and should!) start with `$`, remove it before accessing
+                    // the `variables` map.
+                    let arg = if let Some(rest) = arg.strip_prefix('$') {
+                        rest
+                    } else {
+                        arg.as_str()
+                    };
+                    if let Some(value) = variables.get(arg) {
+                        args.push(value.clone());
+                    } else {
+                        bail!("Unknown variable in validator arguments: ${}", arg);
+                    }
+                }
+                args
+            };
+            Ok(InputValidator::Custom(validator.source.clone(), args))
+        }
+        None => Ok(InputValidator::AssumeValid),
+    }
+}
+
 /// Check if the gen/GEN file is deletable, i.e. it exists and it is autogenerated.
 pub(crate) fn is_gen_gen_deletable(path: &Path) -> Result<bool, Error> {
     if !path.exists() {
@@ -664,13 +1580,14 @@ pub(crate) fn is_gen_gen_deletable(path: &Path) -> Result<bool, Error> {
 
 impl ConstraintOperator {
     /// Apply the operator to the provided values and return the result of the comparison.
-    fn is_valid(&self, lhs: i64, rhs: i64) -> bool {
+    fn is_valid(&self, lhs: ConstraintValue, rhs: ConstraintValue) -> bool {
+        let ordering = lhs.compare(rhs);
         match self {
-            ConstraintOperator::Less => lhs < rhs,
-            ConstraintOperator::LessEqual => lhs <= rhs,
-            ConstraintOperator::Equal => lhs == rhs,
-            ConstraintOperator::Greater => lhs > rhs,
-            ConstraintOperator::GreaterEqual => lhs >= rhs,
+            ConstraintOperator::Less => ordering.is_lt(),
+            ConstraintOperator::LessEqual => ordering.is_le(),
+            ConstraintOperator::Equal => ordering.is_eq(),
+            ConstraintOperator::Greater => ordering.is_gt(),
+            ConstraintOperator::GreaterEqual => ordering.is_ge(),
         }
     }
 }
@@ -703,33 +1620,243 @@ impl ToString for ConstraintOperator {
     }
 }
 
+impl ConstraintValue {
+    /// The value as a float, possibly losing precision.
+    fn as_f64(self) -> f64 {
+        match self {
+            ConstraintValue::Integer(k) => k as f64,
+            ConstraintValue::Float(f) => f,
+        }
+    }
+
+    /// Compare two values. The integers are compared exactly, the other values as floats.
+    fn compare(self, other: ConstraintValue) -> Ordering {
+        match (self, other) {
+            (ConstraintValue::Integer(a), ConstraintValue::Integer(b)) => a.cmp(&b),
+            // The floats are always finite, so they are comparable.
+            _ => self.as_f64().total_cmp(&other.as_f64()),
+        }
+    }
+}
+
+impl FromStr for ConstraintValue {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(k) = i64::from_str(s) {
+            return Ok(ConstraintValue::Integer(k));
+        }
+        match f64::from_str(s) {
+            Ok(f) if f.is_finite() => Ok(ConstraintValue::Float(f)),
+            _ => bail!("Invalid number: {}", s),
+        }
+    }
+}
+
+impl Display for ConstraintValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConstraintValue::Integer(k) => write!(f, "{}", k),
+            ConstraintValue::Float(x) => write!(f, "{:?}", x),
+        }
+    }
+}
+
+impl ArithmeticOperator {
+    /// Apply the operator to the provided values. The operations between integers are exact, if
+    /// the result is not an integer (or it overflows) it is computed with floats.
+    fn apply(&self, lhs: ConstraintValue, rhs: ConstraintValue) -> Result<ConstraintValue, Error> {
+        if matches!(self, ArithmeticOperator::Div | ArithmeticOperator::Rem) && rhs.as_f64() == 0.0
+        {
+            bail!("Division by zero");
+        }
+        if let (ConstraintValue::Integer(a), ConstraintValue::Integer(b)) = (lhs, rhs) {
+            let result = match self {
+                ArithmeticOperator::Add => a.checked_add(b),
+                ArithmeticOperator::Sub => a.checked_sub(b),
+                ArithmeticOperator::Mul => a.checked_mul(b),
+                ArithmeticOperator::Div => match a.checked_rem(b) {
+                    Some(0) => a.checked_div(b),
+                    _ => None,
+                },
+                ArithmeticOperator::Rem => a.checked_rem(b),
+                ArithmeticOperator::Pow => u32::try_from(b).ok().and_then(|b| a.checked_pow(b)),
+            };
+            if let Some(result) = result {
+                return Ok(ConstraintValue::Integer(result));
+            }
+        }
+        let (a, b) = (lhs.as_f64(), rhs.as_f64());
+        let result = match self {
+            ArithmeticOperator::Add => a + b,
+            ArithmeticOperator::Sub => a - b,
+            ArithmeticOperator::Mul => a * b,
+            ArithmeticOperator::Div => a / b,
+            ArithmeticOperator::Rem => a % b,
+            ArithmeticOperator::Pow => a.powf(b),
+        };
+        if !result.is_finite() {
+            bail!(
+                "The result of {} {} {} is not a finite number",
+                lhs,
+                self.symbol(),
+                rhs
+            );
+        }
+        Ok(ConstraintValue::Float(result))
+    }
+
+    /// The symbol of the operator.
+    fn symbol(&self) -> &'static str {
+        match self {
+            ArithmeticOperator::Add => "+",
+            ArithmeticOperator::Sub => "-",
+            ArithmeticOperator::Mul => "*",
+            ArithmeticOperator::Div => "/",
+            ArithmeticOperator::Rem => "%",
+            ArithmeticOperator::Pow => "^",
+        }
+    }
+
+    /// The precedence of the operator, the higher the tighter it binds.
+    fn precedence(&self) -> u8 {
+        match self {
+            ArithmeticOperator::Add | ArithmeticOperator::Sub => 1,
+            ArithmeticOperator::Mul | ArithmeticOperator::Div | ArithmeticOperator::Rem => 2,
+            ArithmeticOperator::Pow => 4,
+        }
+    }
+}
+
+impl FromStr for ArithmeticOperator {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "+" => Ok(ArithmeticOperator::Add),
+            "-" => Ok(ArithmeticOperator::Sub),
+            "*" => Ok(ArithmeticOperator::Mul),
+            "/" => Ok(ArithmeticOperator::Div),
+            "%" => Ok(ArithmeticOperator::Rem),
+            "^" => Ok(ArithmeticOperator::Pow),
+            _ => bail!("Invalid operator: {}", s),
+        }
+    }
+}
+
 impl ConstraintOperand {
-    /// Return the value of this operand which is either the constant or the integer value of the
-    /// variable contained in it. If the variable is not present, or it's not a valid integer `None`
-    /// is returned.
-    fn get_val(&self, vars: &HashMap<String, String>) -> Option<i64> {
-        match self {
-            ConstraintOperand::Constant(k) => Some(*k),
+    /// Return the value of this operand, computed using the provided values of the variables. If a
+    /// variable is not present, or it's not a valid number, `None` is returned. Fails if an
+    /// operation is not valid, like a division by zero.
+    fn get_val(&self, vars: &HashMap<String, String>) -> Result<Option<ConstraintValue>, Error> {
+        let value = match self {
+            ConstraintOperand::Constant(k) => *k,
             ConstraintOperand::Variable(var) => {
-                if let Some(val) = vars.get(var) {
-                    if let Ok(val) = i64::from_str(val) {
-                        Some(val)
-                    } else {
-                        None
-                    }
-                } else {
-                    None
+                match vars
+                    .get(var)
+                    .and_then(|val| ConstraintValue::from_str(val).ok())
+                {
+                    Some(value) => value,
+                    None => return Ok(None),
+                }
+            }
+            ConstraintOperand::Negate(operand) => match operand.get_val(vars)? {
+                Some(ConstraintValue::Integer(k)) => match k.checked_neg() {
+                    Some(k) => ConstraintValue::Integer(k),
+                    None => ConstraintValue::Float(-(k as f64)),
+                },
+                Some(ConstraintValue::Float(f)) => ConstraintValue::Float(-f),
+                None => return Ok(None),
+            },
+            ConstraintOperand::Binary(operator, lhs, rhs, span) => {
+                let (Some(lhs), Some(rhs)) = (lhs.get_val(vars)?, rhs.get_val(vars)?) else {
+                    return Ok(None);
+                };
+                operator
+                    .apply(lhs, rhs)
+                    .map_err(|err| with_code_span(err, span.as_ref()))?
+            }
+            ConstraintOperand::Function(function, args) => {
+                let mut result: Option<ConstraintValue> = None;
+                for arg in args {
+                    let Some(value) = arg.get_val(vars)? else {
+                        return Ok(None);
+                    };
+                    result = Some(match result {
+                        Some(current) => {
+                            let ordering = value.compare(current);
+                            let replace = match function {
+                                ConstraintFunction::Min => ordering.is_lt(),
+                                ConstraintFunction::Max => ordering.is_gt(),
+                            };
+                            if replace {
+                                value
+                            } else {
+                                current
+                            }
+                        }
+                        None => value,
+                    });
                 }
+                result.context("Function without arguments")?
             }
+        };
+        Ok(Some(value))
+    }
+
+    /// The precedence of the outermost operation of this operand, the higher the tighter it binds.
+    fn precedence(&self) -> u8 {
+        match self {
+            ConstraintOperand::Binary(operator, _, _, _) => operator.precedence(),
+            ConstraintOperand::Negate(_) => 3,
+            _ => 5,
         }
     }
 }
 
-impl ToString for ConstraintOperand {
-    fn to_string(&self) -> String {
+impl Display for ConstraintOperand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // Write an operand, adding the parenthesis if it binds less than required.
+        let operand = |f: &mut Formatter<'_>, operand: &ConstraintOperand, precedence: u8| {
+            if operand.precedence() < precedence {
+                write!(f, "({})", operand)
+            } else {
+                write!(f, "{}", operand)
+            }
+        };
         match self {
-            ConstraintOperand::Constant(k) => k.to_string(),
-            ConstraintOperand::Variable(v) => format!("${}", v),
+            ConstraintOperand::Constant(k) => write!(f, "{}", k),
+            ConstraintOperand::Variable(v) => write!(f, "${}", v),
+            ConstraintOperand::Negate(inner) => {
+                write!(f, "-")?;
+                operand(f, inner, self.precedence())
+            }
+            ConstraintOperand::Binary(operator, lhs, rhs, _) => {
+                let precedence = operator.precedence();
+                // The power is right-associative, the other operators are left-associative.
+                let (lhs_precedence, rhs_precedence) = if *operator == ArithmeticOperator::Pow {
+                    (precedence + 1, precedence)
+                } else {
+                    (precedence, precedence + 1)
+                };
+                operand(f, lhs, lhs_precedence)?;
+                write!(f, " {} ", operator.symbol())?;
+                operand(f, rhs, rhs_precedence)
+            }
+            ConstraintOperand::Function(function, args) => {
+                let name = match function {
+                    ConstraintFunction::Min => "min",
+                    ConstraintFunction::Max => "max",
+                };
+                write!(f, "{}(", name)?;
+                for (index, arg) in args.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
         }
     }
 }
@@ -737,17 +1864,88 @@ impl ToString for ConstraintOperand {
 impl Constraint {
     /// Check if the variables verify this constraint, returning `Some(res)` if the check was
     /// successful (i.e. all the variables were present and valid). `None` is returned if this
-    /// constraint cannot be fully verified.
-    fn is_valid(&self, vars: &HashMap<String, String>) -> Option<bool> {
-        let mut last = self.operands[0].get_val(vars)?;
+    /// constraint cannot be fully verified. Fails if the expressions cannot be computed.
+    fn is_valid(&self, vars: &HashMap<String, String>) -> Result<Option<bool>, Error> {
+        let Some(mut last) = self.operands[0].get_val(vars)? else {
+            return Ok(None);
+        };
         for (operator, operand) in self.operators.iter().zip(&self.operands[1..]) {
-            let this = operand.get_val(vars)?;
+            let Some(this) = operand.get_val(vars)? else {
+                return Ok(None);
+            };
             if !operator.is_valid(last, this) {
-                return Some(false);
+                return Ok(Some(false));
             }
             last = this;
         }
-        Some(true)
+        Ok(Some(true))
+    }
+
+    /// The lower and upper bounds that this constraint gives to each of its variables. The bounds
+    /// come from the nearest constants in the chain of operators, and each strict operator in
+    /// between tightens them by one.
+    fn bounds(&self) -> Vec<(String, Option<i64>, Option<i64>)> {
+        let increasing = match self
+            .operators
+            .iter()
+            .find(|op| **op != ConstraintOperator::Equal)
+        {
+            Some(ConstraintOperator::Less | ConstraintOperator::LessEqual) => true,
+            Some(_) => false,
+            // All the variables are equal, there are no bounds to reach.
+            None => return vec![],
+        };
+        let strict_operators = |operators: &[ConstraintOperator]| {
+            operators
+                .iter()
+                .filter(|op| matches!(op, ConstraintOperator::Less | ConstraintOperator::Greater))
+                .count() as i64
+        };
+        // Only the operands without variables that evaluate to an integer are bounds. The operator
+        // at index i is between the operands at index i and i + 1.
+        let constant_at =
+            |index: usize, variable: usize| match self.operands[index].get_val(&HashMap::new()) {
+                Ok(Some(ConstraintValue::Integer(k))) => {
+                    let range = index.min(variable)..index.max(variable);
+                    Some((k, strict_operators(&self.operators[range])))
+                }
+                _ => None,
+            };
+
+        let mut bounds = vec![];
+        for (index, operand) in self.operands.iter().enumerate() {
+            let ConstraintOperand::Variable(variable) = operand else {
+                continue;
+            };
+            let before = (0..index)
+                .rev()
+                .find_map(|i| constant_at(i, index))
+                .map(|(k, strict)| {
+                    if increasing {
+                        k.saturating_add(strict)
+                    } else {
+                        k.saturating_sub(strict)
+                    }
+                });
+            let after = (index + 1..self.operands.len())
+                .find_map(|i| constant_at(i, index))
+                .map(|(k, strict)| {
+                    if increasing {
+                        k.saturating_sub(strict)
+                    } else {
+                        k.saturating_add(strict)
+                    }
+                });
+            let (lower, upper) = if increasing {
+                (before, after)
+            } else {
+                (after, before)
+            };
+            if lower.is_some() || upper.is_some() {
+                bounds.push((variable.clone(), lower, upper));
+            }
+        }
+        bounds
     }
 }
 
@@ -770,7 +1968,7 @@ mod tests {
     use tempfile::TempDir;
 
     use crate::ioi::format::italian_yaml::cases_gen::{
-        CasesGen, ConstraintOperand, ConstraintOperator,
+        CasesGen, ConstraintOperand, ConstraintOperator, ConstraintValue,
     };
     use crate::ioi::format::italian_yaml::TaskInputEntry;
     use crate::ioi::{
@@ -817,6 +2015,10 @@ mod tests {
             std::fs::write(&dest, content.as_ref()).unwrap();
             CasesGen::new(dest, |_| OutputGenerator::StaticFile("nope".into()))
         }
+
+        fn outline<S: AsRef<str>>(&self, content: S) -> CasesGenOutline {
+            CasesGenOutline::new(&self.0.path().join("gen/cases.gen"), content.as_ref())
+        }
     }
 
     #[test]
@@ -1128,7 +2330,7 @@ mod tests {
         assert_eq!(
             constr.operands,
             vec![
-                ConstraintOperand::Constant(1),
+                ConstraintOperand::Constant(ConstraintValue::Integer(1)),
                 ConstraintOperand::Variable("N".into()),
                 ConstraintOperand::Variable("K".into()),
                 ConstraintOperand::Variable("M".into())
@@ -1155,7 +2357,7 @@ mod tests {
             constr.operands,
             vec![
                 ConstraintOperand::Variable("K".into()),
-                ConstraintOperand::Constant(1),
+                ConstraintOperand::Constant(ConstraintValue::Integer(1)),
                 ConstraintOperand::Variable("N".into()),
                 ConstraintOperand::Variable("M".into())
             ]
@@ -1200,7 +2402,45 @@ mod tests {
 
     #[test]
     fn test_add_constraint_floats() {
-        let gen = TestHelper::new().cases_gen(":CONSTRAINT $N < 10.2");
+        let gen = TestHelper::new()
+            .cases_gen(":CONSTRAINT $N < 10.2 < 1e3")
+            .unwrap();
+        let constr = &gen.constraints[0];
+        assert_eq!(
+            constr.operands,
+            vec![
+                ConstraintOperand::Variable("N".into()),
+                ConstraintOperand::Constant(ConstraintValue::Float(10.2)),
+                ConstraintOperand::Constant(ConstraintValue::Float(1000.0))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_add_constraint_expressions() {
+        let gen = TestHelper::new()
+            .cases_gen(":CONSTRAINT -(1 + $N) * $M <= max($K, 10^9) / 2 - 2^-1 % 3")
+            .unwrap();
+        let constr = &gen.constraints[0];
+        assert_eq!(
+            format!("{:?}", constr),
+            "-(1 + $N) * $M <= max($K, 10 ^ 9) / 2 - 2 ^ (-1) % 3"
+        );
+        let gen = TestHelper::new()
+            .cases_gen(":CONSTRAINT (2 ^ 3) ^ 2 = 2 ^ 3 ^ 2 = 1 - (2 - 3) = min($A,$B)")
+            .unwrap();
+        let constr = &gen.constraints[0];
+        assert_eq!(
+            format!("{:?}", constr),
+            "(2 ^ 3) ^ 2 = 2 ^ 3 ^ 2 = 1 - (2 - 3) = min($A, $B)"
+        );
+    }
+
+    #[test]
+    fn test_add_constraint_invalid_expression() {
+        let gen = TestHelper::new().cases_gen(":CONSTRAINT $N * <= 10");
+        assert!(gen.is_err());
+        let gen = TestHelper::new().cases_gen(":CONSTRAINT min() <= 10");
         assert!(gen.is_err());
     }
 
@@ -1436,6 +2676,347 @@ mod tests {
         assert_that(&gen.unwrap_err()).has_error("outside a subtask");
     }
 
+    /**********************
+     * : FOR
+     *********************/
+
+    fn testcase_args<O: Fn(TestcaseId) -> OutputGenerator>(gen: &CasesGen<O>) -> Vec<String> {
+        gen.get_task_entries()
+            .into_iter()
+            .filter_map(|entry| match entry {
+                TaskInputEntry::Testcase(testcase) => match testcase.input_generator {
+                    InputGenerator::Custom(_, args) => Some(args.join(" ")),
+                    InputGenerator::StaticFile(path) => Some(path.display().to_string()),
+                    InputGenerator::MultiCustom(generator, index) => {
+                        Some(format!("{} #{}", generator.args.join(" "), index))
+                    }
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_for_range() {
+        let gen = TestHelper::new()
+            .add_file("gen/gen.py")
+            .cases_gen(":GEN gen gen/gen.py N S\n:SUBTASK 10\n: FOR seed IN 1..3\n10 $seed\n: END")
+            .unwrap();
+        assert_eq!(testcase_args(&gen), vec!["10 1", "10 2", "10 3"]);
+    }
+
+    #[test]
+    fn test_for_nested() {
+        let gen = TestHelper::new()
+            .add_file("gen/gen.py")
+            .cases_gen(
+                ":GEN gen gen/gen.py N S\n:SUBTASK 10\n:FOR N IN 10 1e3\n:FOR seed IN -1..0\n\
+                 $N $seed\n:RUN gen $N$seed 'x $seed'\n:END\n:END\n1 $seed",
+            )
+            .unwrap();
+        assert_eq!(
+            testcase_args(&gen),
+            vec![
+                "10 -1",
+                "10-1 x -1",
+                "10 0",
+                "100 x 0",
+                "1e3 -1",
+                "1e3-1 x -1",
+                "1e3 0",
+                "1e30 x 0",
+                "1 $seed"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_for_escape() {
+        let gen = TestHelper::new()
+            .add_file("gen/gen.py")
+            .cases_gen(
+                ":GEN gen gen/gen.py N S\n:SUBTASK 10\n:FOR i IN 1..2\n$i $$i $$$i $$$$ $x\n:END",
+            )
+            .unwrap();
+        assert_eq!(testcase_args(&gen), vec!["1 $i $1 $$ $x", "2 $i $2 $$ $x"]);
+
+        // the escape is the same outside the loops
+        let gen = TestHelper::new()
+            .add_file("gen/gen.py")
+            .cases_gen(":GEN gen gen/gen.py N S\n:SUBTASK 10\n$$i $$$$ $x\n:RUN gen $$i")
+            .unwrap();
+        assert_eq!(testcase_args(&gen), vec!["$i $$ $x", "$i"]);
+    }
+
+    #[test]
+    fn test_substitute_variables() {
+        let variables = vec![
+            ("a".to_string(), "outer".to_string()),
+            ("b".to_string(), "2".to_string()),
+            ("a".to_string(), "inner".to_string()),
+        ];
+        assert_eq!(substitute_variables("$a-$b", &variables), "inner-2");
+        assert_eq!(substitute_variables("$ab $c $", &variables), "$ab $c $");
+        assert_eq!(substitute_variables("$$a $$$b $$", &variables), "$a $2 $");
+        assert_eq!(substitute_variables("€$b€", &variables), "€2€");
+    }
+
+    #[test]
+    fn test_for_copy() {
+        let helper = TestHelper::new();
+        helper
+            .add_file("gen/gen.py")
+            .add_file("input1.txt")
+            .add_file("input2.txt");
+        let gen = helper
+            .cases_gen(":GEN gen gen/gen.py\n:SUBTASK 10\n:FOR i IN 1..2\n:COPY input$i.txt\n:END")
+            .unwrap();
+        let args = testcase_args(&gen);
+        assert_eq!(args.len(), 2);
+        assert!(args[0].ends_with("input1.txt"));
+        assert!(args[1].ends_with("input2.txt"));
+    }
+
+    #[test]
+    fn test_for_gen_gen() {
+        let gen = TestHelper::new()
+            .add_file("gen/gen.py")
+            .cases_gen(":GEN gen gen/gen.py\n:SUBTASK 10\n:FOR seed IN 1..2\n$seed\n:END\n3")
+            .unwrap();
+        gen.write_gen_gen().unwrap();
+        let data = std::fs::read_to_string(gen.task_dir.join("gen/GEN")).unwrap();
+        assert!(data.contains("# Testcase 0 ($seed = 1)\ngen.py 1\n"));
+        assert!(data.contains("# Testcase 1 ($seed = 2)\ngen.py 2\n"));
+        assert!(data.contains("# Testcase 2\ngen.py 3\n"));
+    }
+
+    #[test]
+    fn test_for_errors() {
+        let cases_gen = |content: &str| {
+            TestHelper::new()
+                .add_file("gen/gen.py")
+                .cases_gen(format!(":GEN gen gen/gen.py\n:SUBTASK 10\n{}", content))
+        };
+        assert_that(&cases_gen(":FOR i IN 1..2\n$i").unwrap_err()).has_error("Missing END");
+        assert_that(&cases_gen("1\n:END").unwrap_err()).has_error("without a matching FOR");
+        assert_that(&cases_gen(":FOR i IN 2..1\n$i\n:END").unwrap_err())
+            .has_error("Empty loop range");
+        assert_that(&cases_gen(":FOR i IN 1..1000000000\n$i\n:END").unwrap_err())
+            .has_error("more than");
+        // the nested loops are limited in total, not each one separately
+        assert_that(&cases_gen(":FOR i IN 1..1000\n:FOR j IN 1..1000\n:END\n:END").unwrap_err())
+            .has_error("iterations in total");
+        assert_that(&cases_gen(":MULTIGEN gen 1000000").unwrap_err())
+            .has_error("more than 100000 testcases");
+        assert_that(&cases_gen(":FOR i IN 1..2\n:SUBTASK 10\n:END").unwrap_err())
+            .has_error("can be inside a FOR loop");
+        assert_that(&cases_gen(":FOR i IN 1..2\n:FOR i IN 1..2\n$i\n:END\n:END").unwrap_err())
+            .has_error("already used by an outer loop");
+    }
+
+    #[test]
+    fn test_multigen() {
+        let gen = TestHelper::new()
+            .add_file("gen/gen.py")
+            .cases_gen(":GEN gen gen/gen.py N\n:SUBTASK 10\n1\n:MULTIGEN gen 3 42 'a b'\n2")
+            .unwrap();
+        assert_eq!(
+            testcase_args(&gen),
+            vec!["1", "42 a b #0", "42 a b #1", "42 a b #2", "2"]
+        );
+        let entries = gen.get_task_entries();
+        for (index, entry) in entries[2..5].iter().enumerate() {
+            match entry {
+                TaskInputEntry::Testcase(testcase) => match &testcase.input_generator {
+                    InputGenerator::MultiCustom(generator, file) => {
+                        assert_eq!(testcase.id, 1 + index as TestcaseId);
+                        assert_eq!(*file, index);
+                        assert_eq!(generator.first_testcase, 1);
+                        assert_eq!(generator.count, 3);
+                        assert_eq!(generator.source.name(), "gen.py");
+                    }
+                    generator => panic!("Expecting a multiple generator, got {:?}", generator),
+                },
+                entry => panic!("Expecting a testcase, got {:?}", entry),
+            }
+        }
+    }
+
+    #[test]
+    fn test_multigen_for() {
+        let gen = TestHelper::new()
+            .add_file("gen/gen.py")
+            .cases_gen(
+                ":GEN gen gen/gen.py\n:SUBTASK 10\n:FOR n IN 1..2\n:MULTIGEN gen $n $n\n:END",
+            )
+            .unwrap();
+        assert_eq!(testcase_args(&gen), vec!["1 #0", "2 #0", "2 #1"]);
+    }
+
+    #[test]
+    fn test_multigen_constraints() {
+        let gen = TestHelper::new().add_file("gen/gen.py").cases_gen(
+            ":GEN gen gen/gen.py N\n:CONSTRAINT $N < 10\n:SUBTASK 10\n:MULTIGEN gen 2 42",
+        );
+        assert_that(&gen.unwrap_err()).has_error("violates constraint");
+    }
+
+    #[test]
+    fn test_multigen_gen_gen() {
+        let gen = TestHelper::new()
+            .add_file("gen/gen.py")
+            .cases_gen(":GEN gen gen/gen.py\n:SUBTASK 10\n:MULTIGEN gen 2 42")
+            .unwrap();
+        gen.write_gen_gen().unwrap();
+        let data = std::fs::read_to_string(gen.task_dir.join("gen/GEN")).unwrap();
+        assert!(data.contains("# Testcase 0\n# MULTIGEN: gen.py 2 42 (file 0)\n"));
+        assert!(data.contains("# Testcase 1\n# MULTIGEN: gen.py 2 42 (file 1)\n"));
+    }
+
+    #[test]
+    fn test_multigen_errors() {
+        let cases_gen = |content: &str| {
+            TestHelper::new()
+                .add_file("gen/gen.py")
+                .cases_gen(format!(":GEN gen gen/gen.py\n{}", content))
+        };
+        assert_that(&cases_gen(":MULTIGEN gen 2").unwrap_err()).has_error("outside a subtask");
+        assert_that(&cases_gen(":SUBTASK 10\n:MULTIGEN gen 0").unwrap_err())
+            .has_error("at least one testcase");
+        assert_that(&cases_gen(":SUBTASK 10\n:MULTIGEN gen x").unwrap_err())
+            .has_error("Invalid number of testcases");
+        assert_that(&cases_gen(":SUBTASK 10\n:MULTIGEN foo 2").unwrap_err())
+            .has_error("not declared");
+    }
+
+    fn included_testcases<O: Fn(TestcaseId) -> OutputGenerator>(
+        gen: &CasesGen<O>,
+    ) -> Vec<Vec<TestcaseId>> {
+        gen.get_task_entries()
+            .into_iter()
+            .filter_map(|entry| match entry {
+                TaskInputEntry::Subtask(subtask) => Some(subtask.included_testcases),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_include_by_id() {
+        let gen = TestHelper::new()
+            .add_file("gen/gen.py")
+            .cases_gen(":GEN gen gen/gen.py\n:SUBTASK 10\n1\n2\n:SUBTASK 20\n:INCLUDE 0\n3")
+            .unwrap();
+        assert_eq!(included_testcases(&gen), vec![vec![], vec![0, 1]]);
+        assert_eq!(testcase_args(&gen), vec!["1", "2", "3"]);
+    }
+
+    #[test]
+    fn test_include_by_description() {
+        let gen = TestHelper::new()
+            .add_file("gen/gen.py")
+            .cases_gen(
+                ":GEN gen gen/gen.py\n:SUBTASK 10 small N\n1\n:SUBTASK 20 small M\n2\n\
+                 :SUBTASK 30 large\n: INCLUDE small*\n3",
+            )
+            .unwrap();
+        assert_eq!(included_testcases(&gen), vec![vec![], vec![], vec![0, 1]]);
+    }
+
+    #[test]
+    fn test_include_transitive() {
+        let gen = TestHelper::new()
+            .add_file("gen/gen.py")
+            .cases_gen(
+                ":GEN gen gen/gen.py\n:SUBTASK 10\n1\n:SUBTASK 20\n:INCLUDE 0\n2\n\
+                 :SUBTASK 30\n:INCLUDE 1\n3",
+            )
+            .unwrap();
+        assert_eq!(included_testcases(&gen), vec![vec![], vec![0], vec![0, 1]]);
+    }
+
+    #[test]
+    fn test_include_gen_gen() {
+        let gen = TestHelper::new()
+            .add_file("gen/gen.py")
+            .cases_gen(":GEN gen gen/gen.py\n:SUBTASK 10\n1\n:SUBTASK 20\n:INCLUDE 0\n2")
+            .unwrap();
+        gen.write_gen_gen().unwrap();
+        let data = std::fs::read_to_string(gen.task_dir.join("gen/GEN")).unwrap();
+        assert!(data.contains("#ST: 20\n# Included testcases: 0\n"));
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let cases_gen = |content: &str| {
+            TestHelper::new()
+                .add_file("gen/gen.py")
+                .cases_gen(format!(":GEN gen gen/gen.py\n{}", content))
+        };
+        assert_that(&cases_gen(":INCLUDE 0").unwrap_err()).has_error("outside a subtask");
+        assert_that(&cases_gen(":SUBTASK 10\n1\n:INCLUDE 0").unwrap_err())
+            .has_error("No previous subtask matches");
+        assert_that(&cases_gen(":SUBTASK 10\n1\n:SUBTASK 20\n:INCLUDE 5").unwrap_err())
+            .has_error("No previous subtask matches");
+        assert_that(
+            &cases_gen(":SUBTASK 10\n1\n:SUBTASK 20\n:FOR i IN 1..2\n:INCLUDE 0\n:END")
+                .unwrap_err(),
+        )
+        .has_error("can be inside a FOR loop");
+    }
+
+    #[test]
+    fn test_outline() {
+        let content = ":GEN gen gen/gen.py N M\n:VAL val gen/val.py $INPUT\n:SUBTASK 10\n\
+                       :FOR seed IN 1..2\n:RUN gen 1 $seed\n:END\n:GEN gen\n";
+        let helper = TestHelper::new();
+        helper.add_file("gen/gen.py").add_file("gen/val.py");
+        let outline = helper.outline(content);
+        assert!(outline.diagnostics.is_empty(), "{:?}", outline.diagnostics);
+        assert_eq!(outline.managers.len(), 2);
+        assert_eq!(outline.managers[0].kind, CasesGenManagerKind::Generator);
+        assert_eq!(outline.managers[0].name, "gen");
+        assert_eq!(outline.managers[0].path, "gen/gen.py");
+        assert_eq!(outline.managers[0].args, vec!["N", "M"]);
+        assert_eq!(outline.managers[0].span.file_offset(), 5);
+        assert_eq!(outline.managers[1].kind, CasesGenManagerKind::Validator);
+        assert_eq!(
+            outline.manager_names(CasesGenManagerKind::Validator),
+            vec!["val"]
+        );
+        assert_eq!(outline.references.len(), 2);
+        assert_eq!(outline.loop_variables, vec!["seed"]);
+        assert!(outline.variables().contains(&"N".to_string()));
+        assert!(outline.variables().contains(&"M".to_string()));
+        assert!(outline.variables().contains(&"seed".to_string()));
+        assert!(outline.variables().contains(&"ST_NUM".to_string()));
+
+        let usage = content.rfind("gen\n").unwrap();
+        assert_eq!(outline.manager_at(usage + 1).unwrap().name, "gen");
+        assert_eq!(outline.manager_at(6).unwrap().name, "gen");
+        assert!(outline.manager_at(0).is_none());
+    }
+
+    #[test]
+    fn test_outline_diagnostics() {
+        let helper = TestHelper::new();
+        helper.add_file("gen/gen.py");
+        let outline = helper.outline(":GEN gen gen/gen.py\n:SUBTASK 10\n:RUN nope 1\n");
+        assert_eq!(outline.diagnostics.len(), 1);
+        let diagnostic = &outline.diagnostics[0];
+        assert!(diagnostic.message().contains("not declared"));
+        assert_eq!(diagnostic.code_spans()[0].line_number(), 3);
+
+        let outline =
+            helper.outline(":GEN gen gen/gen.py\n:SUBTASK 10\n:FOR i IN 1..2\n:SUBTASK 5\n:END");
+        assert_eq!(outline.diagnostics[0].code_spans()[0].line_number(), 4);
+
+        let outline = helper.outline(":GEN gen gen/gen.py\n:SUBTASK nope\n");
+        assert_eq!(outline.managers.len(), 0);
+        assert!(outline.diagnostics[0].message().contains("Invalid syntax"));
+        assert_eq!(outline.diagnostics[0].code_spans()[0].line_number(), 2);
+    }
+
     /**********************
      * testcase
      *********************/
@@ -1619,6 +3200,35 @@ mod tests {
         assert_that(&gen.unwrap_err()).has_error("violates constraint");
     }
 
+    #[test]
+    fn test_testcase_expression_constraints() {
+        let cases_gen = |testcase: &str| {
+            TestHelper::new().add_file("gen/generator.py").cases_gen(format!(
+                ":GEN default gen/generator.py N M K P\n\
+                 :CONSTRAINT $N * $M <= 10^6\n:CONSTRAINT $K <= $N / 2\n:CONSTRAINT 0 <= $P <= 0.5\n\
+                 :CONSTRAINT max($N, $M) % 1000 < 999\n:SUBTASK 42\n{}",
+                testcase
+            ))
+        };
+        assert!(cases_gen("1000 1000 500 0.5").is_ok());
+        assert!(cases_gen("5 3 2 0.25").is_ok());
+        assert_that(&cases_gen("1000 1001 500 0.5").unwrap_err()).has_error("violates constraint");
+        assert_that(&cases_gen("5 3 3 0.25").unwrap_err()).has_error("violates constraint");
+        assert_that(&cases_gen("5 3 2 0.75").unwrap_err()).has_error("violates constraint");
+        assert_that(&cases_gen("999 3 2 0.25").unwrap_err()).has_error("violates constraint");
+        // Integers too big for i64 are compared as floats.
+        assert_that(&cases_gen("99999999999999999999 1 1 0").unwrap_err())
+            .has_error("violates constraint");
+    }
+
+    #[test]
+    fn test_testcase_division_by_zero() {
+        let gen = TestHelper::new().add_file("gen/generator.py").cases_gen(
+            ":GEN default gen/generator.py N M\n:CONSTRAINT $N / $M <= 10\n:SUBTASK 42\n1 0",
+        );
+        assert_that(&gen.unwrap_err()).has_error("Division by zero");
+    }
+
     #[test]
     fn test_testcase_uncheckable_constraints() {
         let gen = TestHelper::new().add_file("gen/generator.py").cases_gen(
@@ -1651,4 +3261,106 @@ mod tests {
         assert!(gen.is_err());
         assert_that(&gen.unwrap_err()).has_error("outside a subtask");
     }
+
+    #[test]
+    fn test_constraint_coverage() {
+        let gen = TestHelper::new()
+            .add_file("gen/gen.py")
+            .cases_gen(
+                ":GEN default gen/gen.py N M\n:CONSTRAINT 1 <= $N < $M <= 100\n\
+                 :SUBTASK 10\n:CONSTRAINT $N > 10\n20 30\n50 x\n:SUBTASK 90\n:CONSTRAINT 5 = $M",
+            )
+            .unwrap();
+        let subtasks: Vec<_> = gen
+            .get_task_entries()
+            .into_iter()
+            .filter_map(|entry| match entry {
+                TaskInputEntry::Subtask(subtask) => Some(subtask),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(subtasks.len(), 2);
+        let coverage = &subtasks[0].constraint_coverage;
+        assert_eq!(coverage.len(), 2);
+        assert_eq!(coverage[0].variable, "M");
+        assert_eq!(coverage[0].lower_bound, Some(2));
+        assert_eq!(coverage[0].upper_bound, Some(100));
+        assert_eq!(coverage[0].values, vec![30]);
+        assert_eq!(coverage[1].variable, "N");
+        assert_eq!(coverage[1].lower_bound, Some(11));
+        assert_eq!(coverage[1].upper_bound, Some(99));
+        assert_eq!(coverage[1].values, vec![20, 50]);
+        assert!(subtasks[1].constraint_coverage[1].values.is_empty());
+    }
+
+    #[test]
+    fn test_constraint_coverage_included() {
+        let gen = TestHelper::new()
+            .add_file("gen/gen.py")
+            .cases_gen(
+                ":GEN default gen/gen.py N\n:CONSTRAINT 1 <= $N <= 100\n\
+                 :SUBTASK 10\n5\n:SUBTASK 90\n:INCLUDE 0\n95",
+            )
+            .unwrap();
+        let subtasks: Vec<_> = gen
+            .get_task_entries()
+            .into_iter()
+            .filter_map(|entry| match entry {
+                TaskInputEntry::Subtask(subtask) => Some(subtask),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(subtasks.len(), 2);
+        assert_eq!(subtasks[0].constraint_coverage[0].values, vec![5]);
+        assert_eq!(subtasks[1].constraint_coverage[0].values, vec![95, 5]);
+    }
+
+    /**********************
+     * Subtasks
+     *********************/
+
+    #[test]
+    fn test_subtasks() {
+        let gen = TestHelper::new()
+            .add_file("gen/gen.py")
+            .add_file("gen/other.py")
+            .add_file("gen/val.py")
+            .cases_gen(
+                ":GEN default gen/gen.py N\n:VAL default gen/val.py $INPUT $N\n:CONSTRAINT 1 <= $N\n\
+                 :SUBTASK 10\n:CONSTRAINT $N <= 10\n5\n:SUBTASK 90\n:GEN other gen/other.py M\n:GEN other\n1",
+            )
+            .unwrap();
+        let subtasks = gen.get_subtasks();
+        assert_eq!(subtasks.len(), 2);
+        assert_eq!(subtasks[0].id, 0);
+        assert_eq!(subtasks[0].generator_names(), vec!["default"]);
+        assert_eq!(subtasks[1].id, 1);
+        assert_eq!(subtasks[1].generator_names(), vec!["default", "other"]);
+
+        let (generator, validator) = subtasks[0].testcase(None, vec!["7".into()], 3).unwrap();
+        if let InputGenerator::Custom(source, args) = generator {
+            assert_eq!(source.name(), "gen.py");
+            assert_eq!(args, vec!["7"]);
+        } else {
+            panic!("Invalid generator: {:?}", generator);
+        }
+        if let InputValidator::Custom(_, args) = validator {
+            assert_eq!(
+                args,
+                vec![TM_VALIDATION_FILE_NAME.to_string(), "7".to_string()]
+            );
+        } else {
+            panic!("Invalid validator: {:?}", validator);
+        }
+
+        let err = subtasks[0]
+            .testcase(None, vec!["11".into()], 3)
+            .unwrap_err();
+        assert_that(&err).has_error("violates constraint");
+        assert!(subtasks[1].check_constraints(None, &["11".into()]).is_ok());
+        assert!(subtasks[1]
+            .check_constraints(Some("default"), &["0".into()])
+            .is_err());
+        assert!(subtasks[1].check_constraints(Some("nope"), &[]).is_err());
+    }
 }
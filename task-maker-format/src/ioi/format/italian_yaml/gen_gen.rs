@@ -57,6 +57,8 @@ where
         testcases: HashMap::new(),
         span: None,
         is_default: true,
+        constraint_coverage: vec![],
+        included_testcases: vec![],
     });
 
     let mut generators = find_source_file(
@@ -112,6 +114,8 @@ where
                             )
                             .ok(),
                             is_default: false,
+                            constraint_coverage: vec![],
+                            included_testcases: vec![],
                         }));
                         subtask_id += 1;
                     }
@@ -253,7 +257,7 @@ mod tests {
             assert_eq!(testcase.id, 0);
             match &testcase.input_generator {
                 InputGenerator::Custom(_, args) => assert_eq!(args, &vec!["1234".to_string()]),
-                InputGenerator::StaticFile(_) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
         } else {
             panic!("Wrong entries returned: {:?}", entries);
@@ -269,7 +273,7 @@ mod tests {
             assert_eq!(testcase.id, 0);
             match &testcase.input_generator {
                 InputGenerator::Custom(_, args) => assert_eq!(args, &vec!["1234".to_string()]),
-                InputGenerator::StaticFile(_) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
         } else {
             panic!("Wrong entries returned: {:?}", entries);
@@ -286,7 +290,7 @@ mod tests {
             assert_eq!(testcase.id, 0);
             match &testcase.input_generator {
                 InputGenerator::Custom(_, args) => assert_eq!(args, &vec!["1234".to_string()]),
-                InputGenerator::StaticFile(_) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
         } else {
             panic!("Wrong entries returned: {:?}", entries);
@@ -302,7 +306,7 @@ mod tests {
             assert_eq!(testcase.id, 0);
             match &testcase.input_generator {
                 InputGenerator::Custom(_, args) => assert_eq!(args, &vec!["1234".to_string()]),
-                InputGenerator::StaticFile(_) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
         } else {
             panic!("Wrong entries returned: {:?}", entries);
@@ -318,7 +322,7 @@ mod tests {
             assert_eq!(testcase.id, 0);
             match &testcase.input_generator {
                 InputGenerator::Custom(_, args) => assert_eq!(args, &vec!["1234".to_string()]),
-                InputGenerator::StaticFile(_) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
         } else {
             panic!("Wrong entries returned: {:?}", entries);
@@ -335,11 +339,11 @@ mod tests {
             assert_eq!(testcase2.id, 1);
             match &testcase1.input_generator {
                 InputGenerator::Custom(_, args) => assert_eq!(args, &vec!["1234".to_string()]),
-                InputGenerator::StaticFile(_) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
             match &testcase2.input_generator {
                 InputGenerator::Custom(_, args) => assert_eq!(args, &vec!["5678".to_string()]),
-                InputGenerator::StaticFile(_) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
         } else {
             panic!("Wrong entries returned: {:?}", entries);
@@ -356,11 +360,11 @@ mod tests {
             assert_eq!(testcase2.id, 1);
             match &testcase1.input_generator {
                 InputGenerator::Custom(_, args) => assert_eq!(args, &vec!["1234".to_string()]),
-                InputGenerator::StaticFile(_) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
             match &testcase2.input_generator {
                 InputGenerator::Custom(_, args) => assert_eq!(args, &vec!["5678".to_string()]),
-                InputGenerator::StaticFile(_) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
         } else {
             panic!("Wrong entries returned: {:?}", entries);
@@ -380,11 +384,11 @@ mod tests {
                 InputGenerator::StaticFile(path) => {
                     assert_eq!(path, &task.path().join("random/file"))
                 }
-                InputGenerator::Custom(_, _) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
             match &testcase2.input_generator {
                 InputGenerator::Custom(_, args) => assert_eq!(args, &vec!["5678".to_string()]),
-                InputGenerator::StaticFile(_) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
         } else {
             panic!("Wrong entries returned: {:?}", entries);
@@ -408,15 +412,15 @@ mod tests {
                 InputGenerator::StaticFile(path) => {
                     assert_eq!(path, &task.path().join("random/file"))
                 }
-                InputGenerator::Custom(_, _) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
             match &testcase2.input_generator {
                 InputGenerator::Custom(_, args) => assert_eq!(args, &vec!["5678".to_string()]),
-                InputGenerator::StaticFile(_) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
             match &testcase3.input_generator {
                 InputGenerator::Custom(_, args) => assert_eq!(args, &vec!["1234".to_string()]),
-                InputGenerator::StaticFile(_) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
         } else {
             panic!("Wrong entries returned: {:?}", entries);
@@ -470,11 +474,11 @@ mod tests {
             assert_eq!(testcase2.id, 1);
             match &testcase1.input_generator {
                 InputGenerator::Custom(_, args) => assert_eq!(args, &vec!["1234".to_string()]),
-                InputGenerator::StaticFile(_) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
             match &testcase2.input_generator {
                 InputGenerator::Custom(_, args) => assert_eq!(args, &vec!["5678".to_string()]),
-                InputGenerator::StaticFile(_) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
         } else {
             panic!("Wrong entries returned: {:?}", entries);
@@ -490,7 +494,7 @@ mod tests {
             assert_eq!(testcase.id, 0);
             match &testcase.input_generator {
                 InputGenerator::Custom(_, args) => assert_eq!(args, &vec!["1234".to_string()]),
-                InputGenerator::StaticFile(_) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
         } else {
             panic!("Wrong entries returned: {:?}", entries);
@@ -268,6 +268,10 @@ use unic::normal::StrNormalForm;
 use unic::ucd::category::GeneralCategory;
 
 pub(crate) use cases_gen::{is_gen_gen_deletable, TM_ALLOW_DELETE_COOKIE};
+pub use cases_gen::{
+    CasesGenManager, CasesGenManagerKind, CasesGenOutline, CasesGenReference, CasesGenSubtask,
+};
+use task_maker_diagnostics::{CodeSpan, Diagnostic};
 use task_maker_lang::GraderMap;
 
 use crate::ioi::sanity_checks::get_sanity_checks;
@@ -277,12 +281,22 @@ use crate::ioi::{
 };
 use crate::ioi::{BatchTypeData, CommunicationTypeData, UserIo};
 use crate::ioi::{InputValidatorGenerator, TM_VALIDATION_FILE_NAME};
-use crate::{find_source_file, list_files, EvaluationConfig, WriteBinTo};
+use crate::{
+    find_source_file, list_files, EvaluationConfig, SanityCheckLevel, SanityCheckLevels, WriteBinTo,
+};
 
 mod cases_gen;
 mod gen_gen;
 mod static_inputs;
 
+/// The patterns of the paths of the official solution, relative to the task directory.
+const OFFICIAL_SOLUTION_PATTERNS: &[&str] = &[
+    "sol/solution.*",
+    "sol/soluzione.*",
+    "sol/solution",
+    "sol/soluzione",
+];
+
 /// The set of valid Unicode General Categories for the characters composing a subtask name.
 pub const VALID_SUBTASK_NAME_CHARACTER_CATEGORIES: &[GeneralCategory] = &[
     // L group (included in XID_Start)
@@ -336,6 +350,9 @@ struct TaskYAML {
     /// The memory limit in MiB of the execution of the solution, if not set it's unlimited.
     #[serde(alias = "memlimit")]
     pub memory_limit: Option<u64>,
+    /// The limit on the number of user-space instructions of the solutions, if not set the
+    /// instructions are not counted.
+    pub instruction_limit: Option<u64>,
 
     /// Whether this is an output only task. Defaults to false.
     #[serde(default = "bool::default")]
@@ -362,6 +379,87 @@ struct TaskYAML {
     /// Can be either "std_io" for using stdin/stdout, or "fifo_io" for using pipes given in argv.
     /// Defaults to "fifo_io".
     pub user_io: Option<String>,
+
+    /// The level of some sanity checks, by name: allow, warn or deny.
+    #[serde(
+        default,
+        deserialize_with = "crate::sanity_checks::deserialize_sanity_check_levels"
+    )]
+    pub sanity_checks: HashMap<String, SanityCheckLevel>,
+}
+
+/// The keys of the task.yaml file, with a short description of each of them. The aliases of a key
+/// are described as "Alias of `key`.".
+pub const TASK_YAML_KEYS: &[(&str, &str)] = &[
+    ("name", "The name of the task (the short one)."),
+    ("nome_breve", "Alias of `name`."),
+    ("title", "The title of the task (the long one)."),
+    ("nome", "Alias of `title`."),
+    ("score_type", "The score type to use for this task."),
+    (
+        "score_precision",
+        "The number of decimal digits when displaying the scores.",
+    ),
+    (
+        "time_limit",
+        "The time limit in seconds for the execution of the solutions.",
+    ),
+    ("timeout", "Alias of `time_limit`."),
+    (
+        "memory_limit",
+        "The memory limit in MiB of the execution of the solutions.",
+    ),
+    ("memlimit", "Alias of `memory_limit`."),
+    (
+        "instruction_limit",
+        "The limit on the number of user-space instructions of the solutions.",
+    ),
+    ("output_only", "Whether this is an output only task."),
+    (
+        "infile",
+        "The input file for the solutions, empty for stdin.",
+    ),
+    (
+        "outfile",
+        "The output file for the solutions, empty for stdout.",
+    ),
+    (
+        "difficulty",
+        "The difficulty of the task, used in the booklets.",
+    ),
+    (
+        "syllabuslevel",
+        "The level of the task inside a syllabus, used in the booklets.",
+    ),
+    (
+        "num_processes",
+        "Number of solution processes to spawn in a communication task.",
+    ),
+    (
+        "user_io",
+        "The type of communication of a communication task: std_io or fifo_io.",
+    ),
+    (
+        "sanity_checks",
+        "The level of some sanity checks, by name: allow, warn or deny.",
+    ),
+];
+
+/// Check the content of the task.yaml file at the provided path, returning the problems found in
+/// it. The content may differ from the one on disk, for example when it is being edited.
+pub fn check_task_yaml(path: &Path, content: &str) -> Vec<Diagnostic> {
+    let err = match serde_yaml::from_str::<TaskYAML>(content) {
+        Ok(_) => return vec![],
+        Err(err) => err,
+    };
+    let mut diagnostic = Diagnostic::error(format!("Invalid task.yaml: {}", err));
+    let span = err
+        .location()
+        .and_then(|location| CodeSpan::from_line(path, content, location.index()).ok());
+    if let Some(span) = span {
+        diagnostic = diagnostic.with_code_span(span);
+    }
+    vec![diagnostic]
 }
 
 /// The iterator item type when following the task input testcases.
@@ -492,6 +590,7 @@ pub fn parse_task<P: AsRef<Path>>(
         title: yaml.title,
         time_limit: yaml.time_limit,
         memory_limit: yaml.memory_limit,
+        instruction_limit: yaml.instruction_limit,
         infile,
         outfile,
         testcase_score_aggregator: yaml
@@ -511,13 +610,16 @@ pub fn parse_task<P: AsRef<Path>>(
         booklets: Vec::new(),
         difficulty: yaml.difficulty,
         syllabus_level: yaml.syllabuslevel,
-        sanity_checks: Arc::new(get_sanity_checks(
-            &eval_config
-                .disabled_sanity_checks
-                .iter()
-                .map(String::as_str)
-                .collect::<Vec<_>>(),
-        )),
+        sanity_checks: Arc::new(
+            get_sanity_checks(
+                &eval_config
+                    .disabled_sanity_checks
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>(),
+            )
+            .with_levels(SanityCheckLevels::new(&yaml.sanity_checks, eval_config)),
+        ),
         input_validator_generator: InputValidatorGenerator::new(
             detect_validator(task_dir.to_path_buf()).context("Failed to detect validator")?,
         ),
@@ -530,6 +632,24 @@ pub fn parse_task<P: AsRef<Path>>(
     Ok(task)
 }
 
+/// Parse the `gen/cases.gen` file of the task, returning the generators, the validator and the
+/// constraints of each subtask. Returns `None` if the task doesn't have a `gen/cases.gen` file.
+pub fn parse_cases_gen_subtasks(task_dir: &Path) -> Result<Option<Vec<CasesGenSubtask>>, Error> {
+    let cases_gen = task_dir.join("gen").join("cases.gen");
+    if !cases_gen.exists() {
+        return Ok(None);
+    }
+    let gen = cases_gen::CasesGen::new(&cases_gen, |_| OutputGenerator::NotAvailable)?;
+    Ok(Some(gen.get_subtasks()))
+}
+
+/// Find the path of the official solution of the task, if any.
+pub fn find_official_solution(task_dir: &Path) -> Option<PathBuf> {
+    list_files(task_dir, OFFICIAL_SOLUTION_PATTERNS.to_vec())
+        .into_iter()
+        .next()
+}
+
 /// Search for a valid input validator inside the task directory. Will return a function that, given
 /// a subtask id, returns an `InputValidator` using that validator. If no validator is found,
 /// `InputValidator::AssumeValid` is used.
@@ -580,12 +700,7 @@ fn detect_output_generator(
 ) -> Result<impl Fn(TestcaseId) -> OutputGenerator, Error> {
     let mut official_solutions = find_source_file(
         &task_dir,
-        vec![
-            "sol/solution.*",
-            "sol/soluzione.*",
-            "sol/solution",
-            "sol/soluzione",
-        ],
+        OFFICIAL_SOLUTION_PATTERNS.to_vec(),
         &task_dir,
         "Official solution at",
         Some(grader_map),
@@ -769,3 +884,92 @@ fn cleanup_subtask_name(id: &str) -> Result<String, Error> {
     }
     Ok(normalized)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    /// The key aliased by an entry of `TASK_YAML_KEYS`, if it is an alias.
+    fn aliased_key(description: &str) -> Option<&str> {
+        description
+            .strip_prefix("Alias of `")
+            .and_then(|rest| rest.strip_suffix("`."))
+    }
+
+    #[test]
+    fn test_task_yaml_keys_match_fields() {
+        let yaml: TaskYAML = serde_yaml::from_str("name: task\ntitle: The Task").unwrap();
+        let value = serde_yaml::to_value(yaml).unwrap();
+        let fields: BTreeSet<_> = value
+            .as_mapping()
+            .unwrap()
+            .keys()
+            .map(|key| key.as_str().unwrap())
+            .collect();
+        let keys: BTreeSet<_> = TASK_YAML_KEYS
+            .iter()
+            .filter(|(_, description)| aliased_key(description).is_none())
+            .map(|(key, _)| *key)
+            .collect();
+        assert_eq!(fields, keys);
+    }
+
+    #[test]
+    fn test_task_yaml_sanity_checks() {
+        let yaml: TaskYAML = serde_yaml::from_str(
+            "name: task\ntitle: The Task\nsanity_checks:\n  MissingSubtaskNames: deny\n",
+        )
+        .unwrap();
+        assert_eq!(
+            yaml.sanity_checks["MissingSubtaskNames"],
+            SanityCheckLevel::Deny
+        );
+
+        // The unknown names are rejected, like with --check-level.
+        let content = "name: task\ntitle: The Task\nsanity_checks:\n  MissingSubtaskName: deny\n";
+        let diagnostics = check_task_yaml(Path::new("task.yaml"), content);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(
+            diagnostics[0]
+                .message()
+                .contains("Unknown sanity check: MissingSubtaskName"),
+            "{:?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_task_yaml_keys_aliases() {
+        let fields = [
+            ("name", "task"),
+            ("title", "The Task"),
+            ("time_limit", "1.5"),
+            ("memory_limit", "256"),
+        ];
+        // Parse the task.yaml with all the fields, writing `key` as `alias`.
+        let parse = |key: &str, alias: &str| {
+            let yaml = fields
+                .iter()
+                .map(|(name, value)| {
+                    let name = if *name == key { alias } else { *name };
+                    format!("{}: {}\n", name, value)
+                })
+                .collect::<String>();
+            let yaml: TaskYAML = serde_yaml::from_str(&yaml).unwrap();
+            serde_yaml::to_value(yaml).unwrap()
+        };
+        for (alias, description) in TASK_YAML_KEYS {
+            let Some(key) = aliased_key(description) else {
+                continue;
+            };
+            assert!(
+                fields.iter().any(|(name, _)| *name == key),
+                "Missing test value for {}",
+                key
+            );
+            assert_eq!(parse(key, alias), parse(key, key), "Wrong alias {}", alias);
+        }
+    }
+}
@@ -44,6 +44,8 @@ where
                 testcases: HashMap::new(),
                 span: None,
                 is_default: true,
+                constraint_coverage: vec![],
+                included_testcases: vec![],
             }));
         }
         let id = self.index - 1; // offset caused by the first iteration
@@ -132,19 +134,19 @@ mod tests {
                 InputGenerator::StaticFile(path) => {
                     assert_eq!(path, &task.path().join("input/input0.txt"))
                 }
-                InputGenerator::Custom(_, _) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
             match &testcase1.input_generator {
                 InputGenerator::StaticFile(path) => {
                     assert_eq!(path, &task.path().join("input/input1.txt"))
                 }
-                InputGenerator::Custom(_, _) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
             match &testcase2.input_generator {
                 InputGenerator::StaticFile(path) => {
                     assert_eq!(path, &task.path().join("input/input2.txt"))
                 }
-                InputGenerator::Custom(_, _) => panic!("Invalid generator"),
+                _ => panic!("Invalid generator"),
             }
         } else {
             panic!("Wrong entries returned: {:?}", entries);
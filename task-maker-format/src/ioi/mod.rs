@@ -15,6 +15,7 @@
 //! a `Checker`, a program that computes the score of the testcase given the input file, the output
 //! file and the _correct_ output file (the one produced by the jury).
 
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::path::{Path, PathBuf};
@@ -67,8 +68,10 @@ pub struct ScoreManager {
     subtask_scores: HashMap<SubtaskId, Option<f64>>,
     /// The maximum score of each subtask.
     max_subtask_scores: HashMap<SubtaskId, f64>,
-    /// The scores of each testcase.
+    /// The scores of each testcase, including the ones included from other subtasks.
     testcase_scores: HashMap<SubtaskId, HashMap<TestcaseId, Option<f64>>>,
+    /// The subtasks that include each testcase owned by another subtask.
+    including_subtasks: HashMap<TestcaseId, Vec<SubtaskId>>,
     /// The aggregator to use for computing the subtask scores.
     aggregator: TestcaseScoreAggregator,
 }
@@ -116,6 +119,9 @@ pub struct IOITask {
     pub time_limit: Option<f64>,
     /// The memory limit in MiB of the execution of the solution, if `None` it's unlimited.
     pub memory_limit: Option<u64>,
+    /// The limit on the number of user-space instructions executed by the solution. When set, the
+    /// time limit is used only as a safety net and the verdicts do not depend on the machine.
+    pub instruction_limit: Option<u64>,
     /// The input file for the solutions, usually `Some("input.txt")` or `None` (stdin).
     pub infile: Option<PathBuf>,
     /// The output file for the solutions, usually `Some("output.txt")` or `None` (stdout).
@@ -162,10 +168,31 @@ pub struct SubtaskInfo {
     pub max_score: f64,
     /// The testcases inside this subtask.
     pub testcases: HashMap<TestcaseId, TestcaseInfo>,
+    /// The testcases owned by other subtasks that are also part of this subtask. They are generated
+    /// and evaluated only once, as part of the subtask that owns them.
+    #[serde(default)]
+    pub included_testcases: Vec<TestcaseId>,
     /// The span of the definition of this subtask.
     pub span: Option<CodeSpan>,
     /// Whether this subtask was created automatically since no subtask was present in gen/GEN.
     pub is_default: bool,
+    /// The values taken in the testcases of this subtask by the variables bounded by the
+    /// constraints of the subtask.
+    #[serde(default)]
+    pub constraint_coverage: Vec<ConstraintCoverage>,
+}
+
+/// The values taken in the testcases of a subtask by a variable bounded by the constraints.
+#[derive(Debug, Clone, Serialize, Deserialize, TypeScriptify)]
+pub struct ConstraintCoverage {
+    /// The name of the variable, without the dollar sign.
+    pub variable: String,
+    /// The minimum value the constraints allow for the variable, if bounded.
+    pub lower_bound: Option<i64>,
+    /// The maximum value the constraints allow for the variable, if bounded.
+    pub upper_bound: Option<i64>,
+    /// The values of the variable in the testcases of the subtask where it's defined.
+    pub values: Vec<i64>,
 }
 
 /// A testcase of a IOI task.
@@ -207,6 +234,7 @@ impl IOITask {
             title: "".to_string(),
             time_limit: None,
             memory_limit: None,
+            instruction_limit: None,
             infile: None,
             outfile: None,
             subtasks: Default::default(),
@@ -286,6 +314,7 @@ impl IOITask {
             .context("Failed to prepare DAG")?;
 
         let mut generated_io: HashMap<_, HashMap<_, _>> = HashMap::new();
+        let mut multi_inputs = HashMap::new();
 
         for subtask in self.subtasks.values() {
             trace!("Executing the generation of subtask {}", subtask.id);
@@ -297,10 +326,26 @@ impl IOITask {
                     subtask.id
                 );
 
-                let input = testcase
-                    .input_generator
-                    .generate_and_bind(eval, subtask.id, testcase.id)
-                    .context("Failed to bind input generator")?;
+                let input = match &testcase.input_generator {
+                    // All the inputs of a multiple generator are produced by the same execution,
+                    // which is added only once.
+                    InputGenerator::MultiCustom(generator, index) => {
+                        let inputs = match multi_inputs.entry(generator.first_testcase) {
+                            Entry::Occupied(entry) => entry.into_mut(),
+                            Entry::Vacant(entry) => entry.insert(
+                                generator
+                                    .generate_and_bind(eval, subtask.id)
+                                    .context("Failed to bind multiple input generator")?,
+                            ),
+                        };
+                        *inputs
+                            .get(*index)
+                            .context("Invalid index of multiple generator input")?
+                    }
+                    generator => generator
+                        .generate_and_bind(eval, subtask.id, testcase.id)
+                        .context("Failed to bind input generator")?,
+                };
                 let val_handle = testcase
                     .input_validator
                     .validate_and_bind(
@@ -487,6 +532,19 @@ impl IOITask {
 }
 
 impl SubtaskInfo {
+    /// The ids of all the testcases of this subtask, including the ones owned by other subtasks.
+    pub fn testcase_ids(&self) -> impl Iterator<Item = TestcaseId> + '_ {
+        self.testcases
+            .keys()
+            .copied()
+            .chain(self.included_testcases.iter().copied())
+    }
+
+    /// Whether this subtask has no testcases, neither owned nor included.
+    pub fn is_empty(&self) -> bool {
+        self.testcases.is_empty() && self.included_testcases.is_empty()
+    }
+
     /// Check if the pattern matches the subtaks name.
     fn name_matches(&self, pattern: &WildMatch) -> bool {
         if let Some(name) = &self.name {
@@ -533,31 +591,29 @@ impl ScoreManager {
     pub fn new(task: &IOITask) -> ScoreManager {
         // NOTE: this will ignore the subtask without any testcase since they will never be
         // notified.
+        let subtasks = || task.subtasks.values().filter(|st| !st.is_empty());
+        let mut including_subtasks: HashMap<_, Vec<_>> = HashMap::new();
+        for subtask in subtasks() {
+            for testcase in &subtask.included_testcases {
+                including_subtasks
+                    .entry(*testcase)
+                    .or_default()
+                    .push(subtask.id);
+            }
+        }
         ScoreManager {
-            subtask_scores: task
-                .subtasks
-                .iter()
-                .filter(|(_, st)| !st.testcases.is_empty())
-                .map(|(st_num, _)| (*st_num, None))
-                .collect(),
-            max_subtask_scores: task
-                .subtasks
-                .values()
-                .filter(|st| !st.testcases.is_empty())
-                .map(|st| (st.id, st.max_score))
-                .collect(),
-            testcase_scores: task
-                .subtasks
-                .values()
-                .filter(|st| !st.testcases.is_empty())
-                .map(|st| (st.id, st.testcases.keys().map(|tc| (*tc, None)).collect()))
+            subtask_scores: subtasks().map(|st| (st.id, None)).collect(),
+            max_subtask_scores: subtasks().map(|st| (st.id, st.max_score)).collect(),
+            testcase_scores: subtasks()
+                .map(|st| (st.id, st.testcase_ids().map(|tc| (tc, None)).collect()))
                 .collect(),
+            including_subtasks,
             aggregator: task.testcase_score_aggregator.clone(),
         }
     }
 
     /// Store the score of the testcase and eventually compute the score of the subtask and of the
-    /// task.
+    /// task. The score is also used for the subtasks that include the testcase.
     pub fn score(
         &mut self,
         subtask_id: SubtaskId,
@@ -578,10 +634,26 @@ impl ScoreManager {
             score,
             message,
         })?;
-        if self.testcase_scores[&subtask_id]
-            .values()
-            .all(Option::is_some)
-        {
+        let including = self
+            .including_subtasks
+            .get(&testcase_id)
+            .cloned()
+            .unwrap_or_default();
+        for subtask in &including {
+            self.testcase_scores
+                .get_mut(subtask)
+                .ok_or_else(|| anyhow!("Unknown subtask {}", subtask))?
+                .insert(testcase_id, Some(score));
+        }
+
+        let mut subtask_completed = false;
+        for subtask_id in std::iter::once(subtask_id).chain(including) {
+            if !self.testcase_scores[&subtask_id]
+                .values()
+                .all(Option::is_some)
+            {
+                continue;
+            }
             let normalized_score = self.aggregator.aggregate(
                 self.testcase_scores[&subtask_id]
                     .values()
@@ -595,17 +667,18 @@ impl ScoreManager {
                 score: subtask_score,
                 normalized_score,
             })?;
-            if self.subtask_scores.values().all(Option::is_some) {
-                let task_score: f64 = self
-                    .subtask_scores
-                    .values()
-                    .map(|score| score.unwrap())
-                    .sum();
-                sender.send(UIMessage::IOITaskScore {
-                    solution,
-                    score: task_score,
-                })?;
-            }
+            subtask_completed = true;
+        }
+        if subtask_completed && self.subtask_scores.values().all(Option::is_some) {
+            let task_score: f64 = self
+                .subtask_scores
+                .values()
+                .map(|score| score.unwrap())
+                .sum();
+            sender.send(UIMessage::IOITaskScore {
+                solution,
+                score: task_score,
+            })?;
         }
         Ok(())
     }
@@ -98,7 +98,7 @@ impl AttSampleFiles {
                     testcases.push(path);
                 }
                 // This subtask is not with the sample cases.
-                InputGenerator::Custom(_, _) => return vec![],
+                InputGenerator::Custom(_, _) | InputGenerator::MultiCustom(_, _) => return vec![],
             }
         }
         testcases
@@ -1,12 +1,13 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 
-use crate::ioi::IOITask;
+use crate::ioi::{IOITask, InputGenerator, TestcaseId};
 use crate::sanity_checks::{make_sanity_check, SanityCheck, SanityCheckCategory};
 use crate::EvaluationData;
-use anyhow::Error;
+use anyhow::{Context, Error};
+use blake3::{Hash, Hasher};
 use itertools::Itertools;
-use task_maker_dag::FileUuid;
+use task_maker_dag::{ExecutionTag, FileUuid};
 use task_maker_diagnostics::Diagnostic;
 
 /// Check that the input and output files end with `\n`.
@@ -131,3 +132,138 @@ impl SanityCheck for IOEndWithNewLine {
         Ok(())
     }
 }
+
+/// Check that the generators are deterministic: each generated input file is generated again with
+/// the cache disabled, and the two files should be equal.
+///
+/// Since this runs all the generators twice, it is opt-in: it has to be given a level, for example
+/// with `--check-level IOGeneratorDeterminism=warn`.
+#[derive(Debug, Default)]
+pub struct IOGeneratorDeterminism {
+    /// The hashes of the input files produced by the two generations of each testcase. The hash
+    /// is the same one used for the `FileStoreKey`.
+    hashes: Arc<Mutex<HashMap<TestcaseId, Vec<Hash>>>>,
+}
+make_sanity_check!(IOGeneratorDeterminism);
+
+impl IOGeneratorDeterminism {
+    /// Compute the hash of the file and store it in the list of hashes of the testcase.
+    fn bind_hash(&self, eval: &mut EvaluationData, file: FileUuid, testcase_id: TestcaseId) {
+        let hashes = self.hashes.clone();
+        let mut hasher = Hasher::new();
+        eval.dag.get_file_content_chunked(file, move |chunk| {
+            if chunk.is_empty() {
+                let mut hashes = hashes.lock().unwrap();
+                hashes
+                    .entry(testcase_id)
+                    .or_default()
+                    .push(hasher.finalize());
+            } else {
+                hasher.update(chunk);
+            }
+            Ok(())
+        });
+    }
+}
+
+impl SanityCheck for IOGeneratorDeterminism {
+    type Task = IOITask;
+
+    fn name(&self) -> &'static str {
+        "IOGeneratorDeterminism"
+    }
+
+    fn category(&self) -> SanityCheckCategory {
+        SanityCheckCategory::Io
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn pre_hook(&self, task: &IOITask, eval: &mut EvaluationData) -> Result<(), Error> {
+        let tag = ExecutionTag::from("generation-determinism");
+        let mut any_generator = false;
+        let mut seen = HashSet::new();
+        for (&subtask_id, subtask) in &task.subtasks {
+            for (&testcase_id, testcase) in &subtask.testcases {
+                if !seen.insert(testcase_id) {
+                    continue;
+                }
+                let input_file = match (&testcase.input_generator, testcase.input_file) {
+                    (InputGenerator::Custom(_, _), Some(input_file)) => input_file,
+                    _ => continue,
+                };
+                let (regenerated, exec) = testcase
+                    .input_generator
+                    .generate(
+                        eval,
+                        format!(
+                            "Checking the determinism of the generation of testcase {}, subtask {}",
+                            testcase_id, subtask_id
+                        ),
+                        subtask_id,
+                        testcase_id,
+                    )
+                    .with_context(|| {
+                        format!("Failed to generate again testcase {}", testcase_id)
+                    })?;
+                if let Some(mut exec) = exec {
+                    exec.tag(tag.clone());
+                    eval.dag.add_execution(exec);
+                    self.bind_hash(eval, input_file, testcase_id);
+                    self.bind_hash(eval, regenerated, testcase_id);
+                    any_generator = true;
+                }
+            }
+        }
+        if any_generator {
+            eval.dag.config_mut().cache_mode.disable_tag(tag);
+        }
+        Ok(())
+    }
+
+    fn post_hook(&self, task: &IOITask, eval: &mut EvaluationData) -> Result<(), Error> {
+        let hashes = self.hashes.lock().unwrap();
+        let mut non_deterministic: HashMap<_, Vec<_>> = HashMap::new();
+        for subtask in task.subtasks.values() {
+            for (testcase_id, testcase) in &subtask.testcases {
+                let (generator, args) = match &testcase.input_generator {
+                    InputGenerator::Custom(generator, args) => (generator, args),
+                    _ => continue,
+                };
+                // if one of the generations failed there is nothing to compare
+                match hashes.get(testcase_id) {
+                    Some(hashes) if hashes.len() == 2 && hashes[0] != hashes[1] => {}
+                    _ => continue,
+                }
+                non_deterministic
+                    .entry(generator.relative_path().to_owned())
+                    .or_default()
+                    .push((*testcase_id, args.join(" ")));
+            }
+        }
+        for (generator, mut testcases) in non_deterministic.into_iter().sorted() {
+            testcases.sort();
+            testcases.dedup();
+            eval.add_diagnostic(
+                Diagnostic::error(format!(
+                    "Generator {} is not deterministic",
+                    generator.display()
+                ))
+                .with_note(format!(
+                    "These testcases differ between two generations: {}",
+                    testcases
+                        .iter()
+                        .map(|(testcase, args)| format!("{} (args: {})", testcase, args))
+                        .join(", ")
+                ))
+                .with_help(
+                    "Make sure the generator doesn't depend on the time, on uninitialized memory \
+                     or on the iteration order of hash maps",
+                ),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -183,11 +183,16 @@ impl SanityCheck for AllOutputsEqual {
 
     fn pre_hook(&self, task: &IOITask, eval: &mut EvaluationData) -> Result<(), Error> {
         let mut outputs = self.outputs.lock().unwrap();
+        let testcases: HashMap<_, _> = task
+            .subtasks
+            .values()
+            .flat_map(|st| st.testcases.iter())
+            .collect();
         for subtask in task.subtasks.values() {
-            if subtask.testcases.len() >= 2 {
+            if subtask.testcase_ids().count() >= 2 {
                 outputs.insert(subtask.id, Vec::new());
 
-                for testcase in subtask.testcases.values() {
+                for testcase in subtask.testcase_ids().filter_map(|id| testcases.get(&id)) {
                     if let Some(output_file) = testcase.official_output_file {
                         OutputHasher::bind(eval, output_file, subtask.id, self.outputs.clone());
                     }
@@ -204,7 +209,7 @@ impl SanityCheck for AllOutputsEqual {
             let Some(subtask) = task.subtasks.get(id) else {
                 continue;
             };
-            if out.len() != subtask.testcases.len() {
+            if out.len() != subtask.testcase_ids().count() {
                 continue;
             }
 
@@ -231,3 +236,69 @@ impl SanityCheck for AllOutputsEqual {
         Ok(())
     }
 }
+
+/// Check that the testcases of each subtask reach the bounds of the variables constrained in
+/// `cases.gen`.
+#[derive(Debug, Default)]
+pub struct ConstraintBoundsNotReached;
+make_sanity_check!(ConstraintBoundsNotReached);
+
+/// A value is close to a bound if its distance from the bound is at most this fraction of the
+/// range of the variable (or of the bound itself, if the variable has a single bound).
+const CLOSE_TO_BOUND_FRACTION: f64 = 0.1;
+
+impl SanityCheck for ConstraintBoundsNotReached {
+    type Task = IOITask;
+
+    fn name(&self) -> &'static str {
+        "ConstraintBoundsNotReached"
+    }
+
+    fn category(&self) -> SanityCheckCategory {
+        SanityCheckCategory::Io
+    }
+
+    fn pre_hook(&self, task: &IOITask, eval: &mut EvaluationData) -> Result<(), Error> {
+        for subtask_id in task.subtasks.keys().sorted() {
+            let subtask = &task.subtasks[subtask_id];
+            for coverage in &subtask.constraint_coverage {
+                let (min, max) = match coverage.values.iter().minmax().into_option() {
+                    Some((min, max)) => (*min, *max),
+                    None => continue,
+                };
+                let tolerance = |bound: i64| match (coverage.lower_bound, coverage.upper_bound) {
+                    (Some(lower), Some(upper)) => {
+                        (upper as f64 - lower as f64) * CLOSE_TO_BOUND_FRACTION
+                    }
+                    _ => (bound as f64).abs() * CLOSE_TO_BOUND_FRACTION,
+                };
+                let mut far_bounds = vec![];
+                if let Some(lower) = coverage.lower_bound {
+                    if min as f64 - lower as f64 > tolerance(lower) {
+                        far_bounds.push(("at least", min, "lower", lower));
+                    }
+                }
+                if let Some(upper) = coverage.upper_bound {
+                    if upper as f64 - max as f64 > tolerance(upper) {
+                        far_bounds.push(("at most", max, "upper", upper));
+                    }
+                }
+                for (extreme, value, kind, bound) in far_bounds {
+                    let mut diagnostic = Diagnostic::warning(format!(
+                        "In subtask {} ${} is {} {}, far from its {} bound {}",
+                        subtask.id, coverage.variable, extreme, value, kind, bound
+                    ))
+                    .with_help(format!(
+                        "Add a testcase with ${} close to {}",
+                        coverage.variable, bound
+                    ));
+                    if let Some(span) = &subtask.span {
+                        diagnostic = diagnostic.with_code_span(span.clone());
+                    }
+                    eval.add_diagnostic(diagnostic)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -33,6 +33,8 @@ pub struct TaskInfoLimits {
     time: Option<f64>,
     /// Memory limit in megabytes.
     memory: Option<u64>,
+    /// Limit on the number of user-space instructions.
+    instructions: Option<u64>,
 }
 
 /// Attachment of the task.
@@ -93,13 +95,14 @@ impl IOITaskInfo {
                     .sorted_by_key(|(&id, _)| id)
                     .map(|(_, subtask)| TaskInfoSubtask {
                         max_score: subtask.max_score,
-                        testcases: subtask.testcases.len() as u64,
+                        testcases: subtask.testcase_ids().count() as u64,
                     })
                     .collect(),
             },
             limits: TaskInfoLimits {
                 time: task.time_limit,
                 memory: task.memory_limit,
+                instructions: task.instruction_limit,
             },
             statements: task
                 .booklets
@@ -190,11 +190,10 @@ impl SolutionEvaluationState {
                             score: None,
                             normalized_score: None,
                             testcases: subtask
-                                .testcases
-                                .values()
+                                .testcase_ids()
                                 .map(|testcase| {
                                     (
-                                        testcase.id,
+                                        testcase,
                                         SolutionTestcaseEvaluationState {
                                             score: None,
                                             status: TestcaseEvaluationStatus::Pending,
@@ -210,6 +209,27 @@ impl SolutionEvaluationState {
                 .collect(),
         }
     }
+
+    /// Copy the state of a testcase from the subtask owning it to the subtasks including it, so
+    /// that they list it as well.
+    fn update_included(&mut self, task: &IOITask, subtask: SubtaskId, testcase: TestcaseId) {
+        let Some(state) = self
+            .subtasks
+            .get(&subtask)
+            .and_then(|st| st.testcases.get(&testcase))
+            .cloned()
+        else {
+            return;
+        };
+        for including in task.subtasks.values() {
+            if !including.included_testcases.contains(&testcase) {
+                continue;
+            }
+            if let Some(st) = self.subtasks.get_mut(&including.id) {
+                st.testcases.insert(testcase, state.clone());
+            }
+        }
+    }
 }
 
 /// The status of the compilation of a dependency of a booklet.
@@ -540,6 +560,7 @@ impl UIStateT for UIState {
                     .evaluations
                     .entry(solution)
                     .or_insert_with(|| SolutionEvaluationState::new(task));
+                let (subtask_id, testcase_id) = (subtask, testcase);
                 let subtask = eval.subtasks.get_mut(&subtask).expect("Missing subtask");
                 let testcase = subtask
                     .testcases
@@ -586,6 +607,7 @@ impl UIStateT for UIState {
                         testcase.status = TestcaseEvaluationStatus::Skipped
                     }
                 }
+                eval.update_included(task, subtask_id, testcase_id);
             }
             UIMessage::IOIChecker {
                 subtask,
@@ -598,6 +620,7 @@ impl UIStateT for UIState {
                     .evaluations
                     .entry(solution)
                     .or_insert_with(|| SolutionEvaluationState::new(task));
+                let (subtask_id, testcase_id) = (subtask, testcase);
                 let subtask = eval.subtasks.get_mut(&subtask).expect("Missing subtask");
                 let testcase = subtask
                     .testcases
@@ -612,6 +635,7 @@ impl UIStateT for UIState {
                     }
                     _ => {}
                 }
+                eval.update_included(task, subtask_id, testcase_id);
             }
             UIMessage::IOITestcaseScore {
                 subtask,
@@ -625,6 +649,7 @@ impl UIStateT for UIState {
                     .evaluations
                     .entry(solution)
                     .or_insert_with(|| SolutionEvaluationState::new(task));
+                let (subtask_id, testcase_id) = (subtask, testcase);
                 let subtask = eval.subtasks.get_mut(&subtask).expect("Missing subtask");
                 let testcase = subtask
                     .testcases
@@ -638,6 +663,7 @@ impl UIStateT for UIState {
                         ScoreStatus::PartialScore => TestcaseEvaluationStatus::Partial(message),
                     };
                 }
+                eval.update_included(task, subtask_id, testcase_id);
             }
             UIMessage::IOISubtaskScore {
                 subtask,
@@ -20,7 +20,7 @@ extern crate log;
 #[macro_use]
 extern crate pest_derive;
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
@@ -30,8 +30,9 @@ use serde::{Deserialize, Serialize};
 use typescript_definitions::TypeScriptify;
 
 pub use detect_format::find_task;
-pub use sanity_checks::get_sanity_check_list;
 pub use sanity_checks::SanityCheckCategory;
+pub use sanity_checks::{check_sanity_check_name, get_sanity_check_list};
+pub use sanity_checks::{SanityCheckLevel, SanityCheckLevels};
 pub use source_file::SourceFile;
 pub use tag::{Tag, VALID_TAGS};
 pub use task_format::*;
@@ -96,6 +97,10 @@ pub struct EvaluationConfig {
     pub solution_paths: Vec<PathBuf>,
     /// List of disabled sanity check names.
     pub disabled_sanity_checks: Vec<String>,
+    /// The level of some sanity checks, overriding the ones set in the task.
+    pub sanity_check_levels: HashMap<String, SanityCheckLevel>,
+    /// Treat the warnings of the sanity checks without an explicit level as errors.
+    pub deny_warnings: bool,
     /// Force this seed in terry evaluations.
     pub seed: Option<Seed>,
     /// Do not write any file inside the task directory.
@@ -1,11 +1,14 @@
 //! Sanity checks for IOI-like tasks.
 
-use std::sync::Mutex;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 
 use anyhow::Error;
-use task_maker_diagnostics::Diagnostic;
+use serde::{Deserialize, Deserializer, Serialize};
+use task_maker_diagnostics::{Diagnostic, DiagnosticLevel};
 
-use crate::EvaluationData;
+use crate::{EvaluationConfig, EvaluationData};
 
 /// Category of a sanity check.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -50,6 +53,98 @@ impl SanityCheckCategory {
     }
 }
 
+/// How the diagnostics emitted by a sanity check are reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SanityCheckLevel {
+    /// The diagnostics are silenced.
+    Allow,
+    /// The diagnostics are reported as they are emitted.
+    #[default]
+    Warn,
+    /// The warnings are turned into errors, and make the evaluation fail.
+    Deny,
+}
+
+impl SanityCheckLevel {
+    /// Apply this level to a diagnostic emitted by a sanity check, returning `None` if the
+    /// diagnostic should not be reported.
+    pub fn apply(self, diagnostic: Diagnostic) -> Option<Diagnostic> {
+        match self {
+            SanityCheckLevel::Allow => None,
+            SanityCheckLevel::Warn => Some(diagnostic),
+            SanityCheckLevel::Deny => Some(diagnostic.with_level(DiagnosticLevel::Error)),
+        }
+    }
+}
+
+impl FromStr for SanityCheckLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SanityCheckLevel, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "allow" => Ok(SanityCheckLevel::Allow),
+            "warn" => Ok(SanityCheckLevel::Warn),
+            "deny" => Ok(SanityCheckLevel::Deny),
+            _ => Err(format!("Unknown sanity check level: {}", s)),
+        }
+    }
+}
+
+/// The level of each sanity check, merged from the task.yaml and the command line.
+#[derive(Debug, Clone, Default)]
+pub struct SanityCheckLevels {
+    /// The level of the sanity checks not listed in `levels`.
+    default: SanityCheckLevel,
+    /// The level of each sanity check, by name.
+    levels: HashMap<String, SanityCheckLevel>,
+}
+
+impl SanityCheckLevels {
+    /// Merge the levels set by the task with the ones of the configuration, the latter take
+    /// precedence.
+    pub fn new(task_levels: &HashMap<String, SanityCheckLevel>, config: &EvaluationConfig) -> Self {
+        let mut levels = task_levels.clone();
+        levels.extend(
+            config
+                .sanity_check_levels
+                .iter()
+                .map(|(name, level)| (name.clone(), *level)),
+        );
+        SanityCheckLevels {
+            default: if config.deny_warnings {
+                SanityCheckLevel::Deny
+            } else {
+                SanityCheckLevel::Warn
+            },
+            levels,
+        }
+    }
+
+    /// The level of the sanity check with the provided name.
+    pub fn level(&self, name: &str) -> SanityCheckLevel {
+        self.levels.get(name).copied().unwrap_or(self.default)
+    }
+
+    /// Whether the sanity check with the provided name should run. The checks that are not enabled
+    /// by default run only if they are explicitly given a level other than allow.
+    pub fn is_enabled(&self, name: &str, enabled_by_default: bool) -> bool {
+        match self.levels.get(name) {
+            Some(level) => enabled_by_default || *level != SanityCheckLevel::Allow,
+            None => enabled_by_default,
+        }
+    }
+
+    /// Whether the diagnostic was emitted by a denied sanity check, and so it should make the
+    /// evaluation fail.
+    pub fn is_denied(&self, diagnostic: &Diagnostic) -> bool {
+        match diagnostic.code() {
+            Some(name) => self.level(name) == SanityCheckLevel::Deny,
+            None => false,
+        }
+    }
+}
+
 /// Trait that describes the behavior of a sanity check.
 pub trait SanityCheck: Send + Sync + std::fmt::Debug {
     /// The type of the task this sanity check is for.
@@ -61,6 +156,12 @@ pub trait SanityCheck: Send + Sync + std::fmt::Debug {
     /// The category of the sanity check.
     fn category(&self) -> SanityCheckCategory;
 
+    /// Whether the sanity check runs without setting its level. The expensive checks are opt-in
+    /// and run only when their level is set in the task.yaml or from the command line.
+    fn enabled_by_default(&self) -> bool {
+        true
+    }
+
     /// This function will be called before the actual execution of the DAG. It can add new
     /// executions to the DAG.
     fn pre_hook(&self, _task: &Self::Task, _eval: &mut EvaluationData) -> Result<(), Error> {
@@ -119,6 +220,8 @@ pub(crate) use make_sanity_check;
 struct SanityChecksState<Task: 'static> {
     /// The list of enabled sanity checks.
     sanity_checks: Vec<Box<dyn SanityCheck<Task = Task>>>,
+    /// The level of the enabled sanity checks.
+    levels: SanityCheckLevels,
 }
 
 /// Sanity checks for a IOI task.
@@ -134,10 +237,22 @@ impl<Task> SanityChecks<Task> {
         SanityChecks {
             state: Mutex::new(SanityChecksState {
                 sanity_checks: checks,
+                levels: Default::default(),
             }),
         }
     }
 
+    /// Set the level of the sanity checks.
+    pub fn with_levels(self, levels: SanityCheckLevels) -> SanityChecks<Task> {
+        self.state.lock().unwrap().levels = levels;
+        self
+    }
+
+    /// The level of the sanity checks.
+    pub fn levels(&self) -> SanityCheckLevels {
+        self.state.lock().unwrap().levels.clone()
+    }
+
     /// Function called for the first pass of sanity checks of the task. This will check all the
     /// statically checkable properties of the task and may add some executions for checking dynamic
     /// properties of the task.
@@ -145,14 +260,25 @@ impl<Task> SanityChecks<Task> {
     /// This is executed after the DAG of the task is built.
     pub fn pre_hook(&self, task: &Task, eval: &mut EvaluationData) -> Result<(), Error> {
         let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
         for check in state.sanity_checks.iter_mut() {
-            if let Err(e) = check.pre_hook(task, eval) {
-                eval.add_diagnostic(Diagnostic::warning(format!(
-                    "Sanity check {} failed: {}",
-                    check.name(),
-                    e
-                )))?;
+            if !state
+                .levels
+                .is_enabled(check.name(), check.enabled_by_default())
+            {
+                continue;
             }
+            let level = state.levels.level(check.name());
+            with_check_sender(eval, check.name(), level, |eval| {
+                if let Err(e) = check.pre_hook(task, eval) {
+                    eval.add_diagnostic(Diagnostic::warning(format!(
+                        "Sanity check {} failed: {}",
+                        check.name(),
+                        e
+                    )))?;
+                }
+                Ok(())
+            })?;
         }
         Ok(())
     }
@@ -161,24 +287,52 @@ impl<Task> SanityChecks<Task> {
     /// valid and the executions added by the pre_hook produced the correct results.
     pub fn post_hook(&self, task: &Task, eval: &mut EvaluationData) -> Result<(), Error> {
         let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
         for check in state.sanity_checks.iter_mut() {
-            if let Err(e) = check.post_hook(task, eval) {
-                eval.add_diagnostic(Diagnostic::warning(format!(
-                    "Sanity check {} failed: {}",
-                    check.name(),
-                    e
-                )))?;
+            if !state
+                .levels
+                .is_enabled(check.name(), check.enabled_by_default())
+            {
+                continue;
             }
+            let level = state.levels.level(check.name());
+            with_check_sender(eval, check.name(), level, |eval| {
+                if let Err(e) = check.post_hook(task, eval) {
+                    eval.add_diagnostic(Diagnostic::warning(format!(
+                        "Sanity check {} failed: {}",
+                        check.name(),
+                        e
+                    )))?;
+                }
+                Ok(())
+            })?;
         }
         Ok(())
     }
 }
 
+/// Run `f` with a UI sender that marks all the diagnostics as emitted by the sanity check `name`
+/// and applies its level to them, including the ones sent later by the callbacks registered inside
+/// `f`.
+fn with_check_sender<T>(
+    eval: &mut EvaluationData,
+    name: &str,
+    level: SanityCheckLevel,
+    f: impl FnOnce(&mut EvaluationData) -> T,
+) -> T {
+    let check_sender = eval.sender.lock().unwrap().for_sanity_check(name, level);
+    let sender = std::mem::replace(&mut eval.sender, Arc::new(Mutex::new(check_sender)));
+    let result = f(eval);
+    eval.sender = sender;
+    result
+}
+
 impl<Task> Default for SanityChecks<Task> {
     fn default() -> SanityChecks<Task> {
         SanityChecks {
             state: Mutex::new(SanityChecksState {
                 sanity_checks: vec![],
+                levels: Default::default(),
             }),
         }
     }
@@ -194,3 +348,103 @@ pub fn get_sanity_check_list() -> Vec<(&'static str, SanityCheckCategory)> {
         .map(|check| (check.name(), check.category()));
     ioi.chain(terry).collect()
 }
+
+/// Check that a sanity check with the provided name exists, for validating the names given by the
+/// user.
+pub fn check_sanity_check_name(name: &str) -> Result<(), String> {
+    if get_sanity_check_list()
+        .iter()
+        .any(|(check, _)| *check == name)
+    {
+        Ok(())
+    } else {
+        Err(format!("Unknown sanity check: {}", name))
+    }
+}
+
+/// Deserialize the levels of the sanity checks of a task.yaml, rejecting the unknown names like
+/// `--check-level` does.
+pub(crate) fn deserialize_sanity_check_levels<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, SanityCheckLevel>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let levels = HashMap::<String, SanityCheckLevel>::deserialize(deserializer)?;
+    for name in levels.keys() {
+        check_sanity_check_name(name).map_err(serde::de::Error::custom)?;
+    }
+    Ok(levels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanity_check_levels() {
+        let task_levels = HashMap::from([
+            ("A".to_string(), SanityCheckLevel::Allow),
+            ("B".to_string(), SanityCheckLevel::Deny),
+        ]);
+        let config = EvaluationConfig {
+            sanity_check_levels: HashMap::from([("B".to_string(), SanityCheckLevel::Warn)]),
+            deny_warnings: true,
+            ..Default::default()
+        };
+        let levels = SanityCheckLevels::new(&task_levels, &config);
+        assert_eq!(levels.level("A"), SanityCheckLevel::Allow);
+        assert_eq!(levels.level("B"), SanityCheckLevel::Warn);
+        assert_eq!(levels.level("C"), SanityCheckLevel::Deny);
+
+        let warning = Diagnostic::warning("Oops");
+        assert!(!levels.is_denied(&warning));
+        assert!(!levels.is_denied(&warning.clone().with_code("B")));
+        assert!(levels.is_denied(&warning.with_code("C")));
+    }
+
+    #[test]
+    fn test_sanity_check_levels_enabled() {
+        let task_levels = HashMap::from([
+            ("A".to_string(), SanityCheckLevel::Allow),
+            ("B".to_string(), SanityCheckLevel::Warn),
+        ]);
+        let levels = SanityCheckLevels::new(&task_levels, &Default::default());
+        assert!(levels.is_enabled("A", true));
+        assert!(!levels.is_enabled("A", false));
+        assert!(levels.is_enabled("B", true));
+        assert!(levels.is_enabled("B", false));
+        assert!(levels.is_enabled("C", true));
+        assert!(!levels.is_enabled("C", false));
+    }
+
+    #[test]
+    fn test_sanity_check_level_apply() {
+        let warning = Diagnostic::warning("Oops");
+        assert_eq!(SanityCheckLevel::Allow.apply(warning.clone()), None);
+        assert_eq!(
+            SanityCheckLevel::Warn.apply(warning.clone()),
+            Some(warning.clone())
+        );
+        let denied = SanityCheckLevel::Deny.apply(warning).unwrap();
+        assert_eq!(denied.level(), DiagnosticLevel::Error);
+        assert_eq!(denied.message(), "Oops");
+    }
+
+    #[test]
+    fn test_check_sanity_check_name() {
+        assert!(check_sanity_check_name("MissingSubtaskNames").is_ok());
+        assert_eq!(
+            check_sanity_check_name("MissingSubtaskName"),
+            Err("Unknown sanity check: MissingSubtaskName".to_string())
+        );
+    }
+
+    #[test]
+    fn test_sanity_check_level_from_str() {
+        assert_eq!("allow".parse(), Ok(SanityCheckLevel::Allow));
+        assert_eq!("Warn".parse(), Ok(SanityCheckLevel::Warn));
+        assert_eq!("DENY".parse(), Ok(SanityCheckLevel::Deny));
+        assert!("nope".parse::<SanityCheckLevel>().is_err());
+    }
+}
@@ -13,6 +13,9 @@ use crate::ui::*;
 use crate::EvaluationData;
 use crate::{bind_exec_callbacks, UISender};
 
+/// Maximum number of bytes of the standard error of the sanitized executions to capture.
+const SANITIZER_CONTENT_LENGTH: usize = 10 * 1024;
+
 /// Wrapper around [`task_maker_lang::SourceFile`](../task_maker_lang/struct.SourceFile.html) that
 /// also sends to the UI the messages about the compilation, making the compilation completely
 /// transparent to the `SourceFile`.
@@ -53,12 +56,15 @@ impl SourceFile {
         description: S,
         args: I,
     ) -> Result<Execution, Error> {
-        let (comp, exec) = self.base.execute(
+        let (comp, mut exec) = self.base.execute(
             &mut eval.dag,
             description,
             args.into_iter().map(|s| s.into()).collect(),
         )?;
         self.bind_compilation_exe(eval, comp)?;
+        if exec.sanitized {
+            Self::bind_sanitizer_reports(eval, &mut exec);
+        }
         Ok(exec)
     }
 
@@ -115,6 +121,50 @@ impl SourceFile {
         }
         Ok(())
     }
+
+    /// Capture the standard error of a sanitized execution and turn the sanitizer reports into
+    /// diagnostics.
+    fn bind_sanitizer_reports(eval: &mut EvaluationData, exec: &mut Execution) {
+        exec.capture_stderr(SANITIZER_CONTENT_LENGTH);
+        let sender = eval.sender.clone();
+        let description = exec.description.clone();
+        eval.dag.on_execution_done(&exec.uuid, move |result| {
+            let stderr = match result.stderr {
+                Some(stderr) => stderr,
+                None => return Ok(()),
+            };
+            let reports = sanitizer_reports(&stderr);
+            let diagnostic = if let Some(report) = reports
+                .iter()
+                .find(|report| report.starts_with("AddressSanitizer"))
+            {
+                Diagnostic::error(format!("Memory error detected in {}", description))
+                    .with_note(report)
+            } else if let Some(report) = reports.first() {
+                Diagnostic::warning(format!("Undefined behavior detected in {}", description))
+                    .with_note(report)
+            } else {
+                return Ok(());
+            };
+            sender.add_diagnostic(diagnostic.with_help_attachment(stderr))
+        });
+    }
+}
+
+/// Extract the sanitizer reports from the standard error of an execution, one line per report.
+fn sanitizer_reports(stderr: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(stderr)
+        .lines()
+        .filter_map(|line| {
+            if let Some(pos) = line.find("ERROR: AddressSanitizer:") {
+                Some(line["ERROR: ".len() + pos..].trim().to_string())
+            } else if line.contains(": runtime error: ") {
+                Some(line.trim().to_string())
+            } else {
+                None
+            }
+        })
+        .collect()
 }
 
 impl Deref for SourceFile {
@@ -130,3 +180,36 @@ impl DerefMut for SourceFile {
         &mut self.base
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitizer_reports_asan() {
+        let stderr = b"=================================================================
+==1234==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x602000000014
+READ of size 4 at 0x602000000014 thread T0
+    #0 0x55d0 in main sol.cpp:5
+";
+        assert_eq!(
+            sanitizer_reports(stderr),
+            vec!["AddressSanitizer: heap-buffer-overflow on address 0x602000000014"]
+        );
+    }
+
+    #[test]
+    fn test_sanitizer_reports_ubsan() {
+        let stderr = b"sol.cpp:7:11: runtime error: signed integer overflow: 2147483647 + 1 cannot be represented in type 'int'
+";
+        assert_eq!(
+            sanitizer_reports(stderr),
+            vec!["sol.cpp:7:11: runtime error: signed integer overflow: 2147483647 + 1 cannot be represented in type 'int'"]
+        );
+    }
+
+    #[test]
+    fn test_sanitizer_reports_none() {
+        assert!(sanitizer_reports(b"debug print\n").is_empty());
+    }
+}
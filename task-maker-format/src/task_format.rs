@@ -5,7 +5,9 @@ use serde::{Deserialize, Serialize};
 
 use task_maker_dag::ExecutionDAGConfig;
 
-use crate::{ui, EvaluationConfig, EvaluationData, IOITask, TaskInfo, TerryTask, UI};
+use crate::{
+    ui, EvaluationConfig, EvaluationData, IOITask, SanityCheckLevels, TaskInfo, TerryTask, UI,
+};
 
 /// The format of the task.
 /// A task format, providing a UI and the parsing and execution abilities.
@@ -60,6 +62,14 @@ impl TaskFormat {
         }
     }
 
+    /// The level of the sanity checks of this task.
+    pub fn sanity_check_levels(&self) -> SanityCheckLevels {
+        match self {
+            TaskFormat::IOI(task) => task.sanity_checks.levels(),
+            TaskFormat::Terry(task) => task.sanity_checks.levels(),
+        }
+    }
+
     /// Clean the task folder removing the files that can be generated automatically.
     pub fn clean(&self) -> Result<(), Error> {
         match self {
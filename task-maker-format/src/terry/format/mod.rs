@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 use std::sync::Arc;
@@ -8,7 +9,9 @@ use serde::{Deserialize, Serialize};
 use crate::terry::dag::{Checker, InputGenerator, InputValidator};
 use crate::terry::sanity_checks::get_sanity_checks;
 use crate::terry::TerryTask;
-use crate::{find_source_file, EvaluationConfig, SourceFile, WriteBinTo};
+use crate::{
+    find_source_file, EvaluationConfig, SanityCheckLevel, SanityCheckLevels, SourceFile, WriteBinTo,
+};
 
 lazy_static! {
     /// The extension suffix for the current platform.
@@ -25,6 +28,12 @@ struct TaskYAML {
     pub description: String,
     /// The maximum score for this task.
     pub max_score: f64,
+    /// The level of some sanity checks, by name: allow, warn or deny.
+    #[serde(
+        default,
+        deserialize_with = "crate::sanity_checks::deserialize_sanity_check_levels"
+    )]
+    pub sanity_checks: HashMap<String, SanityCheckLevel>,
 }
 
 /// Given a path to a task in the Terry format, try to parse the task inside of it.
@@ -53,13 +62,16 @@ pub fn parse_task<P: AsRef<Path>>(
         validator,
         checker,
         official_solution,
-        sanity_checks: Arc::new(get_sanity_checks(
-            &eval_config
-                .disabled_sanity_checks
-                .iter()
-                .map(String::as_str)
-                .collect::<Vec<_>>(),
-        )),
+        sanity_checks: Arc::new(
+            get_sanity_checks(
+                &eval_config
+                    .disabled_sanity_checks
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>(),
+            )
+            .with_levels(SanityCheckLevels::new(&yaml.sanity_checks, eval_config)),
+        ),
     })
 }
 
@@ -319,12 +319,28 @@ fn draw_server_status_summary(
     } else {
         return;
     };
-    let paragraph = Paragraph::new(Spans(vec![
+    let mut spans = vec![
         Span::styled(" Ready ", Style::default().add_modifier(Modifier::BOLD)),
         Span::raw(format!("{} ─", status.ready_execs)),
         Span::styled(" Waiting ", Style::default().add_modifier(Modifier::BOLD)),
         Span::raw(format!("{} ", status.waiting_execs)),
-    ]));
+    ];
+    // the share of the workers each client is using
+    let running: usize = status.clients.iter().map(|client| client.running).sum();
+    for client in &status.clients {
+        let share = if running == 0 {
+            0.0
+        } else {
+            100.0 * client.running as f64 / running as f64
+        };
+        spans.push(Span::raw("─"));
+        spans.push(Span::styled(
+            format!(" {} ", client.identity),
+            Style::default().add_modifier(Modifier::BOLD),
+        ));
+        spans.push(Span::raw(format!("{} ({:.0}%) ", client.running, share)));
+    }
+    let paragraph = Paragraph::new(Spans(spans));
     frame.render_widget(paragraph, rect);
 }
 
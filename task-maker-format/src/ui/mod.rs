@@ -22,6 +22,7 @@ use task_maker_dag::{ExecutionResourcesUsage, ExecutionResult, ExecutionStatus,
 use task_maker_diagnostics::DiagnosticContext;
 pub use ui_message::UIMessage;
 
+use crate::sanity_checks::SanityCheckLevel;
 use crate::{cwrite, cwriteln};
 
 pub mod curses;
@@ -321,17 +322,51 @@ impl<'a> FinishUIUtils<'a> {
 #[derive(Debug)]
 pub struct UIMessageSender {
     sender: UIChannelSender,
+    /// The name and the level of the sanity check that is sending the diagnostics, if any.
+    sanity_check: Option<(String, SanityCheckLevel)>,
 }
 
 impl UIMessageSender {
     /// Make a new pair of UIMessageSender and ChannelReceiver.
     pub fn new() -> (UIMessageSender, UIChannelReceiver) {
         let (sender, receiver) = channel();
-        (UIMessageSender { sender }, receiver)
+        (
+            UIMessageSender {
+                sender,
+                sanity_check: None,
+            },
+            receiver,
+        )
+    }
+
+    /// Make a new sender to the same channel that marks all the diagnostics without a code as
+    /// emitted by the sanity check `name`, and applies the level of the check to all of them.
+    pub fn for_sanity_check(
+        &self,
+        name: impl Into<String>,
+        level: SanityCheckLevel,
+    ) -> UIMessageSender {
+        UIMessageSender {
+            sender: self.sender.clone(),
+            sanity_check: Some((name.into(), level)),
+        }
     }
 
     /// Send a message to the channel.
     pub fn send(&self, message: UIMessage) -> Result<(), Error> {
+        let message = match (message, &self.sanity_check) {
+            (UIMessage::Diagnostic { diagnostic }, Some((name, level))) => {
+                let diagnostic = match diagnostic.code() {
+                    Some(_) => diagnostic,
+                    None => diagnostic.with_code(name),
+                };
+                match level.apply(diagnostic) {
+                    Some(diagnostic) => UIMessage::Diagnostic { diagnostic },
+                    None => return Ok(()),
+                }
+            }
+            (message, _) => message,
+        };
         self.sender.send(message).map_err(|e| e.into())
     }
 }
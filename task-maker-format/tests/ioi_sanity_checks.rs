@@ -1,8 +1,14 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::Arc;
-use task_maker_format::ioi::{Booklet, BookletConfig, IOITask, Statement, StatementConfig};
+use task_maker_dag::{ExecutionTag, File};
+use task_maker_format::ioi::sanity_checks::get_sanity_checks;
+use task_maker_format::ioi::{
+    Booklet, BookletConfig, ConstraintCoverage, IOITask, InputGenerator, Statement, StatementConfig,
+};
 use task_maker_format::ui::UIMessage;
-use task_maker_format::EvaluationData;
+use task_maker_format::{EvaluationData, SanityCheckLevel, SanityCheckLevels, SourceFile};
 use task_maker_lang::GraderMap;
 
 mod utils;
@@ -413,3 +419,154 @@ fn test_sanity_checks_statement_git_known() {
     let warnings = get_post_warnings(&task);
     does_not_have_warning(&warnings, "git");
 }
+
+fn constraint_coverage(
+    variable: &str,
+    lower_bound: Option<i64>,
+    upper_bound: Option<i64>,
+    values: &[i64],
+) -> ConstraintCoverage {
+    ConstraintCoverage {
+        variable: variable.into(),
+        lower_bound,
+        upper_bound,
+        values: values.to_vec(),
+    }
+}
+
+#[test]
+fn test_sanity_checks_constraint_bounds_reached() {
+    let mut task = utils::new_task();
+    task.subtasks.get_mut(&0).unwrap().constraint_coverage =
+        vec![constraint_coverage("N", Some(1), Some(100), &[1, 50, 95])];
+    let warnings = get_warnings(&task);
+    does_not_have_warning(&warnings, "far from its");
+}
+
+#[test]
+fn test_sanity_checks_constraint_bounds_not_reached() {
+    let mut task = utils::new_task();
+    task.subtasks.get_mut(&0).unwrap().constraint_coverage =
+        vec![constraint_coverage("N", Some(1), Some(100), &[1, 50])];
+    let warnings = get_warnings(&task);
+    has_warning(
+        &warnings,
+        "In subtask 0 $N is at most 50, far from its upper bound 100",
+    );
+    does_not_have_warning(&warnings, "lower bound");
+}
+
+#[test]
+fn test_sanity_checks_constraint_bounds_multiple_constraints() {
+    let mut task = utils::new_task();
+    task.subtasks.get_mut(&0).unwrap().constraint_coverage = vec![
+        constraint_coverage("N", Some(1), Some(100), &[1, 100]),
+        constraint_coverage("M", Some(1000), None, &[1500, 2000]),
+        constraint_coverage("K", None, Some(10), &[]),
+    ];
+    let warnings = get_warnings(&task);
+    has_warning(
+        &warnings,
+        "In subtask 0 $M is at least 1500, far from its lower bound 1000",
+    );
+    does_not_have_warning(&warnings, "$N");
+    // a variable not used in the subtask is not reported
+    does_not_have_warning(&warnings, "$K");
+}
+
+/// Make a task whose first testcase is generated by a generator, and run the IOGeneratorDeterminism
+/// check on it, feeding the two generations of the input with the provided contents. Returns the
+/// number of executions added by the check and the diagnostics it emitted.
+fn check_generator_determinism(
+    path: &Path,
+    level: Option<SanityCheckLevel>,
+    contents: [&str; 2],
+) -> (usize, Vec<String>) {
+    let mut task = utils::new_task_with_context(path);
+    std::fs::create_dir(path.join("gen")).unwrap();
+    let gen_path = path.join("gen/gen.py");
+    std::fs::write(&gen_path, "import random\nprint(random.random())\n").unwrap();
+    let generator = SourceFile::new(&gen_path, path, "Generator", None, None::<PathBuf>).unwrap();
+    let testcase = task
+        .subtasks
+        .get_mut(&0)
+        .unwrap()
+        .testcases
+        .get_mut(&0)
+        .unwrap();
+    testcase.input_generator = InputGenerator::Custom(Arc::new(generator), vec!["42".into()]);
+    testcase.input_file = Some(File::new("Input file").uuid);
+    let levels: HashMap<_, _> = level
+        .into_iter()
+        .map(|level| ("IOGeneratorDeterminism".to_string(), level))
+        .collect();
+    task.sanity_checks = Arc::new(
+        get_sanity_checks(&[]).with_levels(SanityCheckLevels::new(&levels, &Default::default())),
+    );
+
+    let (mut eval, recv) = EvaluationData::new(path);
+    task.sanity_checks.pre_hook(&task, &mut eval).unwrap();
+    let tag = ExecutionTag::from("generation-determinism");
+    let executions = eval
+        .dag
+        .data
+        .execution_groups
+        .values()
+        .filter(|group| group.tag().as_ref() == Some(&tag))
+        .count();
+    let mut generations = contents.iter();
+    for callbacks in eval.dag.file_callbacks().values_mut() {
+        if callbacks.get_content_chunked.is_empty() {
+            continue;
+        }
+        let content = generations.next().unwrap();
+        for callback in callbacks.get_content_chunked.iter_mut() {
+            callback(content.as_bytes()).unwrap();
+            callback(&[]).unwrap();
+        }
+    }
+    task.sanity_checks.post_hook(&task, &mut eval).unwrap();
+    let mut warnings = vec![];
+    while let Ok(mex) = recv.try_recv() {
+        if let UIMessage::Diagnostic { diagnostic } = mex {
+            warnings.push(diagnostic.to_string())
+        }
+    }
+    (executions, warnings)
+}
+
+#[test]
+fn test_sanity_checks_generator_determinism_opt_in() {
+    let tmpdir = tempfile::TempDir::new().unwrap();
+    let (executions, warnings) = check_generator_determinism(tmpdir.path(), None, ["", ""]);
+    assert_eq!(executions, 0);
+    does_not_have_warning(&warnings, "is not deterministic");
+}
+
+#[test]
+fn test_sanity_checks_generator_determinism_allowed() {
+    let tmpdir = tempfile::TempDir::new().unwrap();
+    let level = Some(SanityCheckLevel::Allow);
+    let (executions, warnings) = check_generator_determinism(tmpdir.path(), level, ["1", "2"]);
+    assert_eq!(executions, 0);
+    does_not_have_warning(&warnings, "is not deterministic");
+}
+
+#[test]
+fn test_sanity_checks_generator_deterministic() {
+    let tmpdir = tempfile::TempDir::new().unwrap();
+    let level = Some(SanityCheckLevel::Warn);
+    let (executions, warnings) = check_generator_determinism(tmpdir.path(), level, ["1", "1"]);
+    assert_eq!(executions, 1);
+    does_not_have_warning(&warnings, "is not deterministic");
+}
+
+#[test]
+fn test_sanity_checks_generator_not_deterministic() {
+    let tmpdir = tempfile::TempDir::new().unwrap();
+    let level = Some(SanityCheckLevel::Warn);
+    let (executions, warnings) = check_generator_determinism(tmpdir.path(), level, ["1", "2"]);
+    assert_eq!(executions, 1);
+    has_warning(&warnings, "Generator gen/gen.py is not deterministic");
+    has_warning(&warnings, "0 (args: 42)");
+}
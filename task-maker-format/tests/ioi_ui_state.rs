@@ -16,6 +16,7 @@ fn test_ui_state_server_status() {
         connected_workers: vec![],
         ready_execs: 1,
         waiting_execs: 123,
+        clients: vec![],
     };
     assert_eq!(ui.executor_status, None);
     ui.apply(UIMessage::ServerStatus {
@@ -666,6 +667,30 @@ fn test_ui_state_testcase_score_accepted() {
     );
 }
 
+#[test]
+fn test_ui_state_testcase_score_included() {
+    let mut task = utils::new_task();
+    task.subtasks.get_mut(&1).unwrap().included_testcases = vec![0];
+    let mut ui = UIState::new(&task, Default::default());
+    let file = PathBuf::from("file");
+    ui.apply(UIMessage::IOITestcaseScore {
+        subtask: 0,
+        testcase: 0,
+        solution: file.clone(),
+        score: 1.0,
+        message: "yep".to_string(),
+    });
+    for subtask in [0, 1] {
+        let testcase = &ui.evaluations[&file].subtasks[&subtask].testcases[&0];
+        assert_eq!(testcase.score, Some(1.0));
+        assert_eq!(
+            testcase.status,
+            TestcaseEvaluationStatus::Accepted("yep".into())
+        );
+    }
+    assert_eq!(ui.evaluations[&file].subtasks[&1].testcases.len(), 3);
+}
+
 #[test]
 fn test_ui_state_subtask_score() {
     let task = utils::new_task();
@@ -206,3 +206,65 @@ fn test_score_manager_empty_subtask() {
     }
     assert!(receiver.try_recv().is_err());
 }
+
+#[test]
+fn test_score_manager_included_testcases() {
+    let mut task = utils::new_task();
+    // The second subtask also includes the testcase of the first one.
+    task.subtasks.get_mut(&1).unwrap().included_testcases = vec![0];
+
+    let mut manager = ScoreManager::new(&task);
+    let (sender, receiver) = UIMessageSender::new();
+    let sender = Arc::new(Mutex::new(sender));
+
+    manager
+        .score(1, 1, 1.0, "foo".into(), sender.clone(), "sol".into())
+        .unwrap();
+    manager
+        .score(1, 2, 1.0, "foo".into(), sender.clone(), "sol".into())
+        .unwrap();
+    // The second subtask still waits for the included testcase.
+    let messages: Vec<_> = receiver.try_iter().collect();
+    assert_eq!(messages.len(), 2);
+    for mex in messages {
+        if !matches!(mex, UIMessage::IOITestcaseScore { .. }) {
+            panic!("Expecting UIMessage::IOITestcaseScore but was {:?}", mex);
+        }
+    }
+
+    manager
+        .score(0, 0, 0.5, "foo".into(), sender, "sol".into())
+        .unwrap();
+    let messages: Vec<_> = receiver.try_iter().collect();
+    assert_eq!(messages.len(), 4, "{:?}", messages);
+    match &messages[0] {
+        UIMessage::IOITestcaseScore {
+            subtask, testcase, ..
+        } => {
+            assert_eq!(*subtask, 0);
+            assert_eq!(*testcase, 0);
+        }
+        mex => panic!("Expecting UIMessage::IOITestcaseScore but was {:?}", mex),
+    }
+    for (mex, (expected_subtask, expected_score)) in
+        messages[1..3].iter().zip([(0, 5.0), (1, 45.0)])
+    {
+        match mex {
+            UIMessage::IOISubtaskScore {
+                subtask,
+                score,
+                normalized_score,
+                ..
+            } => {
+                assert_eq!(*subtask, expected_subtask);
+                assert_abs_diff_eq!(*score, expected_score);
+                assert_abs_diff_eq!(*normalized_score, 0.5);
+            }
+            mex => panic!("Expecting UIMessage::IOISubtaskScore but was {:?}", mex),
+        }
+    }
+    match &messages[3] {
+        UIMessage::IOITaskScore { score, .. } => assert_abs_diff_eq!(*score, 50.0),
+        mex => panic!("Expecting UIMessage::IOITaskScore but was {:?}", mex),
+    }
+}
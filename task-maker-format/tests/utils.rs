@@ -28,6 +28,7 @@ pub fn new_task_with_context(path: &Path) -> IOITask {
         title: "The Task".to_string(),
         time_limit: None,
         memory_limit: None,
+        instruction_limit: None,
         infile: None,
         outfile: None,
         subtasks: HashMap::new(),
@@ -48,6 +49,8 @@ pub fn new_task_with_context(path: &Path) -> IOITask {
         testcases: HashMap::default(),
         span: None,
         is_default: false,
+        constraint_coverage: vec![],
+        included_testcases: vec![],
     });
     st0.testcases.entry(0).or_insert_with(|| {
         TestcaseInfo::new(
@@ -65,6 +68,8 @@ pub fn new_task_with_context(path: &Path) -> IOITask {
         testcases: HashMap::default(),
         span: None,
         is_default: false,
+        constraint_coverage: vec![],
+        included_testcases: vec![],
     });
     st1.testcases.entry(1).or_insert_with(|| {
         TestcaseInfo::new(
@@ -95,6 +100,7 @@ pub fn good_result() -> ExecutionResult {
             sys_time: 0.0,
             wall_time: 0.0,
             memory: 0,
+            instructions: None,
         },
         stdout: None,
         stderr: None,
@@ -111,6 +117,7 @@ pub fn bad_result() -> ExecutionResult {
             sys_time: 0.0,
             wall_time: 0.0,
             memory: 0,
+            instructions: None,
         },
         stdout: None,
         stderr: None,
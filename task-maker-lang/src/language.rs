@@ -28,6 +28,12 @@ pub trait Language: std::fmt::Debug + Send + Sync {
     /// some extra methods.
     fn need_compilation(&self) -> bool;
 
+    /// Whether the sources in this language can be compiled with the address and undefined
+    /// behavior sanitizers, following `CompilationSettings::sanitize`.
+    fn supports_sanitizers(&self) -> bool {
+        false
+    }
+
     /// The prefix to put at the start of a line to mark the whole line as a comment.
     ///
     /// The return value should include a space character only if required by the language.
@@ -101,6 +107,9 @@ pub struct CompilationSettings {
     pub copy_exe: bool,
     /// Whether to try to link statically the binary.
     pub list_static: bool,
+    /// Whether to compile the binary with the address and undefined behavior sanitizers. Those
+    /// cannot be linked statically, so this takes precedence over `list_static`.
+    pub sanitize: bool,
 }
 
 /// This trait describes the API of a "compiled language builder", a component that builds the DAG
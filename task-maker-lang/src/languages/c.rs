@@ -64,6 +64,10 @@ impl Language for LanguageC {
         true
     }
 
+    fn supports_sanitizers(&self) -> bool {
+        true
+    }
+
     fn inline_comment_prefix(&self) -> Option<&'static str> {
         Some("//")
     }
@@ -93,7 +97,11 @@ impl Language for LanguageC {
         for arg in &self.config.extra_flags {
             metadata.add_arg(arg);
         }
-        if metadata.settings.list_static {
+        if metadata.settings.sanitize {
+            metadata
+                .add_arg("-fsanitize=address,undefined")
+                .add_arg("-fno-omit-frame-pointer");
+        } else if metadata.settings.list_static {
             metadata.add_arg("-static");
         }
 
@@ -165,4 +173,28 @@ mod tests {
         assert_that(&args).contains("-lfoobar".to_string());
         assert_that(&args).contains("-static".to_string());
     }
+
+    #[test]
+    fn test_compilation_args_sanitize() {
+        let tmp = setup();
+
+        let lang = LanguageC::new(LanguageCConfiguration {
+            compiler: ExecutionCommand::System("gcc".into()),
+            std_version: "c11".to_string(),
+            extra_flags: vec![],
+        });
+        let settings = CompilationSettings {
+            list_static: true,
+            sanitize: true,
+            ..Default::default()
+        };
+        let mut builder = lang
+            .compilation_builder(&tmp.path().join("foo.c"), settings)
+            .unwrap();
+        let (comp, _exec) = builder.finalize(&mut ExecutionDAG::new()).unwrap();
+
+        let args = comp.args;
+        assert_that(&args).contains("-fsanitize=address,undefined".to_string());
+        assert_that(&args).does_not_contain("-static".to_string());
+    }
 }
@@ -181,6 +181,12 @@ impl SourceFile {
             }
         }
         self.language.custom_limits(exec.limits_mut());
+        if self.sanitize(dag) {
+            exec.sanitized(true);
+            // the leak detector needs ptrace, which is not available inside the sandbox
+            exec.env("ASAN_OPTIONS", "detect_leaks=0");
+            exec.env("UBSAN_OPTIONS", "print_stacktrace=1");
+        }
         // some languages (e.g. Python) may behave strangely with LC_ALL=C
         exec.copy_env("LANG");
         exec.copy_env("LC_ALL");
@@ -261,7 +267,8 @@ impl SourceFile {
         let settings = CompilationSettings {
             write_to: write_to.map(Into::into),
             list_static: self.link_static,
-            copy_exe: dag.config_mut().copy_exe || self.copy_exe,
+            copy_exe: dag.config().copy_exe || self.copy_exe,
+            sanitize: self.sanitize(dag),
         };
         if let Some(mut metadata) = self.language.compilation_builder(&self.path, settings) {
             if let Some(grader_map) = self.grader_map.as_ref() {
@@ -285,7 +292,7 @@ impl SourceFile {
             Ok(Some(comp_uuid))
         } else {
             let executable = File::new(format!("Source file of {:?}", self.path));
-            if dag.config_mut().copy_exe || self.copy_exe {
+            if dag.config().copy_exe || self.copy_exe {
                 if let Some(write_bin_to) = &self.write_bin_to {
                     dag.write_file_to(&executable, write_bin_to, true);
                 }
@@ -297,6 +304,12 @@ impl SourceFile {
         }
     }
 
+    /// Whether this source file is compiled with the sanitizers. The statically linked ones (e.g.
+    /// the checkers) are never sanitized since the sanitizers don't support static linking.
+    fn sanitize(&self, dag: &ExecutionDAG) -> bool {
+        dag.config().sanitize && !self.link_static && self.language.supports_sanitizers()
+    }
+
     /// The language of the source file.
     pub fn language(&self) -> &dyn Language {
         self.language.as_ref()
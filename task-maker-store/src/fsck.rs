@@ -0,0 +1,306 @@
+use std::collections::HashSet;
+use std::fs::{read_dir, remove_dir, remove_dir_all};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Error};
+
+use crate::index::FileStoreIndex;
+use crate::{FileStore, FileStoreKey, STORE_INDEX_FILE};
+
+/// The outcome of the verification of a [`FileStore`], with all the problems that have been
+/// repaired.
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    /// The number of files whose content has been verified.
+    pub checked: usize,
+    /// The files whose content didn't match their key. They have been removed.
+    pub corrupted: Vec<FileStoreKey>,
+    /// The files in the index that were missing from the disk. They have been removed from the
+    /// index.
+    pub missing: Vec<FileStoreKey>,
+    /// The paths inside the store that are not files of the store, like the leftovers of an
+    /// interrupted write. They have been removed.
+    pub orphans: Vec<PathBuf>,
+    /// The valid files that were not in the index. They have been added back to it.
+    pub untracked: Vec<FileStoreKey>,
+    /// The files whose size in the index didn't match the disk. The index has been fixed.
+    pub resized: Vec<FileStoreKey>,
+    /// The files that were writable. They have been made read-only again.
+    pub writable: Vec<FileStoreKey>,
+}
+
+impl FsckReport {
+    /// Sort all the lists of the report.
+    fn sort(&mut self) {
+        self.corrupted.sort();
+        self.missing.sort();
+        self.orphans.sort();
+        self.untracked.sort();
+        self.resized.sort();
+        self.writable.sort();
+    }
+
+    /// Whether no problem has been found.
+    pub fn is_clean(&self) -> bool {
+        self.corrupted.is_empty()
+            && self.missing.is_empty()
+            && self.orphans.is_empty()
+            && self.untracked.is_empty()
+            && self.resized.is_empty()
+            && self.writable.is_empty()
+    }
+}
+
+impl FileStore {
+    /// Verify the integrity of the store, rehashing all its files and reconciling the index with
+    /// the content of the disk.
+    ///
+    /// The corrupted files and the paths that don't belong to the store are removed, the index
+    /// forgets the missing files and learns the untracked ones, and the sizes and access times of
+    /// the index are fixed. The index is stored at the end.
+    pub fn fsck(&self) -> Result<FsckReport, Error> {
+        let mut index = self.index.lock().unwrap();
+        let mut report = FsckReport::default();
+        let mut seen = HashSet::new();
+        let now = SystemTime::now();
+
+        for first in layout_dirs(&self.base_path)? {
+            for second in layout_dirs(&first)? {
+                let entries = read_dir(&second)
+                    .with_context(|| format!("Failed to list {}", second.display()))?;
+                for entry in entries {
+                    let path = entry.context("Failed to list the store")?.path();
+                    match self.path_to_key(&path) {
+                        // a file stored both plain and compressed is kept only once
+                        Some((key, compressed)) if !seen.contains(&key) => {
+                            self.fsck_file(&mut index, &mut report, &path, &key, compressed, now)?;
+                            seen.insert(key);
+                        }
+                        _ => remove_orphan(&mut report, path),
+                    }
+                }
+                let _ = remove_dir(&second);
+            }
+            let _ = remove_dir(&first);
+        }
+
+        let missing: Vec<_> = index
+            .keys()
+            .filter(|key| !seen.contains(*key))
+            .cloned()
+            .collect();
+        for key in missing {
+            index.remove(&key);
+            report.missing.push(key);
+        }
+        report.sort();
+        index
+            .store(self.base_path.join(STORE_INDEX_FILE))
+            .context("Failed to store the index to file")?;
+        Ok(report)
+    }
+
+    /// Verify a single file of the store, removing it if corrupted.
+    fn fsck_file(
+        &self,
+        index: &mut FileStoreIndex,
+        report: &mut FsckReport,
+        path: &Path,
+        key: &FileStoreKey,
+        compressed: bool,
+        now: SystemTime,
+    ) -> Result<(), Error> {
+        report.checked += 1;
+        // the metadata is read before hashing, which may change the access time
+        let metadata = std::fs::metadata(path)
+            .with_context(|| format!("Failed to get file metadata of {}", path.display()))?;
+        let valid =
+            FileStoreKey::from_stored_file(path, compressed).map_or(false, |actual| &actual == key);
+        if !valid {
+            warn!("File {} is corrupted, removing it", path.display());
+            FileStore::remove_file(path)?;
+            index.remove(key);
+            report.corrupted.push(key.clone());
+            return Ok(());
+        }
+        if !metadata.permissions().readonly() {
+            FileStore::mark_readonly(path)?;
+            report.writable.push(key.clone());
+        }
+        let size = metadata.len();
+        match index.size(key) {
+            None => {
+                let accessed = metadata.accessed().unwrap_or(now).min(now);
+                index.insert(key.clone(), size, accessed);
+                report.untracked.push(key.clone());
+            }
+            Some(indexed) => {
+                if indexed != size {
+                    report.resized.push(key.clone());
+                }
+                // an access in the future would keep the file forever
+                let last_access = index.last_access(key).unwrap_or(now).min(now);
+                index.insert(key.clone(), size, last_access);
+            }
+        }
+        Ok(())
+    }
+
+    /// The key of a file, if its path is the one the store would use for it, and whether the file
+    /// is compressed.
+    fn path_to_key(&self, path: &Path) -> Option<(FileStoreKey, bool)> {
+        if !path.is_file() {
+            return None;
+        }
+        let key = FileStoreKey::from_hex(path.file_stem()?.to_str()?)?;
+        if self.key_to_path(&key) == path {
+            Some((key, false))
+        } else if self.compressed_path(&key) == path {
+            Some((key, true))
+        } else {
+            None
+        }
+    }
+}
+
+/// The subdirectories of the store layout (named with 2 hex digits) inside `dir`. The other paths
+/// are ignored.
+fn layout_dirs(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut dirs = vec![];
+    let entries = read_dir(dir).with_context(|| format!("Failed to list {}", dir.display()))?;
+    for entry in entries {
+        let path = entry.context("Failed to list the store")?.path();
+        let is_layout = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| {
+                name.len() == 2 && name.bytes().all(|b| b.is_ascii_hexdigit())
+            });
+        if is_layout && path.is_dir() {
+            dirs.push(path);
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Remove a path that doesn't belong to the store.
+fn remove_orphan(report: &mut FsckReport, path: PathBuf) {
+    warn!(
+        "Removing {}, which is not a file of the store",
+        path.display()
+    );
+    let res = if path.is_dir() {
+        remove_dir_all(&path).with_context(|| format!("Failed to remove {}", path.display()))
+    } else {
+        FileStore::remove_file(&path)
+    };
+    if let Err(e) = res {
+        warn!("Cannot remove {}: {:?}", path.display(), e);
+    }
+    report.orphans.push(path);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+
+    use tempfile::TempDir;
+
+    use crate::ReadFileIterator;
+
+    use super::*;
+
+    fn add_file(store: &FileStore, dir: &Path, content: &str) -> FileStoreKey {
+        let path = dir.join("file.txt");
+        std::fs::write(&path, content).unwrap();
+        let key = FileStoreKey::from_file(&path).unwrap();
+        store
+            .store(&key, ReadFileIterator::new(&path).unwrap())
+            .unwrap();
+        key
+    }
+
+    fn make_writable(path: &Path) {
+        let mut perms = std::fs::metadata(path).unwrap().permissions();
+        perms.set_mode(0o600);
+        std::fs::set_permissions(path, perms).unwrap();
+    }
+
+    #[test]
+    fn test_fsck_clean() {
+        let tmp = TempDir::new().unwrap();
+        let store = FileStore::new(tmp.path().join("store"), 1000, 1000).unwrap();
+        add_file(&store, tmp.path(), "hello");
+        add_file(&store, tmp.path(), "world");
+
+        let report = store.fsck().unwrap();
+        assert_eq!(report.checked, 2);
+        assert!(report.is_clean(), "{:?}", report);
+    }
+
+    #[test]
+    fn test_fsck_repair() {
+        let tmp = TempDir::new().unwrap();
+        let store = FileStore::new(tmp.path().join("store"), 1000, 1000).unwrap();
+        let corrupted = add_file(&store, tmp.path(), "corrupted");
+        let missing = add_file(&store, tmp.path(), "missing");
+        let writable = add_file(&store, tmp.path(), "writable");
+
+        let path = store.key_to_path(&corrupted);
+        make_writable(&path);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"!!!").unwrap();
+        FileStore::remove_file(&store.key_to_path(&missing)).unwrap();
+        make_writable(&store.key_to_path(&writable));
+        let orphan = store.key_to_path(&writable).with_file_name(".tmp1234");
+        std::fs::create_dir(&orphan).unwrap();
+        let untracked = FileStoreKey::from_content(b"untracked");
+        let path = store.key_to_path(&untracked);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "untracked").unwrap();
+
+        let report = store.fsck().unwrap();
+        assert_eq!(report.corrupted, vec![corrupted.clone()]);
+        assert_eq!(report.missing, vec![missing]);
+        let mut expected = vec![writable.clone(), untracked.clone()];
+        expected.sort();
+        assert_eq!(report.writable, expected);
+        assert_eq!(report.untracked, vec![untracked.clone()]);
+        assert_eq!(report.orphans, vec![orphan.clone()]);
+        assert!(!orphan.exists());
+        assert!(store.get(&corrupted).is_none());
+        assert!(store.get(&writable).is_some());
+        assert!(store.get(&untracked).is_some());
+        assert_eq!(store.num_files(), 2);
+        assert_eq!(store.total_size(), "writable".len() as u64 + 9);
+
+        let report = store.fsck().unwrap();
+        assert!(report.is_clean(), "{:?}", report);
+    }
+
+    #[test]
+    fn test_fsck_compressed() {
+        let tmp = TempDir::new().unwrap();
+        let store = FileStore::new(tmp.path().join("store"), 1_000_000, 1_000_000)
+            .unwrap()
+            .with_compression(Some(3));
+        let content = "compressed".repeat(10_000);
+        let valid = add_file(&store, tmp.path(), &content);
+        let corrupted = add_file(&store, tmp.path(), &content.to_uppercase());
+        assert!(store.compressed_path(&valid).exists());
+
+        let path = store.compressed_path(&corrupted);
+        make_writable(&path);
+        std::fs::write(&path, "not zstd").unwrap();
+
+        let report = store.fsck().unwrap();
+        assert_eq!(report.checked, 2);
+        assert_eq!(report.corrupted, vec![corrupted]);
+        assert!(report.resized.is_empty());
+        assert!(store.get(&valid).is_some());
+    }
+}
@@ -1,6 +1,6 @@
 use std::cmp::Ordering;
 use std::collections::hash_map::Entry;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
 use std::fs::{create_dir_all, remove_dir, File};
 use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::Path;
@@ -18,7 +18,15 @@ use crate::{FileStore, FileStoreKey, LockedFiles};
 /// The newline at the end of the string is required. For example, let's say there are 2 versions:
 /// v0.1 and v0.11; running v0.11 first, and then v0.1, without the newline the magic of the old
 /// version is a prefix of the magic of the new version.
-const MAGIC: &[u8] = formatcp!("task-maker-store v{}\n", env!("CARGO_PKG_VERSION")).as_bytes();
+const MAGIC: &[u8] = formatcp!(
+    "task-maker-store v{} r{}\n",
+    env!("CARGO_PKG_VERSION"),
+    FORMAT_REVISION
+)
+.as_bytes();
+
+/// Revision of the format of the index file, to bump when it changes between two releases.
+const FORMAT_REVISION: u32 = 1;
 
 /// An entry of a file inside the file store.
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
@@ -41,12 +49,18 @@ impl PartialOrd for FileStoreIndexItem {
 }
 
 /// Index with all the files known, allowing efficient LRU file flushing.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub(crate) struct FileStoreIndex {
     /// The sum of the size of all the files in the index.
     total_size: u64,
     /// The list of all the files known in the index.
     known_files: HashMap<FileStoreKey, FileStoreIndexItem>,
+    /// The named sets of files that are never removed by the flush.
+    pins: BTreeMap<String, HashSet<FileStoreKey>>,
+    /// The sum of the size of the pinned files in the index, each counted once. It's kept updated
+    /// since it's needed at every store, and recomputed when the index is loaded.
+    #[serde(skip)]
+    pinned_size: u64,
 }
 
 impl FileStoreIndex {
@@ -55,10 +69,7 @@ impl FileStoreIndex {
         let path = path.as_ref();
         if !path.exists() {
             debug!("Index at {:?} not found, creating new one", path);
-            return Ok(FileStoreIndex {
-                total_size: 0,
-                known_files: HashMap::new(),
-            });
+            return Ok(FileStoreIndex::default());
         }
 
         debug!("Loading index from {:?}", path);
@@ -75,13 +86,14 @@ impl FileStoreIndex {
                 "FileStore version mismatch:\nExpected: {:?}\nFound: {:?}",
                 MAGIC, magic
             );
-            return Ok(FileStoreIndex {
-                total_size: 0,
-                known_files: HashMap::new(),
-            });
+            return Ok(FileStoreIndex::default());
         }
 
-        bincode::deserialize_from(reader).context("Failed to deserialize index file")
+        let mut index: FileStoreIndex =
+            bincode::deserialize_from(reader).context("Failed to deserialize index file")?;
+        let pinned: HashSet<_> = index.pins.values().flatten().collect();
+        index.pinned_size = pinned.into_iter().filter_map(|key| index.size(key)).sum();
+        Ok(index)
     }
 
     /// Store a dump of this index to the path provided.
@@ -107,6 +119,115 @@ impl FileStoreIndex {
         Ok(())
     }
 
+    /// The size of a file in the index, if known.
+    pub(crate) fn size(&self, key: &FileStoreKey) -> Option<u64> {
+        self.known_files.get(key).map(|file| file.size)
+    }
+
+    /// The sum of the size of all the files in the index.
+    pub(crate) fn total_size(&self) -> u64 {
+        self.total_size
+    }
+
+    /// The number of files in the index.
+    pub(crate) fn num_files(&self) -> usize {
+        self.known_files.len()
+    }
+
+    /// The keys of all the files in the index.
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &FileStoreKey> {
+        self.known_files.keys()
+    }
+
+    /// Add a file to the index, or replace its size and access time if already present.
+    pub(crate) fn insert(&mut self, key: FileStoreKey, size: u64, last_access: SystemTime) {
+        let pinned = self.is_pinned(&key);
+        let item = FileStoreIndexItem { size, last_access };
+        if let Some(old) = self.known_files.insert(key, item) {
+            self.total_size -= old.size;
+            if pinned {
+                self.pinned_size -= old.size;
+            }
+        }
+        self.total_size += size;
+        if pinned {
+            self.pinned_size += size;
+        }
+    }
+
+    /// Remove a file from the index, returning whether it was present.
+    pub(crate) fn remove(&mut self, key: &FileStoreKey) -> bool {
+        match self.known_files.remove(key) {
+            Some(old) => {
+                self.total_size -= old.size;
+                if self.is_pinned(key) {
+                    self.pinned_size -= old.size;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The time of the last access to a file in the index, if known.
+    pub(crate) fn last_access(&self, key: &FileStoreKey) -> Option<SystemTime> {
+        self.known_files.get(key).map(|file| file.last_access)
+    }
+
+    /// The named sets of pinned files.
+    pub(crate) fn pins(&self) -> &BTreeMap<String, HashSet<FileStoreKey>> {
+        &self.pins
+    }
+
+    /// Add some files to the pin with the provided name, creating it if needed.
+    pub(crate) fn pin<I>(&mut self, name: &str, keys: I)
+    where
+        I: IntoIterator<Item = FileStoreKey>,
+    {
+        for key in keys {
+            if !self.is_pinned(&key) {
+                self.pinned_size += self.size(&key).unwrap_or(0);
+            }
+            self.pins.entry(name.to_string()).or_default().insert(key);
+        }
+    }
+
+    /// Remove the pin with the provided name, returning whether it existed.
+    pub(crate) fn unpin(&mut self, name: &str) -> bool {
+        match self.pins.remove(name) {
+            Some(keys) => {
+                for key in keys {
+                    if !self.is_pinned(&key) {
+                        self.pinned_size -= self.size(&key).unwrap_or(0);
+                    }
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the file is part of at least one pin.
+    pub(crate) fn is_pinned(&self, key: &FileStoreKey) -> bool {
+        self.pins.values().any(|pin| pin.contains(key))
+    }
+
+    /// The total size of the pinned files in the index, as if also the files in `extra` were
+    /// pinned. Each file is counted once, even if it's part of many pins.
+    pub(crate) fn pinned_size_with(&self, extra: &HashSet<FileStoreKey>) -> u64 {
+        let extra_size: u64 = extra
+            .iter()
+            .filter(|key| !self.is_pinned(key))
+            .filter_map(|key| self.size(key))
+            .sum();
+        self.pinned_size + extra_size
+    }
+
+    /// The total size of the pinned files in the index.
+    pub(crate) fn pinned_size(&self) -> u64 {
+        self.pinned_size
+    }
+
     /// Mark a file as accessed, bumping its position in the LRU.
     pub(crate) fn touch(&mut self, key: &FileStoreKey) {
         if let Some(file) = self.known_files.get_mut(key) {
@@ -124,23 +245,30 @@ impl FileStoreIndex {
             Entry::Vacant(entry) => {
                 let metadata = std::fs::metadata(path)
                     .with_context(|| format!("Cannot get file metadata of {}", path.display()))?;
+                let pinned = self.pins.values().any(|pin| pin.contains(entry.key()));
                 entry.insert(FileStoreIndexItem {
                     size: metadata.len(),
                     last_access: SystemTime::now(),
                 });
                 self.total_size += metadata.len();
+                if pinned {
+                    self.pinned_size += metadata.len();
+                }
             }
         }
         Ok(())
     }
 
-    /// Whether this file store needs to flush away some files to free space.
-    pub(crate) fn need_flush(&self, size_limit: u64) -> bool {
-        self.total_size >= size_limit
+    /// Whether this file store needs to flush away some files to free space. The pinned files have
+    /// their own budget, so they don't count, while the decompressed copies of the files in use do.
+    pub(crate) fn need_flush(&self, size_limit: u64, locked_files: &LockedFiles) -> bool {
+        self.total_size - self.pinned_size() + locked_files.materialized_size >= size_limit
     }
 
     /// Perform a flushing operation, cleaning some space on the disk by removing the Least Recently
-    /// Used files. This function won't remove the files currently locked.
+    /// Used files. This function won't remove the files currently locked, nor the pinned ones, and
+    /// `target_size` doesn't include the pinned files. The decompressed copies of the files in use
+    /// cannot be removed, but they take space, so they are counted toward `target_size`.
     pub(crate) fn flush(
         &mut self,
         file_store: &FileStore,
@@ -152,6 +280,8 @@ impl FileStoreIndex {
             self.total_size / 1024 / 1024,
             target_size / 1024 / 1024
         );
+        // the size of the files that count toward the limit
+        let mut size = self.total_size - self.pinned_size() + locked_files.materialized_size;
         // list of entries that survive the flush
         let mut surviving = Vec::new();
         let mut priority_queue: BinaryHeap<(FileStoreIndexItem, FileStoreKey)> =
@@ -159,21 +289,25 @@ impl FileStoreIndex {
         // number of removed bytes
         let mut removed = 0;
         // continue to remove until the space requirement is met
-        while self.total_size > target_size {
+        while size > target_size {
             let (entry, key) = match priority_queue.pop() {
                 Some(e) => e,
                 // the queue is emptied before reaching the space requirement (maybe because of
                 // locking)
                 None => break,
             };
-            // cannot remove a file used by some other process
-            if locked_files.ref_counts.contains_key(&key) {
+            // cannot remove a file used by some other process, or explicitly pinned
+            if locked_files.ref_counts.contains_key(&key) || self.is_pinned(&key) {
                 surviving.push((key, entry));
             } else {
                 self.total_size -= entry.size;
+                size -= entry.size;
                 removed += entry.size;
 
-                let path = file_store.key_to_path(&key);
+                let path = match file_store.find_file(&key) {
+                    Some((path, _)) => path,
+                    None => file_store.key_to_path(&key),
+                };
                 debug!("Removing file {:?} claiming {}KiB", path, entry.size / 1024);
                 if let Err(e) = FileStore::remove_file(&path) {
                     warn!("Cannot flush file {:?}: {}", path, e.to_string());
@@ -338,6 +472,88 @@ mod tests {
         assert!(!store.key_to_path(&key3).exists());
     }
 
+    #[test]
+    fn test_flush_pinned() {
+        let cwd = get_cwd();
+        let store = FileStore::new(cwd.path(), 200, 100).unwrap();
+        let key1 = add_file_to_store(&store, 90).key.clone();
+        let key2 = add_file_to_store(&store, 95).key.clone();
+        store.pin("task", vec![key1.clone()]).unwrap();
+        // the pinned file doesn't count, so there is still space
+        let key3 = add_file_to_store(&store, 50).key.clone();
+        assert_eq!(store.index.lock().unwrap().total_size, 235);
+        // the new file is locked by its handle while flushing
+        let key4 = add_file_to_store(&store, 60).key.clone();
+
+        let index = store.index.lock().unwrap();
+        assert_eq!(index.total_size, 150);
+        assert!(index.is_pinned(&key1));
+        assert!(store.key_to_path(&key1).exists());
+        assert!(!store.key_to_path(&key2).exists());
+        assert!(!store.key_to_path(&key3).exists());
+        assert!(store.key_to_path(&key4).exists());
+    }
+
+    #[test]
+    fn test_pin_budget() {
+        let cwd = get_cwd();
+        let store = FileStore::new(cwd.path(), 200, 100)
+            .unwrap()
+            .with_pin_budget(100);
+        let key1 = add_file_to_store(&store, 60).key.clone();
+        let key2 = add_file_to_store(&store, 50).key.clone();
+        store.pin("task1", vec![key1.clone()]).unwrap();
+        assert!(store
+            .pin("task2", vec![key1.clone(), key2.clone()])
+            .is_err());
+        assert!(!store.pins().contains_key("task2"));
+        // a file in many pins is counted once
+        store.pin("task2", vec![key1]).unwrap();
+        assert_eq!(store.pinned_size(), 60);
+        assert!(store.unpin("task1"));
+        assert!(store.unpin("task2"));
+        store.pin("task2", vec![key2]).unwrap();
+        assert_eq!(store.pinned_size(), 50);
+    }
+
+    #[test]
+    fn test_pinned_size_updated() {
+        let cwd = get_cwd();
+        {
+            let store = FileStore::new(cwd.path(), 200, 100).unwrap();
+            let key1 = add_file_to_store(&store, 60).key.clone();
+            // a file pinned before being stored is counted when it's stored
+            let key2 = FileStoreKey::from_content(&[123; 30]);
+            store.pin("task", vec![key1.clone(), key2]).unwrap();
+            assert_eq!(store.pinned_size(), 60);
+            add_file_to_store(&store, 30);
+            assert_eq!(store.pinned_size(), 90);
+            store.index.lock().unwrap().remove(&key1);
+            assert_eq!(store.pinned_size(), 30);
+        }
+        // the size is recomputed when the index is loaded
+        let store = FileStore::new(cwd.path(), 200, 100).unwrap();
+        assert_eq!(store.pinned_size(), 30);
+        assert!(store.unpin("task"));
+        assert_eq!(store.pinned_size(), 0);
+    }
+
+    #[test]
+    fn test_load_pins() {
+        let cwd = get_cwd();
+        let key = {
+            let store = FileStore::new(cwd.path(), 200, 100).unwrap();
+            let key = add_file_to_store(&store, 10).key.clone();
+            store.pin("task", vec![key.clone()]).unwrap();
+            key
+        };
+        let store = FileStore::new(cwd.path(), 200, 100).unwrap();
+        assert_eq!(store.pins()["task"], vec![key]);
+        assert!(store.unpin("task"));
+        assert!(!store.unpin("task"));
+        assert!(store.pins().is_empty());
+    }
+
     #[test]
     fn test_flush_touch() {
         let cwd = get_cwd();
@@ -2,7 +2,16 @@
 //!
 //! The files are stored in a read-only manner (removing the write bit permission) and their access
 //! is granted via their hash. The size of the store folder is limited to a specific amount and the
-//! least-recently-used files are removed automatically.
+//! least-recently-used files are removed automatically, unless they are pinned. The pinned files
+//! don't count toward that limit, they have a separate size budget.
+//!
+//! Optionally the files can be kept compressed with zstd. Their key is still the hash of the
+//! uncompressed content, and they are decompressed while there are handles to them, so the users of
+//! the store always see the plain files.
+//!
+//! A second store can be shared between many users (for example on a network mount) as a read-only
+//! tier: the files not found in the local store are searched there, and copied into the local store
+//! when found.
 //!
 //! The access to the store directory via this crate is exclusive even between processes.
 //!
@@ -41,9 +50,10 @@
 extern crate log;
 
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Formatter;
-use std::io::Write;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
 use std::os::unix::prelude::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
@@ -54,8 +64,10 @@ use fslock::LockFile;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::index::FileStoreIndex;
+pub use fsck::FsckReport;
 pub use read_file_iterator::ReadFileIterator;
 
+mod fsck;
 mod index;
 mod read_file_iterator;
 
@@ -65,12 +77,22 @@ const INTEGRITY_CHECKS_ENABLED: bool = false;
 const STORE_LOCK_FILE: &str = "exclusive.lock";
 /// The name of the index of the file store.
 const STORE_INDEX_FILE: &str = "index.bin";
+/// The extension of the compressed files of the store.
+const COMPRESSED_EXTENSION: &str = "zst";
+/// The directory with the decompressed copies of the compressed files currently in use.
+const MATERIALIZED_DIR: &str = "materialized";
+/// The files smaller than this are never compressed, the saving is not worth the effort.
+const COMPRESSION_THRESHOLD: u64 = 16 * 1024;
 
 /// Container with the ref counts of all the handles still alive.
 #[derive(Debug)]
 struct LockedFiles {
     /// Map from a `FileStoreKey` to the number of handles alive.
     ref_counts: HashMap<FileStoreKey, usize>,
+    /// Map from a `FileStoreKey` to the size of its decompressed copy, if there is one.
+    materialized: HashMap<FileStoreKey, u64>,
+    /// The sum of the sizes of the decompressed copies.
+    materialized_size: u64,
 }
 
 /// A file store will manage all the files in the store directory.
@@ -94,6 +116,12 @@ pub struct FileStore {
     max_store_size: u64,
     /// Target size of the file store after the flush.
     min_store_size: u64,
+    /// Maximum total size of the pinned files.
+    max_pinned_size: u64,
+    /// The zstd level to use for compressing the new files, `None` to store them uncompressed.
+    compression_level: Option<i32>,
+    /// The base directory of the shared store, where to search the files missing from this one.
+    shared_path: Option<PathBuf>,
 }
 
 /// Handle of a file in the `FileStore`, this must be computable given the content of the file, i.e.
@@ -113,6 +141,9 @@ pub struct FileStoreHandle {
     key: FileStoreKey,
     /// The path to the file on disk.
     path: PathBuf,
+    /// Whether `path` is a decompressed copy of a compressed file, to remove when the last handle
+    /// is dropped.
+    materialized: bool,
     /// A reference to the locked files. Will be used to remove self from the ref counts.
     locked_files: Arc<Mutex<LockedFiles>>,
 }
@@ -164,6 +195,13 @@ impl FileStore {
                 .context("Failed to obtain exclusive lock on storage")?;
         }
 
+        // the decompressed copies left by a previous instance are not used by anyone anymore
+        let materialized = base_path.join(MATERIALIZED_DIR);
+        if materialized.exists() {
+            std::fs::remove_dir_all(&materialized)
+                .with_context(|| format!("Failed to remove {}", materialized.display()))?;
+        }
+
         let index = FileStoreIndex::load(base_path.join(STORE_INDEX_FILE))
             .context("Failed to load storage index")?;
         Ok(FileStore {
@@ -173,9 +211,37 @@ impl FileStore {
             index: Arc::new(Mutex::new(index)),
             max_store_size,
             min_store_size,
+            max_pinned_size: u64::MAX,
+            compression_level: None,
+            shared_path: None,
         })
     }
 
+    /// Compress with zstd at the provided level the files stored from now on, or store them
+    /// uncompressed if `None`. The files already in the store are kept as they are.
+    ///
+    /// The compressed files are decompressed on demand, the handles always point to the plain
+    /// content of the file.
+    pub fn with_compression(mut self, level: Option<i32>) -> FileStore {
+        self.compression_level = level;
+        self
+    }
+
+    /// Limit the total size of the pinned files, which are not counted in the size of the store.
+    /// By default there is no limit.
+    pub fn with_pin_budget(mut self, max_pinned_size: u64) -> FileStore {
+        self.max_pinned_size = max_pinned_size;
+        self
+    }
+
+    /// Search the files missing from this store also in the shared store in the specified
+    /// directory, if any, copying them here when found. The shared store is never modified, nor
+    /// locked.
+    pub fn with_shared<P: Into<PathBuf>>(mut self, shared_path: Option<P>) -> FileStore {
+        self.shared_path = shared_path.map(Into::into);
+        self
+    }
+
     /// Given an iterator of `Vec<u8>` consume all of it writing the content to the disk if the file
     /// is not already present on disk. The file is stored inside the base directory and `chmod -w`.
     ///
@@ -211,14 +277,30 @@ impl FileStore {
     pub fn store<I>(&self, key: &FileStoreKey, content: I) -> Result<FileStoreHandle, Error>
     where
         I: IntoIterator<Item = Vec<u8>>,
+    {
+        self.try_store(key, content.into_iter().map(Ok))
+    }
+
+    /// Store a file inside the store like [`store`](struct.FileStore.html#method.store), reading
+    /// its content from an iterator that may fail. If it does, the file is not stored and the
+    /// error is returned.
+    pub fn try_store<I>(&self, key: &FileStoreKey, content: I) -> Result<FileStoreHandle, Error>
+    where
+        I: IntoIterator<Item = Result<Vec<u8>, Error>>,
     {
         let path = self.key_to_path(key);
         trace!("Storing {:?}", path);
         // make the key to avoid racing while writing
-        let handle = FileStoreHandle::new(self, key);
-        if path.exists() {
-            trace!("File {:?} already exists", path);
-            content.into_iter().last(); // consume all the iterator
+        let mut handle = FileStoreHandle::new(self, key);
+        if let Some((stored, compressed)) = self.find_file(key) {
+            trace!("File {:?} already exists", stored);
+            // consume all the iterator
+            for data in content {
+                data.context("Failed to read the file to store")?;
+            }
+            if compressed {
+                self.materialize(&mut handle, &stored)?;
+            }
         } else {
             // assuming moving files is atomic this should be MT-safe
             let dir = path.parent().unwrap();
@@ -229,25 +311,67 @@ impl FileStore {
             let tmpfile_path = tmpdir.path().join("file");
             let mut tmpfile =
                 std::fs::File::create(&tmpfile_path).context("Failed to create temporary file")?;
-            if !content
-                .into_iter()
-                .map(|data| tmpfile.write_all(&data))
-                .all(|r| r.is_ok())
-            {
-                bail!("Failed to store file");
+            for data in content {
+                let data = data.context("Failed to read the file to store")?;
+                if tmpfile.write_all(&data).is_err() {
+                    bail!("Failed to store file");
+                }
             }
-            std::fs::rename(&tmpfile_path, &path).with_context(|| {
-                format!(
-                    "Failed to rename {} -> {}",
-                    tmpfile_path.display(),
-                    path.display()
-                )
-            })?;
-            FileStore::mark_readonly(&path).context("Failed to mark file as readonly")?;
+            let size = tmpfile
+                .metadata()
+                .context("Failed to get the size of the temporary file")?
+                .len();
+            let compressed_path = tmpdir.path().join("file.zst");
+            let compressed = match self.compression_level {
+                Some(level) if size >= COMPRESSION_THRESHOLD => {
+                    let compressed_size = compress_file(&tmpfile_path, &compressed_path, level)?;
+                    // incompressible files are stored as they are
+                    compressed_size < size
+                }
+                _ => false,
+            };
+            let stored = if compressed {
+                let stored = self.compressed_path(key);
+                std::fs::rename(&compressed_path, &stored).with_context(|| {
+                    format!(
+                        "Failed to rename {} -> {}",
+                        compressed_path.display(),
+                        stored.display()
+                    )
+                })?;
+                // the plain content is already here, there is no need to decompress it
+                handle.path = self.materialized_path(key);
+                handle.materialized = true;
+                create_parent_dir(&handle.path)?;
+                std::fs::rename(&tmpfile_path, &handle.path).with_context(|| {
+                    format!(
+                        "Failed to rename {} -> {}",
+                        tmpfile_path.display(),
+                        handle.path.display()
+                    )
+                })?;
+                FileStore::mark_readonly(&handle.path)
+                    .context("Failed to mark file as readonly")?;
+                self.locked_files
+                    .lock()
+                    .unwrap()
+                    .add_materialized(key, size);
+                stored
+            } else {
+                std::fs::rename(&tmpfile_path, &path).with_context(|| {
+                    format!(
+                        "Failed to rename {} -> {}",
+                        tmpfile_path.display(),
+                        path.display()
+                    )
+                })?;
+                path
+            };
+            FileStore::mark_readonly(&stored).context("Failed to mark file as readonly")?;
             {
                 let mut index = self.index.lock().unwrap();
                 index
-                    .add(key.clone(), path)
+                    .add(key.clone(), stored)
                     .context("Failed to add file to index")?;
                 self.maybe_flush(&mut index)?;
                 // FIXME: maybe this can be done less frequently
@@ -291,10 +415,13 @@ impl FileStore {
     /// # }
     /// ```
     pub fn get(&self, key: &FileStoreKey) -> Option<FileStoreHandle> {
-        let path = self.key_to_path(key);
-        if !path.exists() {
-            return None;
-        }
+        // make the handle first, so that the flush after importing the file doesn't remove it
+        let mut handle = FileStoreHandle::new(self, key);
+        let (path, compressed) = match self.find_file(key) {
+            Some(file) => file,
+            None => self.import_shared(key)?,
+        };
+        let metadata = std::fs::metadata(&path).ok()?;
         if INTEGRITY_CHECKS_ENABLED && !self.check_integrity(key) {
             warn!("File {:?} failed the integrity check", path);
             if let Err(e) = FileStore::remove_file(&path) {
@@ -304,9 +431,97 @@ impl FileStore {
         }
         {
             let mut index = self.index.lock().unwrap();
+            // cheap check against truncated or overwritten files, the full one is done by fsck
+            if index.size(key).map_or(false, |size| size != metadata.len()) {
+                warn!("File {:?} has not the expected size, removing it", path);
+                if let Err(e) = FileStore::remove_file(&path) {
+                    warn!("Cannot remove corrupted file: {:?}", e);
+                }
+                index.remove(key);
+                return None;
+            }
             index.touch(key);
         }
-        Some(FileStoreHandle::new(self, key))
+        if compressed {
+            if let Err(e) = self.materialize(&mut handle, &path) {
+                warn!("Cannot decompress {:?}, removing it: {:?}", path, e);
+                if let Err(e) = FileStore::remove_file(&path) {
+                    warn!("Cannot remove corrupted file: {:?}", e);
+                }
+                self.index.lock().unwrap().remove(key);
+                return None;
+            }
+        }
+        Some(handle)
+    }
+
+    /// The size of a file inside the store, if it's known.
+    pub fn file_size(&self, key: &FileStoreKey) -> Option<u64> {
+        self.index.lock().unwrap().size(key)
+    }
+
+    /// The total size of the files inside the store.
+    pub fn total_size(&self) -> u64 {
+        self.index.lock().unwrap().total_size()
+    }
+
+    /// The number of files inside the store.
+    pub fn num_files(&self) -> usize {
+        self.index.lock().unwrap().num_files()
+    }
+
+    /// The total size of the pinned files, each counted once.
+    pub fn pinned_size(&self) -> u64 {
+        self.index.lock().unwrap().pinned_size()
+    }
+
+    /// The maximum total size of the pinned files.
+    pub fn pin_budget(&self) -> u64 {
+        self.max_pinned_size
+    }
+
+    /// The pinned files, grouped by the name of their pin. The pinned files are never removed when
+    /// the store is flushed.
+    pub fn pins(&self) -> BTreeMap<String, Vec<FileStoreKey>> {
+        let index = self.index.lock().unwrap();
+        index
+            .pins()
+            .iter()
+            .map(|(name, keys)| {
+                let mut keys: Vec<_> = keys.iter().cloned().collect();
+                keys.sort();
+                (name.clone(), keys)
+            })
+            .collect()
+    }
+
+    /// Pin some files with the provided name, preventing the flush from removing them. If a pin
+    /// with the same name already exists, the files are added to it.
+    ///
+    /// Fails, without pinning anything, if the pinned files would exceed the pin budget.
+    pub fn pin<I>(&self, name: &str, keys: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = FileStoreKey>,
+    {
+        let keys: HashSet<_> = keys.into_iter().collect();
+        let mut index = self.index.lock().unwrap();
+        let size = index.pinned_size_with(&keys);
+        if size > self.max_pinned_size {
+            bail!(
+                "Pinning {} would take {} bytes, more than the budget of {} bytes",
+                name,
+                size,
+                self.max_pinned_size
+            );
+        }
+        index.pin(name, keys);
+        Ok(())
+    }
+
+    /// Remove the pin with the provided name, returning whether it existed. Its files will be
+    /// flushed normally.
+    pub fn unpin(&self, name: &str) -> bool {
+        self.index.lock().unwrap().unpin(name)
     }
 
     /// Path of the file to disk.
@@ -314,6 +529,119 @@ impl FileStore {
         self.base_path.join(key.suffix())
     }
 
+    /// Path of the file to disk, when it's stored compressed.
+    fn compressed_path(&self, key: &FileStoreKey) -> PathBuf {
+        self.key_to_path(key).with_extension(COMPRESSED_EXTENSION)
+    }
+
+    /// Path of the decompressed copy of a compressed file.
+    fn materialized_path(&self, key: &FileStoreKey) -> PathBuf {
+        self.base_path.join(MATERIALIZED_DIR).join(key.to_string())
+    }
+
+    /// The path of the file with that key on disk, if present, and whether it's compressed.
+    fn find_file(&self, key: &FileStoreKey) -> Option<(PathBuf, bool)> {
+        find_file_in(&self.base_path, key)
+    }
+
+    /// Copy the file with that key from the shared store, if present there. Returns the path of
+    /// the copy and whether it's compressed.
+    fn import_shared(&self, key: &FileStoreKey) -> Option<(PathBuf, bool)> {
+        let shared_path = self.shared_path.as_ref()?;
+        let (source, compressed) = find_file_in(shared_path, key)?;
+        match self.copy_shared_file(key, &source, compressed) {
+            Ok(path) => Some((path, compressed)),
+            Err(e) => {
+                warn!("Cannot copy {:?} from the shared store: {:?}", source, e);
+                None
+            }
+        }
+    }
+
+    /// Copy a file of the shared store into this store, adding it to the index.
+    ///
+    /// The caller must hold a handle to the file, so that the flush doesn't remove it.
+    fn copy_shared_file(
+        &self,
+        key: &FileStoreKey,
+        source: &Path,
+        compressed: bool,
+    ) -> Result<PathBuf, Error> {
+        debug!("Copying {:?} from the shared store", source);
+        let path = if compressed {
+            self.compressed_path(key)
+        } else {
+            self.key_to_path(key)
+        };
+        create_parent_dir(&path)?;
+        let tmpdir = tempfile::TempDir::new_in(path.parent().unwrap())
+            .context("Failed to create temporary directory for copying the file")?;
+        let tmpfile_path = tmpdir.path().join("file");
+        std::fs::copy(source, &tmpfile_path).with_context(|| {
+            format!(
+                "Failed to copy {} -> {}",
+                source.display(),
+                tmpfile_path.display()
+            )
+        })?;
+        // the shared store is written by other people, its content cannot be trusted
+        if &FileStoreKey::from_stored_file(&tmpfile_path, compressed)? != key {
+            bail!("The file in the shared store is corrupted");
+        }
+        std::fs::rename(&tmpfile_path, &path).with_context(|| {
+            format!(
+                "Failed to rename {} -> {}",
+                tmpfile_path.display(),
+                path.display()
+            )
+        })?;
+        FileStore::mark_readonly(&path).context("Failed to mark file as readonly")?;
+        let mut index = self.index.lock().unwrap();
+        index
+            .add(key.clone(), &path)
+            .context("Failed to add file to index")?;
+        self.maybe_flush(&mut index)?;
+        index
+            .store(self.base_path.join(STORE_INDEX_FILE))
+            .context("Failed to store the index to file")?;
+        Ok(path)
+    }
+
+    /// Make the handle point to the decompressed copy of the compressed file, decompressing it if
+    /// there isn't one already.
+    ///
+    /// The handle must already be counted, so that the copy is not removed in the meantime.
+    fn materialize(&self, handle: &mut FileStoreHandle, compressed: &Path) -> Result<(), Error> {
+        handle.path = self.materialized_path(&handle.key);
+        handle.materialized = true;
+        if handle.path.exists() {
+            return Ok(());
+        }
+        trace!("Decompressing {:?}", compressed);
+        create_parent_dir(&handle.path)?;
+        let dir = handle.path.parent().unwrap();
+        let mut tmpfile = tempfile::NamedTempFile::new_in(dir)
+            .context("Failed to create temporary file for decompressing")?;
+        let file = File::open(compressed)
+            .with_context(|| format!("Failed to open {}", compressed.display()))?;
+        zstd::stream::copy_decode(BufReader::new(file), tmpfile.as_file_mut())
+            .with_context(|| format!("Failed to decompress {}", compressed.display()))?;
+        let size = tmpfile
+            .as_file()
+            .metadata()
+            .context("Failed to get the size of the decompressed file")?
+            .len();
+        tmpfile
+            .persist(&handle.path)
+            .with_context(|| format!("Failed to persist {}", handle.path.display()))?;
+        FileStore::mark_readonly(&handle.path).context("Failed to mark file as readonly")?;
+        self.locked_files
+            .lock()
+            .unwrap()
+            .add_materialized(&handle.key, size);
+        Ok(())
+    }
+
     /// Mark a file as readonly.
     fn mark_readonly(path: &Path) -> Result<(), Error> {
         let mut perms = std::fs::metadata(path)
@@ -340,7 +668,10 @@ impl FileStore {
 
     /// Check if the file is not corrupted.
     fn check_integrity(&self, key: &FileStoreKey) -> bool {
-        let path = self.key_to_path(key);
+        let (path, compressed) = match self.find_file(key) {
+            Some(file) => file,
+            None => return false,
+        };
         let metadata = std::fs::metadata(&path);
         // if the last modified time is the same of creation time assume it's
         // not corrupted
@@ -353,7 +684,7 @@ impl FileStore {
                 }
             }
         }
-        match FileStoreKey::from_file(&path) {
+        match FileStoreKey::from_stored_file(&path, compressed) {
             Ok(key2) => key2.hash == key.hash,
             Err(_) => false,
         }
@@ -361,8 +692,8 @@ impl FileStore {
 
     /// Check if the file store needs flushing, and do so if needed.
     fn maybe_flush(&self, index: &mut FileStoreIndex) -> Result<(), Error> {
-        if index.need_flush(self.max_store_size) {
-            let locked = self.locked_files.lock().unwrap();
+        let locked = self.locked_files.lock().unwrap();
+        if index.need_flush(self.max_store_size, &locked) {
             index
                 .flush(self, &locked, self.min_store_size)
                 .context("Failed to flush index")?;
@@ -382,7 +713,7 @@ impl Drop for FileStore {
                         return;
                     }
                 };
-                if index.need_flush(self.max_store_size) {
+                if index.need_flush(self.max_store_size, &locked) {
                     if let Err(e) = index.flush(self, &locked, self.min_store_size) {
                         warn!("Cannot flush the index: {}", e.to_string());
                     }
@@ -430,6 +761,33 @@ impl FileStoreKey {
         })
     }
 
+    /// Make a new `FileStoreKey` from a file of the store, decompressing it if needed.
+    fn from_stored_file(path: &Path, compressed: bool) -> Result<FileStoreKey, Error> {
+        if !compressed {
+            return FileStoreKey::from_file(path);
+        }
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut hasher = Hasher::new();
+        zstd::stream::copy_decode(BufReader::new(file), &mut hasher)
+            .with_context(|| format!("Failed to decompress {}", path.display()))?;
+        Ok(FileStoreKey {
+            hash: hasher.finalize(),
+        })
+    }
+
+    /// Parse a key from its hexadecimal representation, the one used for the file names.
+    fn from_hex(hex: &str) -> Option<FileStoreKey> {
+        if hex.len() != 2 * blake3::OUT_LEN {
+            return None;
+        }
+        let mut raw = [0u8; blake3::OUT_LEN];
+        for (i, byte) in raw.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok()?;
+        }
+        Some(FileStoreKey { hash: raw.into() })
+    }
+
     /// Make a new `FileStoreKey` from an in-memory file.
     pub fn from_content(content: &[u8]) -> FileStoreKey {
         FileStoreKey {
@@ -491,6 +849,7 @@ impl FileStoreHandle {
         *locked_files.ref_counts.entry(key.clone()).or_default() += 1;
         FileStoreHandle {
             path,
+            materialized: false,
             locked_files: store.locked_files.clone(),
             key: key.clone(),
         }
@@ -520,6 +879,7 @@ impl Clone for FileStoreHandle {
 
         FileStoreHandle {
             path: self.path.clone(),
+            materialized: self.materialized,
             locked_files: self.locked_files.clone(),
             key: self.key.clone(),
         }
@@ -544,15 +904,79 @@ impl Drop for FileStoreHandle {
             .expect("Ref counts are broken") -= 1;
         if locked_files.ref_counts[&self.key] == 0 {
             locked_files.ref_counts.remove(&self.key);
+            // the lock is still held, so no new handle can start using the copy meanwhile. The
+            // sandboxes that hard-linked it keep their own link.
+            if self.materialized && self.path.exists() {
+                if let Err(e) = std::fs::remove_file(&self.path) {
+                    warn!("Cannot remove decompressed file {:?}: {:?}", self.path, e);
+                }
+                locked_files.remove_materialized(&self.key);
+            }
         }
     }
 }
 
+/// The path of the file with that key inside the store in `base_path`, if present, and whether it's
+/// compressed.
+fn find_file_in(base_path: &Path, key: &FileStoreKey) -> Option<(PathBuf, bool)> {
+    let path = base_path.join(key.suffix());
+    if path.exists() {
+        return Some((path, false));
+    }
+    let path = path.with_extension(COMPRESSED_EXTENSION);
+    if path.exists() {
+        return Some((path, true));
+    }
+    None
+}
+
+/// Compress a file with zstd, returning the size of the compressed file.
+fn compress_file(source: &Path, dest: &Path, level: i32) -> Result<u64, Error> {
+    let input =
+        File::open(source).with_context(|| format!("Failed to open {}", source.display()))?;
+    let output =
+        File::create(dest).with_context(|| format!("Failed to create {}", dest.display()))?;
+    let mut output = BufWriter::new(output);
+    zstd::stream::copy_encode(BufReader::new(input), &mut output, level)
+        .with_context(|| format!("Failed to compress {}", source.display()))?;
+    output
+        .flush()
+        .context("Failed to write the compressed file")?;
+    let size = std::fs::metadata(dest)
+        .with_context(|| format!("Failed to get file metadata of {}", dest.display()))?
+        .len();
+    Ok(size)
+}
+
+/// Create the parent directory of a path, if missing.
+fn create_parent_dir(path: &Path) -> Result<(), Error> {
+    let dir = path.parent().context("Invalid path without parent")?;
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Cannot create directory at {}", dir.display()))
+}
+
 impl LockedFiles {
     /// Make a new, empty, `LockedFiles`.
     fn new() -> LockedFiles {
         LockedFiles {
             ref_counts: HashMap::new(),
+            materialized: HashMap::new(),
+            materialized_size: 0,
+        }
+    }
+
+    /// Keep track of the size of a new decompressed copy of a file.
+    fn add_materialized(&mut self, key: &FileStoreKey, size: u64) {
+        if let Some(old) = self.materialized.insert(key.clone(), size) {
+            self.materialized_size -= old;
+        }
+        self.materialized_size += size;
+    }
+
+    /// Forget about the decompressed copy of a file, since it has been removed.
+    fn remove_materialized(&mut self, key: &FileStoreKey) {
+        if let Some(old) = self.materialized.remove(key) {
+            self.materialized_size -= old;
         }
     }
 }
@@ -647,6 +1071,17 @@ mod tests {
             .readonly());
     }
 
+    #[test]
+    fn test_try_store_error() {
+        let cwd = get_cwd();
+        let store = FileStore::new(cwd.path(), 1000, 1000).unwrap();
+        let key = FileStoreKey::from_content(b"test");
+        let content = vec![Ok(b"te".to_vec()), Err(anyhow::anyhow!("broken"))];
+        assert!(store.try_store(&key, content).is_err());
+        assert!(!store.key_to_path(&key).exists());
+        assert!(store.get(&key).is_none());
+    }
+
     #[test]
     fn test_get() {
         let cwd = get_cwd();
@@ -681,6 +1116,149 @@ mod tests {
         assert!(handle.is_none());
     }
 
+    #[test]
+    fn test_get_wrong_size() {
+        let cwd = get_cwd();
+        let store = FileStore::new(cwd.path(), 1000, 1000).unwrap();
+        let key = add_file_to_store(&cwd.path().join("test.txt"), "ciao", &store)
+            .key
+            .clone();
+        let path_in_store = store.key_to_path(&key);
+        FileStore::remove_file(&path_in_store).unwrap();
+        write(&path_in_store, "truncated").unwrap();
+
+        assert!(store.get(&key).is_none());
+        assert!(!path_in_store.exists());
+        assert_eq!(store.num_files(), 0);
+    }
+
+    #[test]
+    fn test_store_compressed() {
+        let cwd = get_cwd();
+        let store = FileStore::new(cwd.path(), 1_000_000, 1_000_000)
+            .unwrap()
+            .with_compression(Some(3));
+        let content = "hello world".repeat(10_000);
+        let handle = add_file_to_store(&cwd.path().join("test.txt"), &content, &store);
+        let key = handle.key.clone();
+        assert!(!store.key_to_path(&key).exists());
+        assert!(store.compressed_path(&key).exists());
+        assert_eq!(handle.path(), store.materialized_path(&key));
+        assert_eq!(read_to_string(handle.path()).unwrap(), content);
+        assert!(store.file_size(&key).unwrap() < content.len() as u64);
+        let materialized_size = || store.locked_files.lock().unwrap().materialized_size;
+        assert_eq!(materialized_size(), content.len() as u64);
+
+        // the decompressed copy lives as long as the handles
+        let path = handle.path().to_owned();
+        drop(handle);
+        assert!(!path.exists());
+        assert_eq!(materialized_size(), 0);
+        let handle = store.get(&key).unwrap();
+        assert_eq!(read_to_string(handle.path()).unwrap(), content);
+        let handle2 = handle.clone();
+        drop(handle);
+        assert!(handle2.path().exists());
+        drop(handle2);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_store_compressed_small() {
+        let cwd = get_cwd();
+        let store = FileStore::new(cwd.path(), 1000, 1000)
+            .unwrap()
+            .with_compression(Some(3));
+        let handle = add_file_to_store(&cwd.path().join("test.txt"), "ciao", &store);
+        assert_eq!(handle.path(), store.key_to_path(&handle.key));
+        assert!(!store.compressed_path(&handle.key).exists());
+    }
+
+    #[test]
+    fn test_get_compressed_corrupted() {
+        let cwd = get_cwd();
+        let store = FileStore::new(cwd.path(), 1_000_000, 1_000_000)
+            .unwrap()
+            .with_compression(Some(3));
+        let content = "hello world".repeat(10_000);
+        let key = add_file_to_store(&cwd.path().join("test.txt"), &content, &store)
+            .key
+            .clone();
+        let path_in_store = store.compressed_path(&key);
+        let size = metadata(&path_in_store).unwrap().len() as usize;
+        FileStore::remove_file(&path_in_store).unwrap();
+        write(&path_in_store, vec![42; size]).unwrap();
+
+        assert!(store.get(&key).is_none());
+        assert!(!path_in_store.exists());
+        assert_eq!(store.num_files(), 0);
+    }
+
+    #[test]
+    fn test_get_shared() {
+        let cwd = get_cwd();
+        let content = "hello world".repeat(10_000);
+        let (plain, compressed) = {
+            let shared = FileStore::new(cwd.path().join("shared"), 1_000_000, 1_000_000).unwrap();
+            let plain = add_file_to_store(&cwd.path().join("test.txt"), "ciao", &shared);
+            let shared = shared.with_compression(Some(3));
+            let compressed = add_file_to_store(&cwd.path().join("test.txt"), &content, &shared);
+            (plain.key.clone(), compressed.key.clone())
+        };
+
+        let store = FileStore::new(cwd.path().join("local"), 1_000_000, 1_000_000)
+            .unwrap()
+            .with_shared(Some(cwd.path().join("shared")));
+        let handle = store.get(&plain).unwrap();
+        assert_eq!(read_to_string(handle.path()).unwrap(), "ciao");
+        assert!(store.key_to_path(&plain).exists());
+        let handle = store.get(&compressed).unwrap();
+        assert_eq!(read_to_string(handle.path()).unwrap(), content);
+        assert!(store.compressed_path(&compressed).exists());
+        assert_eq!(store.num_files(), 2);
+        assert!(store.get(&FileStoreKey::from_content(b"missing")).is_none());
+    }
+
+    #[test]
+    fn test_get_shared_full_store() {
+        let cwd = get_cwd();
+        let content = "hello world".repeat(100);
+        let key = {
+            let shared = FileStore::new(cwd.path().join("shared"), 1_000_000, 1_000_000).unwrap();
+            add_file_to_store(&cwd.path().join("test.txt"), &content, &shared)
+                .key
+                .clone()
+        };
+
+        // the imported file alone exceeds the limit of the store
+        let store = FileStore::new(cwd.path().join("local"), 1000, 500)
+            .unwrap()
+            .with_shared(Some(cwd.path().join("shared")));
+        add_file_to_store(&cwd.path().join("test.txt"), "ciao", &store);
+        let handle = store.get(&key).unwrap();
+        assert_eq!(read_to_string(handle.path()).unwrap(), content);
+    }
+
+    #[test]
+    fn test_get_shared_corrupted() {
+        let cwd = get_cwd();
+        let key = {
+            let shared = FileStore::new(cwd.path().join("shared"), 1000, 1000).unwrap();
+            let key = add_file_to_store(&cwd.path().join("test.txt"), "ciao", &shared)
+                .key
+                .clone();
+            corrupt_file(&shared.key_to_path(&key));
+            key
+        };
+
+        let store = FileStore::new(cwd.path().join("local"), 1000, 1000)
+            .unwrap()
+            .with_shared(Some(cwd.path().join("shared")));
+        assert!(store.get(&key).is_none());
+        assert!(!store.key_to_path(&key).exists());
+        assert_eq!(store.num_files(), 0);
+    }
+
     #[test]
     fn test_corrupted_file() {
         if !INTEGRITY_CHECKS_ENABLED {
@@ -145,6 +145,8 @@ impl TestInterface {
                 no_statement: false,
                 solution_paths: vec![],
                 disabled_sanity_checks: vec![],
+                sanity_check_levels: Default::default(),
+                deny_warnings: false,
                 seed: None,
                 dry_run: false,
             },
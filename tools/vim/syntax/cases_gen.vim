@@ -7,16 +7,19 @@ syntax match GenExe       /[^ \t]\+/ contained
 syntax match GenNameGV    /\i\+/ contained nextgroup=GenExe skipwhite
 syntax match GenRunV      /\i\+/ contained nextgroup=GenParams skipwhite
 syntax match GenScore     /\d\+/ contained nextgroup=GenName skipwhite
-syntax match GenNumber    /\d\+/ contained
+syntax match GenNumber    /\d\+\(\.\d\+\)\=\([eE][-+]\=\d\+\)\=/ contained
 syntax match GenVariable  /\$\i\+/
 syntax match GenDollar    /\$\$/
 syntax keyword GenGeneratorK contained GEN VAL nextgroup=GenNameGV skipwhite
-syntax keyword GenRunK contained RUN nextgroup=GenRunV skipwhite
+syntax keyword GenRunK contained RUN MULTIGEN nextgroup=GenRunV skipwhite
 syntax keyword GenCopyK contained COPY nextgroup=GenExe skipwhite
 syntax keyword GenSubtaskK contained SUBTASK nextgroup=GenScore skipwhite
+syntax keyword GenIncludeK contained INCLUDE nextgroup=GenName skipwhite
+syntax keyword GenLoopK contained FOR IN END
 syntax keyword GenConstraintK contained CONSTRAINT nextgroup=GenNumber
-syntax match GenCommand   /^:.*/ contains=GenGeneratorK,GenSubtaskK,GenRunK,GenCopyK
-syntax match GenConstraintCommand   /^:\s*CONSTRAINT.*/ contains=GenConstraintK,GenNumber,GenVariable
+syntax keyword GenFunction contained min max
+syntax match GenCommand   /^:.*/ contains=GenGeneratorK,GenSubtaskK,GenRunK,GenCopyK,GenLoopK,GenIncludeK
+syntax match GenConstraintCommand   /^:\s*CONSTRAINT.*/ contains=GenConstraintK,GenNumber,GenVariable,GenFunction
 syntax match GenComment   /^#.*/
 
 hi def link GenComment Comment
@@ -33,7 +36,10 @@ hi def link GenRunK vimCommand
 hi def link GenCopyK vimCommand
 hi def link GenSubtaskK vimCommand
 hi def link GenConstraintK vimCommand
+hi def link GenLoopK vimCommand
+hi def link GenIncludeK vimCommand
 hi def link GenVariable Macro
+hi def link GenFunction Function
 hi def link GenDollar Macro
 
 let b:current_syntax = 'cases_gen'